@@ -1,12 +1,14 @@
-//! API Key Authentication Middleware
+//! API Authentication Middleware
 //!
-//! Provides tower-compatible middleware for API key validation.
-//! Keys are passed via the `X-API-Key` header.
+//! Provides tower-compatible middleware for client authentication.
+//! In `api_key` mode keys are passed via the `X-API-Key` header; in `jwt`
+//! mode a token is passed via `Authorization: Bearer <token>` (see
+//! [`super::jwt`]).
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{header, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
     Json,
@@ -14,17 +16,27 @@ use axum::{
 use serde::Serialize;
 use std::sync::Arc;
 
-use crate::config::AuthConfig;
+use super::jwt::JwtValidator;
+use crate::config::{AuthConfig, AuthMode};
 
 /// Authentication state shared across requests
 #[derive(Clone)]
 pub struct AuthState {
     pub config: AuthConfig,
+    jwt: Option<Arc<JwtValidator>>,
 }
 
+/// The client a request was authenticated as: its API key, or the subject
+/// of its token
+///
+/// Added to the request extensions by [`authenticate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedClient(pub String);
+
 impl AuthState {
     pub fn new(config: AuthConfig) -> Self {
-        Self { config }
+        let jwt = (config.mode == AuthMode::Jwt).then(|| Arc::new(JwtValidator::new(&config.jwt)));
+        Self { config, jwt }
     }
 
     /// Check if the given API key is valid
@@ -53,11 +65,23 @@ struct AuthError {
     message: String,
 }
 
-/// API Key authentication middleware
+fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(AuthError {
+            error: "unauthorized".to_string(),
+            message: message.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Authentication middleware
 ///
-/// Validates the `X-API-Key` header against configured API keys.
-/// Paths in `exclude_paths` are allowed without authentication.
-pub async fn api_key_auth(
+/// Validates the `X-API-Key` header against configured API keys, or the
+/// bearer token in `jwt` mode. Paths in `exclude_paths` are allowed
+/// without authentication.
+pub async fn authenticate(
     State(auth_state): State<Arc<AuthState>>,
     request: Request<Body>,
     next: Next,
@@ -74,6 +98,14 @@ pub async fn api_key_auth(
         return next.run(request).await;
     }
 
+    match &auth_state.jwt {
+        Some(validator) => bearer_auth(validator, request, next).await,
+        None => api_key_auth(&auth_state, request, next).await,
+    }
+}
+
+/// Validate the `X-API-Key` header against configured API keys
+async fn api_key_auth(auth_state: &AuthState, mut request: Request<Body>, next: Next) -> Response {
     // Check for API key header
     let api_key = request
         .headers()
@@ -83,29 +115,47 @@ pub async fn api_key_auth(
     match api_key {
         Some(key) if auth_state.is_valid_key(key) => {
             // Valid key, proceed
+            let client = AuthenticatedClient(key.to_string());
+            request.extensions_mut().insert(client);
             next.run(request).await
         }
-        Some(_) => {
-            // Invalid key
-            (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "unauthorized".to_string(),
-                    message: "Invalid API key".to_string(),
-                }),
-            )
-                .into_response()
+        // Invalid key
+        Some(_) => unauthorized("Invalid API key"),
+        // Missing key
+        None => unauthorized("Missing X-API-Key header"),
+    }
+}
+
+/// Validate the bearer token of the `Authorization` header
+async fn bearer_auth(validator: &JwtValidator, mut request: Request<Body>, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    let Some(token) = token else {
+        let mut response = unauthorized("Missing Authorization: Bearer header");
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+        return response;
+    };
+
+    match validator.validate(&token).await {
+        Ok(claims) => {
+            let client = AuthenticatedClient(claims.sub.unwrap_or(token));
+            request.extensions_mut().insert(client);
+            next.run(request).await
         }
-        None => {
-            // Missing key
-            (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "unauthorized".to_string(),
-                    message: "Missing X-API-Key header".to_string(),
-                }),
-            )
-                .into_response()
+        Err(message) => {
+            let mut response = unauthorized(&message);
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                "Bearer error=\"invalid_token\"".parse().unwrap(),
+            );
+            response
         }
     }
 }
@@ -120,6 +170,7 @@ mod tests {
             enabled: true,
             api_keys: vec!["secret-key-123".to_string(), "another-key".to_string()],
             exclude_paths: vec!["/health".to_string()],
+            ..AuthConfig::default()
         };
         let state = AuthState::new(config);
 
@@ -135,6 +186,7 @@ mod tests {
             enabled: true,
             api_keys: vec![],
             exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
+            ..AuthConfig::default()
         };
         let state = AuthState::new(config);
 
@@ -150,6 +202,7 @@ mod tests {
             enabled: true,
             api_keys: vec![],
             exclude_paths: vec!["/public/*".to_string(), "/docs/*".to_string()],
+            ..AuthConfig::default()
         };
         let state = AuthState::new(config);
 
@@ -165,6 +218,7 @@ mod tests {
             enabled: true,
             api_keys: vec![],
             exclude_paths: vec![],
+            ..AuthConfig::default()
         };
         let state = AuthState::new(config);
 
@@ -0,0 +1,59 @@
+//! Cross-origin (CORS) headers for browser dashboards
+//!
+//! Without a `cors` section the API sends no CORS headers, so browsers only
+//! let pages of its own origin read the responses. Listed origins may call
+//! every endpoint with `GET` and `POST`, sending the API key and JSON
+//! bodies; `permissive` allows any origin, method and header.
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+
+/// How long browsers may cache a preflight response
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// The layer answering preflight requests and adding the CORS headers, if
+/// any origin is allowed
+pub fn cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    if config.permissive {
+        return Some(CorsLayer::permissive());
+    }
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+
+    // Validation has rejected origins that are not valid header values
+    let origins: Vec<HeaderValue> = config
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+    Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([
+                header::CONTENT_TYPE,
+                header::AUTHORIZATION,
+                HeaderName::from_static("x-api-key"),
+            ])
+            .max_age(PREFLIGHT_MAX_AGE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_down_by_default() {
+        assert!(cors_layer(&CorsConfig::default()).is_none());
+        assert!(cors_layer(&CorsConfig {
+            permissive: true,
+            ..CorsConfig::default()
+        })
+        .is_some());
+    }
+}
@@ -4,11 +4,18 @@
 //! and WebSocket for real-time register updates.
 
 pub mod auth;
+pub mod cors;
+pub mod jwt;
+pub mod naming;
+pub mod pretty;
+pub mod rate_limit;
+pub mod snapshot;
 
 use axum::{
     extract::{
+        rejection::JsonRejection,
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        FromRequest, Path, Query, Request, State,
     },
     http::StatusCode,
     middleware,
@@ -19,25 +26,137 @@ use axum::{
 use futures_util::{SinkExt, StreamExt};
 use metrics_exporter_prometheus::PrometheusHandle;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
+use tokio::sync::{broadcast, RwLock};
 use tracing::{debug, error, info, warn};
 
-use crate::config::AuthConfig;
-use crate::modbus::reader::RegisterStore;
-
-use self::auth::{api_key_auth, AuthState};
+use crate::config::{
+    AuthConfig, Config, ConversionStep, CorsConfig, DataType, HistoryConfig, RawFormat,
+    RegisterBuilder, RegisterConfig, RegisterType, ServerConfig, TextEncoding, WordOrder,
+    WriteQueuePolicy,
+};
+use crate::metrics::ReadHealth;
+use crate::modbus::expression::Formula;
+use crate::modbus::history::{History, Sample, SampleRange};
+use crate::modbus::identification::{DeviceInfo, DeviceInfoStore};
+use crate::modbus::pause::PausedDevices;
+use crate::modbus::reader::{self, Quality, RegisterStore, RegisterValue};
+use crate::modbus::removed::{Removal, RemovedRegisters};
+use crate::storage::Storage;
+
+use self::auth::{authenticate, AuthState};
+use self::naming::RegisterNames;
 
 /// Broadcast channel capacity for WebSocket updates
 const BROADCAST_CAPACITY: usize = 1024;
 
+/// Longest coil pulse accepted by the write endpoint
+pub const MAX_PULSE_MS: u64 = 60_000;
+
+/// Longest write timeout a client may request
+pub const MAX_WRITE_TIMEOUT_MS: u64 = 60_000;
+
+/// `Retry-After` (seconds) sent when the write queue is full
+const WRITE_RETRY_AFTER_SECS: u64 = 1;
+
 /// API state shared across handlers
 #[derive(Clone)]
 pub struct ApiState {
     pub register_store: RegisterStore,
     pub update_tx: broadcast::Sender<RegisterUpdate>,
+    /// Value transitions, a subset of the updates
+    pub change_tx: broadcast::Sender<ValueChange>,
     pub write_tx: tokio::sync::mpsc::Sender<WriteRequest>,
     pub metrics_handle: Option<PrometheusHandle>,
+    pub config: Arc<RwLock<Config>>,
+    /// MQTT broker connection flag (None when MQTT is disabled)
+    pub mqtt_connected: Option<Arc<AtomicBool>>,
+    /// On-demand reads routed to device workers (None if unsupported)
+    pub raw_tx: Option<tokio::sync::mpsc::Sender<RawReadRequest>>,
+    /// Recent read outcomes per register
+    pub read_health: ReadHealth,
+    /// Identification (FC43) of devices that support it
+    pub device_info: DeviceInfoStore,
+    /// Outcome of configuration reloads since startup
+    pub reload_history: Arc<RwLock<ReloadHistory>>,
+    /// File `POST /api/reload` reads the configuration from (None when
+    /// reloads are not available)
+    pub config_path: Option<String>,
+    /// Recent values of every register, if enabled
+    pub history: History,
+    /// Register history database, served instead of `history` if enabled
+    pub storage: Option<Storage>,
+    /// Devices whose polling is paused for maintenance
+    pub paused: PausedDevices,
+    /// Polled devices and registers a reload removed
+    pub removed: RemovedRegisters,
+    /// Whether the gateway is still starting up
+    pub readiness: Readiness,
+    /// Open WebSocket connections
+    pub ws_connections: Arc<AtomicUsize>,
+    /// Origins whose pages may call the API, fixed when the router is
+    /// created
+    pub cors: CorsConfig,
+}
+
+/// Startup progress, reported by `/health` and `/ready`
+///
+/// The gateway is starting until every device has completed its first
+/// successful poll cycle, for at most the startup grace period.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness {
+    /// Set after each device's first successful poll cycle
+    devices: Vec<tokio::sync::watch::Receiver<bool>>,
+    /// End of the grace period, `None` when there is none
+    deadline: Option<std::time::Instant>,
+}
+
+impl Readiness {
+    /// Track the devices' first poll cycles for up to `grace`
+    pub fn new(
+        devices: Vec<tokio::sync::watch::Receiver<bool>>,
+        grace: std::time::Duration,
+    ) -> Self {
+        Self {
+            devices,
+            deadline: Some(std::time::Instant::now() + grace),
+        }
+    }
+
+    /// Whether devices are still waiting for their first successful cycle
+    /// within the grace period
+    pub fn is_starting(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| std::time::Instant::now() < deadline)
+            && self.devices.iter().any(|ready| !*ready.borrow())
+    }
+}
+
+/// Configuration reloads since startup, reported by `/api/reload/status`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadHistory {
+    /// Reload attempts, successful or not
+    pub reloads: u64,
+    pub failures: u64,
+    /// When the last reload was attempted
+    pub last_reload: Option<String>,
+    pub last_success: Option<bool>,
+    /// Why the last reload failed
+    pub last_error: Option<String>,
+}
+
+impl ReloadHistory {
+    fn record(&mut self, error: Option<String>) {
+        self.reloads += 1;
+        if error.is_some() {
+            self.failures += 1;
+        }
+        self.last_reload = Some(chrono::Utc::now().to_rfc3339());
+        self.last_success = Some(error.is_none());
+        self.last_error = error;
+    }
 }
 
 impl ApiState {
@@ -47,11 +166,27 @@ impl ApiState {
         write_tx: tokio::sync::mpsc::Sender<WriteRequest>,
     ) -> Self {
         let (update_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let (change_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
         Self {
             register_store,
             update_tx,
+            change_tx,
             write_tx,
             metrics_handle: None,
+            config: Arc::new(RwLock::new(Config::default())),
+            mqtt_connected: None,
+            raw_tx: None,
+            read_health: ReadHealth::new(ServerConfig::default_success_ratio_window()),
+            device_info: DeviceInfoStore::default(),
+            reload_history: Arc::default(),
+            config_path: None,
+            history: History::new(&HistoryConfig::default()),
+            storage: None,
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            readiness: Readiness::default(),
+            ws_connections: Arc::default(),
+            cors: CorsConfig::default(),
         }
     }
 
@@ -62,11 +197,141 @@ impl ApiState {
         metrics_handle: PrometheusHandle,
     ) -> Self {
         let (update_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let (change_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
         Self {
             register_store,
             update_tx,
+            change_tx,
             write_tx,
             metrics_handle: Some(metrics_handle),
+            config: Arc::new(RwLock::new(Config::default())),
+            mqtt_connected: None,
+            raw_tx: None,
+            read_health: ReadHealth::new(ServerConfig::default_success_ratio_window()),
+            device_info: DeviceInfoStore::default(),
+            reload_history: Arc::default(),
+            config_path: None,
+            history: History::new(&HistoryConfig::default()),
+            storage: None,
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            readiness: Readiness::default(),
+            ws_connections: Arc::default(),
+            cors: CorsConfig::default(),
+        }
+    }
+
+    /// Set the running configuration exposed by the API (this also sizes
+    /// the read success window and sets the allowed CORS origins)
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.read_health = ReadHealth::new(config.server.success_ratio_window);
+        self.history = History::new(&config.history);
+        self.removed = RemovedRegisters::new(&config);
+        self.cors = config.cors.clone();
+        self.config = Arc::new(RwLock::new(config));
+        self
+    }
+
+    /// Let `POST /api/reload` re-read the configuration from `path`
+    pub fn with_config_path(mut self, path: impl Into<String>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Record a configuration reload; a successfully loaded configuration
+    /// becomes the running one exposed by the API, and the values of
+    /// devices and registers it no longer has are evicted
+    ///
+    /// Device workers keep their configuration, so a configuration that
+    /// adds or changes devices or registers is rejected. Returns why the
+    /// reload failed.
+    pub async fn apply_reload(&self, result: anyhow::Result<Config>) -> Option<String> {
+        let result = result.and_then(|config| {
+            let removal = self.removed.update(&config)?;
+            Ok((config, removal))
+        });
+        let error = match result {
+            Ok((config, removal)) => {
+                self.evict(&removal).await;
+                *self.config.write().await = config;
+                info!("Configuration reloaded for the API; device polling is unchanged");
+                None
+            }
+            Err(e) => {
+                warn!("Configuration reload failed: {:#}", e);
+                Some(format!("{:#}", e))
+            }
+        };
+        crate::metrics::record_config_reload(error.is_none());
+        self.reload_history.write().await.record(error.clone());
+        error
+    }
+
+    /// Drop the stored values and history of removed devices and registers
+    async fn evict(&self, removal: &Removal) {
+        let mut store = self.register_store.write().await;
+        for device_id in &removal.devices {
+            if store.remove(device_id).is_some() {
+                info!(
+                    "Evicted device {}: removed from the configuration",
+                    device_id
+                );
+            }
+            self.history.remove(device_id, None);
+        }
+        for (device_id, register_name) in &removal.registers {
+            let registers = store.get_mut(device_id);
+            if registers.is_some_and(|registers| registers.remove(register_name).is_some()) {
+                info!(
+                    "Evicted register {}/{}: removed from the configuration",
+                    device_id, register_name
+                );
+            }
+            self.history.remove(device_id, Some(register_name));
+        }
+    }
+
+    /// Track the MQTT broker connection for health reporting
+    pub fn with_mqtt_status(mut self, connected: Arc<AtomicBool>) -> Self {
+        self.mqtt_connected = Some(connected);
+        self
+    }
+
+    /// Report the gateway as starting until `readiness` says otherwise
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Serve register history from the given database
+    pub fn with_storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Enable on-demand raw reads through the given channel
+    pub fn with_raw_reads(mut self, raw_tx: tokio::sync::mpsc::Sender<RawReadRequest>) -> Self {
+        self.raw_tx = Some(raw_tx);
+        self
+    }
+
+    /// Whether the MQTT broker is connected, if MQTT is in use
+    pub fn mqtt_connected(&self) -> Option<bool> {
+        self.mqtt_connected
+            .as_ref()
+            .map(|connected| connected.load(Ordering::SeqCst))
+    }
+
+    /// Output names of registers under the running configuration
+    async fn register_names(&self) -> RegisterNames {
+        RegisterNames::new(&*self.config.read().await)
+    }
+
+    /// Raw word format for a request, falling back to the configured one
+    async fn raw_format(&self, requested: Option<RawFormat>) -> RawFormat {
+        match requested {
+            Some(format) => format,
+            None => self.config.read().await.raw_format,
         }
     }
 
@@ -74,6 +339,11 @@ impl ApiState {
     pub fn subscribe(&self) -> broadcast::Receiver<RegisterUpdate> {
         self.update_tx.subscribe()
     }
+
+    /// Get a receiver for value changes
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<ValueChange> {
+        self.change_tx.subscribe()
+    }
 }
 
 /// Register update message for WebSocket broadcast
@@ -81,9 +351,59 @@ impl ApiState {
 pub struct RegisterUpdate {
     pub device_id: String,
     pub register_name: String,
+    /// Scaled engineering value
     pub value: f64,
+    /// Decoded number before scale/offset
+    pub decoded: f64,
+    /// Register words as read from the device
     pub raw: Vec<u16>,
+    /// Content of a string register
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
     pub unit: Option<String>,
+    #[serde(default)]
+    pub quality: Quality,
+    /// The value differs from the previous reading, or is the first one.
+    /// Decided once in the poll loop so every output agrees.
+    #[serde(default)]
+    pub changed: bool,
+    /// Previous value (text for string registers), absent on the first
+    /// reading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<serde_json::Value>,
+    pub timestamp: String,
+}
+
+impl RegisterUpdate {
+    /// The change event for this update, if it is a transition from an
+    /// earlier reading
+    pub fn value_change(&self) -> Option<ValueChange> {
+        if !self.changed {
+            return None;
+        }
+        let old_value = self.old_value.clone()?;
+        let new_value = match &self.text {
+            Some(text) => text.clone().into(),
+            None => self.value.into(),
+        };
+        Some(ValueChange {
+            device_id: self.device_id.clone(),
+            register: self.register_name.clone(),
+            old_value,
+            new_value,
+            timestamp: self.timestamp.clone(),
+        })
+    }
+}
+
+/// A register value that differs from its previous reading
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValueChange {
+    pub device_id: String,
+    pub register: String,
+    /// Previous value (text for string registers)
+    pub old_value: serde_json::Value,
+    pub new_value: serde_json::Value,
     pub timestamp: String,
 }
 
@@ -91,40 +411,115 @@ pub struct RegisterUpdate {
 #[derive(Debug)]
 pub struct WriteRequest {
     pub device_id: String,
+    /// Coils are written with the coil functions, holding registers with
+    /// the register ones
+    pub register_type: RegisterType,
     pub address: u16,
-    pub value: u16,
+    /// Words to write from `address` on; a single word for masked writes
+    /// and coil pulses
+    pub values: Vec<u16>,
+    /// Only write the bits set in this mask, preserving the others
+    pub mask: Option<u16>,
+    /// Pulse a coil: write `value`, wait this long, then write the opposite
+    pub pulse_ms: Option<u64>,
+    /// When the API queued the write, to measure how long it waited
+    pub queued_at: std::time::Instant,
     pub response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
 }
 
+/// On-demand read sent to a device's worker
+#[derive(Debug)]
+pub struct RawReadRequest {
+    pub device_id: String,
+    pub register_type: RegisterType,
+    pub address: u16,
+    pub count: u16,
+    /// Read from this unit id instead of the device's configured one
+    pub unit_id: Option<u8>,
+    /// Give up on the device after this long (None uses its read timeout)
+    pub timeout: Option<std::time::Duration>,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<Vec<u16>, RawReadError>>,
+}
+
+/// Why an on-demand read returned no values
+#[derive(Debug, thiserror::Error)]
+pub enum RawReadError {
+    /// The device answered with a Modbus exception
+    #[error("{0}")]
+    Exception(String),
+    /// No valid answer: timeout, transport error or device not running
+    #[error("{0}")]
+    Failed(String),
+}
+
 /// Create the API router
 pub fn create_router(state: ApiState, auth_config: AuthConfig) -> Router {
+    let write_limit = rate_limit::WriteRateLimit::new(state.config.clone());
     let auth_state = Arc::new(AuthState::new(auth_config));
 
-    Router::new()
+    let router = Router::new()
         // Health & Info
         .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/api/health/detailed", get(health_detailed))
         .route("/api/info", get(api_info))
+        .route("/api/stats", get(gateway_stats))
         // Metrics (Prometheus)
         .route("/metrics", get(metrics_handler))
+        // Configuration
+        .route("/api/config.yaml", get(get_config_yaml))
+        .route("/api/reload", post(reload_config))
+        .route("/api/reload/status", get(reload_status))
         // Devices
         .route("/api/devices", get(list_devices))
         .route("/api/devices/:device_id", get(get_device))
+        .route("/api/devices/:device_id/pause", post(pause_device))
+        .route("/api/devices/:device_id/resume", post(resume_device))
         // Registers (read)
-        .route("/api/devices/:device_id/registers", get(get_registers))
+        .route(
+            "/api/devices/:device_id/registers",
+            get(get_registers).post(get_registers_batch),
+        )
         .route(
             "/api/devices/:device_id/registers/:register_name",
             get(get_register),
         )
-        // Registers (write)
+        .route(
+            "/api/devices/:device_id/registers/:register_name/health",
+            get(get_register_health),
+        )
+        .route(
+            "/api/devices/:device_id/registers/:register_name/history",
+            get(get_register_history),
+        )
+        // Registers (write), limited to protect slow devices
         .route(
             "/api/devices/:device_id/registers/:register_name",
-            post(write_register),
+            post(write_register).layer(middleware::from_fn_with_state(
+                write_limit,
+                rate_limit::write_rate_limit,
+            )),
         )
-        // WebSocket
+        // Commissioning
+        .route("/api/devices/:device_id/raw", post(raw_read))
+        .route("/api/devices/:device_id/scan", post(scan_bus))
+        .route("/api/convert", post(convert))
+        // Diagnostics
+        .route("/api/snapshot", post(write_snapshot))
+        // Streaming (WebSocket and plain HTTP)
         .route("/ws", get(ws_handler))
-        // Apply API key authentication middleware
-        .layer(middleware::from_fn_with_state(auth_state, api_key_auth))
-        .with_state(Arc::new(state))
+        .route("/api/stream", get(stream_updates))
+        // Apply API key or bearer token authentication middleware
+        .layer(middleware::from_fn_with_state(auth_state, authenticate))
+        // Outermost, so authentication errors are indented too
+        .layer(middleware::from_fn(pretty::pretty_json));
+    // Around authentication, since browsers send preflight requests
+    // without credentials
+    let router = match cors::cors_layer(&state.cors) {
+        Some(cors) => router.layer(cors),
+        None => router,
+    };
+    router.with_state(Arc::new(state))
 }
 
 // ============================================================================
@@ -168,6 +563,64 @@ impl ApiError {
     }
 }
 
+/// JSON request body whose rejections are reported as [`ApiError`] like
+/// every other error, instead of axum's plain-text responses
+struct JsonBody<T>(T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Json<ApiError>);
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => {
+                let code = match rejection {
+                    JsonRejection::MissingJsonContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                Err(ApiError::with_details(
+                    code,
+                    "Invalid request body",
+                    rejection.body_text(),
+                ))
+            }
+        }
+    }
+}
+
+/// Error of the write endpoint; a full write queue also tells the client
+/// when to try again
+struct WriteError {
+    error: (StatusCode, Json<ApiError>),
+    retry_after_secs: Option<u64>,
+}
+
+impl From<(StatusCode, Json<ApiError>)> for WriteError {
+    fn from(error: (StatusCode, Json<ApiError>)) -> Self {
+        Self {
+            error,
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl IntoResponse for WriteError {
+    fn into_response(self) -> Response {
+        let mut response = self.error.into_response();
+        if let Some(secs) = self.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(axum::http::header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
 // ============================================================================
 // Health & Info Endpoints
 // ============================================================================
@@ -179,11 +632,79 @@ struct HealthResponse {
     version: &'static str,
 }
 
-async fn health() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok",
-        version: env!("CARGO_PKG_VERSION"),
-    })
+async fn health(State(state): State<Arc<ApiState>>) -> (StatusCode, Json<HealthResponse>) {
+    let (code, status) = health_status(&state).await;
+    (
+        code,
+        Json(HealthResponse {
+            status,
+            version: env!("CARGO_PKG_VERSION"),
+        }),
+    )
+}
+
+/// Readiness probe: 200 once startup is complete and the gateway is
+/// healthy, 503 before
+async fn ready(State(state): State<Arc<ApiState>>) -> (StatusCode, Json<HealthResponse>) {
+    let (code, status) = match health_status(&state).await {
+        (StatusCode::OK, "ok") => (StatusCode::OK, "ready"),
+        (_, status) => (StatusCode::SERVICE_UNAVAILABLE, status),
+    };
+    (
+        code,
+        Json(HealthResponse {
+            status,
+            version: env!("CARGO_PKG_VERSION"),
+        }),
+    )
+}
+
+/// Detailed health response
+#[derive(Serialize)]
+struct DetailedHealthResponse {
+    status: &'static str,
+    version: &'static str,
+    /// Broker connection state, `null` when MQTT is disabled
+    mqtt_connected: Option<bool>,
+    /// Whether a broken MQTT connection makes the bridge unhealthy
+    mqtt_required: bool,
+    devices: usize,
+    /// Online state and circuit breaker state per device
+    device_health: std::collections::BTreeMap<String, crate::metrics::DeviceState>,
+}
+
+async fn health_detailed(
+    State(state): State<Arc<ApiState>>,
+) -> (StatusCode, Json<DetailedHealthResponse>) {
+    let (code, status) = health_status(&state).await;
+    let mqtt_required = state.config.read().await.mqtt.required;
+    let devices = state.register_store.read().await.len();
+
+    (
+        code,
+        Json(DetailedHealthResponse {
+            status,
+            version: env!("CARGO_PKG_VERSION"),
+            mqtt_connected: state.mqtt_connected(),
+            mqtt_required,
+            devices,
+            device_health: crate::metrics::device_states(),
+        }),
+    )
+}
+
+/// Overall health: `starting` during startup, then unhealthy only when a
+/// required dependency is down
+async fn health_status(state: &ApiState) -> (StatusCode, &'static str) {
+    if state.readiness.is_starting() {
+        return (StatusCode::OK, "starting");
+    }
+    let mqtt_required = state.config.read().await.mqtt.required;
+    if mqtt_required && state.mqtt_connected() == Some(false) {
+        (StatusCode::SERVICE_UNAVAILABLE, "unhealthy")
+    } else {
+        (StatusCode::OK, "ok")
+    }
 }
 
 /// API info response
@@ -191,6 +712,7 @@ async fn health() -> Json<HealthResponse> {
 struct ApiInfoResponse {
     name: &'static str,
     version: &'static str,
+    gateway_id: String,
     description: &'static str,
     endpoints: Vec<EndpointInfo>,
 }
@@ -202,10 +724,11 @@ struct EndpointInfo {
     description: &'static str,
 }
 
-async fn api_info() -> Json<ApiInfoResponse> {
+async fn api_info(State(state): State<Arc<ApiState>>) -> Json<ApiInfoResponse> {
     Json(ApiInfoResponse {
         name: "RustBridge API",
         version: env!("CARGO_PKG_VERSION"),
+        gateway_id: state.config.read().await.gateway_id.clone(),
         description: "Industrial Protocol Bridge - Modbus TCP/RTU to JSON/MQTT Gateway",
         endpoints: vec![
             EndpointInfo {
@@ -213,11 +736,41 @@ async fn api_info() -> Json<ApiInfoResponse> {
                 path: "/health",
                 description: "Health check",
             },
+            EndpointInfo {
+                method: "GET",
+                path: "/ready",
+                description: "Readiness probe, ready once devices were first read",
+            },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/health/detailed",
+                description: "Health check including MQTT connectivity",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/api/info",
                 description: "API information",
             },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/stats",
+                description: "Gateway uptime, device and read counters",
+            },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/config.yaml",
+                description: "Download the running configuration as YAML",
+            },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/reload",
+                description: "Re-read the configuration file",
+            },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/reload/status",
+                description: "Configuration reload count and last result",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/api/devices",
@@ -228,26 +781,77 @@ async fn api_info() -> Json<ApiInfoResponse> {
                 path: "/api/devices/:device_id",
                 description: "Get device details",
             },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/devices/:device_id/pause",
+                description: "Stop polling a device for maintenance",
+            },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/devices/:device_id/resume",
+                description: "Poll a paused device again",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/api/devices/:device_id/registers",
                 description: "List device registers",
             },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/devices/:device_id/registers",
+                description: "Get the values of the registers named in the body",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/api/devices/:device_id/registers/:name",
                 description: "Get register value",
             },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/devices/:device_id/registers/:name/health",
+                description: "Read success ratio over recent reads",
+            },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/devices/:device_id/registers/:name/history",
+                description: "Recent values of a register",
+            },
             EndpointInfo {
                 method: "POST",
                 path: "/api/devices/:device_id/registers/:name",
                 description: "Write register value",
             },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/devices/:device_id/raw",
+                description: "Read arbitrary registers (optionally from another unit id)",
+            },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/devices/:device_id/scan",
+                description: "Probe a range of unit ids for responding devices",
+            },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/convert",
+                description:
+                    "Convert raw words with a register's decoding options, without a device",
+            },
+            EndpointInfo {
+                method: "POST",
+                path: "/api/snapshot",
+                description: "Write all current register values to the snapshot file",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/ws",
                 description: "WebSocket for real-time updates",
             },
+            EndpointInfo {
+                method: "GET",
+                path: "/api/stream",
+                description: "Register updates as NDJSON or Server-Sent Events",
+            },
             EndpointInfo {
                 method: "GET",
                 path: "/metrics",
@@ -276,6 +880,127 @@ async fn metrics_handler(State(state): State<Arc<ApiState>>) -> impl IntoRespons
     }
 }
 
+/// Gateway counters, also published as MQTT diagnostics
+async fn gateway_stats() -> Json<crate::metrics::GatewayStats> {
+    Json(crate::metrics::gateway_stats())
+}
+
+// ============================================================================
+// Configuration Endpoints
+// ============================================================================
+
+#[derive(Deserialize)]
+struct ConfigQuery {
+    /// Replace secrets (passwords, API keys) with a placeholder
+    #[serde(default)]
+    redact: bool,
+}
+
+/// Running configuration as YAML, in the same format as the config file
+async fn get_config_yaml(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<ConfigQuery>,
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
+    let config = state.config.read().await;
+    let config = if query.redact {
+        config.redacted()
+    } else {
+        config.clone()
+    };
+
+    let yaml = serde_yaml::to_string(&config).map_err(|e| {
+        ApiError::with_details(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to serialize config",
+            e.to_string(),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        [("content-type", "application/yaml; charset=utf-8")],
+        yaml,
+    )
+        .into_response())
+}
+
+/// Re-read the configuration file and make it the running configuration
+async fn reload_config(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<ReloadHistory>, (StatusCode, Json<ApiError>)> {
+    let path = state.config_path.clone().ok_or_else(|| {
+        ApiError::with_details(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Reload not available",
+            "The gateway was not started from a configuration file",
+        )
+    })?;
+
+    // File IO blocks
+    let result = tokio::task::spawn_blocking(move || crate::config::load_config_file(&path))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+    if let Some(error) = state.apply_reload(result).await {
+        return Err(ApiError::with_details(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Configuration reload failed",
+            error,
+        ));
+    }
+    Ok(Json(state.reload_history.read().await.clone()))
+}
+
+/// Configuration reloads since startup
+async fn reload_status(State(state): State<Arc<ApiState>>) -> Json<ReloadHistory> {
+    Json(state.reload_history.read().await.clone())
+}
+
+/// Write the register store to `server.snapshot_path`
+async fn write_snapshot(
+    State(state): State<Arc<ApiState>>,
+) -> Result<Json<snapshot::SnapshotSummary>, (StatusCode, Json<ApiError>)> {
+    let (path, gateway_id) = {
+        let config = state.config.read().await;
+        (
+            config.server.snapshot_path.clone(),
+            config.gateway_id.clone(),
+        )
+    };
+    let path = path.ok_or_else(|| {
+        ApiError::with_details(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Snapshots not enabled",
+            "Set server.snapshot_path to enable snapshots",
+        )
+    })?;
+
+    // File IO blocks, so serialize on a blocking thread straight from the
+    // store, which is locked while it is serialized
+    let store = state.register_store.clone();
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let summary = tokio::task::spawn_blocking(move || {
+        snapshot::write(std::path::Path::new(&path), &store, &gateway_id, &timestamp)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result.map_err(|e| e.to_string()))
+    .map_err(|e| {
+        error!("Failed to write snapshot: {}", e);
+        ApiError::with_details(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to write snapshot",
+            e,
+        )
+    })?;
+
+    info!(
+        "Snapshot of {} registers written to {} ({} bytes)",
+        summary.registers, summary.path, summary.bytes
+    );
+    Ok(Json(summary))
+}
+
 // ============================================================================
 // Device Endpoints
 // ============================================================================
@@ -322,6 +1047,9 @@ async fn list_devices(State(state): State<Arc<ApiState>>) -> Json<DeviceListResp
 #[derive(Serialize)]
 struct DeviceResponse {
     id: String,
+    /// Vendor, product code and revision, if the device reports them (FC43)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_info: Option<DeviceInfo>,
     registers: Vec<RegisterResponse>,
     register_count: usize,
 }
@@ -330,15 +1058,46 @@ struct DeviceResponse {
 struct RegisterResponse {
     name: String,
     value: f64,
-    raw: Vec<u16>,
+    decoded: f64,
+    raw: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
     unit: Option<String>,
+    quality: Quality,
     timestamp: String,
 }
 
+impl RegisterResponse {
+    /// Response for a register, under its output `name`
+    fn new(register: &RegisterValue, name: String, raw_format: RawFormat) -> Self {
+        Self {
+            name,
+            value: register.value,
+            decoded: register.decoded,
+            raw: raw_format.to_json(&register.raw),
+            text: register.text.clone(),
+            unit: register.unit.clone(),
+            quality: register.quality,
+            timestamp: register.timestamp.to_rfc3339(),
+        }
+    }
+}
+
+/// Output options for endpoints returning register values
+#[derive(Deserialize, Default)]
+struct OutputQuery {
+    /// Override the configured `raw_format`
+    #[serde(default)]
+    raw_format: Option<RawFormat>,
+}
+
 async fn get_device(
     State(state): State<Arc<ApiState>>,
     Path(device_id): Path<String>,
+    Query(query): Query<OutputQuery>,
 ) -> Result<Json<DeviceResponse>, (StatusCode, Json<ApiError>)> {
+    let raw_format = state.raw_format(query.raw_format).await;
+    let names = state.register_names().await;
     let store = state.register_store.read().await;
 
     let registers = store
@@ -347,23 +1106,75 @@ async fn get_device(
 
     let registers: Vec<RegisterResponse> = registers
         .values()
-        .map(|r| RegisterResponse {
-            name: r.name.clone(),
-            value: r.value,
-            raw: r.raw.clone(),
-            unit: r.unit.clone(),
-            timestamp: r.timestamp.to_rfc3339(),
-        })
+        .map(|r| RegisterResponse::new(r, names.qualify(&device_id, &r.name), raw_format))
         .collect();
 
     let register_count = registers.len();
+    let device_info = state.device_info.read().await.get(&device_id).cloned();
     Ok(Json(DeviceResponse {
         id: device_id,
+        device_info,
         registers,
         register_count,
     }))
 }
 
+/// Polling state of a device after a pause or resume request
+#[derive(Serialize)]
+struct PauseResponse {
+    device_id: String,
+    paused: bool,
+    /// Whether the request changed the state
+    changed: bool,
+}
+
+/// Stop polling a device until it is resumed, keeping its last values
+async fn pause_device(
+    State(state): State<Arc<ApiState>>,
+    Path(device_id): Path<String>,
+) -> Result<Json<PauseResponse>, (StatusCode, Json<ApiError>)> {
+    configured_device(&state, &device_id).await?;
+    let changed = state.paused.pause(&device_id);
+    if changed {
+        info!("Polling paused for device {}", device_id);
+    }
+    Ok(Json(PauseResponse {
+        device_id,
+        paused: true,
+        changed,
+    }))
+}
+
+/// Poll a paused device again
+async fn resume_device(
+    State(state): State<Arc<ApiState>>,
+    Path(device_id): Path<String>,
+) -> Result<Json<PauseResponse>, (StatusCode, Json<ApiError>)> {
+    configured_device(&state, &device_id).await?;
+    let changed = state.paused.resume(&device_id);
+    if changed {
+        info!("Polling resumed for device {}", device_id);
+    }
+    Ok(Json(PauseResponse {
+        device_id,
+        paused: false,
+        changed,
+    }))
+}
+
+/// Reject ids that are not a configured device
+async fn configured_device(
+    state: &ApiState,
+    device_id: &str,
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    let config = state.config.read().await;
+    if config.devices.iter().any(|d| d.id == device_id) {
+        Ok(())
+    } else {
+        Err(ApiError::new(StatusCode::NOT_FOUND, "Device not found"))
+    }
+}
+
 // ============================================================================
 // Register Endpoints
 // ============================================================================
@@ -371,7 +1182,10 @@ async fn get_device(
 async fn get_registers(
     State(state): State<Arc<ApiState>>,
     Path(device_id): Path<String>,
+    Query(query): Query<OutputQuery>,
 ) -> Result<Json<Vec<RegisterResponse>>, (StatusCode, Json<ApiError>)> {
+    let raw_format = state.raw_format(query.raw_format).await;
+    let names = state.register_names().await;
     let store = state.register_store.read().await;
 
     let registers = store
@@ -380,46 +1194,222 @@ async fn get_registers(
 
     let registers: Vec<RegisterResponse> = registers
         .values()
-        .map(|r| RegisterResponse {
-            name: r.name.clone(),
-            value: r.value,
-            raw: r.raw.clone(),
-            unit: r.unit.clone(),
-            timestamp: r.timestamp.to_rfc3339(),
-        })
+        .map(|r| RegisterResponse::new(r, names.qualify(&device_id, &r.name), raw_format))
         .collect();
 
     Ok(Json(registers))
 }
 
-async fn get_register(
+/// Registers to read in one request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchReadRequest {
+    names: Vec<String>,
+}
+
+/// A register of a batch read, in the order it was requested
+#[derive(Serialize)]
+struct BatchRegister {
+    /// Name as requested
+    name: String,
+    found: bool,
+    /// Current value, if the register has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    register: Option<RegisterResponse>,
+}
+
+/// Current values of several registers of a device at once
+///
+/// Names without a value are reported with `found: false` rather than
+/// failing the request.
+async fn get_registers_batch(
     State(state): State<Arc<ApiState>>,
-    Path((device_id, register_name)): Path<(String, String)>,
-) -> Result<Json<RegisterResponse>, (StatusCode, Json<ApiError>)> {
+    Path(device_id): Path<String>,
+    Query(query): Query<OutputQuery>,
+    JsonBody(body): JsonBody<BatchReadRequest>,
+) -> Result<Json<Vec<BatchRegister>>, (StatusCode, Json<ApiError>)> {
+    let raw_format = state.raw_format(query.raw_format).await;
+    let names = state.register_names().await;
     let store = state.register_store.read().await;
 
     let registers = store
         .get(&device_id)
         .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
 
-    let register = registers
-        .get(&register_name)
-        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Register not found"))?;
+    let registers = body
+        .names
+        .into_iter()
+        .map(|name| {
+            let register = registers
+                .get(&name)
+                .map(|r| RegisterResponse::new(r, names.qualify(&device_id, &r.name), raw_format));
+            BatchRegister {
+                name,
+                found: register.is_some(),
+                register,
+            }
+        })
+        .collect();
 
-    Ok(Json(RegisterResponse {
-        name: register.name.clone(),
-        value: register.value,
-        raw: register.raw.clone(),
-        unit: register.unit.clone(),
-        timestamp: register.timestamp.to_rfc3339(),
-    }))
+    Ok(Json(registers))
+}
+
+async fn get_register(
+    State(state): State<Arc<ApiState>>,
+    Path((device_id, register_name)): Path<(String, String)>,
+    Query(query): Query<OutputQuery>,
+) -> Result<Json<RegisterResponse>, (StatusCode, Json<ApiError>)> {
+    let raw_format = state.raw_format(query.raw_format).await;
+    let name = state
+        .register_names()
+        .await
+        .qualify(&device_id, &register_name);
+    let store = state.register_store.read().await;
+
+    let registers = store
+        .get(&device_id)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
+
+    let register = registers
+        .get(&register_name)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Register not found"))?;
+
+    Ok(Json(RegisterResponse::new(register, name, raw_format)))
+}
+
+/// Read success over the register's recent reads
+#[derive(Serialize)]
+struct RegisterHealthResponse {
+    device_id: String,
+    register: String,
+    window: usize,
+    reads: usize,
+    successes: usize,
+    failures: usize,
+    /// `null` until the register has been read
+    success_ratio: Option<f64>,
+}
+
+async fn get_register_health(
+    State(state): State<Arc<ApiState>>,
+    Path((device_id, register_name)): Path<(String, String)>,
+) -> Result<Json<RegisterHealthResponse>, (StatusCode, Json<ApiError>)> {
+    let health = match state.read_health.get(&device_id, &register_name) {
+        Some(health) => health,
+        None => {
+            let config = state.config.read().await;
+            let device = config
+                .devices
+                .iter()
+                .find(|d| d.id == device_id)
+                .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
+            if !device.registers.iter().any(|r| r.name == register_name) {
+                return Err(ApiError::new(StatusCode::NOT_FOUND, "Register not found"));
+            }
+            state.read_health.empty()
+        }
+    };
+
+    Ok(Json(RegisterHealthResponse {
+        device_id,
+        register: register_name,
+        window: health.window,
+        reads: health.reads,
+        successes: health.successes,
+        failures: health.reads - health.successes,
+        success_ratio: health.success_ratio,
+    }))
+}
+
+/// Recent values of a register, oldest first, optionally limited to the
+/// samples between `from` and `to` and to the `limit` most recent of them
+#[derive(Serialize)]
+struct RegisterHistoryResponse {
+    device_id: String,
+    register: String,
+    samples: Vec<Sample>,
+    /// Samples held across all registers
+    total_samples: usize,
+}
+
+async fn get_register_history(
+    State(state): State<Arc<ApiState>>,
+    Path((device_id, register_name)): Path<(String, String)>,
+    Query(range): Query<SampleRange>,
+) -> Result<Json<RegisterHistoryResponse>, (StatusCode, Json<ApiError>)> {
+    if state.storage.is_none() && !state.history.is_enabled() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "History not enabled",
+        ));
+    }
+    if let (Some(from), Some(to)) = (range.from, range.to) {
+        if from > to {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "from must not be later than to",
+            ));
+        }
+    }
+
+    let (samples, total_samples) = match &state.storage {
+        Some(storage) => {
+            let (device, register) = (device_id.clone(), register_name.clone());
+            let samples = storage
+                .blocking(move |storage| storage.query(&device, &register, &range))
+                .await
+                .map_err(|e| {
+                    ApiError::with_details(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to read history",
+                        format!("{:#}", e),
+                    )
+                })?;
+            (samples, storage.sample_count())
+        }
+        None => (
+            state.history.query(&device_id, &register_name, &range),
+            state.history.sample_count(),
+        ),
+    };
+    if samples.is_empty() {
+        let config = state.config.read().await;
+        let device = config
+            .devices
+            .iter()
+            .find(|d| d.id == device_id)
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
+        if !device.registers.iter().any(|r| r.name == register_name) {
+            return Err(ApiError::new(StatusCode::NOT_FOUND, "Register not found"));
+        }
+    }
+
+    Ok(Json(RegisterHistoryResponse {
+        device_id,
+        register: register_name,
+        samples,
+        total_samples,
+    }))
 }
 
 /// Write register request body
 #[derive(Deserialize)]
 struct WriteRegisterRequest {
-    /// Raw u16 value to write
-    value: u16,
+    /// Number to write, in the register's data type before scaling
+    value: serde_json::Number,
+    /// Optional bit mask; only the masked bits of `value` are written
+    #[serde(default)]
+    mask: Option<u16>,
+    /// Optional momentary coil pulse duration in milliseconds
+    #[serde(default)]
+    pulse_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct WriteQuery {
+    /// Override `server.write_timeout_ms` for this request
+    #[serde(default)]
+    timeout_ms: Option<u64>,
 }
 
 /// Write register response
@@ -428,29 +1418,184 @@ struct WriteRegisterResponse {
     success: bool,
     device_id: String,
     register_name: String,
-    value_written: u16,
+    value_written: serde_json::Number,
+    /// The words the value was encoded into
+    raw_written: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pulse_ms: Option<u64>,
     message: String,
 }
 
 async fn write_register(
     State(state): State<Arc<ApiState>>,
     Path((device_id, register_name)): Path<(String, String)>,
-    Json(payload): Json<WriteRegisterRequest>,
-) -> Result<Json<WriteRegisterResponse>, (StatusCode, Json<ApiError>)> {
-    // Validate device and register exist
-    let address = {
+    Query(query): Query<WriteQuery>,
+    JsonBody(payload): JsonBody<WriteRegisterRequest>,
+) -> Result<Json<WriteRegisterResponse>, WriteError> {
+    if let Some(timeout_ms) = query.timeout_ms {
+        if timeout_ms == 0 || timeout_ms > MAX_WRITE_TIMEOUT_MS {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!(
+                    "`timeout_ms` must be between 1 and {}",
+                    MAX_WRITE_TIMEOUT_MS
+                ),
+            )
+            .into());
+        }
+    }
+
+    if let Some(pulse_ms) = payload.pulse_ms {
+        if payload.mask.is_some() {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                "`mask` and `pulse_ms` cannot be combined",
+            )
+            .into());
+        }
+        if pulse_ms == 0 || pulse_ms > MAX_PULSE_MS {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!("`pulse_ms` must be between 1 and {}", MAX_PULSE_MS),
+            )
+            .into());
+        }
+    }
+
+    // Validate device and register exist; the address to write and how the
+    // value is encoded come from the register's configuration
+    let (register_type, address, values, mask) = {
         let store = state.register_store.read().await;
         let registers = store
             .get(&device_id)
             .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
 
-        let _register = registers
+        registers
             .get(&register_name)
             .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Register not found"))?;
 
-        // For now, we'll use a placeholder address
-        // In production, this would come from the config
-        0u16
+        let config = state.config.read().await;
+        let device = config
+            .devices
+            .iter()
+            .find(|d| d.id == device_id)
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Device not found"))?;
+
+        // Derived values (e.g. exposed words) exist only in the store and
+        // have no register of their own to write to
+        let register = device
+            .registers
+            .iter()
+            .find(|r| r.name == register_name)
+            .ok_or_else(|| {
+                ApiError::with_details(
+                    StatusCode::BAD_REQUEST,
+                    "Register is read-only",
+                    format!("{} is derived from another register", register_name),
+                )
+            })?;
+
+        // Modbus has no function to write these
+        let read_only = match register.register_type {
+            RegisterType::Input => Some("an input register"),
+            RegisterType::Discrete => Some("a discrete input"),
+            RegisterType::Virtual => Some("a virtual register"),
+            RegisterType::Holding | RegisterType::Coil => None,
+        };
+        if let Some(kind) = read_only {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Register is read-only",
+                format!("{} is {}", register_name, kind),
+            )
+            .into());
+        }
+        if register.register_type != RegisterType::Coil && payload.pulse_ms.is_some() {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!(
+                    "`pulse_ms` only applies to coils, {} is not one",
+                    register_name
+                ),
+            )
+            .into());
+        }
+        if register.register_type == RegisterType::Coil && payload.mask.is_some() {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!(
+                    "`mask` does not apply to {}, which is a coil",
+                    register_name
+                ),
+            )
+            .into());
+        }
+
+        // Writes are an explicit opt-in per register
+        if !register.writable {
+            return Err(ApiError::with_details(
+                StatusCode::FORBIDDEN,
+                "Register is not writable",
+                format!(
+                    "{} is not marked `writable: true` in the configuration",
+                    register_name
+                ),
+            )
+            .into());
+        }
+
+        let value = payload.value.as_f64().unwrap_or(f64::NAN);
+        let values = reader::encode_value(value, register).map_err(|reason| {
+            ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!("{}: {}", register_name, reason),
+            )
+        })?;
+        // Masks and pulses change a single word or coil
+        if values.len() > 1 && (payload.mask.is_some() || payload.pulse_ms.is_some()) {
+            return Err(ApiError::with_details(
+                StatusCode::BAD_REQUEST,
+                "Invalid write request",
+                format!(
+                    "`mask` and `pulse_ms` need a single-register value, {} takes {} registers",
+                    register_name,
+                    values.len()
+                ),
+            )
+            .into());
+        }
+
+        // A bit register writes its one bit and leaves the rest of the word
+        let mask = match register.bit {
+            Some(_) if payload.mask.is_some() || payload.pulse_ms.is_some() => {
+                return Err(ApiError::with_details(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid write request",
+                    format!(
+                        "`mask` and `pulse_ms` do not apply to {}, which reads a single bit",
+                        register_name
+                    ),
+                )
+                .into());
+            }
+            Some(bit) => Some(1 << bit),
+            None => payload.mask,
+        };
+
+        (
+            register.register_type.clone(),
+            register.address,
+            values,
+            mask,
+        )
     };
 
     // Create response channel
@@ -459,34 +1604,79 @@ async fn write_register(
     // Send write request
     let write_request = WriteRequest {
         device_id: device_id.clone(),
+        register_type,
         address,
-        value: payload.value,
+        values: values.clone(),
+        mask,
+        pulse_ms: payload.pulse_ms,
+        queued_at: std::time::Instant::now(),
         response_tx,
     };
 
-    state.write_tx.send(write_request).await.map_err(|_| {
-        ApiError::with_details(
-            StatusCode::SERVICE_UNAVAILABLE,
-            "Write service unavailable",
-            "The Modbus write handler is not running",
-        )
-    })?;
+    // Never block the handler on a full queue indefinitely: reject right
+    // away or wait a bounded time, depending on the configured policy
+    let server = state.config.read().await.server.clone();
+    let enqueued = match server.write_queue_policy {
+        WriteQueuePolicy::Reject => state
+            .write_tx
+            .try_send(write_request)
+            .map_err(|e| matches!(e, TrySendError::Full(_))),
+        WriteQueuePolicy::Wait => {
+            let wait = std::time::Duration::from_millis(server.write_queue_wait_ms);
+            state
+                .write_tx
+                .send_timeout(write_request, wait)
+                .await
+                .map_err(|e| matches!(e, SendTimeoutError::Timeout(_)))
+        }
+    };
+    match enqueued {
+        Ok(()) => {}
+        Err(true) => {
+            warn!(
+                "Write queue full, rejecting write to {}:{}",
+                device_id, register_name
+            );
+            return Err(WriteError {
+                error: ApiError::with_details(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Write queue full",
+                    format!(
+                        "{} writes are already waiting for the devices, try again later",
+                        server.write_queue_capacity
+                    ),
+                ),
+                retry_after_secs: Some(WRITE_RETRY_AFTER_SECS),
+            });
+        }
+        Err(false) => {
+            return Err(ApiError::with_details(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Write service unavailable",
+                "The Modbus write handler is not running",
+            )
+            .into())
+        }
+    }
 
-    // Wait for response with timeout
-    let result = tokio::time::timeout(std::time::Duration::from_secs(5), response_rx)
+    // Wait for response with timeout (a pulse only completes after it has
+    // been released again)
+    let timeout_ms = query.timeout_ms.unwrap_or(server.write_timeout_ms);
+    let timeout = std::time::Duration::from_millis(timeout_ms + payload.pulse_ms.unwrap_or(0));
+    let result = tokio::time::timeout(timeout, response_rx)
         .await
         .map_err(|_| {
             ApiError::with_details(
                 StatusCode::GATEWAY_TIMEOUT,
                 "Write timeout",
-                "The Modbus device did not respond in time",
+                format!("The Modbus device did not respond within {}ms", timeout_ms),
             )
         })?
         .map_err(|_| {
             ApiError::with_details(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Write failed",
-                "Response channel closed unexpectedly",
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Write service unavailable",
+                "The Modbus write handler stopped before responding",
             )
         })?;
 
@@ -501,17 +1691,445 @@ async fn write_register(
                 device_id,
                 register_name,
                 value_written: payload.value,
-                message: "Register written successfully".to_string(),
+                raw_written: values,
+                mask,
+                pulse_ms: payload.pulse_ms,
+                message: if payload.pulse_ms.is_some() {
+                    "Coil pulsed successfully".to_string()
+                } else {
+                    "Register written successfully".to_string()
+                },
             }))
         }
+        Err(e) => {
+            Err(ApiError::with_details(StatusCode::BAD_GATEWAY, "Modbus write failed", e).into())
+        }
+    }
+}
+
+// ============================================================================
+// Commissioning Endpoints
+// ============================================================================
+
+/// Largest number of registers a raw read may request (FC3/FC4 limit)
+const MAX_RAW_READ_COUNT: u16 = 125;
+
+/// Raw read request body
+#[derive(Deserialize)]
+struct RawReadBody {
+    register_type: RegisterType,
+    address: u16,
+    #[serde(default = "RawReadBody::default_count")]
+    count: u16,
+    /// Override the device's unit id for this request only
+    #[serde(default)]
+    unit_id: Option<u8>,
+}
+
+impl RawReadBody {
+    fn default_count() -> u16 {
+        1
+    }
+}
+
+/// Raw read response
+#[derive(Serialize)]
+struct RawReadResponse {
+    device_id: String,
+    register_type: RegisterType,
+    address: u16,
+    count: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_id: Option<u8>,
+    values: Vec<u16>,
+}
+
+/// Read registers that are not configured, through the device's connection
+async fn raw_read(
+    State(state): State<Arc<ApiState>>,
+    Path(device_id): Path<String>,
+    JsonBody(body): JsonBody<RawReadBody>,
+) -> Result<Json<RawReadResponse>, (StatusCode, Json<ApiError>)> {
+    if body.count == 0 || body.count > MAX_RAW_READ_COUNT {
+        return Err(ApiError::with_details(
+            StatusCode::BAD_REQUEST,
+            "Invalid read request",
+            format!("`count` must be between 1 and {}", MAX_RAW_READ_COUNT),
+        ));
+    }
+    if body.unit_id == Some(0) {
+        return Err(ApiError::with_details(
+            StatusCode::BAD_REQUEST,
+            "Invalid read request",
+            "`unit_id` 0 is the broadcast address and cannot be read from",
+        ));
+    }
+
+    if body.unit_id.is_some() {
+        require_other_units(&state).await?;
+    }
+
+    let timeout_ms = device_timeout_ms(&state, &device_id).await?;
+    let result = send_raw_read(
+        &state,
+        RawRead {
+            device_id: &device_id,
+            register_type: body.register_type.clone(),
+            address: body.address,
+            count: body.count,
+            unit_id: body.unit_id,
+            device_timeout: None,
+        },
+        timeout_ms,
+    )
+    .await?;
+
+    match result {
+        Ok(values) => Ok(Json(RawReadResponse {
+            device_id,
+            register_type: body.register_type,
+            address: body.address,
+            count: body.count,
+            unit_id: body.unit_id,
+            values,
+        })),
         Err(e) => Err(ApiError::with_details(
             StatusCode::BAD_GATEWAY,
-            "Modbus write failed",
-            e,
+            "Modbus read failed",
+            e.to_string(),
         )),
     }
 }
 
+/// Reads from other unit ids reach devices that are not configured, so they
+/// are only served with `server.allow_other_units`
+async fn require_other_units(state: &ApiState) -> Result<(), (StatusCode, Json<ApiError>)> {
+    if state.config.read().await.server.allow_other_units {
+        return Ok(());
+    }
+    Err(ApiError::with_details(
+        StatusCode::FORBIDDEN,
+        "Reads from other unit ids not enabled",
+        "Set server.allow_other_units to read from other unit ids and scan buses",
+    ))
+}
+
+/// API round-trip timeout for a known device; 404 for unknown devices
+async fn device_timeout_ms(
+    state: &ApiState,
+    device_id: &str,
+) -> Result<u64, (StatusCode, Json<ApiError>)> {
+    let config = state.config.read().await;
+    let known = config.devices.iter().any(|d| d.id == device_id)
+        || state.register_store.read().await.contains_key(device_id);
+    if !known {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Device not found"));
+    }
+    Ok(config.server.write_timeout_ms)
+}
+
+/// Parameters of one on-demand read
+struct RawRead<'a> {
+    device_id: &'a str,
+    register_type: RegisterType,
+    address: u16,
+    count: u16,
+    unit_id: Option<u8>,
+    device_timeout: Option<std::time::Duration>,
+}
+
+/// Hand a read to the device worker and wait up to `timeout_ms` for it
+async fn send_raw_read(
+    state: &ApiState,
+    read: RawRead<'_>,
+    timeout_ms: u64,
+) -> Result<Result<Vec<u16>, RawReadError>, (StatusCode, Json<ApiError>)> {
+    let unavailable = || {
+        ApiError::with_details(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Read service unavailable",
+            "The device worker is not running",
+        )
+    };
+    let raw_tx = state.raw_tx.as_ref().ok_or_else(unavailable)?;
+
+    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+    raw_tx
+        .send(RawReadRequest {
+            device_id: read.device_id.to_string(),
+            register_type: read.register_type,
+            address: read.address,
+            count: read.count,
+            unit_id: read.unit_id,
+            timeout: read.device_timeout,
+            response_tx,
+        })
+        .await
+        .map_err(|_| unavailable())?;
+
+    tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), response_rx)
+        .await
+        .map_err(|_| {
+            ApiError::with_details(
+                StatusCode::GATEWAY_TIMEOUT,
+                "Read timeout",
+                format!("The Modbus device did not respond within {}ms", timeout_ms),
+            )
+        })?
+        .map_err(|_| unavailable())
+}
+
+/// Longest per-probe timeout a bus scan accepts
+const MAX_PROBE_TIMEOUT_MS: u64 = 5_000;
+
+/// Bus scan request body
+#[derive(Deserialize)]
+struct ScanBody {
+    #[serde(default = "ScanBody::default_first_unit_id")]
+    first_unit_id: u8,
+    #[serde(default = "ScanBody::default_last_unit_id")]
+    last_unit_id: u8,
+    /// Register probed on every unit id
+    #[serde(default = "ScanBody::default_register_type")]
+    register_type: RegisterType,
+    #[serde(default)]
+    address: u16,
+    /// How long to wait for each unit id before moving on
+    #[serde(default = "ScanBody::default_probe_timeout_ms")]
+    probe_timeout_ms: u64,
+}
+
+impl ScanBody {
+    fn default_first_unit_id() -> u8 {
+        1
+    }
+
+    fn default_last_unit_id() -> u8 {
+        247
+    }
+
+    fn default_register_type() -> RegisterType {
+        RegisterType::Holding
+    }
+
+    fn default_probe_timeout_ms() -> u64 {
+        200
+    }
+}
+
+/// A unit id that answered a scan probe
+#[derive(Serialize)]
+struct ScanHit {
+    unit_id: u8,
+    /// `ok` with the register values, or `exception` if the unit answered
+    /// but rejected the probed register
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Bus scan response
+#[derive(Serialize)]
+struct ScanResponse {
+    device_id: String,
+    scanned: usize,
+    responding: Vec<ScanHit>,
+}
+
+/// Probe a range of unit ids on a device's connection
+async fn scan_bus(
+    State(state): State<Arc<ApiState>>,
+    Path(device_id): Path<String>,
+    JsonBody(body): JsonBody<ScanBody>,
+) -> Result<Json<ScanResponse>, (StatusCode, Json<ApiError>)> {
+    if body.first_unit_id == 0 || body.first_unit_id > body.last_unit_id {
+        return Err(ApiError::with_details(
+            StatusCode::BAD_REQUEST,
+            "Invalid scan request",
+            "Unit id range must start at 1 or higher and not be reversed",
+        ));
+    }
+    if body.probe_timeout_ms == 0 || body.probe_timeout_ms > MAX_PROBE_TIMEOUT_MS {
+        return Err(ApiError::with_details(
+            StatusCode::BAD_REQUEST,
+            "Invalid scan request",
+            format!(
+                "`probe_timeout_ms` must be between 1 and {}",
+                MAX_PROBE_TIMEOUT_MS
+            ),
+        ));
+    }
+
+    require_other_units(&state).await?;
+
+    let scanned = (body.last_unit_id - body.first_unit_id) as usize + 1;
+
+    // Each probe may wait behind a poll before the device timeout starts
+    let timeout_ms = device_timeout_ms(&state, &device_id).await? + body.probe_timeout_ms;
+    let mut responding = Vec::new();
+
+    for unit_id in body.first_unit_id..=body.last_unit_id {
+        let read = RawRead {
+            device_id: &device_id,
+            register_type: body.register_type.clone(),
+            address: body.address,
+            count: 1,
+            unit_id: Some(unit_id),
+            device_timeout: Some(std::time::Duration::from_millis(body.probe_timeout_ms)),
+        };
+
+        match send_raw_read(&state, read, timeout_ms).await? {
+            Ok(values) => responding.push(ScanHit {
+                unit_id,
+                status: "ok",
+                values: Some(values),
+                error: None,
+            }),
+            Err(RawReadError::Exception(e)) => responding.push(ScanHit {
+                unit_id,
+                status: "exception",
+                values: None,
+                error: Some(e),
+            }),
+            Err(RawReadError::Failed(e)) => debug!("Scan of unit {}: {}", unit_id, e),
+        }
+    }
+
+    info!(
+        "Bus scan on {}: {} of {} unit ids responded",
+        device_id,
+        responding.len(),
+        scanned
+    );
+
+    Ok(Json(ScanResponse {
+        device_id,
+        scanned,
+        responding,
+    }))
+}
+
+/// Conversion test request body: raw words and the decoding options of a
+/// register, named as in the configuration
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConvertBody {
+    raw: Vec<u16>,
+    data_type: DataType,
+    #[serde(default)]
+    signed_bits: Option<u8>,
+    #[serde(default)]
+    word_order: WordOrder,
+    /// Exponent as read from a `scale_from_register`
+    #[serde(default)]
+    scale_factor: Option<f64>,
+    #[serde(default)]
+    scale: Option<f64>,
+    #[serde(default)]
+    offset: Option<f64>,
+    #[serde(default)]
+    decimals: Option<u8>,
+    #[serde(default)]
+    clamp_min: Option<f64>,
+    #[serde(default)]
+    clamp_max: Option<f64>,
+    #[serde(default)]
+    pipeline: Option<Vec<ConversionStep>>,
+    #[serde(default, alias = "expr")]
+    formula: Option<Formula>,
+    #[serde(default)]
+    encoding: TextEncoding,
+    #[serde(default)]
+    swap_bytes: bool,
+}
+
+impl ConvertBody {
+    /// Register configured like the body, spanning its raw words
+    fn register(&self) -> RegisterConfig {
+        let mut register = RegisterBuilder::holding("convert", 0)
+            .data_type(self.data_type.clone())
+            .count(self.raw.len() as u16)
+            .build();
+        register.signed_bits = self.signed_bits;
+        register.word_order = self.word_order;
+        register.scale = self.scale;
+        register.offset = self.offset;
+        register.decimals = self.decimals;
+        register.clamp_min = self.clamp_min;
+        register.clamp_max = self.clamp_max;
+        register.pipeline = self.pipeline.clone();
+        register.formula = self.formula.clone();
+        register.encoding = self.encoding;
+        register.swap_bytes = self.swap_bytes;
+        register
+    }
+}
+
+/// Conversion test response
+#[derive(Serialize)]
+struct ConvertResponse {
+    data_type: DataType,
+    raw: Vec<u16>,
+    decoded: f64,
+    value: serde_json::Value,
+    quality: Quality,
+}
+
+/// Convert raw words the way a register configured like the body would be,
+/// without a device
+async fn convert(
+    JsonBody(body): JsonBody<ConvertBody>,
+) -> Result<Json<ConvertResponse>, (StatusCode, Json<ApiError>)> {
+    let invalid = |details: String| {
+        ApiError::with_details(
+            StatusCode::BAD_REQUEST,
+            "Invalid conversion request",
+            details,
+        )
+    };
+
+    let words = body.data_type.word_count();
+    if body.raw.len() < usize::from(words) || body.raw.len() > usize::from(MAX_RAW_READ_COUNT) {
+        return Err(invalid(format!(
+            "`raw` must have between {} and {} words for this data type",
+            words, MAX_RAW_READ_COUNT
+        )));
+    }
+    let register = body.register();
+    let errors = register.conversion_errors();
+    if !errors.is_empty() {
+        return Err(invalid(errors.join("; ")));
+    }
+    reader::validate_raw(&body.raw, &register).map_err(invalid)?;
+
+    let decoded = reader::decode_value(&body.raw, &register);
+    let scaled = match body.scale_factor {
+        Some(exponent) => reader::apply_scale_factor(decoded, exponent).ok_or_else(|| {
+            invalid(format!(
+                "`scale_factor` {} is not a whole number between -{max} and {max}",
+                exponent,
+                max = reader::MAX_SCALE_EXPONENT
+            ))
+        })?,
+        None => decoded,
+    };
+    let value = reader::scale_value(scaled, &register);
+    let (value, quality) = match reader::decode_text(&body.raw, &register) {
+        Some((text, quality)) => (text.into(), quality),
+        None => (value.into(), Quality::Good),
+    };
+
+    Ok(Json(ConvertResponse {
+        data_type: body.data_type,
+        raw: body.raw,
+        decoded,
+        value,
+        quality,
+    }))
+}
+
 // ============================================================================
 // WebSocket Endpoint
 // ============================================================================
@@ -520,15 +2138,23 @@ async fn write_register(
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type")]
 enum WsMessage {
-    /// Subscribe to specific devices/registers
+    /// Subscribe to specific devices/registers, optionally also receiving
+    /// change events
     #[serde(rename = "subscribe")]
-    Subscribe { devices: Option<Vec<String>> },
+    Subscribe {
+        devices: Option<Vec<String>>,
+        #[serde(default)]
+        changes: bool,
+    },
     /// Unsubscribe from updates
     #[serde(rename = "unsubscribe")]
     Unsubscribe,
     /// Register update (server -> client)
     #[serde(rename = "update")]
     Update(RegisterUpdate),
+    /// Value transition with the previous value (server -> client)
+    #[serde(rename = "change")]
+    Change(ValueChange),
     /// Error message
     #[serde(rename = "error")]
     Error { message: String },
@@ -542,12 +2168,233 @@ enum WsMessage {
     Pong,
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<ApiState>>) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<OutputQuery>,
+) -> Response {
+    let raw_format = state.raw_format(query.raw_format).await;
+    ws.on_upgrade(move |socket| handle_socket(socket, state, raw_format))
+}
+
+/// Serialize a register update for a WebSocket client
+fn ws_update_json(update: RegisterUpdate, raw_format: RawFormat) -> serde_json::Result<String> {
+    let mut msg = serde_json::to_value(WsMessage::Update(update))?;
+    format_raw(&mut msg, raw_format)?;
+    serde_json::to_string(&msg)
+}
+
+/// Render the `raw` words of a serialized update in the requested format
+fn format_raw(msg: &mut serde_json::Value, raw_format: RawFormat) -> serde_json::Result<()> {
+    if let Some(raw) = msg.get_mut("raw") {
+        let words: Vec<u16> = serde_json::from_value(raw.take())?;
+        *raw = raw_format.to_json(&words);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// HTTP Streaming
+// ============================================================================
+
+/// Wire format of `GET /api/stream`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StreamFormat {
+    /// One JSON object per line (`application/x-ndjson`)
+    Ndjson,
+    /// Server-sent events (`text/event-stream`)
+    Sse,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamQuery {
+    /// Defaults to SSE when the client accepts `text/event-stream`, else NDJSON
+    #[serde(default)]
+    format: Option<StreamFormat>,
+    /// Comma-separated device ids (default: all)
+    #[serde(default)]
+    devices: Option<String>,
+    /// Comma-separated register names (default: all)
+    #[serde(default)]
+    registers: Option<String>,
+    #[serde(default)]
+    raw_format: Option<RawFormat>,
+}
+
+/// Which updates a stream client asked for
+struct StreamFilter {
+    devices: Option<Vec<String>>,
+    registers: Option<Vec<String>>,
+}
+
+impl StreamFilter {
+    fn new(query: &StreamQuery) -> Self {
+        let list = |names: &Option<String>| {
+            names.as_ref().map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+        };
+        Self {
+            devices: list(&query.devices),
+            registers: list(&query.registers),
+        }
+    }
+
+    fn matches(&self, update: &RegisterUpdate) -> bool {
+        is_subscribed(&self.devices, &update.device_id)
+            && is_subscribed(&self.registers, &update.register_name)
+    }
+}
+
+/// Stream live register updates over plain HTTP
+///
+/// A lighter alternative to `/ws` for clients behind proxies that don't
+/// pass WebSocket upgrades. The subscription is dropped with the response
+/// body when the client disconnects.
+async fn stream_updates(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<StreamQuery>,
+    headers: axum::http::HeaderMap,
+) -> Response {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+    use std::convert::Infallible;
+
+    let raw_format = state.raw_format(query.raw_format).await;
+    let format = query.format.unwrap_or_else(|| {
+        let accepts_sse = headers
+            .get(axum::http::header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/event-stream"));
+        if accepts_sse {
+            StreamFormat::Sse
+        } else {
+            StreamFormat::Ndjson
+        }
+    });
+    let filter = StreamFilter::new(&query);
+    let names = state.register_names().await;
+    // Ends the response; EventSource clients reconnect on their own
+    let max_lifetime = state.config.read().await.server.stream_max_lifetime_secs;
+    let close_at =
+        max_lifetime.map(|secs| tokio::time::Instant::now() + std::time::Duration::from_secs(secs));
+
+    let updates = futures_util::stream::unfold(state.subscribe(), |mut update_rx| async move {
+        loop {
+            match update_rx.recv().await {
+                Ok(update) => return Some((update, update_rx)),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Stream client lagged, missed {} updates", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter_map(move |mut update| {
+        let json = filter.matches(&update).then(|| {
+            update.register_name = names.qualify(&update.device_id, &update.register_name);
+            let mut msg = serde_json::to_value(update)?;
+            format_raw(&mut msg, raw_format)?;
+            serde_json::to_string(&msg)
+        });
+        futures_util::future::ready(json.and_then(Result::ok))
+    })
+    .take_until(expired(close_at));
+
+    debug!("Stream client connected ({:?})", format);
+    match format {
+        StreamFormat::Sse => Sse::new(
+            updates.map(|json| Ok::<_, Infallible>(Event::default().event("update").data(json))),
+        )
+        .keep_alive(KeepAlive::default())
+        .into_response(),
+        StreamFormat::Ndjson => (
+            [(axum::http::header::CONTENT_TYPE, "application/x-ndjson")],
+            axum::body::Body::from_stream(
+                updates.map(|json| Ok::<_, Infallible>(format!("{}\n", json))),
+            ),
+        )
+            .into_response(),
+    }
+}
+
+/// An open WebSocket connection, counted in the connections gauge while
+/// it lives
+struct WsConnection(Arc<AtomicUsize>);
+
+impl WsConnection {
+    fn open(connections: &Arc<AtomicUsize>) -> Self {
+        let count = connections.fetch_add(1, Ordering::SeqCst) + 1;
+        crate::metrics::record_websocket_connections(count);
+        Self(connections.clone())
+    }
+}
+
+impl Drop for WsConnection {
+    fn drop(&mut self) {
+        let count = self.0.fetch_sub(1, Ordering::SeqCst) - 1;
+        crate::metrics::record_websocket_connections(count);
+    }
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<ApiState>) {
+/// Resolve at `deadline`, never without one
+async fn expired(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next ping, forever if pings are disabled
+async fn ping_due(pings: Option<&mut tokio::time::Interval>) {
+    match pings {
+        Some(pings) => {
+            pings.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Tell the client why the server closes the connection
+async fn send_close<S>(sender: &mut S, reason: &'static str)
+where
+    S: futures_util::Sink<Message> + Unpin,
+{
+    let frame = axum::extract::ws::CloseFrame {
+        code: axum::extract::ws::close_code::AWAY,
+        reason: reason.into(),
+    };
+    let _ = sender.send(Message::Close(Some(frame))).await;
+}
+
+async fn handle_socket(socket: WebSocket, state: Arc<ApiState>, raw_format: RawFormat) {
     let (mut sender, mut receiver) = socket.split();
+    let _connection = WsConnection::open(&state.ws_connections);
+
+    // Clients that died without closing are detected by their silence:
+    // pings keep live but quiet clients answering
+    let (ping_interval, idle_timeout, max_lifetime) = {
+        let server = &state.config.read().await.server;
+        let secs = |secs: u64| (secs > 0).then(|| std::time::Duration::from_secs(secs));
+        (
+            secs(server.ws_ping_interval_secs),
+            secs(server.ws_idle_timeout_secs),
+            server.stream_max_lifetime_secs.and_then(secs),
+        )
+    };
+    let mut pings = ping_interval.map(|period| {
+        let mut pings = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        pings.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        pings
+    });
+    let idle_deadline = || idle_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let mut idle_until = idle_deadline();
+    let close_at = max_lifetime.map(|lifetime| tokio::time::Instant::now() + lifetime);
 
     // Send connection confirmation
     let connected_msg = WsMessage::Connected {
@@ -561,22 +2408,50 @@ async fn handle_socket(socket: WebSocket, state: Arc<ApiState>) {
 
     info!("WebSocket client connected");
 
+    let names = state.register_names().await;
+
     // Subscribe to register updates
     let mut update_rx = state.subscribe();
+    let mut change_rx = state.subscribe_changes();
 
     // Track subscribed devices (None = all devices)
     let mut subscribed_devices: Option<Vec<String>> = None;
+    // Change events are opt-in
+    let mut send_changes = false;
 
     loop {
         tokio::select! {
+            _ = ping_due(pings.as_mut()) => {
+                if sender.send(Message::Ping(vec![])).await.is_err() {
+                    break;
+                }
+            }
+            _ = expired(idle_until) => {
+                info!("Closing idle WebSocket connection");
+                send_close(&mut sender, "idle timeout").await;
+                break;
+            }
+            _ = expired(close_at) => {
+                info!("Closing WebSocket connection at its maximum lifetime");
+                send_close(&mut sender, "maximum lifetime reached").await;
+                break;
+            }
             // Handle incoming messages from client
             msg = receiver.next() => {
+                // Any message, pongs included, shows the client is alive
+                if let Some(Ok(_)) = &msg {
+                    idle_until = idle_deadline();
+                }
                 match msg {
                     Some(Ok(Message::Text(text))) => {
                         match serde_json::from_str::<WsMessage>(&text) {
-                            Ok(WsMessage::Subscribe { devices }) => {
+                            Ok(WsMessage::Subscribe { devices, changes }) => {
                                 subscribed_devices = devices.clone();
-                                debug!("Client subscribed to: {:?}", subscribed_devices);
+                                send_changes = changes;
+                                debug!(
+                                    "Client subscribed to: {:?} (changes: {})",
+                                    subscribed_devices, send_changes
+                                );
                             }
                             Ok(WsMessage::Unsubscribe) => {
                                 subscribed_devices = Some(vec![]);
@@ -603,7 +2478,8 @@ async fn handle_socket(socket: WebSocket, state: Arc<ApiState>) {
                         }
                     }
                     Some(Ok(Message::Ping(data))) => {
-                        if sender.send(Message::Pong(data)).await.is_err() {
+                        let sent = sender.send(Message::Pong(data)).await;
+                        if sent.is_err() {
                             break;
                         }
                     }
@@ -622,17 +2498,11 @@ async fn handle_socket(socket: WebSocket, state: Arc<ApiState>) {
             // Handle register updates from broadcast channel
             update = update_rx.recv() => {
                 match update {
-                    Ok(register_update) => {
-                        // Check if client is subscribed to this device
-                        let should_send = match &subscribed_devices {
-                            None => true, // Subscribed to all
-                            Some(devices) if devices.is_empty() => false, // Unsubscribed
-                            Some(devices) => devices.contains(&register_update.device_id),
-                        };
-
-                        if should_send {
-                            let msg = WsMessage::Update(register_update);
-                            if let Ok(json) = serde_json::to_string(&msg) {
+                    Ok(mut register_update) => {
+                        if is_subscribed(&subscribed_devices, &register_update.device_id) {
+                            register_update.register_name = names
+                                .qualify(&register_update.device_id, &register_update.register_name);
+                            if let Ok(json) = ws_update_json(register_update, raw_format) {
                                 if sender.send(Message::Text(json)).await.is_err() {
                                     break;
                                 }
@@ -647,8 +2517,38 @@ async fn handle_socket(socket: WebSocket, state: Arc<ApiState>) {
                     }
                 }
             }
+            // Handle value changes for clients that asked for them
+            change = change_rx.recv() => {
+                match change {
+                    Ok(mut change) => {
+                        if send_changes && is_subscribed(&subscribed_devices, &change.device_id) {
+                            change.register = names.qualify(&change.device_id, &change.register);
+                            if let Ok(json) = serde_json::to_string(&WsMessage::Change(change)) {
+                                if sender.send(Message::Text(json)).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("WebSocket client lagged, missed {} changes", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break;
+                    }
+                }
+            }
         }
     }
 
     info!("WebSocket connection closed");
 }
+
+/// Whether a WebSocket client wants messages for a device (None = all
+/// devices, empty = unsubscribed)
+fn is_subscribed(subscribed_devices: &Option<Vec<String>>, device_id: &str) -> bool {
+    match subscribed_devices {
+        None => true,
+        Some(devices) => devices.iter().any(|d| d == device_id),
+    }
+}
@@ -0,0 +1,90 @@
+//! Qualified register names in outputs
+//!
+//! Register names only have to be unique within a device, so several
+//! devices commonly have a `temperature`. Consumers with a flat data model
+//! can have names qualified by their device instead, from
+//! `register_name_template`, e.g. `{device_type}.{device_id}.{name}` turns
+//! `temperature` of the TCP device `plc-001` into `tcp.plc-001.temperature`.
+//! Configuration, API paths and MQTT topics keep using the bare names.
+
+use std::collections::HashMap;
+
+use crate::config::{Config, DeviceType};
+
+/// Placeholders a `register_name_template` may use
+pub const PLACEHOLDERS: [&str; 3] = ["device_type", "device_id", "name"];
+
+/// Names of registers as they appear in outputs
+#[derive(Debug, Clone, Default)]
+pub struct RegisterNames {
+    template: Option<String>,
+    device_types: HashMap<String, &'static str>,
+}
+
+impl RegisterNames {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            template: config.register_name_template.clone(),
+            device_types: config
+                .devices
+                .iter()
+                .map(|device| {
+                    let device_type = match device.device_type {
+                        DeviceType::Tcp => "tcp",
+                        DeviceType::Rtu => "rtu",
+                    };
+                    (device.id.clone(), device_type)
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether names are qualified at all
+    pub fn is_qualified(&self) -> bool {
+        self.template.is_some()
+    }
+
+    /// Output name of a device's register
+    pub fn qualify(&self, device_id: &str, name: &str) -> String {
+        let Some(template) = &self.template else {
+            return name.to_string();
+        };
+        let device_type = self
+            .device_types
+            .get(device_id)
+            .copied()
+            .unwrap_or("unknown");
+        template
+            .replace("{device_type}", device_type)
+            .replace("{device_id}", device_id)
+            .replace("{name}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigBuilder, DeviceBuilder};
+
+    #[test]
+    fn test_qualify() {
+        let mut config = ConfigBuilder::new()
+            .device(DeviceBuilder::tcp("plc-001", "localhost", 502, 1))
+            .device(DeviceBuilder::rtu("meter-1", "/dev/ttyUSB0", 9600, 1))
+            .build()
+            .unwrap();
+        assert_eq!(
+            RegisterNames::new(&config).qualify("plc-001", "temperature"),
+            "temperature"
+        );
+
+        config.register_name_template = Some("{device_type}.{device_id}.{name}".to_string());
+        let names = RegisterNames::new(&config);
+        assert!(names.is_qualified());
+        assert_eq!(
+            names.qualify("plc-001", "temperature"),
+            "tcp.plc-001.temperature"
+        );
+        assert_eq!(names.qualify("meter-1", "energy"), "rtu.meter-1.energy");
+    }
+}
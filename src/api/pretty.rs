@@ -0,0 +1,147 @@
+//! Pretty-printed JSON responses
+//!
+//! Responses are compact JSON. Adding `?pretty=true` (or just `?pretty`) to
+//! a request indents a JSON response for reading, e.g. with curl. The
+//! response is re-indented as a whole, so this applies to every endpoint
+//! including error responses; other content types are left alone.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+
+/// Re-indent the JSON response when the request asks for `pretty`
+pub async fn pretty_json(request: Request, next: Next) -> Response {
+    let pretty = wants_pretty(request.uri().query());
+    let response = next.run(request).await;
+    if !pretty || !is_json(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    // Only JSON this server produced is valid; anything else goes out as is
+    if serde_json::from_slice::<serde::de::IgnoredAny>(&bytes).is_err() {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(Bytes::from(indent(&bytes))))
+}
+
+/// Whether the query string has `pretty`, `pretty=true` or `pretty=1`
+fn wants_pretty(query: Option<&str>) -> bool {
+    query.is_some_and(|query| {
+        query.split('&').any(|pair| {
+            matches!(
+                pair.split_once('=').unwrap_or((pair, "")),
+                ("pretty", "" | "true" | "1")
+            )
+        })
+    })
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// Indent valid JSON the way `serde_json::to_string_pretty` does, keeping
+/// the order of object keys
+fn indent(json: &[u8]) -> Vec<u8> {
+    fn newline(out: &mut Vec<u8>, depth: usize) {
+        out.push(b'\n');
+        out.extend(std::iter::repeat_n(b' ', depth * 2));
+    }
+
+    let mut out = Vec::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut bytes = json.iter().copied().peekable();
+    while let Some(b) = bytes.next() {
+        if in_string {
+            out.push(b);
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => {
+                in_string = true;
+                out.push(b);
+            }
+            b'{' | b'[' => {
+                out.push(b);
+                let close = if b == b'{' { b'}' } else { b']' };
+                // Empty containers stay on one line
+                if bytes.peek() == Some(&close) {
+                    out.push(close);
+                    bytes.next();
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            b'}' | b']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(b);
+            }
+            b',' => {
+                out.push(b);
+                newline(&mut out, depth);
+            }
+            b':' => out.extend_from_slice(b": "),
+            b' ' | b'\t' | b'\n' | b'\r' => {}
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indent_matches_serde_pretty() {
+        let value = serde_json::json!({
+            "devices": [{"id": "plc-001", "tags": [], "meta": {}}, {"id": "a,b: {c}"}],
+            "count": 2,
+            "text": "quote \" and \\\\ backslash",
+            "nested": [[1, 2], [null, true, -1.5e-7]],
+        });
+        let compact = serde_json::to_vec(&value).unwrap();
+        assert_eq!(
+            String::from_utf8(indent(&compact)).unwrap(),
+            serde_json::to_string_pretty(&value).unwrap()
+        );
+        // Key order is kept, not sorted
+        assert_eq!(
+            indent(br#"{"b":1,"a":2}"#),
+            b"{\n  \"b\": 1,\n  \"a\": 2\n}"
+        );
+    }
+
+    #[test]
+    fn test_wants_pretty() {
+        assert!(wants_pretty(Some("pretty=true")));
+        assert!(wants_pretty(Some("limit=5&pretty")));
+        assert!(wants_pretty(Some("pretty=1")));
+        assert!(!wants_pretty(Some("pretty=false")));
+        assert!(!wants_pretty(Some("prettyish=true")));
+        assert!(!wants_pretty(None));
+    }
+}
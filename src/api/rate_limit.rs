@@ -0,0 +1,185 @@
+//! Rate limit for API register writes
+//!
+//! Every write goes out to a device, and a slow serial device can only take
+//! a few per second. With `server.write_rate_limit` set, writes are limited
+//! by a token bucket: each write takes a token, tokens refill at
+//! `requests_per_second` up to `burst`, and a write without a token is
+//! answered `429 Too Many Requests` with `Retry-After`. Each authenticated client
+//! (API key or token subject) has its own bucket; without authentication
+//! all clients share one. The limit is read from the running configuration on every write.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+
+use super::auth::AuthenticatedClient;
+use super::ApiError;
+use crate::config::{Config, WriteRateLimitConfig};
+
+/// Write buckets, shared by all requests through one router
+#[derive(Debug, Clone)]
+pub struct WriteRateLimit {
+    config: Arc<RwLock<Config>>,
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+/// Tokens left, as of the last write
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: &WriteRateLimitConfig, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    /// Tokens available at `now`
+    fn tokens(&self, limit: &WriteRateLimitConfig, now: Instant) -> f64 {
+        let refilled = now.duration_since(self.updated).as_secs_f64() * limit.requests_per_second;
+        (self.tokens + refilled).min(limit.burst as f64)
+    }
+
+    /// Take a token, or tell how long until the next one is available
+    fn take(&mut self, limit: &WriteRateLimitConfig, now: Instant) -> Result<(), Duration> {
+        self.tokens = self.tokens(limit, now);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / limit.requests_per_second,
+            ))
+        }
+    }
+}
+
+impl WriteRateLimit {
+    pub fn new(config: Arc<RwLock<Config>>) -> Self {
+        Self {
+            config,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Take a token from the bucket of the client `key`
+    ///
+    /// Buckets that have refilled are dropped, as a new one is just as full,
+    /// so only clients that wrote recently are kept.
+    fn check(&self, limit: &WriteRateLimitConfig, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, bucket| bucket.tokens(limit, now) < limit.burst as f64);
+        buckets
+            .entry(key.to_string())
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .take(limit, now)
+    }
+}
+
+/// Answer 429 to writes beyond the configured rate
+pub async fn write_rate_limit(
+    State(limiter): State<WriteRateLimit>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = limiter.config.read().await.server.write_rate_limit.clone();
+    let Some(limit) = limit else {
+        return next.run(request).await;
+    };
+    let key = request
+        .extensions()
+        .get::<AuthenticatedClient>()
+        .map_or("", |client| client.0.as_str());
+
+    match limiter.check(&limit, key, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            crate::metrics::record_write_rate_limited();
+            let mut response = ApiError::with_details(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many writes",
+                format!(
+                    "At most {} writes per second are allowed, try again later",
+                    limit.requests_per_second
+                ),
+            )
+            .into_response();
+            // Whole seconds, rounded up so a retry is not rejected again
+            let secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.into());
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = WriteRateLimitConfig {
+            requests_per_second: 2.0,
+            burst: 3,
+        };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(&limit, start);
+
+        // The burst goes through at once, then the bucket is empty
+        for _ in 0..3 {
+            assert!(bucket.take(&limit, start).is_ok());
+        }
+        assert_eq!(bucket.take(&limit, start), Err(Duration::from_millis(500)));
+
+        // One token every 500ms, never more than the burst
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.take(&limit, later).is_ok());
+        assert!(bucket.take(&limit, later).is_err());
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(bucket.take(&limit, much_later).is_ok());
+        }
+        assert!(bucket.take(&limit, much_later).is_err());
+    }
+
+    #[test]
+    fn test_refilled_buckets_are_dropped() {
+        let limit = WriteRateLimitConfig {
+            requests_per_second: 1.0,
+            burst: 2,
+        };
+        let limiter = WriteRateLimit::new(Arc::default());
+        let start = Instant::now();
+        for key in ["a", "b", "c"] {
+            limiter.check(&limit, key, start).unwrap();
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 3);
+
+        // "a" and "b" have refilled by the time "c" writes again
+        let later = start + Duration::from_secs(2);
+        limiter.check(&limit, "c", later).unwrap();
+        assert_eq!(
+            limiter.buckets.lock().unwrap().keys().collect::<Vec<_>>(),
+            ["c"]
+        );
+        // A dropped client starts over with a full bucket
+        limiter.check(&limit, "a", later).unwrap();
+        limiter.check(&limit, "a", later).unwrap();
+        assert!(limiter.check(&limit, "a", later).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Register store snapshots
+//!
+//! Writes the complete current state (values, timestamps and quality of
+//! every register) as pretty JSON for diagnostics bundles. The store is
+//! serialized straight into the file, without building a copy in memory,
+//! and is only locked while it is serialized.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::modbus::reader::{RegisterStore, RegisterValue};
+
+/// File layout: devices and registers sorted by name
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    gateway_id: &'a str,
+    timestamp: &'a str,
+    devices: BTreeMap<&'a str, BTreeMap<&'a str, &'a RegisterValue>>,
+}
+
+/// What a written snapshot contains
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub path: String,
+    pub bytes: u64,
+    pub devices: usize,
+    pub registers: usize,
+    pub timestamp: String,
+}
+
+/// Write the store to `path`, blocking the calling thread
+///
+/// The snapshot is written next to `path` first and then renamed over it, so
+/// readers never see a partial file.
+pub fn write(
+    path: &Path,
+    store: &RegisterStore,
+    gateway_id: &str,
+    timestamp: &str,
+) -> std::io::Result<SnapshotSummary> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+
+    let (devices, registers) = {
+        let store = store.blocking_read();
+        let devices: BTreeMap<_, BTreeMap<_, _>> = store
+            .iter()
+            .map(|(device_id, registers)| {
+                let registers = registers
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value))
+                    .collect();
+                (device_id.as_str(), registers)
+            })
+            .collect();
+        let registers = devices.values().map(BTreeMap::len).sum();
+        let snapshot = Snapshot {
+            gateway_id,
+            timestamp,
+            devices,
+        };
+        serde_json::to_writer_pretty(&mut writer, &snapshot)?;
+        (snapshot.devices.len(), registers)
+    };
+
+    // Polling goes on while the file is flushed to disk
+    writer.write_all(b"\n")?;
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(&partial, path)?;
+
+    Ok(SnapshotSummary {
+        path: path.display().to_string(),
+        bytes: std::fs::metadata(path)?.len(),
+        devices,
+        registers,
+        timestamp: timestamp.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::reader::Quality;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_snapshot() {
+        let value = RegisterValue {
+            name: "temperature".to_string(),
+            raw: vec![235],
+            decoded: 235.0,
+            value: 23.5,
+            text: None,
+            unit: Some("°C".to_string()),
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+        let store = RegisterStore::default();
+        store.blocking_write().insert(
+            "plc-001".to_string(),
+            HashMap::from([("temperature".to_string(), value)]),
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("rustbridge-snapshot-{}.json", std::process::id()));
+        let summary = write(&path, &store, "gw-1", "2025-12-27T10:30:00+00:00").unwrap();
+        assert_eq!(summary.devices, 1);
+        assert_eq!(summary.registers, 1);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(summary.bytes, content.len() as u64);
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["gateway_id"], "gw-1");
+        let register = &json["devices"]["plc-001"]["temperature"];
+        assert_eq!(register["value"], 23.5);
+        assert_eq!(register["quality"], "good");
+        assert!(register["timestamp"].is_string());
+    }
+}
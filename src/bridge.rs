@@ -5,19 +5,38 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::RwLock;
-use tracing::info;
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{info, Instrument};
+use tracing_subscriber::filter::Directive;
 
-use crate::api::{self, ApiState, RegisterUpdate, WriteRequest};
-use crate::config::Config;
-use crate::metrics::{self, ReadMetrics};
-use crate::modbus::reader::{self, RegisterStore, RegisterValue};
+use crate::api::naming::RegisterNames;
+use crate::api::{
+    self, ApiState, RawReadError, RawReadRequest, RegisterUpdate, ValueChange, WriteRequest,
+};
+use crate::config::{Config, ConnectionConfig, RegisterType};
+use crate::influx::InfluxPublisher;
+use crate::metrics::{self, ReadHealth, ReadMetrics};
+use crate::modbus::breaker::{Admission, BreakerState, CircuitBreaker};
+use crate::modbus::bus::SerialBus;
+use crate::modbus::edges::EdgeCounters;
+use crate::modbus::expression::Expr;
+use crate::modbus::history::History;
+use crate::modbus::identification::DeviceInfoStore;
+use crate::modbus::pause::PausedDevices;
+use crate::modbus::reader::{self, Quality, RegisterStore, RegisterValue};
+use crate::modbus::removed::RemovedRegisters;
+use crate::modbus::throttle::{RateLimiter, Throttle};
+use crate::modbus::SharedAccess;
 use crate::mqtt::MqttPublisher;
+use crate::storage::Storage;
 
 /// Main bridge that orchestrates all components
 pub struct Bridge {
     config: Config,
     register_store: RegisterStore,
+    /// File `POST /api/reload` reads the configuration from
+    config_path: Option<String>,
 }
 
 impl Bridge {
@@ -28,71 +47,309 @@ impl Bridge {
         Ok(Self {
             config,
             register_store,
+            config_path: None,
         })
     }
 
-    /// Run the bridge
+    /// Let `POST /api/reload` re-read the configuration from `path`
+    pub fn with_config_path(mut self, path: impl Into<String>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Run the bridge until the API server exits or the process receives
+    /// SIGINT or SIGTERM, in which case it is shut down gracefully
     pub async fn run(self) -> Result<()> {
-        // Create write request channel
-        let (write_tx, mut write_rx) = tokio::sync::mpsc::channel::<WriteRequest>(100);
+        let mut handle = self.start().await?;
+        tokio::select! {
+            result = &mut handle.server => return result?,
+            _ = shutdown_signal() => {}
+        }
+        info!("Shutting down");
+        handle.stop().await?;
+        info!("Shutdown complete");
+        Ok(())
+    }
+
+    /// Start all components in the background
+    ///
+    /// The bridge keeps running until [`BridgeHandle::stop`] is called or the
+    /// handle is dropped.
+    pub async fn start(self) -> Result<BridgeHandle> {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut workers = Vec::new();
+        metrics::mark_started();
+
+        // Create write and on-demand read request channels
+        let write_capacity = self.config.server.write_queue_capacity;
+        let (write_tx, write_rx) = mpsc::channel::<WriteRequest>(write_capacity);
+        let (raw_tx, raw_rx) = mpsc::channel::<RawReadRequest>(100);
 
         // Initialize Prometheus metrics if enabled
-        let api_state = if self.config.server.metrics_enabled {
-            let metrics_handle = metrics::init_metrics();
+        let mut api_state = if self.config.server.metrics_enabled {
+            let metrics_handle =
+                metrics::init_metrics(&self.config.metrics, &self.config.gateway_id);
             info!("Prometheus metrics enabled at /metrics");
             ApiState::with_metrics(self.register_store.clone(), write_tx, metrics_handle)
         } else {
             ApiState::new(self.register_store.clone(), write_tx)
-        };
+        }
+        .with_config(self.config.clone())
+        .with_raw_reads(raw_tx);
+        if let Some(path) = &self.config_path {
+            api_state = api_state.with_config_path(path.clone());
+        }
 
-        // Clone for the polling tasks to broadcast updates
-        let update_broadcaster = api_state.update_tx.clone();
+        // Shared by the polling tasks to store and broadcast their results
+        let sinks = PollSinks {
+            store: self.register_store.clone(),
+            updates: api_state.update_tx.clone(),
+            changes: api_state.change_tx.clone(),
+            health: api_state.read_health.clone(),
+            device_info: api_state.device_info.clone(),
+            history: api_state.history.clone(),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::new(&self.config.devices),
+            paused: api_state.paused.clone(),
+            removed: api_state.removed.clone(),
+            reporter: self
+                .config
+                .report_by_exception
+                .enabled
+                .then(|| ExceptionReporter::new(&self.config.report_by_exception)),
+        };
 
         // Start MQTT publisher if enabled
-        if self.config.mqtt.enabled {
-            let mqtt_publisher = Arc::new(MqttPublisher::new(&self.config.mqtt).await?);
+        let mqtt_publisher = if self.config.mqtt.enabled {
+            let mqtt_publisher = Arc::new(
+                MqttPublisher::new(&self.config.mqtt, &self.config.devices)
+                    .await?
+                    .with_raw_format(self.config.raw_format)
+                    .with_bool_format(&self.config.bool_format)
+                    .with_register_names(RegisterNames::new(&self.config))
+                    .with_gateway_id(&self.config.gateway_id),
+            );
             let mqtt_rx = api_state.subscribe();
+            api_state = api_state.with_mqtt_status(mqtt_publisher.connection_state());
 
             // Spawn MQTT publishing loop
-            tokio::spawn(async move {
-                mqtt_publisher.start_publishing(mqtt_rx).await;
-            });
+            let publisher = mqtt_publisher.clone();
+            let mut shutdown = shutdown_rx.clone();
+            workers.push(tokio::spawn(async move {
+                tokio::select! {
+                    _ = publisher.start_publishing(mqtt_rx) => {}
+                    _ = shutdown_requested(&mut shutdown) => {}
+                }
+            }));
+
+            if self.config.mqtt.change_events {
+                let publisher = mqtt_publisher.clone();
+                let change_rx = api_state.subscribe_changes();
+                let mut shutdown = shutdown_rx.clone();
+                workers.push(tokio::spawn(async move {
+                    tokio::select! {
+                        _ = publisher.start_publishing_changes(change_rx) => {}
+                        _ = shutdown_requested(&mut shutdown) => {}
+                    }
+                }));
+            }
+
+            if self.config.mqtt.republish_on_connect {
+                let publisher = mqtt_publisher.clone();
+                let store = self.register_store.clone();
+                let mut connects = mqtt_publisher.subscribe_connects();
+                let mut shutdown = shutdown_rx.clone();
+                workers.push(tokio::spawn(async move {
+                    tokio::select! {
+                        _ = republish_on_connect(&publisher, &store, &mut connects) => {}
+                        _ = shutdown_requested(&mut shutdown) => {}
+                    }
+                }));
+            }
+
+            // Gateway diagnostics run beside the register publishing loop
+            let diagnostics = &self.config.mqtt.diagnostics;
+            if diagnostics.enabled {
+                let publisher = mqtt_publisher.clone();
+                let interval = std::time::Duration::from_millis(diagnostics.interval_ms);
+                let mut shutdown = shutdown_rx.clone();
+                workers.push(tokio::spawn(async move {
+                    tokio::select! {
+                        _ = publisher.start_diagnostics(interval) => {}
+                        _ = shutdown_requested(&mut shutdown) => {}
+                    }
+                }));
+            }
 
             info!(
                 "MQTT publishing enabled: {}:{}/{}",
                 self.config.mqtt.host, self.config.mqtt.port, self.config.mqtt.topic_prefix
             );
+            Some(mqtt_publisher)
         } else {
             info!("MQTT publishing disabled");
+            None
+        };
+
+        // Start the InfluxDB writer if enabled; it writes its last batch
+        // when the bridge stops
+        if self.config.influx.enabled {
+            let influx = InfluxPublisher::new(&self.config.influx)?;
+            let influx_rx = api_state.subscribe();
+            let mut shutdown = shutdown_rx.clone();
+            workers.push(tokio::spawn(async move {
+                influx
+                    .start_publishing(influx_rx, shutdown_requested(&mut shutdown))
+                    .await
+            }));
+            info!("InfluxDB writer enabled: {}", self.config.influx.url);
+        }
+
+        // Record the history to the database if enabled; the API serves it
+        // from there
+        if self.config.storage.enabled {
+            let storage = Storage::open(&self.config.storage)?;
+            let storage_rx = api_state.subscribe();
+            let recorder = storage.clone();
+            let mut shutdown = shutdown_rx.clone();
+            workers.push(tokio::spawn(async move {
+                recorder
+                    .start_recording(storage_rx, shutdown_requested(&mut shutdown))
+                    .await
+            }));
+            api_state = api_state.with_storage(storage);
+            info!("History storage enabled: {}", self.config.storage.path);
         }
 
-        // Start polling for each device with WebSocket broadcast
-        for device in &self.config.devices {
-            let store = self.register_store.clone();
+        // Start polling for each device with WebSocket broadcast.
+        // Each polling task is the device's worker: it owns the Modbus
+        // connection and also executes write requests routed to it.
+        let mut device_writers = HashMap::new();
+        let mut device_readers = HashMap::new();
+        // Devices tell the ones depending on them when they are up
+        let (ready_senders, ready_receivers): (Vec<_>, Vec<_>) = self
+            .config
+            .devices
+            .iter()
+            .map(|_| watch::channel(false))
+            .unzip();
+        api_state = api_state.with_readiness(api::Readiness::new(
+            ready_receivers.clone(),
+            std::time::Duration::from_millis(self.config.startup.grace_period_ms),
+        ));
+        let mut connected = Vec::new();
+        let group_limiters: HashMap<&str, RateLimiter> = self
+            .config
+            .rate_limit_groups
+            .iter()
+            .map(|g| (g.id.as_str(), RateLimiter::new(g.max_reads_per_second)))
+            .collect();
+        let buses = shared_buses(&self.config.devices);
+        let poll_offsets = poll_offsets(&self.config.devices, self.config.startup.stagger_polls);
+        for ((device, ready), poll_offset) in self
+            .config
+            .devices
+            .iter()
+            .zip(ready_senders)
+            .zip(poll_offsets)
+        {
+            let throttle = Throttle::new(
+                device
+                    .max_reads_per_second
+                    .map(RateLimiter::new)
+                    .into_iter()
+                    .chain(
+                        device
+                            .rate_limit_group
+                            .as_deref()
+                            .and_then(|group| group_limiters.get(group).cloned()),
+                    )
+                    .collect(),
+            );
+            let shared = SharedAccess {
+                throttle,
+                bus: match &device.connection {
+                    ConnectionConfig::Rtu(rtu) => buses.get(rtu.port.as_str()).cloned(),
+                    ConnectionConfig::Tcp(_) => None,
+                },
+            };
+            let (connected_tx, connected_rx) = watch::channel(false);
+            connected.push((device.id.clone(), connected_rx));
+            let startup = StartupOrder {
+                connected: connected_tx,
+                ready,
+                dependencies: device
+                    .depends_on
+                    .iter()
+                    .filter_map(|id| {
+                        let index = self.config.devices.iter().position(|d| &d.id == id)?;
+                        Some((id.clone(), ready_receivers[index].clone()))
+                    })
+                    .collect(),
+                poll_offset,
+            };
             let device_config = device.clone();
-            let broadcaster = update_broadcaster.clone();
+            let sinks = sinks.clone();
+            let mqtt = mqtt_publisher.clone();
+            let (writes_tx, writes) = mpsc::channel::<WriteRequest>(write_capacity);
+            let (raw_reads_tx, raw_reads) = mpsc::channel::<RawReadRequest>(100);
+            device_writers.insert(device.id.clone(), writes_tx);
+            device_readers.insert(device.id.clone(), raw_reads_tx);
+            let inbox = DeviceInbox { writes, raw_reads };
+            let shutdown = shutdown_rx.clone();
+            let span = device_span(&device.id);
 
-            tokio::spawn(async move {
-                if let Err(e) =
-                    start_polling_with_broadcast(device_config, store, broadcaster).await
-                {
-                    tracing::error!("Polling error: {}", e);
+            workers.push(tokio::spawn(
+                async move {
+                    if let Err(e) = start_polling_with_broadcast(
+                        device_config,
+                        sinks,
+                        inbox,
+                        startup,
+                        shared,
+                        mqtt,
+                        shutdown,
+                    )
+                    .await
+                    {
+                        tracing::error!("Polling error: {}", e);
+                    }
                 }
-            });
+                .instrument(span),
+            ));
         }
 
-        // Spawn write request handler
-        tokio::spawn(async move {
-            while let Some(request) = write_rx.recv().await {
-                // For now, acknowledge the write request
-                // In production, this would forward to the actual Modbus client
-                let _ = request.response_tx.send(Ok(()));
-                info!(
-                    "Write request received: {}@{} = {}",
-                    request.device_id, request.address, request.value
-                );
+        let startup = &self.config.startup;
+        if startup.require_all_devices {
+            let timeout = std::time::Duration::from_millis(startup.timeout_ms);
+            if let Err(e) = wait_for_connections(connected, timeout).await {
+                let _ = shutdown_tx.send(true);
+                return Err(e);
             }
-        });
+            info!("All {} devices connected", self.config.devices.len());
+        }
+
+        // Evict devices that stopped answering from the API
+        if let Some(ttl_secs) = self.config.server.device_ttl_secs {
+            workers.push(spawn_stale_device_sweeper(
+                self.register_store.clone(),
+                api_state.paused.clone(),
+                std::time::Duration::from_secs(ttl_secs),
+                shutdown_rx.clone(),
+            ));
+        }
+
+        // Spawn request dispatchers
+        workers.push(spawn_dispatcher(
+            write_rx,
+            device_writers,
+            shutdown_rx.clone(),
+        ));
+        workers.push(spawn_dispatcher(
+            raw_rx,
+            device_readers,
+            shutdown_rx.clone(),
+        ));
 
         // Start API server
         let app = api::create_router(api_state, self.config.auth.clone());
@@ -120,101 +377,2499 @@ impl Bridge {
         }
 
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        axum::serve(listener, app).await?;
+        let local_addr = listener.local_addr()?;
+        let mut shutdown = shutdown_rx;
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async move { shutdown_requested(&mut shutdown).await })
+                .await?;
+            Ok(())
+        });
 
-        Ok(())
+        Ok(BridgeHandle {
+            register_store: self.register_store,
+            local_addr,
+            shutdown_tx,
+            server,
+            workers,
+            mqtt: mqtt_publisher,
+        })
+    }
+}
+
+/// Longest time [`BridgeHandle::stop`] waits for the bridge to wind down
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Resolve on SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Handle to a bridge started with [`Bridge::start`]
+///
+/// Dropping the handle shuts the bridge down without waiting for it.
+pub struct BridgeHandle {
+    register_store: RegisterStore,
+    local_addr: SocketAddr,
+    shutdown_tx: watch::Sender<bool>,
+    server: JoinHandle<Result<()>>,
+    workers: Vec<JoinHandle<()>>,
+    mqtt: Option<Arc<MqttPublisher>>,
+}
+
+impl BridgeHandle {
+    /// Latest values of all registers, keyed by device and register name
+    #[allow(dead_code)] // Library API, not used by the binary
+    pub fn register_store(&self) -> RegisterStore {
+        self.register_store.clone()
+    }
+
+    /// Address the API server is listening on
+    #[allow(dead_code)] // Library API, not used by the binary
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop polling and the API server, waiting for in-flight work to finish
+    ///
+    /// Devices are reported offline and disconnected, in-flight API requests
+    /// are answered, and the MQTT client disconnects after sending what was
+    /// queued. Tasks still running after [`SHUTDOWN_TIMEOUT`] are aborted.
+    pub async fn stop(self) -> Result<()> {
+        let _ = self.shutdown_tx.send(true);
+
+        let aborts: Vec<_> = self
+            .workers
+            .iter()
+            .map(JoinHandle::abort_handle)
+            .chain([self.server.abort_handle()])
+            .collect();
+        let drain = async {
+            for worker in self.workers {
+                worker.await?;
+            }
+            self.server.await??;
+            // Offline statuses from the workers go out before the disconnect
+            if let Some(mqtt) = &self.mqtt {
+                mqtt.disconnect().await?;
+            }
+            Ok(())
+        };
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, drain).await {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!(
+                    "Shutdown did not complete within {:?}, aborting remaining tasks",
+                    SHUTDOWN_TIMEOUT
+                );
+                for abort in aborts {
+                    abort.abort();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Request handled by the worker of the device it targets
+trait DeviceRequest: Send + 'static {
+    fn device_id(&self) -> &str;
+
+    /// Answer the request with an error without executing it
+    fn reject(self, message: String);
+}
+
+impl DeviceRequest for WriteRequest {
+    fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    fn reject(self, message: String) {
+        let _ = self.response_tx.send(Err(message));
+    }
+}
+
+impl DeviceRequest for RawReadRequest {
+    fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    fn reject(self, message: String) {
+        let _ = self.response_tx.send(Err(RawReadError::Failed(message)));
+    }
+}
+
+/// Route requests from the API to the per-device worker channels
+fn spawn_dispatcher<R: DeviceRequest>(
+    mut rx: mpsc::Receiver<R>,
+    devices: HashMap<String, mpsc::Sender<R>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let request = tokio::select! {
+                request = rx.recv() => match request {
+                    Some(request) => request,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown) => break,
+            };
+
+            match devices.get(request.device_id()) {
+                Some(device_tx) => {
+                    if let Err(e) = device_tx.send(request).await {
+                        let request = e.0;
+                        let message = format!("Device {} is not running", request.device_id());
+                        request.reject(message);
+                    }
+                }
+                None => {
+                    let message = format!("Unknown device {}", request.device_id());
+                    request.reject(message);
+                }
+            }
+        }
+    })
+}
+
+/// Span a device's worker runs in, so its messages carry the device id
+fn device_span(device_id: &str) -> tracing::Span {
+    tracing::info_span!("device", device_id)
+}
+
+/// Log filter directives applying each device's `log_level` to the
+/// messages logged within its span
+pub fn device_log_directives(config: &Config) -> Vec<Directive> {
+    config
+        .devices
+        .iter()
+        .filter_map(|device| {
+            let level = device.log_level.as_ref()?;
+            let directive = format!("[device{{device_id={}}}]={}", device.id, level);
+            match directive.parse() {
+                Ok(directive) => Some(directive),
+                Err(e) => {
+                    tracing::warn!("Ignoring log_level of device {}: {}", device.id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Channels through which the API reaches a device's worker
+struct DeviceInbox {
+    writes: mpsc::Receiver<WriteRequest>,
+    raw_reads: mpsc::Receiver<RawReadRequest>,
+}
+
+/// A device's place in the startup order
+struct StartupOrder {
+    /// Set once the device's first connection is open
+    connected: watch::Sender<bool>,
+    /// Set after the device's first successful poll cycle
+    ready: watch::Sender<bool>,
+    /// Devices to wait for before connecting
+    dependencies: Vec<(String, watch::Receiver<bool>)>,
+    /// Delay of the first poll cycle, staggering devices polled together
+    poll_offset: std::time::Duration,
+}
+
+impl StartupOrder {
+    /// Wait until every dependency has been read successfully
+    ///
+    /// A dependency whose worker stopped (e.g. it gave up connecting) is no
+    /// longer waited for. Returns `false` when shutdown is requested.
+    async fn wait_for_dependencies(
+        &mut self,
+        device_id: &str,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> bool {
+        for (dependency, ready) in &mut self.dependencies {
+            if *ready.borrow() {
+                continue;
+            }
+            info!("Device {} waiting for device {}", device_id, dependency);
+            tokio::select! {
+                result = ready.wait_for(|ready| *ready) => {
+                    if result.is_err() {
+                        tracing::warn!(
+                            "Device {}: dependency {} stopped, connecting anyway",
+                            device_id,
+                            dependency
+                        );
+                    }
+                }
+                _ = shutdown_requested(shutdown) => return false,
+            }
+        }
+        true
+    }
+
+    /// Report the device reachable to the startup check
+    fn mark_connected(&self) {
+        self.connected.send_replace(true);
+    }
+
+    /// Release the devices waiting for this one
+    fn mark_ready(&self) {
+        self.ready
+            .send_if_modified(|ready| !std::mem::replace(ready, true));
+    }
+}
+
+/// Wait until every device has opened its first connection
+///
+/// Fails as soon as a device gives up connecting, or with the devices still
+/// unconnected once `timeout` has passed.
+async fn wait_for_connections(
+    mut devices: Vec<(String, watch::Receiver<bool>)>,
+    timeout: std::time::Duration,
+) -> Result<()> {
+    let waits = devices.iter_mut().map(|(device_id, connected)| async move {
+        // The sender is dropped when the worker stops without connecting
+        match connected.wait_for(|c| *c).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Device {} failed to connect and startup.require_all_devices is set",
+                device_id
+            )),
+        }
+    });
+    if let Ok(result) =
+        tokio::time::timeout(timeout, futures_util::future::try_join_all(waits)).await
+    {
+        return result.map(|_| ());
+    }
+
+    let pending: Vec<_> = devices
+        .iter()
+        .filter(|(_, connected)| !*connected.borrow())
+        .map(|(id, _)| id.as_str())
+        .collect();
+    anyhow::bail!(
+        "Devices not connected within {}ms and startup.require_all_devices is set: {}",
+        timeout.as_millis(),
+        pending.join(", ")
+    )
+}
+
+/// Where a device's worker delivers its poll results
+#[derive(Clone)]
+struct PollSinks {
+    store: RegisterStore,
+    updates: broadcast::Sender<RegisterUpdate>,
+    changes: broadcast::Sender<ValueChange>,
+    health: ReadHealth,
+    device_info: DeviceInfoStore,
+    history: History,
+    edges: EdgeCounters,
+    deadbands: Deadbands,
+    /// Devices whose poll cycles are skipped
+    paused: PausedDevices,
+    /// Registers whose values are discarded after a reload removed them
+    removed: RemovedRegisters,
+    /// Filters `updates` when reporting by exception
+    reporter: Option<ExceptionReporter>,
+}
+
+/// Report-by-exception state: when each register was last broadcast
+#[derive(Clone)]
+struct ExceptionReporter {
+    heartbeat: std::time::Duration,
+    last_sent: Arc<std::sync::Mutex<HashMap<(String, String), Instant>>>,
+}
+
+impl ExceptionReporter {
+    fn new(config: &crate::config::ReportByExceptionConfig) -> Self {
+        Self {
+            heartbeat: std::time::Duration::from_millis(config.heartbeat_ms),
+            last_sent: Arc::default(),
+        }
+    }
+
+    /// Whether an update is broadcast: when its value changed, or as the
+    /// heartbeat of a register not sent for a while
+    fn should_send(&self, update: &RegisterUpdate, now: Instant) -> bool {
+        let key = (update.device_id.clone(), update.register_name.clone());
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let due = update.changed
+            || last_sent
+                .get(&key)
+                .is_none_or(|sent| now.duration_since(*sent) >= self.heartbeat);
+        if due {
+            last_sent.insert(key, now);
+        }
+        due
+    }
+}
+
+/// Deadband state: each register's deadband and the reading its changes
+/// are measured from
+#[derive(Clone, Default)]
+struct Deadbands {
+    widths: Arc<HashMap<(String, String), f64>>,
+    /// Last reading of each register that counted as a change
+    baselines: Arc<std::sync::Mutex<HashMap<(String, String), RegisterValue>>>,
+}
+
+impl Deadbands {
+    fn new(devices: &[crate::config::DeviceConfig]) -> Self {
+        let widths = devices
+            .iter()
+            .flat_map(|device| {
+                device.registers.iter().filter_map(|register| {
+                    let width = register.deadband_width()?;
+                    Some(((device.id.clone(), register.name.clone()), width))
+                })
+            })
+            .collect();
+        Self {
+            widths: Arc::new(widths),
+            baselines: Arc::default(),
+        }
+    }
+
+    /// Whether an update is held back because its register has a deadband
+    /// the reading stayed within
+    ///
+    /// Registers without a deadband, or with a deadband of 0, are sent on
+    /// every reading.
+    fn suppresses(&self, update: &RegisterUpdate) -> bool {
+        let key = (update.device_id.clone(), update.register_name.clone());
+        !update.changed && self.widths.get(&key).is_some_and(|&width| width > 0.0)
+    }
+
+    /// Build the update for a new reading
+    ///
+    /// A register with a deadband is compared with its last reading that
+    /// counted as a change rather than the previous one, so a slow drift is
+    /// reported once it adds up to the deadband. That reading is also the
+    /// update's old value.
+    fn update(
+        &self,
+        device_id: &str,
+        current: RegisterValue,
+        previous: Option<RegisterValue>,
+    ) -> RegisterUpdate {
+        let key = (device_id.to_string(), current.name.clone());
+        let Some(&width) = self.widths.get(&key) else {
+            return register_update(device_id, current, previous.as_ref());
+        };
+        let mut baselines = self.baselines.lock().unwrap();
+        let baseline = baselines.get(&key).cloned().or(previous);
+        let changed = baseline
+            .as_ref()
+            .is_none_or(|baseline| reader::exceeds_deadband(baseline, &current, width));
+        if changed {
+            baselines.insert(key, current.clone());
+        }
+        RegisterUpdate {
+            changed,
+            ..register_update(device_id, current, baseline.as_ref())
+        }
     }
 }
 
+/// Resolve once shutdown has been requested (or the handle was dropped)
+async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// Periodically remove devices without a successful read within `ttl`
+fn spawn_stale_device_sweeper(
+    store: RegisterStore,
+    paused: PausedDevices,
+    ttl: std::time::Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    // Check a few times per TTL, but at most once a second
+    let period = (ttl / 4).clamp(
+        std::time::Duration::from_secs(1),
+        std::time::Duration::from_secs(60),
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    for device_id in reader::evict_stale_devices(&store, ttl, &paused).await {
+                        tracing::warn!(
+                            "Evicted device {}: no successful read for {}s",
+                            device_id,
+                            ttl.as_secs()
+                        );
+                    }
+                }
+                _ = shutdown_requested(&mut shutdown) => return,
+            }
+        }
+    })
+}
+
 /// Start polling with WebSocket broadcast support and metrics
+///
+/// Write and on-demand read requests for the device are handled between
+/// poll ticks, so they never interleave with polling on the same connection.
 async fn start_polling_with_broadcast(
-    config: crate::config::DeviceConfig,
-    store: RegisterStore,
-    broadcaster: tokio::sync::broadcast::Sender<RegisterUpdate>,
+    mut config: crate::config::DeviceConfig,
+    sinks: PollSinks,
+    mut inbox: DeviceInbox,
+    mut startup: StartupOrder,
+    shared: SharedAccess,
+    mqtt: Option<Arc<MqttPublisher>>,
+    mut shutdown: watch::Receiver<bool>,
 ) -> Result<()> {
     use crate::modbus::ModbusClient;
-    use tokio::time::{interval, Duration};
+    use tokio::time::Duration;
 
-    let mut client = ModbusClient::new(&config).await?;
     let device_id = config.id.clone();
-    let poll_interval = Duration::from_millis(config.poll_interval_ms);
+    if !startup
+        .wait_for_dependencies(&device_id, &mut shutdown)
+        .await
+    {
+        return Ok(());
+    }
+    let Some(client) = connect_with_retry(&config, &shared, mqtt.as_deref(), &mut shutdown).await?
+    else {
+        return Ok(());
+    };
+    startup.mark_connected();
+
+    // The first connection also carries writes and on-demand reads
+    let mut clients = vec![client];
+    identify_device(&mut clients[0], &device_id, &sinks.device_info).await;
+    if let Some(sunspec) = config.sunspec.clone() {
+        add_sunspec_registers(&mut clients[0], &mut config, sunspec.base_address).await;
+    }
+    // Virtual registers are computed after each cycle instead of read
+    let virtual_registers = match config.virtual_registers() {
+        Ok(registers) => registers,
+        Err(errors) => anyhow::bail!("Device {}: {}", device_id, errors.join(", ")),
+    };
+    config
+        .registers
+        .retain(|r| r.register_type != RegisterType::Virtual);
+    if config.parallel_reads > 1 && config.read_connections() == 1 {
+        tracing::warn!(
+            "Device {} is read sequentially: parallel_reads needs a Modbus TCP connection",
+            device_id
+        );
+    }
+    for _ in 1..config.read_connections() {
+        let client = tokio::select! {
+            client = ModbusClient::connect(&config, &shared) => client,
+            _ = shutdown_requested(&mut shutdown) => return Ok(()),
+        };
+        match client {
+            Ok(client) => clients.push(client),
+            Err(e) => {
+                tracing::warn!(
+                    "Device {}: extra read connection failed, using {}: {}",
+                    device_id,
+                    clients.len(),
+                    e
+                );
+                break;
+            }
+        }
+    }
 
     info!(
-        "Starting polling for device {} every {}ms",
-        device_id, config.poll_interval_ms
+        "Starting polling for device {} every {}ms{}",
+        device_id,
+        config.poll_interval_ms,
+        if config.spread_reads {
+            " (reads spread across the interval)"
+        } else {
+            ""
+        }
     );
 
     // Record device as connected
-    metrics::record_device_status(&device_id, true);
+    let mut online = true;
+    announce_device_status(&device_id, online, mqtt.as_deref()).await;
+
+    let mut ticker = tokio::time::interval_at(
+        tokio::time::Instant::now() + startup.poll_offset,
+        tick_period(&config),
+    );
+    let reads_per_tick = if config.spread_reads { 1 } else { usize::MAX };
+
+    // Progress through the current poll cycle, which spans several ticks
+    // when reads are spread
+    let mut next_register: usize = 0;
+    let mut cycle_failed = 0;
+    let mut cycle_busy = Duration::ZERO;
+
+    // Bounded cycles resume where the last one was cut short
+    let cycle_budget = config.max_cycle_ms.map(Duration::from_millis);
+    let mut rotation: usize = 0;
+    let mut truncating = false;
+
+    let mut breaker = config.circuit_breaker.as_ref().map(CircuitBreaker::new);
+    if breaker.is_some() {
+        metrics::record_breaker_state(&device_id, BreakerState::Closed);
+    }
+    // The current cycle only reads one register to test the device
+    let mut probing = false;
+
+    let mut heartbeat = config.heartbeat.clone().map(Heartbeat::new);
 
-    let mut ticker = interval(poll_interval);
+    // The first connection is lost and being reopened
+    let mut outage = false;
+    let mut reconnect = Reconnect::new(config.reconnect.backoff.clone());
 
     loop {
-        ticker.tick().await;
-        let cycle_start = Instant::now();
-
-        for register in &config.registers {
-            // Start metrics timing
-            let read_metrics = ReadMetrics::start(&device_id, &register.name);
-
-            match client.read_registers(register).await {
-                Ok(raw_values) => {
-                    let value = reader::convert_value(&raw_values, register);
-
-                    // Record successful read metrics
-                    read_metrics.success(value);
-
-                    let reg_value = RegisterValue {
-                        name: register.name.clone(),
-                        raw: raw_values.clone(),
-                        value,
-                        unit: register.unit.clone(),
-                        timestamp: chrono::Utc::now(),
-                    };
+        // Pending writes go before the next poll cycle and on-demand reads
+        tokio::select! {
+            biased;
+            _ = shutdown_requested(&mut shutdown) => {
+                info!("Stopping polling for device {}", device_id);
+                for client in &mut clients {
+                    client.disconnect().await;
+                }
+                announce_device_status(&device_id, false, mqtt.as_deref()).await;
+                return Ok(());
+            }
+            _ = heartbeat_due(heartbeat.as_mut()) => {
+                let heartbeat = heartbeat.as_mut().expect("heartbeat is configured");
+                if let Some(healthy) = heartbeat.beat(&mut clients[0], &device_id).await {
+                    if healthy != online {
+                        online = healthy;
+                        announce_device_status(&device_id, online, mqtt.as_deref()).await;
+                    }
+                }
+            }
+            Some(request) = inbox.writes.recv() => {
+                execute_write(&mut clients[0], &config, request).await;
+            }
+            Some(request) = inbox.raw_reads.recv() => {
+                execute_raw_read(&mut clients[0], request).await;
+            }
+            _ = ticker.tick() => {
+                if sinks.paused.is_paused(&device_id) {
+                    // A cycle cut short by the pause starts over on resume
+                    next_register = 0;
+                    cycle_failed = 0;
+                    cycle_busy = Duration::ZERO;
+                    continue;
+                }
+                let connected =
+                    reconnect_lost(&mut clients, &config, &shared, &sinks, &mut reconnect).await;
+                if connected == outage {
+                    outage = !connected;
+                    if outage {
+                        tracing::warn!("Device {} lost its connection", device_id);
+                    } else {
+                        info!("Device {} reconnected", device_id);
+                    }
+                    if online != connected {
+                        online = connected;
+                        announce_device_status(&device_id, online, mqtt.as_deref()).await;
+                    }
+                }
+                if outage {
+                    // A cycle cut short by the outage starts over once reconnected
+                    next_register = 0;
+                    cycle_failed = 0;
+                    cycle_busy = Duration::ZERO;
+                    continue;
+                }
+                if next_register == 0 {
+                    match breaker.as_mut().map(|b| b.admit(Instant::now())) {
+                        Some(Admission::Skip) => continue,
+                        Some(Admission::Probe) => {
+                            probing = true;
+                            metrics::record_breaker_state(&device_id, BreakerState::HalfOpen);
+                        }
+                        _ => probing = false,
+                    }
+                }
 
-                    // Store the value
-                    {
-                        let mut store = store.write().await;
-                        let device_map =
-                            store.entry(device_id.clone()).or_insert_with(HashMap::new);
-                        device_map.insert(register.name.clone(), reg_value.clone());
-                    }
-
-                    // Broadcast to WebSocket clients (and MQTT if enabled)
-                    let update = RegisterUpdate {
-                        device_id: device_id.clone(),
-                        register_name: register.name.clone(),
-                        value: reg_value.value,
-                        raw: reg_value.raw,
-                        unit: reg_value.unit,
-                        timestamp: reg_value.timestamp.to_rfc3339(),
+                let total = if probing {
+                    config.registers.len().min(1)
+                } else {
+                    config.registers.len()
+                };
+                // Registers read in this cycle
+                let mut read = total;
+                let started = Instant::now();
+                if let Some(budget) = cycle_budget.filter(|_| !probing) {
+                    let writes = config.prioritize_writes.then_some(&mut inbox.writes);
+                    let (n, failed) = poll_registers_bounded(
+                        &mut clients,
+                        &config,
+                        &mut rotation,
+                        budget,
+                        &sinks,
+                        writes,
+                    )
+                    .await;
+                    cycle_failed += failed;
+                    cycle_busy += started.elapsed();
+
+                    if n < total {
+                        read = n;
+                        metrics::record_poll_truncated(&device_id, total - n);
+                        if !truncating {
+                            tracing::warn!(
+                                "Poll cycle for device {} exceeded max_cycle_ms ({}ms): read {}/{} registers, continuing next cycle",
+                                device_id,
+                                budget.as_millis(),
+                                n,
+                                total
+                            );
+                        }
+                    } else if truncating {
+                        info!("Poll cycles for device {} complete within max_cycle_ms again", device_id);
+                    }
+                    truncating = n < total;
+                } else {
+                    let end = next_register.saturating_add(reads_per_tick).min(total);
+                    let registers = &config.registers[next_register..end];
+                    cycle_failed += if config.prioritize_writes {
+                        poll_registers_preemptible(
+                            &mut clients,
+                            &config,
+                            registers,
+                            &sinks,
+                            &mut inbox.writes,
+                        )
+                        .await
+                    } else {
+                        poll_registers_concurrently(&mut clients, &config, registers, &sinks).await
                     };
-                    let _ = broadcaster.send(update);
+                    cycle_busy += started.elapsed();
 
-                    tracing::debug!(
-                        "Device {} register {} = {} {:?}",
-                        device_id,
-                        register.name,
-                        value,
-                        register.unit
-                    );
+                    if end < total {
+                        next_register = end;
+                        continue;
+                    }
                 }
-                Err(e) => {
-                    // Record failed read metrics
-                    read_metrics.failure("modbus_error");
 
-                    tracing::error!(
-                        "Failed to read register {} from {}: {}",
-                        register.name,
-                        device_id,
-                        e
-                    );
+                // Cycle complete
+                compute_virtual_registers(&device_id, &virtual_registers, &sinks).await;
+                next_register = 0;
+                metrics::record_poll_cycle(&device_id, cycle_busy.as_millis() as u64);
+                cycle_busy = Duration::ZERO;
+                let failed = std::mem::take(&mut cycle_failed);
+                if read == 0 || failed < read {
+                    startup.mark_ready();
+                }
+
+                if let Some(breaker) = breaker.as_mut() {
+                    // A device that answers at all is reachable
+                    let reachable = read == 0 || failed < read;
+                    if let Some(state) = breaker.record(reachable, Instant::now()) {
+                        metrics::record_breaker_state(&device_id, state);
+                        match state {
+                            BreakerState::Open if !probing => tracing::warn!(
+                                "Circuit breaker for device {} opened, probing every {}ms",
+                                device_id,
+                                config.circuit_breaker.as_ref().map_or(0, |b| b.probe_interval_ms)
+                            ),
+                            BreakerState::Closed => {
+                                info!("Circuit breaker for device {} closed", device_id)
+                            }
+                            _ => {}
+                        }
+                        // Open or closed breakers decide the device state
+                        let breaker_online = state == BreakerState::Closed
+                            && heartbeat.as_ref().is_none_or(|h| !h.failing);
+                        if breaker_online != online {
+                            online = breaker_online;
+                            announce_device_status(&device_id, online, mqtt.as_deref()).await;
+                        }
+                    }
+                    if probing {
+                        continue;
+                    }
+                }
+
+                if let Some(ratio) = config.offline_error_ratio {
+                    let healthy = !exceeds_error_ratio(failed, read, ratio)
+                        && heartbeat.as_ref().is_none_or(|h| !h.failing);
+                    if healthy != online {
+                        online = healthy;
+                        if online {
+                            info!("Device {} is back online", device_id);
+                        } else {
+                            tracing::warn!(
+                                "Device {} marked offline: {}/{} register reads failed, reconnecting",
+                                device_id,
+                                failed,
+                                read
+                            );
+                            force_reconnect(&mut clients, &device_id).await;
+                        }
+                        announce_device_status(&device_id, online, mqtt.as_deref()).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Watchdog writes of a device, on their own interval
+struct Heartbeat {
+    config: crate::config::HeartbeatConfig,
+    ticker: tokio::time::Interval,
+    /// Value of the last write
+    value: u16,
+    /// Whether the last write failed
+    failing: bool,
+}
+
+impl Heartbeat {
+    fn new(config: crate::config::HeartbeatConfig) -> Self {
+        let mut ticker =
+            tokio::time::interval(std::time::Duration::from_millis(config.interval_ms));
+        // A late write must not be followed by a burst of catch-up writes
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Self {
+            config,
+            ticker,
+            value: 0,
+            failing: false,
+        }
+    }
+
+    /// Write the next heartbeat value
+    ///
+    /// Returns whether the device is healthy when that changed with this
+    /// write, i.e. on the first failure and on the first success after it.
+    async fn beat(
+        &mut self,
+        client: &mut crate::modbus::ModbusClient,
+        device_id: &str,
+    ) -> Option<bool> {
+        let value = self.config.mode.next(self.value);
+        match client.write_register(self.config.address, value).await {
+            Ok(()) => {
+                self.value = value;
+                if !std::mem::replace(&mut self.failing, false) {
+                    return None;
+                }
+                info!("Device {}: heartbeat write succeeded again", device_id);
+                Some(true)
+            }
+            Err(e) => {
+                metrics::record_heartbeat_failure(device_id);
+                if std::mem::replace(&mut self.failing, true) {
+                    tracing::debug!("Device {}: heartbeat write failed: {:#}", device_id, e);
+                    return None;
                 }
+                tracing::warn!(
+                    "Device {} marked offline: heartbeat write to {} failed: {:#}",
+                    device_id,
+                    self.config.address,
+                    e
+                );
+                Some(false)
             }
         }
+    }
+}
+
+/// Wait for the next heartbeat, forever if there is none
+async fn heartbeat_due(heartbeat: Option<&mut Heartbeat>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Read one cycle of registers in round-robin order, stopping once `budget`
+/// has elapsed
+///
+/// Reading starts at `*start`, which is moved to the first register not
+/// read so the next cycle picks up there. At least one batch is always
+/// read. Returns the number of registers read and how many failed.
+async fn poll_registers_bounded(
+    clients: &mut [crate::modbus::ModbusClient],
+    config: &crate::config::DeviceConfig,
+    start: &mut usize,
+    budget: std::time::Duration,
+    sinks: &PollSinks,
+    mut writes: Option<&mut mpsc::Receiver<WriteRequest>>,
+) -> (usize, usize) {
+    let registers = &config.registers;
+    let total = registers.len();
+    let batch = clients.len().max(1);
+    let started = Instant::now();
+
+    let mut read = 0;
+    let mut failed = 0;
+    while read < total && (read == 0 || started.elapsed() < budget) {
+        let from = (*start + read) % total;
+        // Batches don't wrap around the end of the register list
+        let blocks = register_blocks(config, &registers[from..]);
+        let n = block_batches(&blocks, batch)[0].end.min(total - read);
+        run_pending_writes(&mut clients[0], config, writes.as_deref_mut()).await;
+        failed +=
+            poll_registers_concurrently(clients, config, &registers[from..from + n], sinks).await;
+        read += n;
+    }
+
+    *start = (*start + read) % total.max(1);
+    (read, failed)
+}
+
+/// How long a device may take to answer the identification request
+const IDENTIFICATION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Read a device's identification (FC43) after connecting
+///
+/// Devices that don't support it, or don't answer in time, are simply left
+/// without one.
+async fn identify_device(
+    client: &mut crate::modbus::ModbusClient,
+    device_id: &str,
+    device_info: &DeviceInfoStore,
+) {
+    let result = tokio::time::timeout(IDENTIFICATION_TIMEOUT, client.read_device_info()).await;
+    match result {
+        Ok(Ok(Some(info))) => {
+            info!(
+                "Device {} identified: {} {} {}",
+                device_id,
+                info.vendor_name.as_deref().unwrap_or("-"),
+                info.product_code.as_deref().unwrap_or("-"),
+                info.revision.as_deref().unwrap_or("-")
+            );
+            device_info
+                .write()
+                .await
+                .insert(device_id.to_string(), info);
+        }
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => tracing::debug!("Device {} identification failed: {}", device_id, e),
+        Err(_) => tracing::debug!("Device {} did not answer device identification", device_id),
+    }
+}
+
+/// Backoff between attempts to reopen a device's connections
+#[derive(Debug)]
+struct Reconnect {
+    backoff: crate::config::ConnectRetryConfig,
+    /// Attempts that failed since the connection was lost
+    failed_attempts: u32,
+    /// No attempt before this
+    next_attempt: Option<Instant>,
+}
+
+impl Reconnect {
+    fn new(backoff: crate::config::ConnectRetryConfig) -> Self {
+        Self {
+            backoff,
+            failed_attempts: 0,
+            next_attempt: None,
+        }
+    }
+
+    /// Whether the retries are used up
+    fn gave_up(&self) -> bool {
+        !self.backoff.should_retry(self.failed_attempts)
+    }
+
+    /// Whether an attempt may be made now
+    fn due(&self, now: Instant) -> bool {
+        !self.gave_up() && self.next_attempt.is_none_or(|at| now >= at)
+    }
+
+    /// Record a failed attempt; returns the delay before the next one, or
+    /// `None` when the retries are used up
+    fn failed(&mut self, now: Instant) -> Option<std::time::Duration> {
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        if self.gave_up() {
+            self.next_attempt = None;
+            return None;
+        }
+        let delay = self.backoff.delay(self.failed_attempts);
+        self.next_attempt = Some(now + delay);
+        Some(delay)
+    }
+
+    fn succeeded(&mut self) {
+        self.failed_attempts = 0;
+        self.next_attempt = None;
+    }
+}
+
+/// Reopen connections that were lost or stopped answering
+///
+/// A connection is reopened when it is closed, when `reconnect.after_failures`
+/// reads in a row got no answer, or when `reconnect_after_timeouts` reads in
+/// a row timed out. While the device stays unreachable, attempts back off
+/// as configured by `reconnect`. A reopened connection starts the device's
+/// "since reconnect" statistics over, and with `reset_stats_on_reconnect`
+/// its success ratio windows too; the first one is identified again, as
+/// the device may have been replaced.
+///
+/// Returns whether the first connection, which also carries writes, is up.
+async fn reconnect_lost(
+    clients: &mut [crate::modbus::ModbusClient],
+    config: &crate::config::DeviceConfig,
+    shared: &SharedAccess,
+    sinks: &PollSinks,
+    reconnect: &mut Reconnect,
+) -> bool {
+    let after_failures = Some(config.reconnect.after_failures).filter(|&n| n > 0);
+    for (index, client) in clients.iter_mut().enumerate() {
+        if client.is_connected() {
+            if after_failures.is_some_and(|n| client.consecutive_failures() >= n) {
+                tracing::warn!(
+                    "Device {}: {} reads in a row failed, reconnecting",
+                    config.id,
+                    client.consecutive_failures()
+                );
+            } else if config
+                .reconnect_after_timeouts
+                .is_some_and(|n| client.consecutive_timeouts() >= n)
+            {
+                tracing::warn!(
+                    "Device {}: {} reads in a row timed out, reconnecting",
+                    config.id,
+                    client.consecutive_timeouts()
+                );
+            } else {
+                continue;
+            }
+            metrics::record_forced_reconnect(&config.id);
+            client.disconnect().await;
+        }
+
+        if !reconnect.due(Instant::now()) {
+            continue;
+        }
+        match crate::modbus::ModbusClient::connect(config, shared).await {
+            Ok(new_client) => {
+                *client = new_client;
+                reconnect.succeeded();
+                metrics::record_reconnected(&config.id, config.reset_stats_on_reconnect);
+                if config.reset_stats_on_reconnect {
+                    sinks.health.reset_device(&config.id);
+                }
+                if index == 0 {
+                    identify_device(client, &config.id, &sinks.device_info).await;
+                }
+            }
+            Err(e) => match reconnect.failed(Instant::now()) {
+                Some(delay) => tracing::warn!(
+                    "Device {}: reconnect attempt {} failed, retrying in {:?}: {:#}",
+                    config.id,
+                    reconnect.failed_attempts,
+                    delay,
+                    e
+                ),
+                None => tracing::error!(
+                    "Device {}: giving up after {} reconnect attempts: {:#}",
+                    config.id,
+                    reconnect.failed_attempts,
+                    e
+                ),
+            },
+        }
+    }
+    clients[0].is_connected()
+}
+
+/// Close the device's connections for the next [`reconnect_lost`] to reopen
+async fn force_reconnect(clients: &mut [crate::modbus::ModbusClient], device_id: &str) {
+    for client in clients.iter_mut().filter(|client| client.is_connected()) {
+        metrics::record_forced_reconnect(device_id);
+        client.disconnect().await;
+    }
+}
+
+/// Add the points of the device's SunSpec models to its registers
+///
+/// Configured registers keep precedence over discovered ones of the same
+/// name. If discovery fails only the configured registers are polled.
+async fn add_sunspec_registers(
+    client: &mut crate::modbus::ModbusClient,
+    config: &mut crate::config::DeviceConfig,
+    base_address: Option<u16>,
+) {
+    match client.discover_sunspec(base_address).await {
+        Ok(discovered) => {
+            for register in discovered {
+                if config.registers.iter().all(|r| r.name != register.name) {
+                    config.registers.push(register);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Device {}: SunSpec discovery failed: {:#}", config.id, e),
+    }
+}
+
+/// Serial buses of the RTU ports configured for more than one device,
+/// keyed by port
+fn shared_buses(devices: &[crate::config::DeviceConfig]) -> HashMap<&str, SerialBus> {
+    let mut ports: HashMap<&str, Vec<&crate::config::RtuConnection>> = HashMap::new();
+    for device in devices {
+        if let ConnectionConfig::Rtu(rtu) = &device.connection {
+            ports.entry(rtu.port.as_str()).or_default().push(rtu);
+        }
+    }
+    ports
+        .into_iter()
+        .filter(|(_, devices)| devices.len() > 1)
+        .map(|(port, devices)| {
+            info!("Serial bus {} is shared by {} devices", port, devices.len());
+            (port, SerialBus::new(devices[0]))
+        })
+        .collect()
+}
+
+/// Open the device's first connection, retrying with backoff while it is
+/// unreachable
+///
+/// The device is reported offline after the first failure. Returns `None`
+/// when shutdown is requested while waiting.
+async fn connect_with_retry(
+    config: &crate::config::DeviceConfig,
+    shared: &SharedAccess,
+    mqtt: Option<&MqttPublisher>,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<Option<crate::modbus::ModbusClient>> {
+    let retry = &config.connect_retry;
+    let mut failed_attempts: u32 = 0;
+
+    loop {
+        let result = tokio::select! {
+            client = crate::modbus::ModbusClient::connect(config, shared) => client,
+            _ = shutdown_requested(shutdown) => return Ok(None),
+        };
+        let e = match result {
+            Ok(client) => return Ok(Some(client)),
+            Err(e) => e,
+        };
+
+        failed_attempts = failed_attempts.saturating_add(1);
+        if failed_attempts == 1 {
+            announce_device_status(&config.id, false, mqtt).await;
+        }
+        if !retry.should_retry(failed_attempts) {
+            return Err(e.context(format!(
+                "Device {}: giving up after {} connection attempts",
+                config.id, failed_attempts
+            )));
+        }
+
+        let delay = retry.delay(failed_attempts);
+        tracing::warn!(
+            "Device {}: connection attempt {} failed, retrying in {:?}: {:#}",
+            config.id,
+            failed_attempts,
+            delay,
+            e
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown_requested(shutdown) => return Ok(None),
+        }
+    }
+}
+
+/// Delay of each device's first poll cycle
+///
+/// With `stagger`, the devices sharing a poll interval start at equal steps
+/// across it, e.g. four devices polled every second at 0, 250, 500 and
+/// 750 ms, so their reads don't all hit the network and the register store
+/// at the same moment. Otherwise every device starts at once.
+fn poll_offsets(
+    devices: &[crate::config::DeviceConfig],
+    stagger: bool,
+) -> Vec<std::time::Duration> {
+    let mut per_interval: HashMap<u64, u32> = HashMap::new();
+    for device in devices {
+        *per_interval.entry(device.poll_interval_ms).or_default() += 1;
+    }
+    let mut started: HashMap<u64, u32> = HashMap::new();
+    devices
+        .iter()
+        .map(|device| {
+            if !stagger {
+                return std::time::Duration::ZERO;
+            }
+            let interval = std::time::Duration::from_millis(device.poll_interval_ms);
+            let index = started.entry(device.poll_interval_ms).or_default();
+            let offset = interval * *index / per_interval[&device.poll_interval_ms];
+            *index += 1;
+            offset
+        })
+        .collect()
+}
+
+/// Time between poll ticks: the poll interval, or an equal share of it per
+/// register when reads are spread
+fn tick_period(config: &crate::config::DeviceConfig) -> std::time::Duration {
+    let interval = std::time::Duration::from_millis(config.poll_interval_ms);
+    match config.registers.len() {
+        n if config.spread_reads && n > 1 => interval / n as u32,
+        _ => interval,
+    }
+}
+
+/// Whether the failed reads of a cycle reach the device's offline threshold
+fn exceeds_error_ratio(failed: usize, total: usize, ratio: f64) -> bool {
+    failed > 0 && total > 0 && failed as f64 / total as f64 >= ratio
+}
+
+/// Record a device's online state and publish it to MQTT if enabled
+async fn announce_device_status(device_id: &str, online: bool, mqtt: Option<&MqttPublisher>) {
+    metrics::record_device_status(device_id, online);
+
+    if let Some(mqtt) = mqtt {
+        if let Err(e) = mqtt.publish_status(device_id, online).await {
+            tracing::error!("Failed to publish status for {}: {}", device_id, e);
+        }
+    }
+}
+
+/// Read the given registers once, one batch per connection at a time,
+/// running writes that arrive in between before the next batch
+///
+/// Control actions thus wait for at most one in-flight read instead of the
+/// whole sweep. Returns the number of registers that failed to read.
+async fn poll_registers_preemptible(
+    clients: &mut [crate::modbus::ModbusClient],
+    config: &crate::config::DeviceConfig,
+    registers: &[crate::config::RegisterConfig],
+    sinks: &PollSinks,
+    writes: &mut mpsc::Receiver<WriteRequest>,
+) -> usize {
+    let mut failed = 0;
+    for batch in block_batches(&register_blocks(config, registers), clients.len().max(1)) {
+        run_pending_writes(&mut clients[0], config, Some(&mut *writes)).await;
+        failed += poll_registers_concurrently(clients, config, &registers[batch], sinks).await;
+    }
+    failed
+}
+
+/// Execute the writes already waiting for the device
+async fn run_pending_writes(
+    client: &mut crate::modbus::ModbusClient,
+    config: &crate::config::DeviceConfig,
+    writes: Option<&mut mpsc::Receiver<WriteRequest>>,
+) {
+    let Some(writes) = writes else {
+        return;
+    };
+    while let Ok(request) = writes.try_recv() {
+        execute_write(client, config, request).await;
+    }
+}
+
+/// Read the given registers once, split evenly across the device's
+/// connections which are read concurrently
+///
+/// Blocks are never split between connections. Returns the number of
+/// registers that failed to read.
+async fn poll_registers_concurrently(
+    clients: &mut [crate::modbus::ModbusClient],
+    config: &crate::config::DeviceConfig,
+    registers: &[crate::config::RegisterConfig],
+    sinks: &PollSinks,
+) -> usize {
+    let blocks = register_blocks(config, registers);
+    let chunk_size = blocks.len().div_ceil(clients.len()).max(1);
+    let polls = clients
+        .iter_mut()
+        .zip(block_batches(&blocks, chunk_size))
+        .map(|(client, batch)| poll_registers(client, config, &registers[batch], sinks));
+
+    futures_util::future::join_all(polls)
+        .await
+        .into_iter()
+        .sum()
+}
+
+/// Store new values of a device and broadcast them
+async fn publish_values(device_id: &str, values: Vec<RegisterValue>, sinks: &PollSinks) {
+    // Registers a reload removed are polled until restart, but not kept
+    let values: Vec<RegisterValue> = values
+        .into_iter()
+        .filter(|value| !sinks.removed.contains(device_id, &value.name))
+        .collect();
+
+    // Store the values and decide once, for every output, which of them
+    // changed
+    let mut updates = Vec::with_capacity(values.len());
+    {
+        let mut store = sinks.store.write().await;
+        let device_map = store.entry(device_id.to_string()).or_default();
+        for reg_value in values {
+            sinks.history.record(device_id, &reg_value);
+            let previous = device_map.insert(reg_value.name.clone(), reg_value.clone());
+            updates.push(sinks.deadbands.update(device_id, reg_value, previous));
+        }
+    }
+
+    // Broadcast to WebSocket/stream clients (and MQTT if enabled)
+    for update in updates {
+        let change = update.value_change();
+        let send = match &sinks.reporter {
+            Some(reporter) => reporter.should_send(&update, Instant::now()),
+            None => !sinks.deadbands.suppresses(&update),
+        };
+        if send {
+            let _ = sinks.updates.send(update);
+        }
+        if let Some(change) = change {
+            let _ = sinks.changes.send(change);
+        }
+    }
+}
+
+/// Compute a device's virtual registers from the current values of the
+/// registers they reference, in dependency order
+///
+/// A virtual register whose inputs have not been read yet, or whose result
+/// is not a finite number, is skipped this cycle.
+async fn compute_virtual_registers(
+    device_id: &str,
+    registers: &[(crate::config::RegisterConfig, Expr)],
+    sinks: &PollSinks,
+) {
+    if registers.is_empty() {
+        return;
+    }
+
+    let mut values = Vec::with_capacity(registers.len());
+    {
+        let store = sinks.store.read().await;
+        let current = store.get(device_id);
+        // Virtual registers computed earlier in this cycle
+        let mut computed: HashMap<&str, f64> = HashMap::new();
+        for (register, expr) in registers {
+            let lookup = |name: &str| {
+                computed.get(name).copied().or_else(|| {
+                    current?
+                        .get(name)
+                        .filter(|value| value.quality != Quality::Bad && value.text.is_none())
+                        .map(|value| value.value)
+                })
+            };
+            let Some(decoded) = expr.eval(&lookup).filter(|v| v.is_finite()) else {
+                tracing::debug!(
+                    "Device {}: virtual register {} not computed, inputs missing or result not finite",
+                    device_id,
+                    register.name
+                );
+                continue;
+            };
+
+            let value = reader::scale_value(decoded, register);
+            computed.insert(&register.name, value);
+            values.push(RegisterValue {
+                name: register.name.clone(),
+                raw: vec![],
+                decoded,
+                value,
+                text: None,
+                unit: register.unit.clone(),
+                quality: Quality::Good,
+                timestamp: chrono::Utc::now(),
+            });
+        }
+    }
+
+    publish_values(device_id, values, sinks).await;
+}
+
+/// Current value of a device's scale factor register, if its last read
+/// succeeded
+async fn scale_factor(store: &RegisterStore, device_id: &str, register: &str) -> Option<f64> {
+    store
+        .read()
+        .await
+        .get(device_id)?
+        .get(register)
+        .filter(|value| value.quality != Quality::Bad)
+        .map(|value| value.value)
+}
+
+/// Read the given registers of a device once
+///
+/// With `block_reads`, adjacent registers are read with one request per
+/// block. Returns the number of registers that failed to read.
+async fn poll_registers(
+    client: &mut crate::modbus::ModbusClient,
+    config: &crate::config::DeviceConfig,
+    registers: &[crate::config::RegisterConfig],
+    sinks: &PollSinks,
+) -> usize {
+    let device_id = &config.id;
+    let mut failed = 0;
+
+    for block in register_blocks(config, registers) {
+        let block = &registers[block];
+        // Start metrics timing
+        let read_metrics: Vec<_> = block
+            .iter()
+            .map(|register| ReadMetrics::start(device_id, &register.name))
+            .collect();
+
+        let results = match block {
+            [register] => vec![client
+                .read_registers(register)
+                .await
+                .map_err(ReadFailure::from)],
+            _ => read_block(client, block).await,
+        };
+        for ((register, read_metrics), result) in block.iter().zip(read_metrics).zip(results) {
+            if !record_read(device_id, register, read_metrics, result, sinks).await {
+                failed += 1;
+            }
+        }
+    }
+
+    failed
+}
+
+/// Blocks of `registers` read with one request each, as index ranges
+///
+/// Without `block_reads` every register is a block of its own.
+fn register_blocks(
+    config: &crate::config::DeviceConfig,
+    registers: &[crate::config::RegisterConfig],
+) -> Vec<std::ops::Range<usize>> {
+    match &config.block_reads {
+        Some(blocks) => reader::read_blocks(registers, blocks),
+        None => (0..registers.len()).map(|i| i..i + 1).collect(),
+    }
+}
+
+/// Consecutive blocks grouped into batches of up to `per_batch` blocks, as
+/// index ranges of the registers they cover
+fn block_batches(
+    blocks: &[std::ops::Range<usize>],
+    per_batch: usize,
+) -> Vec<std::ops::Range<usize>> {
+    blocks
+        .chunks(per_batch)
+        .map(|batch| batch[0].start..batch[batch.len() - 1].end)
+        .collect()
+}
+
+/// Why a register could not be read
+#[derive(Debug, Clone)]
+struct ReadFailure {
+    /// Error type recorded in the read metrics
+    kind: &'static str,
+    message: String,
+}
+
+impl From<anyhow::Error> for ReadFailure {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            kind: crate::modbus::error_type(&error),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Read a block of registers with one request and cut the response back
+/// into the registers' words
+async fn read_block(
+    client: &mut crate::modbus::ModbusClient,
+    block: &[crate::config::RegisterConfig],
+) -> Vec<Result<Vec<u16>, ReadFailure>> {
+    let request = reader::block_request(block);
+    match client.read_registers(&request).await {
+        Ok(words) => reader::slice_block(request.address, &words, block)
+            .into_iter()
+            .map(|(raw, quality)| match quality {
+                Quality::Bad => Err(ReadFailure {
+                    kind: "length_mismatch",
+                    message: format!(
+                        "block read of {} words returned only {}",
+                        request.count,
+                        words.len()
+                    ),
+                }),
+                _ => Ok(raw),
+            })
+            .collect(),
+        Err(e) => {
+            let failure = ReadFailure::from(e);
+            block.iter().map(|_| Err(failure.clone())).collect()
+        }
+    }
+}
+
+/// Decode, record and publish the outcome of reading one register
+///
+/// Returns whether the register was read successfully.
+async fn record_read(
+    device_id: &str,
+    register: &crate::config::RegisterConfig,
+    read_metrics: ReadMetrics,
+    result: Result<Vec<u16>, ReadFailure>,
+    sinks: &PollSinks,
+) -> bool {
+    let raw_values = match result {
+        Ok(raw_values) => raw_values,
+        Err(failure) => {
+            // Record failed read metrics
+            read_metrics.failure(failure.kind);
+            sinks.health.record(device_id, &register.name, false);
+
+            tracing::error!(
+                "Failed to read register {} from {}: {}",
+                register.name,
+                device_id,
+                failure.message
+            );
+            publish_fault(device_id, register, sinks).await;
+            return false;
+        }
+    };
+
+    // A value the device encoded wrongly is bad, not just odd
+    if let Err(reason) = reader::validate_raw(&raw_values, register) {
+        read_metrics.failure("invalid_value");
+        sinks.health.record(device_id, &register.name, false);
+
+        tracing::warn!(
+            "Bad value for register {} from {}: {}",
+            register.name,
+            device_id,
+            reason
+        );
+        publish_fault(device_id, register, sinks).await;
+        return false;
+    }
+
+    let decoded = reader::decode_value(&raw_values, register);
+    let scaled = match &register.scale_from_register {
+        Some(source) => {
+            let exponent = scale_factor(&sinks.store, device_id, source).await;
+            match exponent.and_then(|e| reader::apply_scale_factor(decoded, e)) {
+                Some(scaled) => scaled,
+                None => {
+                    read_metrics.failure("missing_scale_factor");
+                    sinks.health.record(device_id, &register.name, false);
+
+                    tracing::warn!(
+                        "No usable scale factor '{}' for register {} from {}: {:?}",
+                        source,
+                        register.name,
+                        device_id,
+                        exponent
+                    );
+                    publish_fault(device_id, register, sinks).await;
+                    return false;
+                }
+            }
+        }
+        None => decoded,
+    };
+    let value = reader::scale_value(scaled, register);
+    let (text, quality) = match reader::decode_text(&raw_values, register) {
+        Some((text, quality)) => (Some(text), quality),
+        None => (None, Quality::Good),
+    };
+    if quality != Quality::Good {
+        tracing::warn!(
+            "Register {} from {} decoded with replacement characters: {:?}",
+            register.name,
+            device_id,
+            text
+        );
+    }
+
+    // Record successful read metrics
+    read_metrics.success(value);
+    sinks.health.record(device_id, &register.name, true);
+
+    let reg_value = RegisterValue {
+        name: register.name.clone(),
+        raw: raw_values.clone(),
+        decoded,
+        value,
+        text,
+        unit: register.unit.clone(),
+        quality,
+        timestamp: chrono::Utc::now(),
+    };
+
+    // Derived per-word diagnostics come from the same read
+    let mut values = if register.expose_words {
+        reader::word_values(&reg_value)
+    } else {
+        vec![]
+    };
+    if register.count_edges {
+        values.push(sinks.edges.record(device_id, &reg_value));
+    }
+    values.insert(0, reg_value);
+    publish_values(device_id, values, sinks).await;
+
+    tracing::debug!(
+        "Device {} register {} = {} {:?}",
+        device_id,
+        register.name,
+        value,
+        register.unit
+    );
+    true
+}
+
+/// Replace a register's value with its `fault_value` after a failed read,
+/// if it has one, so consumers don't mistake the last good value for a
+/// current one
+async fn publish_fault(
+    device_id: &str,
+    register: &crate::config::RegisterConfig,
+    sinks: &PollSinks,
+) {
+    let Some(fault_value) = register.fault_value else {
+        return;
+    };
+    let value = RegisterValue {
+        name: register.name.clone(),
+        raw: vec![],
+        decoded: f64::NAN,
+        value: fault_value,
+        text: None,
+        unit: register.unit.clone(),
+        quality: Quality::Bad,
+        timestamp: chrono::Utc::now(),
+    };
+    publish_values(device_id, vec![value], sinks).await;
+}
+
+/// Publish every stored register each time the MQTT broker connection is
+/// established, so retained topics are repopulated without waiting for the
+/// next poll
+async fn republish_on_connect(
+    publisher: &MqttPublisher,
+    store: &RegisterStore,
+    connects: &mut watch::Receiver<u64>,
+) {
+    while connects.changed().await.is_ok() {
+        let updates = stored_updates(store).await;
+        info!(
+            "Republishing {} registers after connecting to the MQTT broker",
+            updates.len()
+        );
+        for update in &updates {
+            if let Err(e) = publisher.publish_update(update).await {
+                tracing::error!("MQTT republish error: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Updates replaying the current value of every stored register, ordered by
+/// device and register
+async fn stored_updates(store: &RegisterStore) -> Vec<RegisterUpdate> {
+    let store = store.read().await;
+    let mut updates: Vec<RegisterUpdate> = store
+        .iter()
+        .flat_map(|(device_id, registers)| {
+            registers
+                .values()
+                .map(|value| register_update(device_id, value.clone(), Some(value)))
+        })
+        .collect();
+    updates.sort_by(|a, b| (&a.device_id, &a.register_name).cmp(&(&b.device_id, &b.register_name)));
+    updates
+}
+
+/// Build the update published for a new reading, with its change state
+/// relative to the previous reading
+fn register_update(
+    device_id: &str,
+    current: RegisterValue,
+    previous: Option<&RegisterValue>,
+) -> RegisterUpdate {
+    let changed = previous.is_none_or(|previous| reader::value_changed(previous, &current));
+    RegisterUpdate {
+        device_id: device_id.to_string(),
+        old_value: previous.map(reader::output_value),
+        changed,
+        register_name: current.name,
+        value: current.value,
+        decoded: current.decoded,
+        raw: current.raw,
+        text: current.text,
+        unit: current.unit,
+        quality: current.quality,
+        timestamp: current.timestamp.to_rfc3339(),
+    }
+}
+
+/// Execute a write request on the device's connection and report the result
+async fn execute_write(
+    client: &mut crate::modbus::ModbusClient,
+    config: &crate::config::DeviceConfig,
+    request: WriteRequest,
+) {
+    let wait = request.queued_at.elapsed();
+    metrics::record_write_wait(&request.device_id, wait);
+    info!(
+        "Write request received: {}@{} = {:?} (mask: {:?}, pulse: {:?}ms, queued {:?})",
+        request.device_id, request.address, request.values, request.mask, request.pulse_ms, wait
+    );
+
+    let value = request.values.first().copied().unwrap_or(0);
+    let result = match (request.register_type, request.mask, request.pulse_ms) {
+        (RegisterType::Coil, Some(_), _) => Err(anyhow::anyhow!(
+            "A mask cannot be written to coil {}",
+            request.address
+        )),
+        (RegisterType::Coil, None, Some(pulse_ms)) => {
+            client
+                .pulse_coil(
+                    request.address,
+                    value != 0,
+                    std::time::Duration::from_millis(pulse_ms),
+                )
+                .await
+        }
+        (_, _, Some(_)) => Err(anyhow::anyhow!(
+            "Only coils can be pulsed, {} is not one",
+            request.address
+        )),
+        (RegisterType::Coil, None, None) => client.write_coil(request.address, value != 0).await,
+        (_, Some(mask), None) => {
+            client
+                .set_bits(request.address, mask, value, config.mask_write)
+                .await
+        }
+        (_, None, None) if request.values.len() > 1 => {
+            client
+                .write_registers(request.address, &request.values)
+                .await
+        }
+        (_, None, None) => client.write_register(request.address, value).await,
+    };
+
+    if let Err(e) = &result {
+        tracing::error!(
+            "Write to register {} on {} failed: {}",
+            request.address,
+            request.device_id,
+            e
+        );
+    }
+
+    let _ = request.response_tx.send(result.map_err(|e| e.to_string()));
+}
+
+/// Execute an on-demand read on the device's connection and report the result
+async fn execute_raw_read(client: &mut crate::modbus::ModbusClient, request: RawReadRequest) {
+    tracing::debug!(
+        "Raw read request received: {} {:?}@{} x{} (unit {:?})",
+        request.device_id,
+        request.register_type,
+        request.address,
+        request.count,
+        request.unit_id
+    );
+
+    let result = client
+        .read_raw(
+            request.register_type,
+            request.address,
+            request.count,
+            request.unit_id,
+            request.timeout,
+        )
+        .await
+        .map_err(|e| {
+            if crate::modbus::is_exception(&e) {
+                RawReadError::Exception(e.to_string())
+            } else {
+                RawReadError::Failed(e.to_string())
+            }
+        });
+    let _ = request.response_tx.send(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_log_level_applies_within_its_span() {
+        use crate::config::{ConfigBuilder, DeviceBuilder};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::EnvFilter;
+
+        /// Counts the events that pass the filter
+        struct Counter(Arc<AtomicUsize>);
+        impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Counter {
+            fn on_event(
+                &self,
+                _: &tracing::Event<'_>,
+                _: tracing_subscriber::layer::Context<'_, S>,
+            ) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let config = ConfigBuilder::new()
+            .device(DeviceBuilder::tcp("flaky", "localhost", 502, 1).log_level("debug"))
+            .device(DeviceBuilder::tcp("steady", "localhost", 502, 1))
+            .build()
+            .unwrap();
+        let directives = device_log_directives(&config);
+        assert_eq!(directives.len(), 1);
+
+        let filter = directives
+            .into_iter()
+            .fold(EnvFilter::new("info"), EnvFilter::add_directive);
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(Counter(count.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            device_span("flaky").in_scope(|| tracing::debug!("shown"));
+            device_span("steady").in_scope(|| tracing::debug!("hidden"));
+            tracing::debug!("hidden");
+            tracing::info!("shown");
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_startup_waits_for_dependencies() {
+        let (gateway_ready, gateway_rx) = watch::channel(false);
+        let gateway = StartupOrder {
+            connected: watch::channel(false).0,
+            ready: gateway_ready,
+            dependencies: vec![],
+            poll_offset: std::time::Duration::ZERO,
+        };
+        let (meter_ready, _) = watch::channel(false);
+        let mut meter = StartupOrder {
+            connected: watch::channel(false).0,
+            ready: meter_ready,
+            dependencies: vec![("gateway".to_string(), gateway_rx)],
+            poll_offset: std::time::Duration::ZERO,
+        };
+        let (_shutdown_tx, mut shutdown) = watch::channel(false);
+
+        let waiting =
+            tokio::spawn(async move { meter.wait_for_dependencies("meter", &mut shutdown).await });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        gateway.mark_ready();
+        assert!(waiting.await.unwrap());
+
+        // A dependency that stopped is not waited for
+        let (stopped, stopped_rx) = watch::channel(false);
+        drop(stopped);
+        let (ready, _) = watch::channel(false);
+        let mut orphan = StartupOrder {
+            connected: watch::channel(false).0,
+            ready,
+            dependencies: vec![("gone".to_string(), stopped_rx)],
+            poll_offset: std::time::Duration::ZERO,
+        };
+        let (_shutdown_tx, mut shutdown) = watch::channel(false);
+        assert!(orphan.wait_for_dependencies("orphan", &mut shutdown).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_connections() {
+        let timeout = std::time::Duration::from_millis(50);
+
+        let (plc, plc_rx) = watch::channel(false);
+        let (meter, meter_rx) = watch::channel(false);
+        let devices = vec![("plc".to_string(), plc_rx), ("meter".to_string(), meter_rx)];
+        plc.send_replace(true);
+        meter.send_replace(true);
+        assert!(wait_for_connections(devices.clone(), timeout).await.is_ok());
+
+        // A device still connecting at the deadline
+        let (_slow, slow_rx) = watch::channel(false);
+        let mut pending = devices.clone();
+        pending.push(("slow".to_string(), slow_rx));
+        let error = wait_for_connections(pending, timeout).await.unwrap_err();
+        assert!(error.to_string().ends_with(": slow"), "{}", error);
+
+        // A device that gave up fails without waiting for the deadline
+        let (gave_up, gave_up_rx) = watch::channel(false);
+        drop(gave_up);
+        let mut failed = devices;
+        failed.push(("gave_up".to_string(), gave_up_rx));
+        let error = wait_for_connections(failed, std::time::Duration::from_secs(60))
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Device gave_up failed to connect"));
+    }
+
+    #[tokio::test]
+    async fn test_writes_preempt_pending_reads() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+        use crate::modbus::ModbusClient;
+
+        let config = DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .register(RegisterBuilder::holding("a", 0))
+            .register(RegisterBuilder::holding("b", 1))
+            .register(RegisterBuilder::holding("c", 2))
+            .build();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        let mut clients = vec![ModbusClient::disconnected(&config)];
+        let (writes_tx, mut writes) = mpsc::channel(4);
+        let queue_write = || {
+            let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+            writes_tx
+                .try_send(WriteRequest {
+                    device_id: "plc-001".to_string(),
+                    register_type: RegisterType::Holding,
+                    address: 1,
+                    values: vec![1],
+                    mask: None,
+                    pulse_ms: None,
+                    queued_at: Instant::now(),
+                    response_tx,
+                })
+                .unwrap();
+            response_rx
+        };
+
+        // Without priority the write waits for the cycle to finish
+        let _response = queue_write();
+        let failed =
+            poll_registers_concurrently(&mut clients, &config, &config.registers, &sinks).await;
+        assert_eq!(failed, 3);
+        assert!(writes.try_recv().is_ok());
+
+        // With priority it runs during the cycle
+        let response = queue_write();
+        let failed = poll_registers_preemptible(
+            &mut clients,
+            &config,
+            &config.registers,
+            &sinks,
+            &mut writes,
+        )
+        .await;
+        assert_eq!(failed, 3);
+        assert!(response.await.unwrap().is_err(), "write was not executed");
+        assert!(writes.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_read_publishes_fault_value() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+        use crate::modbus::ModbusClient;
+
+        let config = DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .register(RegisterBuilder::holding("temperature", 0).fault_value(-9999.0))
+            .register(RegisterBuilder::holding("pressure", 1))
+            .build();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        let mut updates = sinks.updates.subscribe();
+        let mut client = ModbusClient::disconnected(&config);
+
+        let failed = poll_registers(&mut client, &config, &config.registers, &sinks).await;
+        assert_eq!(failed, 2);
+
+        let store = sinks.store.read().await;
+        let temperature = &store["plc-001"]["temperature"];
+        assert_eq!(temperature.value, -9999.0);
+        assert_eq!(temperature.quality, Quality::Bad);
+        // Without a fault value the last good value (here none) is kept
+        assert!(!store["plc-001"].contains_key("pressure"));
+
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.register_name, "temperature");
+        assert_eq!(update.quality, Quality::Bad);
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_block_read_fails_every_register() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+        use crate::modbus::ModbusClient;
+
+        let config = DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .register(RegisterBuilder::holding("a", 0).fault_value(-1.0))
+            .register(RegisterBuilder::holding("b", 1).fault_value(-2.0))
+            .register(RegisterBuilder::input("c", 2))
+            .block_reads(0)
+            .build();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        assert_eq!(
+            register_blocks(&config, &config.registers),
+            vec![0..2, 2..3]
+        );
+        assert_eq!(block_batches(&[0..2, 2..3, 3..4], 2), vec![0..3, 3..4]);
+
+        let mut client = ModbusClient::disconnected(&config);
+        let failed = poll_registers(&mut client, &config, &config.registers, &sinks).await;
+        assert_eq!(failed, 3);
+
+        let store = sinks.store.read().await;
+        assert_eq!(store["plc-001"]["a"].value, -1.0);
+        assert_eq!(store["plc-001"]["b"].value, -2.0);
+    }
+
+    #[tokio::test]
+    async fn test_compute_virtual_registers() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+
+        let config = DeviceBuilder::tcp("meter", "localhost", 502, 1)
+            .register(RegisterBuilder::holding("phase_a", 0))
+            .register(RegisterBuilder::holding("phase_b", 1))
+            .register(RegisterBuilder::holding("phase_c", 2))
+            .register(RegisterBuilder::computed("kw", "total / 1000"))
+            .register(RegisterBuilder::computed("total", "phase_a + phase_b").unit("W"))
+            .register(RegisterBuilder::computed("all", "total + phase_c"))
+            .build();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        let reading = |name: &str, value: f64| RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+        publish_values(
+            "meter",
+            vec![reading("phase_a", 1200.0), reading("phase_b", 800.0)],
+            &sinks,
+        )
+        .await;
+
+        let virtuals = config.virtual_registers().unwrap();
+        compute_virtual_registers("meter", &virtuals, &sinks).await;
+
+        let store = sinks.store.read().await;
+        let device = &store["meter"];
+        assert_eq!(device["total"].value, 2000.0);
+        assert_eq!(device["total"].unit.as_deref(), Some("W"));
+        assert_eq!(device["kw"].value, 2.0);
+        // phase_c has not been read yet
+        assert!(!device.contains_key("all"));
+    }
+
+    #[tokio::test]
+    async fn test_values_of_removed_registers_not_stored() {
+        use crate::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+        let config = |registers: &[&str]| {
+            let device = registers.iter().enumerate().fold(
+                DeviceBuilder::tcp("meter", "localhost", 502, 1),
+                |device, (address, name)| {
+                    device.register(RegisterBuilder::holding(*name, address as u16))
+                },
+            );
+            ConfigBuilder::new().device(device).build().unwrap()
+        };
+        let removed = RemovedRegisters::new(&config(&["phase_a", "phase_b"]));
+        removed.update(&config(&["phase_a"])).unwrap();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed,
+            reporter: None,
+        };
+        let mut updates = sinks.updates.subscribe();
+        let reading = |name: &str, value: f64| RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        // The worker still reads phase_b until the gateway restarts
+        publish_values(
+            "meter",
+            vec![reading("phase_a", 1200.0), reading("phase_b", 800.0)],
+            &sinks,
+        )
+        .await;
+
+        let store = sinks.store.read().await;
+        assert!(store["meter"].contains_key("phase_a"));
+        assert!(!store["meter"].contains_key("phase_b"));
+        assert_eq!(updates.try_recv().unwrap().register_name, "phase_a");
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_failure_marks_device_unhealthy() {
+        use crate::config::{DeviceBuilder, HeartbeatMode};
+        use crate::modbus::ModbusClient;
+
+        let config = DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .heartbeat(100, 1000, HeartbeatMode::Increment)
+            .build();
+        let mut client = ModbusClient::disconnected(&config);
+        let mut heartbeat = Heartbeat::new(config.heartbeat.clone().unwrap());
+
+        // Only the first failure changes the device state
+        assert_eq!(heartbeat.beat(&mut client, "plc-001").await, Some(false));
+        assert_eq!(heartbeat.beat(&mut client, "plc-001").await, None);
+        assert!(heartbeat.failing);
+        assert_eq!(heartbeat.value, 0, "failed writes don't advance the value");
+    }
+
+    #[test]
+    fn test_tick_period_spreads_reads() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+        use std::time::Duration;
+
+        let device = DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .poll_interval_ms(1000)
+            .register(RegisterBuilder::holding("a", 0))
+            .register(RegisterBuilder::holding("b", 1))
+            .register(RegisterBuilder::holding("c", 2))
+            .register(RegisterBuilder::holding("d", 3));
+
+        assert_eq!(tick_period(&device.clone().build()), Duration::from_secs(1));
+        assert_eq!(
+            tick_period(&device.spread_reads(true).build()),
+            Duration::from_millis(250)
+        );
+
+        // A single register has nothing to spread
+        let single = DeviceBuilder::tcp("plc-002", "localhost", 502, 1)
+            .poll_interval_ms(1000)
+            .spread_reads(true)
+            .register(RegisterBuilder::holding("a", 0))
+            .build();
+        assert_eq!(tick_period(&single), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_poll_offsets() {
+        use crate::config::DeviceBuilder;
+        use std::time::Duration;
+
+        let device = |id: &str, interval_ms: u64| {
+            DeviceBuilder::tcp(id, "localhost", 502, 1)
+                .poll_interval_ms(interval_ms)
+                .build()
+        };
+        let devices = vec![
+            device("plc-1", 1000),
+            device("plc-2", 1000),
+            device("meter-1", 5000),
+            device("plc-3", 1000),
+            device("plc-4", 1000),
+            device("meter-2", 5000),
+        ];
+
+        let ms = Duration::from_millis;
+        assert_eq!(
+            poll_offsets(&devices, true),
+            [ms(0), ms(250), ms(0), ms(500), ms(750), ms(2500)]
+        );
+        assert_eq!(poll_offsets(&devices, false), [Duration::ZERO; 6]);
+    }
+
+    #[test]
+    fn test_parallel_reads_only_for_tcp() {
+        use crate::config::DeviceBuilder;
+
+        let tcp = DeviceBuilder::tcp("plc-001", "localhost", 502, 1).parallel_reads(4);
+        assert_eq!(tcp.build().read_connections(), 4);
+
+        let rtu = DeviceBuilder::rtu("meter", "/dev/ttyUSB0", 9600, 1).parallel_reads(4);
+        assert_eq!(rtu.build().read_connections(), 1);
+
+        let mut gateway = DeviceBuilder::tcp("gateway", "localhost", 502, 1)
+            .parallel_reads(4)
+            .build();
+        if let crate::config::ConnectionConfig::Tcp(tcp) = &mut gateway.connection {
+            tcp.slave_id = Some(3);
+        }
+        assert_eq!(gateway.read_connections(), 1);
+    }
+
+    #[test]
+    fn test_register_update_change_state() {
+        let reading = |value: f64| RegisterValue {
+            name: "temperature".to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        // The first reading is new to every output but not a transition
+        let first = register_update("plc-001", reading(20.0), None);
+        assert!(first.changed);
+        assert!(first.old_value.is_none());
+        assert!(first.value_change().is_none());
+
+        let same = register_update("plc-001", reading(20.0), Some(&reading(20.0)));
+        assert!(!same.changed);
+        assert_eq!(same.old_value, Some(serde_json::json!(20.0)));
+        assert!(same.value_change().is_none());
+
+        let change = register_update("plc-001", reading(21.0), Some(&reading(20.0)))
+            .value_change()
+            .unwrap();
+        assert_eq!(change.register, "temperature");
+        assert_eq!(change.old_value, serde_json::json!(20.0));
+        assert_eq!(change.new_value, serde_json::json!(21.0));
+    }
+
+    #[test]
+    fn test_deadband_measures_from_last_change() {
+        let config = crate::config::ConfigBuilder::new()
+            .device(
+                crate::config::DeviceBuilder::tcp("plc-001", "localhost", 502, 1).register(
+                    crate::config::RegisterBuilder::holding("level", 0)
+                        .deadband_percent(1.0, 0.0, 10.0),
+                ),
+            )
+            .build()
+            .unwrap();
+        let deadbands = Deadbands::new(&config.devices);
+        let reading = |name: &str, value: f64| RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        // Each reading is 0.05 above the previous one: every other one has
+        // drifted the 0.1 deadband away from the last change
+        let mut previous = None;
+        let mut changed = vec![];
+        for value in [5.0, 5.05, 5.1, 5.15, 5.2] {
+            let update = deadbands.update("plc-001", reading("level", value), previous);
+            changed.push(update.changed);
+            previous = Some(reading("level", value));
+        }
+        assert_eq!(changed, [true, false, true, false, true]);
+
+        let change = deadbands
+            .update("plc-001", reading("level", 5.3), previous)
+            .value_change()
+            .unwrap();
+        assert_eq!(change.old_value, serde_json::json!(5.2));
+
+        // Registers without a deadband report any difference
+        let update = deadbands.update(
+            "plc-001",
+            reading("other", 5.05),
+            Some(reading("other", 5.0)),
+        );
+        assert!(update.changed);
+    }
+
+    #[tokio::test]
+    async fn test_deadband_suppresses_unchanged_updates() {
+        use crate::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+        let config = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(RegisterBuilder::holding("level", 0).deadband(0.5))
+                    .register(RegisterBuilder::holding("flat", 1).deadband(0.0))
+                    .register(RegisterBuilder::holding("other", 2)),
+            )
+            .build()
+            .unwrap();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(64).0,
+            changes: broadcast::channel(64).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::new(&config.devices),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        let mut updates = sinks.updates.subscribe();
+        let reading = |name: &str, value: f64| RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        for level in [20.0, 20.2, 20.4, 20.6, 20.6, 19.9] {
+            let values = ["level", "flat", "other"]
+                .map(|name| reading(name, if name == "level" { level } else { 1.0 }));
+            publish_values("plc-001", values.to_vec(), &sinks).await;
+        }
+
+        let mut sent: HashMap<String, Vec<f64>> = HashMap::new();
+        while let Ok(update) = updates.try_recv() {
+            sent.entry(update.register_name)
+                .or_default()
+                .push(update.value);
+        }
+        // Only readings at least 0.5 from the last one sent go out
+        assert_eq!(sent["level"], [20.0, 20.6, 19.9]);
+        // Without a deadband, or with 0, every reading goes out
+        assert_eq!(sent["flat"].len(), 6);
+        assert_eq!(sent["other"].len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_stored_updates_replay_current_values() {
+        let reading = |name: &str, value: f64| RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+        let store = RegisterStore::default();
+        store.write().await.insert(
+            "plc-001".to_string(),
+            HashMap::from([
+                ("pressure".to_string(), reading("pressure", 2.0)),
+                ("flow".to_string(), reading("flow", 5.0)),
+            ]),
+        );
+
+        let updates = stored_updates(&store).await;
+        let names: Vec<_> = updates.iter().map(|u| u.register_name.as_str()).collect();
+        assert_eq!(names, ["flow", "pressure"]);
+        assert_eq!(updates[1].value, 2.0);
+        // A replay is not a change
+        assert!(updates.iter().all(|u| !u.changed));
+    }
+
+    #[test]
+    fn test_report_by_exception_heartbeat() {
+        let reporter = ExceptionReporter::new(&crate::config::ReportByExceptionConfig {
+            enabled: true,
+            heartbeat_ms: 1000,
+        });
+        let reading = |value: f64| RegisterValue {
+            name: "temperature".to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+        let unchanged = register_update("plc-001", reading(20.0), Some(&reading(20.0)));
+        let changed = register_update("plc-001", reading(21.0), Some(&reading(20.0)));
+        let start = Instant::now();
+        let after = |ms| start + std::time::Duration::from_millis(ms);
+
+        // Never sent yet, then quiet until the heartbeat is due
+        assert!(reporter.should_send(&unchanged, start));
+        assert!(!reporter.should_send(&unchanged, after(500)));
+        assert!(reporter.should_send(&changed, after(600)));
+        assert!(!reporter.should_send(&unchanged, after(1500)));
+        assert!(reporter.should_send(&unchanged, after(1600)));
+
+        // Registers are tracked separately
+        let other = register_update("plc-002", reading(20.0), Some(&reading(20.0)));
+        assert!(reporter.should_send(&other, after(1700)));
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let mut reconnect = Reconnect::new(crate::config::ConnectRetryConfig {
+            max_attempts: 8,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert!(reconnect.due(start), "first attempt is immediate");
+
+        let delays: Vec<u64> = (0..7)
+            .map(|_| reconnect.failed(start).unwrap().as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+        assert!(!reconnect.due(start + std::time::Duration::from_secs(29)));
+        assert!(reconnect.due(start + std::time::Duration::from_secs(30)));
+
+        assert_eq!(reconnect.failed(start), None, "retries used up");
+        assert!(reconnect.gave_up());
+        assert!(!reconnect.due(start + std::time::Duration::from_secs(3600)));
+
+        reconnect.succeeded();
+        assert!(reconnect.due(start));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_lost_connection() {
+        use crate::config::DeviceBuilder;
+        use crate::modbus::ModbusClient;
+
+        // Accepts connections and closes them, like a device that rebooted
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let config = DeviceBuilder::tcp("reconnect-test-plc", "127.0.0.1", port, 1).build();
+        let sinks = PollSinks {
+            store: RegisterStore::default(),
+            updates: broadcast::channel(16).0,
+            changes: broadcast::channel(16).0,
+            health: ReadHealth::new(10),
+            device_info: DeviceInfoStore::default(),
+            history: History::new(&Default::default()),
+            edges: EdgeCounters::default(),
+            deadbands: Deadbands::default(),
+            paused: PausedDevices::default(),
+            removed: RemovedRegisters::default(),
+            reporter: None,
+        };
+        let mut clients = vec![ModbusClient::disconnected(&config)];
+        let mut reconnect = Reconnect::new(config.reconnect.backoff.clone());
+
+        // Backing off after a failed attempt
+        reconnect.failed(Instant::now());
+        let shared = SharedAccess::default();
+        assert!(!reconnect_lost(&mut clients, &config, &shared, &sinks, &mut reconnect).await);
+        assert!(!clients[0].is_connected());
+
+        // The attempt once the delay has passed reopens the connection
+        reconnect.next_attempt = Some(Instant::now());
+        assert!(reconnect_lost(&mut clients, &config, &shared, &sinks, &mut reconnect).await);
+        assert!(clients[0].is_connected());
+        assert_eq!(reconnect.failed_attempts, 0);
+
+        // Too many failed reads force a reconnect through the same path
+        force_reconnect(&mut clients, &config.id).await;
+        assert!(!clients[0].is_connected());
+        assert!(reconnect_lost(&mut clients, &config, &shared, &sinks, &mut reconnect).await);
+        assert!(clients[0].is_connected());
+    }
+
+    #[test]
+    fn test_exceeds_error_ratio() {
+        // Half the registers failing reaches a 0.5 threshold
+        assert!(exceeds_error_ratio(5, 10, 0.5));
+        assert!(!exceeds_error_ratio(4, 10, 0.5));
+
+        // Only a total outage trips a 1.0 threshold
+        assert!(exceeds_error_ratio(3, 3, 1.0));
+        assert!(!exceeds_error_ratio(2, 3, 1.0));
 
-        // Record poll cycle duration
-        let cycle_duration = cycle_start.elapsed().as_millis() as u64;
-        metrics::record_poll_cycle(&device_id, cycle_duration);
+        // A clean cycle never trips, even at ratio 0
+        assert!(!exceeds_error_ratio(0, 10, 0.0));
+        assert!(!exceeds_error_ratio(0, 0, 0.5));
     }
 }
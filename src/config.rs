@@ -2,31 +2,106 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
+use crate::modbus::expression::{Expr, Formula};
+
+#[allow(dead_code)] // Library API, not used by the binary
+mod builder;
+
+#[allow(unused_imports)]
+pub use builder::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+/// Configuration schema version understood by this build
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Most decimal places a register's value can be rounded to; an f64 has no
+/// more significant digits
+pub const MAX_DECIMALS: u8 = 15;
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Configuration schema version
+    #[serde(default = "Config::current_version")]
+    pub version: u32,
     /// Server configuration
     pub server: ServerConfig,
     /// MQTT broker configuration
     pub mqtt: MqttConfig,
+    /// InfluxDB writer configuration
+    #[serde(default)]
+    pub influx: InfluxConfig,
     /// API authentication configuration
     #[serde(default)]
     pub auth: AuthConfig,
+    /// Cross-origin requests to the API from browser pages
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// How raw register words are serialized in API, WebSocket and MQTT JSON
+    #[serde(default)]
+    pub raw_format: RawFormat,
+    /// How boolean values appear in MQTT payloads, unless a register sets
+    /// its own `bool_format`
+    #[serde(default)]
+    pub bool_format: BoolFormat,
+    /// Qualified register names in API, WebSocket, stream and MQTT output,
+    /// e.g. `{device_type}.{device_id}.{name}` (default: the bare name)
+    #[serde(default)]
+    pub register_name_template: Option<String>,
+    /// Broadcast a register only when its value changes, plus a periodic
+    /// heartbeat
+    #[serde(default)]
+    pub report_by_exception: ReportByExceptionConfig,
+    /// In-memory history of recent register values
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Register history persisted to a local SQLite database
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Startup requirements
+    #[serde(default)]
+    pub startup: StartupConfig,
+    /// Prometheus metrics settings
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Identifies this gateway in metric labels, MQTT payloads and
+    /// `/api/info` (default: the hostname)
+    #[serde(default = "default_gateway_id")]
+    pub gateway_id: String,
+    /// Request rate limits shared by several devices, e.g. the devices on
+    /// one serial segment
+    #[serde(default)]
+    pub rate_limit_groups: Vec<RateLimitGroupConfig>,
     /// List of Modbus devices
     pub devices: Vec<DeviceConfig>,
 }
 
+/// A request rate limit shared by the devices that name it in
+/// `rate_limit_group`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitGroupConfig {
+    pub id: String,
+    /// Most Modbus requests per second across all devices of the group
+    pub max_reads_per_second: f64,
+}
+
 /// API Authentication configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
-    /// Enable API key authentication
+    /// Enable authentication
     #[serde(default)]
     pub enabled: bool,
-    /// List of valid API keys
+    /// How clients authenticate
+    #[serde(default)]
+    pub mode: AuthMode,
+    /// List of valid API keys (`api_key` mode)
     #[serde(default)]
     pub api_keys: Vec<String>,
+    /// Validation of bearer tokens (`jwt` mode)
+    #[serde(default)]
+    pub jwt: JwtConfig,
     /// Paths excluded from authentication (e.g., /health, /metrics)
     #[serde(default = "AuthConfig::default_exclude_paths")]
     pub exclude_paths: Vec<String>,
@@ -36,15 +111,163 @@ impl Default for AuthConfig {
     fn default() -> Self {
         Self {
             enabled: false,
+            mode: AuthMode::default(),
             api_keys: vec![],
+            jwt: JwtConfig::default(),
             exclude_paths: Self::default_exclude_paths(),
         }
     }
 }
 
+/// How API clients authenticate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// A key of `api_keys` in the `X-API-Key` header
+    #[default]
+    ApiKey,
+    /// A JSON Web Token in the `Authorization: Bearer` header
+    Jwt,
+}
+
+/// Validation of JSON Web Tokens issued by an identity provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtConfig {
+    /// Shared secret of HMAC-signed tokens (HS256, HS384, HS512)
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// JSON Web Key Set of the identity provider, for tokens signed with a
+    /// private key (RS*, PS*, ES*, EdDSA)
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Accepted `aud` claims (any audience when empty)
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    /// Accepted `iss` claims (any issuer when empty)
+    #[serde(default)]
+    pub issuers: Vec<String>,
+    /// Clock difference tolerated when checking `exp` and `nbf` (seconds)
+    #[serde(default = "JwtConfig::default_leeway_secs")]
+    pub leeway_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            jwks_url: None,
+            audiences: vec![],
+            issuers: vec![],
+            leeway_secs: Self::default_leeway_secs(),
+        }
+    }
+}
+
+impl JwtConfig {
+    fn default_leeway_secs() -> u64 {
+        60
+    }
+}
+
+/// Cross-origin (CORS) access to the API, for dashboards served from
+/// another origin; no origin is allowed by default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Origins whose pages may call the API, e.g.
+    /// `https://dashboard.example.com`
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allow every origin, method and header (for development)
+    #[serde(default)]
+    pub permissive: bool,
+}
+
+impl CorsConfig {
+    /// Problems that keep the origins from being matched
+    fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.permissive && !self.allowed_origins.is_empty() {
+            errors.push(
+                "cors.permissive and cors.allowed_origins are mutually exclusive".to_string(),
+            );
+        }
+        for origin in &self.allowed_origins {
+            // Browsers send the scheme, host and port only, without a path
+            let host = origin
+                .strip_prefix("http://")
+                .or_else(|| origin.strip_prefix("https://"));
+            let valid = host.is_some_and(|host| {
+                !host.is_empty()
+                    && !host.contains('/')
+                    && host.bytes().all(|b| b.is_ascii_graphic())
+            });
+            if !valid {
+                errors.push(format!(
+                    "cors.allowed_origins entry '{}' must be an origin like https://host:port",
+                    origin
+                ));
+            }
+        }
+        errors
+    }
+}
+
 impl AuthConfig {
     fn default_exclude_paths() -> Vec<String> {
-        vec!["/health".to_string(), "/metrics".to_string()]
+        vec![
+            "/health".to_string(),
+            "/ready".to_string(),
+            "/metrics".to_string(),
+        ]
+    }
+
+    /// Problems that keep clients from authenticating in the chosen mode
+    fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.mode != AuthMode::Jwt {
+            return errors;
+        }
+        match (&self.jwt.secret, &self.jwt.jwks_url) {
+            (Some(_), Some(_)) => errors
+                .push("auth.jwt.secret and auth.jwt.jwks_url are mutually exclusive".to_string()),
+            (None, None) => {
+                errors.push("auth.mode jwt needs auth.jwt.secret or auth.jwt.jwks_url".to_string())
+            }
+            (Some(secret), None) if secret.is_empty() => {
+                errors.push("auth.jwt.secret must not be empty".to_string())
+            }
+            (None, Some(url)) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                errors.push(format!(
+                    "auth.jwt.jwks_url must start with http:// or https://, got '{}'",
+                    url
+                ))
+            }
+            _ => {}
+        }
+        errors
+    }
+}
+
+/// Prometheus metrics configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Prefix of every metric name, e.g. `rustbridge` for
+    /// `rustbridge_register_reads_total`
+    #[serde(default = "MetricsConfig::default_prefix")]
+    pub prefix: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            prefix: Self::default_prefix(),
+        }
+    }
+}
+
+impl MetricsConfig {
+    fn default_prefix() -> String {
+        crate::metrics::DEFAULT_PREFIX.to_string()
     }
 }
 
@@ -56,6 +279,103 @@ pub struct ServerConfig {
     pub port: u16,
     /// Enable metrics endpoint
     pub metrics_enabled: bool,
+    /// How long a write request waits for the device before the API
+    /// answers with a timeout (milliseconds)
+    #[serde(default = "ServerConfig::default_write_timeout_ms")]
+    pub write_timeout_ms: u64,
+    /// Number of recent reads per register the success ratio is computed over
+    #[serde(default = "ServerConfig::default_success_ratio_window")]
+    pub success_ratio_window: usize,
+    /// Drop a device's values from the API once it has had no successful
+    /// read for this long (seconds; never when unset)
+    #[serde(default)]
+    pub device_ttl_secs: Option<u64>,
+    /// Number of API writes that may wait for the device workers
+    #[serde(default = "ServerConfig::default_write_queue_capacity")]
+    pub write_queue_capacity: usize,
+    /// What a write request does when the write queue is full
+    #[serde(default)]
+    pub write_queue_policy: WriteQueuePolicy,
+    /// How long a write waits for room in the queue under the `wait`
+    /// policy (milliseconds)
+    #[serde(default = "ServerConfig::default_write_queue_wait_ms")]
+    pub write_queue_wait_ms: u64,
+    /// File `POST /api/snapshot` writes the register store to (snapshots
+    /// are disabled when unset)
+    #[serde(default)]
+    pub snapshot_path: Option<String>,
+    /// How often WebSocket clients are pinged (seconds; 0 disables pings)
+    #[serde(default = "ServerConfig::default_ws_ping_interval_secs")]
+    pub ws_ping_interval_secs: u64,
+    /// Close a WebSocket connection after this long without any message
+    /// or pong from the client (seconds; 0 keeps idle connections open)
+    #[serde(default = "ServerConfig::default_ws_idle_timeout_secs")]
+    pub ws_idle_timeout_secs: u64,
+    /// Close WebSocket and `/api/stream` connections this long after they
+    /// were opened, active or not (seconds; never when unset)
+    #[serde(default)]
+    pub stream_max_lifetime_secs: Option<u64>,
+    /// Limit the rate of API register writes, per API key when
+    /// authentication is enabled (unlimited when unset)
+    #[serde(default)]
+    pub write_rate_limit: Option<WriteRateLimitConfig>,
+    /// Allow raw reads from other unit ids and bus scans, which reach
+    /// devices that are not configured
+    #[serde(default)]
+    pub allow_other_units: bool,
+}
+
+/// Token bucket limiting API register writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteRateLimitConfig {
+    /// Writes per second allowed on average
+    pub requests_per_second: f64,
+    /// Writes allowed at once after a quiet period
+    #[serde(default = "WriteRateLimitConfig::default_burst")]
+    pub burst: u32,
+}
+
+impl WriteRateLimitConfig {
+    fn default_burst() -> u32 {
+        1
+    }
+}
+
+/// Handling of API writes while the write queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteQueuePolicy {
+    /// Answer 503 with `Retry-After` right away
+    #[default]
+    Reject,
+    /// Wait up to `write_queue_wait_ms` for room, then answer 503
+    Wait,
+}
+
+impl ServerConfig {
+    pub(crate) fn default_write_queue_capacity() -> usize {
+        100
+    }
+
+    fn default_write_queue_wait_ms() -> u64 {
+        1000
+    }
+
+    fn default_write_timeout_ms() -> u64 {
+        5000
+    }
+
+    pub(crate) fn default_success_ratio_window() -> usize {
+        100
+    }
+
+    pub(crate) fn default_ws_ping_interval_secs() -> u64 {
+        30
+    }
+
+    pub(crate) fn default_ws_idle_timeout_secs() -> u64 {
+        90
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,452 +400,3743 @@ pub struct MqttConfig {
     pub username: Option<String>,
     /// Password (optional)
     pub password: Option<String>,
+    /// Content of device status messages
+    #[serde(default)]
+    pub status_payload: StatusPayloadConfig,
+    /// Report the bridge unhealthy while the broker is unreachable
+    #[serde(default)]
+    pub required: bool,
+    /// Periodic gateway statistics on `{topic_prefix}/gateway/diagnostics`
+    #[serde(default)]
+    pub diagnostics: DiagnosticsConfig,
+    /// Also publish value transitions to `{prefix}/{device_id}/{register}/change`
+    #[serde(default)]
+    pub change_events: bool,
+    /// Publish the current value of every register each time the broker
+    /// connection is established, repopulating retained topics
+    #[serde(default)]
+    pub republish_on_connect: bool,
+    /// Number each register topic's messages with a `seq` field
+    #[serde(default)]
+    pub sequence_numbers: bool,
+    /// Topic of the bridge's own `online`/`offline` status, also its last
+    /// will (default: `{topic_prefix}/bridge/status`)
+    #[serde(default)]
+    pub status_topic: Option<String>,
+    /// Connect to the broker over TLS (mqtts)
+    #[serde(default)]
+    pub tls: MqttTlsConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeviceConfig {
-    /// Unique device ID
-    pub id: String,
-    /// Human-readable name
-    pub name: String,
-    /// Device type: "tcp" or "rtu"
-    pub device_type: DeviceType,
-    /// Connection settings
-    pub connection: ConnectionConfig,
-    /// Polling interval in milliseconds
-    pub poll_interval_ms: u64,
-    /// Registers to read
-    pub registers: Vec<RegisterConfig>,
+/// TLS for the broker connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MqttTlsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// CA certificate (PEM) to verify the broker with instead of the
+    /// system's root certificates
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// Client certificate (PEM) for brokers that require mutual TLS
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// Private key (PEM) of `client_cert`
+    #[serde(default)]
+    pub client_key: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum DeviceType {
-    Tcp,
-    Rtu,
+impl MqttConfig {
+    /// Topic the bridge's own status is published to
+    pub fn bridge_status_topic(&self) -> String {
+        self.status_topic
+            .clone()
+            .unwrap_or_else(|| format!("{}/bridge/status", self.topic_prefix))
+    }
 }
 
+/// Writing register updates to InfluxDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum ConnectionConfig {
-    Tcp(TcpConnection),
-    Rtu(RtuConnection),
+pub struct InfluxConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Base URL of the server, e.g. `http://localhost:8086`
+    #[serde(default = "InfluxConfig::default_url")]
+    pub url: String,
+    /// Organization to write to (InfluxDB 2.x)
+    #[serde(default)]
+    pub org: Option<String>,
+    /// Bucket to write to (InfluxDB 2.x)
+    #[serde(default)]
+    pub bucket: Option<String>,
+    /// Database to write to (InfluxDB 1.x), instead of `org` and `bucket`
+    #[serde(default)]
+    pub database: Option<String>,
+    /// API token sent with every write
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Measurement the points are written to
+    #[serde(default = "InfluxConfig::default_measurement")]
+    pub measurement: String,
+    /// Longest time a point waits before its batch is written
+    #[serde(default = "InfluxConfig::default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Points written with one request; a full batch is written right away
+    #[serde(default = "InfluxConfig::default_batch_size")]
+    pub batch_size: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TcpConnection {
-    /// Host address
-    pub host: String,
-    /// Port (default: 502)
-    pub port: u16,
-    /// Modbus unit ID
-    pub unit_id: u8,
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: Self::default_url(),
+            org: None,
+            bucket: None,
+            database: None,
+            token: None,
+            measurement: Self::default_measurement(),
+            flush_interval_ms: Self::default_flush_interval_ms(),
+            batch_size: Self::default_batch_size(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RtuConnection {
-    /// Serial port path (e.g., /dev/ttyUSB0)
-    pub port: String,
-    /// Baud rate
-    pub baud_rate: u32,
-    /// Data bits
-    pub data_bits: u8,
-    /// Stop bits
-    pub stop_bits: u8,
-    /// Parity: "none", "even", "odd"
-    pub parity: String,
-    /// Modbus unit ID
-    pub unit_id: u8,
+impl InfluxConfig {
+    fn default_url() -> String {
+        "http://localhost:8086".to_string()
+    }
+
+    fn default_measurement() -> String {
+        "modbus".to_string()
+    }
+
+    fn default_flush_interval_ms() -> u64 {
+        1_000
+    }
+
+    fn default_batch_size() -> usize {
+        1_000
+    }
+
+    /// Problems that keep the writer from working
+    fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            errors.push(format!(
+                "influx.url must start with http:// or https://, got '{}'",
+                self.url
+            ));
+        }
+        match (&self.bucket, &self.database) {
+            (Some(_), Some(_)) => {
+                errors.push("influx.bucket and influx.database are mutually exclusive".to_string())
+            }
+            (None, None) => {
+                errors.push("influx needs a bucket (InfluxDB 2.x) or a database (1.x)".to_string())
+            }
+            (Some(_), None) if self.org.is_none() => {
+                errors.push("influx.bucket requires influx.org".to_string())
+            }
+            _ => {}
+        }
+        if self.measurement.is_empty() {
+            errors.push("influx.measurement must not be empty".to_string());
+        }
+        if self.flush_interval_ms == 0 || self.batch_size == 0 {
+            errors.push(
+                "influx.flush_interval_ms and influx.batch_size must be greater than 0".to_string(),
+            );
+        }
+        errors
+    }
 }
 
+/// Gateway diagnostics publishing
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RegisterConfig {
-    /// Register name
-    pub name: String,
-    /// Register address
-    pub address: u16,
-    /// Register type: "holding", "input", "coil", "discrete"
-    pub register_type: RegisterType,
-    /// Number of registers to read
-    pub count: u16,
-    /// Data type for interpretation
-    pub data_type: DataType,
-    /// Unit of measurement (optional)
-    pub unit: Option<String>,
-    /// Scaling factor (optional)
-    pub scale: Option<f64>,
-    /// Offset (optional)
-    pub offset: Option<f64>,
+pub struct DiagnosticsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Time between diagnostics messages
+    #[serde(default = "DiagnosticsConfig::default_interval_ms")]
+    pub interval_ms: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum RegisterType {
-    Holding,
-    Input,
-    Coil,
-    Discrete,
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: Self::default_interval_ms(),
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    fn default_interval_ms() -> u64 {
+        60_000
+    }
 }
 
+/// Recent values kept per register, served by the history endpoint
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum DataType {
-    U16,
-    I16,
-    U32,
-    I32,
-    F32,
-    Bool,
+pub struct HistoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Samples kept per register
+    #[serde(default = "HistoryConfig::default_depth")]
+    pub depth: usize,
+    /// Samples kept across all registers; the oldest are evicted beyond it
+    #[serde(default = "HistoryConfig::default_max_samples")]
+    pub max_samples: usize,
 }
 
-impl Default for Config {
+impl Default for HistoryConfig {
     fn default() -> Self {
         Self {
-            server: ServerConfig {
-                host: "0.0.0.0".to_string(),
-                port: 3000,
-                metrics_enabled: true,
-            },
-            mqtt: MqttConfig {
-                enabled: false,
-                host: "localhost".to_string(),
-                port: 1883,
-                client_id: "rustbridge".to_string(),
-                topic_prefix: "rustbridge".to_string(),
-                qos: 1,
-                retain: false,
-                username: None,
-                password: None,
-            },
-            auth: AuthConfig::default(),
-            devices: vec![],
+            enabled: false,
+            depth: Self::default_depth(),
+            max_samples: Self::default_max_samples(),
         }
     }
 }
 
-/// Load configuration from file or use defaults
-pub fn load_config() -> Result<Config> {
-    let config_path =
-        std::env::var("RUSTBRIDGE_CONFIG").unwrap_or_else(|_| "config.yaml".to_string());
-
-    if Path::new(&config_path).exists() {
-        let content = std::fs::read_to_string(&config_path)
-            .with_context(|| format!("Failed to read config file: {}", config_path))?;
-
-        let config: Config =
-            serde_yaml::from_str(&content).with_context(|| "Failed to parse config file")?;
+impl HistoryConfig {
+    fn default_depth() -> usize {
+        100
+    }
 
-        Ok(config)
-    } else {
-        tracing::warn!("Config file not found, using defaults");
-        Ok(Config::default())
+    fn default_max_samples() -> usize {
+        100_000
     }
 }
 
+/// Register updates recorded to a local SQLite database, served by the
+/// history endpoint instead of the in-memory history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Database file, created if missing
+    #[serde(default = "StorageConfig::default_path")]
+    pub path: String,
+    /// Rows older than this are deleted
+    #[serde(default = "StorageConfig::default_retention_hours")]
+    pub retention_hours: u64,
+    /// Longest time an update waits before its batch is written
+    #[serde(default = "StorageConfig::default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Updates written with one transaction; a full batch is written right
+    /// away
+    #[serde(default = "StorageConfig::default_batch_size")]
+    pub batch_size: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: Self::default_path(),
+            retention_hours: Self::default_retention_hours(),
+            flush_interval_ms: Self::default_flush_interval_ms(),
+            batch_size: Self::default_batch_size(),
+        }
+    }
+}
+
+impl StorageConfig {
+    fn default_path() -> String {
+        "rustbridge-history.db".to_string()
+    }
+
+    fn default_retention_hours() -> u64 {
+        24 * 7
+    }
+
+    fn default_flush_interval_ms() -> u64 {
+        1_000
+    }
+
+    fn default_batch_size() -> usize {
+        500
+    }
+
+    /// Problems that keep the storage from working
+    fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.path.trim().is_empty() {
+            errors.push("storage.path must not be empty".to_string());
+        }
+        if self.retention_hours == 0 {
+            errors.push("storage.retention_hours must be greater than 0".to_string());
+        }
+        if self.flush_interval_ms == 0 || self.batch_size == 0 {
+            errors.push(
+                "storage.flush_interval_ms and storage.batch_size must be greater than 0"
+                    .to_string(),
+            );
+        }
+        errors
+    }
+}
+
+/// Startup requirements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupConfig {
+    /// Fail startup unless every device has connected within `timeout_ms`,
+    /// instead of running with the devices that are reachable
+    #[serde(default)]
+    pub require_all_devices: bool,
+    /// Time devices have to connect when `require_all_devices` is set
+    #[serde(default = "StartupConfig::default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Longest time `/health` reports `starting` and `/ready` is not ready
+    /// while devices have not completed their first poll cycle (0: ready
+    /// at once)
+    #[serde(default = "StartupConfig::default_grace_period_ms")]
+    pub grace_period_ms: u64,
+    /// Spread the poll cycles of devices with the same `poll_interval_ms`
+    /// evenly across the interval instead of starting them all at once
+    #[serde(default)]
+    pub stagger_polls: bool,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            require_all_devices: false,
+            timeout_ms: Self::default_timeout_ms(),
+            grace_period_ms: Self::default_grace_period_ms(),
+            stagger_polls: false,
+        }
+    }
+}
+
+impl StartupConfig {
+    fn default_timeout_ms() -> u64 {
+        60_000
+    }
+
+    fn default_grace_period_ms() -> u64 {
+        60_000
+    }
+}
+
+/// Report by exception: WebSocket, stream and MQTT consumers receive a
+/// register when its value changed, and otherwise once per heartbeat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportByExceptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Longest time an unchanged register goes without being sent
+    #[serde(default = "ReportByExceptionConfig::default_heartbeat_ms")]
+    pub heartbeat_ms: u64,
+}
+
+impl Default for ReportByExceptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            heartbeat_ms: Self::default_heartbeat_ms(),
+        }
+    }
+}
+
+impl ReportByExceptionConfig {
+    fn default_heartbeat_ms() -> u64 {
+        60_000
+    }
+}
+
+/// Device status message content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusPayloadConfig {
+    /// `plain` publishes the status string as-is, `json` wraps it in an
+    /// object with device, gateway and timestamp
+    #[serde(default)]
+    pub format: StatusPayloadFormat,
+    /// Status string for a device that is online
+    #[serde(default = "StatusPayloadConfig::default_online")]
+    pub online: String,
+    /// Status string for a device that is offline
+    #[serde(default = "StatusPayloadConfig::default_offline")]
+    pub offline: String,
+}
+
+impl Default for StatusPayloadConfig {
+    fn default() -> Self {
+        Self {
+            format: StatusPayloadFormat::default(),
+            online: Self::default_online(),
+            offline: Self::default_offline(),
+        }
+    }
+}
+
+impl StatusPayloadConfig {
+    fn default_online() -> String {
+        "online".to_string()
+    }
+
+    fn default_offline() -> String {
+        "offline".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusPayloadFormat {
+    #[default]
+    Plain,
+    Json,
+}
+
+/// JSON representation of raw register words
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    /// Numbers, e.g. `[250]`
+    #[default]
+    Decimal,
+    /// Zero-padded hex strings, e.g. `["0x00FA"]`
+    Hex,
+}
+
+impl RawFormat {
+    /// Serialize register words in this format
+    pub fn to_json(self, raw: &[u16]) -> serde_json::Value {
+        match self {
+            RawFormat::Decimal => serde_json::json!(raw),
+            RawFormat::Hex => raw
+                .iter()
+                .map(|word| serde_json::Value::String(format!("0x{:04X}", word)))
+                .collect(),
+        }
+    }
+}
+
+/// JSON representation of boolean values (coils, discrete inputs and
+/// `bool` registers): one of the presets, or custom strings given as
+/// `{ on: "OPEN", off: "CLOSED" }`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BoolFormat {
+    Preset(BoolPreset),
+    Custom { on: String, off: String },
+}
+
+/// Built-in boolean representations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoolPreset {
+    /// `1` / `0`
+    #[default]
+    Number,
+    /// `true` / `false`
+    Bool,
+    /// `"ON"` / `"OFF"`
+    OnOff,
+}
+
+impl Default for BoolFormat {
+    fn default() -> Self {
+        BoolFormat::Preset(BoolPreset::default())
+    }
+}
+
+impl BoolFormat {
+    /// Represent a boolean state in this format
+    pub fn to_json(&self, state: bool) -> serde_json::Value {
+        match self {
+            BoolFormat::Preset(BoolPreset::Number) => u8::from(state).into(),
+            BoolFormat::Preset(BoolPreset::Bool) => state.into(),
+            BoolFormat::Preset(BoolPreset::OnOff) => if state { "ON" } else { "OFF" }.into(),
+            BoolFormat::Custom { on, off } => if state { on } else { off }.clone().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Unique device ID
+    pub id: String,
+    /// Human-readable name
+    pub name: String,
+    /// Device type: "tcp" or "rtu"
+    pub device_type: DeviceType,
+    /// Connection settings
+    pub connection: ConnectionConfig,
+    /// Polling interval in milliseconds
+    pub poll_interval_ms: u64,
+    /// Give up on a read the device has not answered after this long
+    /// (milliseconds)
+    #[serde(default = "DeviceConfig::default_read_timeout_ms")]
+    pub read_timeout_ms: u64,
+    /// Registers to read
+    pub registers: Vec<RegisterConfig>,
+    /// Device supports FC22 Mask Write Register for bit writes.
+    /// When disabled, bit writes use a read-modify-write instead.
+    #[serde(default)]
+    pub mask_write: bool,
+    /// MQTT topic prefix for this device (overrides `mqtt.topic_prefix`)
+    #[serde(default)]
+    pub mqtt_topic_prefix: Option<String>,
+    /// Log every Modbus request/response for this device (verbose, for
+    /// troubleshooting only; emitted at trace level)
+    #[serde(default)]
+    pub wire_log: bool,
+    /// Mark the device offline and reconnect when at least this fraction
+    /// (0.0-1.0) of its registers fail in one poll cycle; online again after
+    /// a healthy cycle
+    #[serde(default)]
+    pub offline_error_ratio: Option<f64>,
+    /// Pace register reads evenly across the poll interval instead of
+    /// reading them all back-to-back at the start of each cycle
+    #[serde(default)]
+    pub spread_reads: bool,
+    /// What to do when two registers of the same type share addresses
+    #[serde(default)]
+    pub register_overlaps: OverlapPolicy,
+    /// Maximum number of register reads in flight at once. Values above 1
+    /// open extra connections to Modbus TCP devices; serial lines are
+    /// always read one request at a time.
+    #[serde(default = "DeviceConfig::default_parallel_reads")]
+    pub parallel_reads: usize,
+    /// Stop polling a device that keeps failing and probe it instead
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Retries of the initial connection when the device is unreachable
+    #[serde(default)]
+    pub connect_retry: ConnectRetryConfig,
+    /// Reopening the connection after it stopped working
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// Stop a poll cycle after this long and continue with the remaining
+    /// registers in the next one
+    #[serde(default)]
+    pub max_cycle_ms: Option<u64>,
+    /// Run writes that arrive during a poll cycle between two reads instead
+    /// of after the whole cycle
+    #[serde(default = "DeviceConfig::default_prioritize_writes")]
+    pub prioritize_writes: bool,
+    /// Devices that must have completed a successful read before this one
+    /// connects
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Log level for this device's messages, overriding the global level
+    /// (`trace`, `debug`, `info`, `warn`, `error` or `off`)
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// Drop and reopen a connection after this many reads in a row timed
+    /// out, for devices that stop answering while the socket stays up
+    #[serde(default)]
+    pub reconnect_after_timeouts: Option<u32>,
+    /// Start the rolling read statistics (success ratio windows and
+    /// consecutive failures) over after every successful reconnect
+    #[serde(default)]
+    pub reset_stats_on_reconnect: bool,
+    /// Discover the device's SunSpec models after connecting and poll their
+    /// points in addition to `registers`
+    #[serde(default)]
+    pub sunspec: Option<SunSpecConfig>,
+    /// Periodically write a watchdog register the PLC monitors
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Most Modbus requests per second to this device, counting reads,
+    /// writes and retries; requests beyond it wait their turn
+    #[serde(default)]
+    pub max_reads_per_second: Option<f64>,
+    /// Rate limit group (see `rate_limit_groups`) this device's requests
+    /// also count towards
+    #[serde(default)]
+    pub rate_limit_group: Option<String>,
+    /// Read runs of adjacent registers of the same type with one request
+    /// each instead of one request per register
+    #[serde(default)]
+    pub block_reads: Option<BlockReadConfig>,
+}
+
+/// Watchdog register written periodically to show the PLC the gateway is
+/// alive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Holding register to write
+    pub address: u16,
+    /// Time between writes
+    #[serde(default = "HeartbeatConfig::default_interval_ms")]
+    pub interval_ms: u64,
+    /// How the written value changes from one write to the next
+    #[serde(default)]
+    pub mode: HeartbeatMode,
+}
+
+impl HeartbeatConfig {
+    fn default_interval_ms() -> u64 {
+        1_000
+    }
+}
+
+/// Value sequence of a heartbeat register
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartbeatMode {
+    /// 1, 2, 3, ... wrapping from 65535 to 0
+    #[default]
+    Increment,
+    /// 1, 0, 1, 0, ...
+    Toggle,
+}
+
+impl HeartbeatMode {
+    /// The value written after `previous`
+    pub fn next(self, previous: u16) -> u16 {
+        match self {
+            HeartbeatMode::Increment => previous.wrapping_add(1),
+            HeartbeatMode::Toggle => u16::from(previous == 0),
+        }
+    }
+}
+
+/// SunSpec discovery settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SunSpecConfig {
+    /// Address of the "SunS" marker (default: probe 40000, 50000 and 0)
+    #[serde(default)]
+    pub base_address: Option<u16>,
+}
+
+/// Retry policy for a device's initial connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectRetryConfig {
+    /// Give up after this many failed attempts (0 = keep retrying)
+    #[serde(default)]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    #[serde(default = "ConnectRetryConfig::default_delay_ms")]
+    pub delay_ms: u64,
+    /// Upper bound for the delay between attempts
+    #[serde(default = "ConnectRetryConfig::default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 0,
+            delay_ms: Self::default_delay_ms(),
+            max_delay_ms: Self::default_max_delay_ms(),
+        }
+    }
+}
+
+impl ConnectRetryConfig {
+    fn default_delay_ms() -> u64 {
+        1_000
+    }
+
+    fn default_max_delay_ms() -> u64 {
+        30_000
+    }
+
+    /// Delay after the given number of failed attempts (1 = first failure)
+    pub fn delay(&self, failed_attempts: u32) -> std::time::Duration {
+        let factor = 1u64 << failed_attempts.saturating_sub(1).min(20);
+        let delay = self.delay_ms.saturating_mul(factor).min(self.max_delay_ms);
+        std::time::Duration::from_millis(delay)
+    }
+
+    /// Whether another attempt is allowed after `failed_attempts` failures
+    pub fn should_retry(&self, failed_attempts: u32) -> bool {
+        self.max_attempts == 0 || failed_attempts < self.max_attempts
+    }
+}
+
+/// Reconnection of a device whose connection stopped working, e.g. after
+/// a PLC reboot or a network outage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconnectConfig {
+    /// Reopen the connection after this many reads in a row got no answer
+    /// (timeouts and transport errors, not Modbus exceptions); 0 only
+    /// reopens connections that are closed
+    #[serde(default = "ReconnectConfig::default_after_failures")]
+    pub after_failures: u32,
+    /// Backoff between attempts while the device stays unreachable
+    #[serde(flatten)]
+    pub backoff: ConnectRetryConfig,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            after_failures: Self::default_after_failures(),
+            backoff: ConnectRetryConfig::default(),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    fn default_after_failures() -> u32 {
+        3
+    }
+}
+
+/// Coalescing of adjacent registers into block reads
+///
+/// Registers next to each other in the register list are read together
+/// when they have the same type and unit id, follow each other in address
+/// order and fit into one request; the response is sliced back into the
+/// individual registers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockReadConfig {
+    /// Unused addresses allowed between two registers of a block; they are
+    /// read along and discarded
+    #[serde(default)]
+    pub max_gap: u16,
+    /// Most registers (or coils) read with one request
+    #[serde(default = "BlockReadConfig::default_max_size")]
+    pub max_size: u16,
+}
+
+impl Default for BlockReadConfig {
+    fn default() -> Self {
+        Self {
+            max_gap: 0,
+            max_size: Self::default_max_size(),
+        }
+    }
+}
+
+impl BlockReadConfig {
+    fn default_max_size() -> u16 {
+        crate::modbus::MAX_READ_COUNT
+    }
+}
+
+/// Circuit breaker settings for a device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive poll cycles without a single successful read that open
+    /// the breaker
+    #[serde(default = "CircuitBreakerConfig::default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Time between probes while the breaker is open
+    #[serde(default = "CircuitBreakerConfig::default_probe_interval_ms")]
+    pub probe_interval_ms: u64,
+}
+
+impl CircuitBreakerConfig {
+    fn default_failure_threshold() -> u32 {
+        5
+    }
+
+    fn default_probe_interval_ms() -> u64 {
+        30_000
+    }
+}
+
+impl DeviceConfig {
+    fn default_parallel_reads() -> usize {
+        1
+    }
+
+    fn default_prioritize_writes() -> bool {
+        true
+    }
+
+    fn default_read_timeout_ms() -> u64 {
+        2_000
+    }
+
+    /// Unit id requests are addressed to unless a register overrides it
+    pub fn unit_id(&self) -> u8 {
+        match &self.connection {
+            ConnectionConfig::Tcp(tcp) => tcp.slave_id.unwrap_or(tcp.unit_id),
+            ConnectionConfig::Rtu(rtu) => rtu.unit_id,
+        }
+    }
+
+    /// Whether requests go out as RTU frames (serial, or tunnelled over TCP)
+    pub fn uses_rtu_framing(&self) -> bool {
+        match &self.connection {
+            ConnectionConfig::Tcp(tcp) => tcp.slave_id.is_some(),
+            ConnectionConfig::Rtu(_) => true,
+        }
+    }
+
+    /// Number of connections the device is polled over
+    pub fn read_connections(&self) -> usize {
+        match &self.connection {
+            _ if self.uses_rtu_framing() => 1,
+            _ => self.parallel_reads.max(1),
+        }
+    }
+
+    /// The device's virtual registers with their parsed expressions, each
+    /// after the virtual registers it references
+    ///
+    /// Fails with every unparsable expression, unknown or string register
+    /// reference and reference cycle.
+    pub fn virtual_registers(&self) -> Result<Vec<(RegisterConfig, Expr)>, Vec<String>> {
+        let mut errors = Vec::new();
+        let mut parsed = Vec::new();
+        for register in &self.registers {
+            if register.register_type != RegisterType::Virtual {
+                continue;
+            }
+            let Some(source) = &register.expression else {
+                errors.push(format!(
+                    "register '{}' is virtual but has no expression",
+                    register.name
+                ));
+                continue;
+            };
+            let expr = match Expr::parse(source) {
+                Ok(expr) => expr,
+                Err(e) => {
+                    errors.push(format!(
+                        "register '{}' expression '{}' is invalid: {}",
+                        register.name, source, e
+                    ));
+                    continue;
+                }
+            };
+            for name in expr.references() {
+                // Edge counts are published alongside their input
+                let edges_of = name.strip_suffix(".edges");
+                let referenced = self.registers.iter().find(|r| {
+                    r.name == name || (r.count_edges && Some(r.name.as_str()) == edges_of)
+                });
+                match referenced {
+                    None => errors.push(format!(
+                        "register '{}' expression references unknown register '{}'",
+                        register.name, name
+                    )),
+                    Some(r) if matches!(r.data_type, DataType::String) => errors.push(format!(
+                        "register '{}' expression references string register '{}'",
+                        register.name, name
+                    )),
+                    Some(_) => {}
+                }
+            }
+            parsed.push((register, expr));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // Depth-first, emitting a register once everything it references is
+        fn visit<'a>(
+            index: usize,
+            parsed: &'a [(&'a RegisterConfig, Expr)],
+            path: &mut Vec<usize>,
+            done: &mut Vec<bool>,
+            order: &mut Vec<usize>,
+        ) -> Result<(), String> {
+            if let Some(start) = path.iter().position(|&p| p == index) {
+                let mut cycle: Vec<&str> = path[start..]
+                    .iter()
+                    .map(|&p| parsed[p].0.name.as_str())
+                    .collect();
+                cycle.push(&parsed[index].0.name);
+                return Err(format!(
+                    "virtual register expressions form a cycle: {}",
+                    cycle.join(" -> ")
+                ));
+            }
+            if done[index] {
+                return Ok(());
+            }
+            path.push(index);
+            for name in parsed[index].1.references() {
+                if let Some(dependency) = parsed.iter().position(|(r, _)| r.name == name) {
+                    visit(dependency, parsed, path, done, order)?;
+                }
+            }
+            path.pop();
+            done[index] = true;
+            order.push(index);
+            Ok(())
+        }
+
+        let mut done = vec![false; parsed.len()];
+        let mut order = Vec::with_capacity(parsed.len());
+        for index in 0..parsed.len() {
+            visit(index, &parsed, &mut Vec::new(), &mut done, &mut order).map_err(|e| vec![e])?;
+        }
+        Ok(order
+            .into_iter()
+            .map(|i| (parsed[i].0.clone(), parsed[i].1.clone()))
+            .collect())
+    }
+}
+
+/// Handling of registers whose address ranges overlap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// Overlaps are intentional (e.g. a block and its parts); stay quiet
+    Allow,
+    /// Log each overlap at load time
+    #[default]
+    Warn,
+    /// Reject the configuration
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    Tcp,
+    Rtu,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConnectionConfig {
+    Tcp(TcpConnection),
+    Rtu(RtuConnection),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpConnection {
+    /// Host address
+    pub host: String,
+    /// Port (default: 502)
+    pub port: u16,
+    /// Modbus unit ID (MBAP unit identifier)
+    pub unit_id: u8,
+    /// Slave address for devices behind a transparent serial bridge.
+    /// When set, RTU frames addressed to this slave are sent over the TCP
+    /// connection instead of MBAP frames, and `unit_id` is not used.
+    #[serde(default)]
+    pub slave_id: Option<u8>,
+    /// Which resolved addresses to use when `host` is a hostname
+    #[serde(default)]
+    pub address_family: AddressFamily,
+}
+
+/// Address family selection for TCP connections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// Try addresses in the order the resolver returns them
+    #[default]
+    Any,
+    /// Try IPv4 addresses first, then IPv6
+    PreferIpv4,
+    /// Try IPv6 addresses first, then IPv4
+    PreferIpv6,
+    /// Only use IPv4 addresses
+    Ipv4,
+    /// Only use IPv6 addresses
+    Ipv6,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RtuConnection {
+    /// Serial port path (e.g., /dev/ttyUSB0)
+    pub port: String,
+    /// Baud rate
+    pub baud_rate: u32,
+    /// Data bits
+    pub data_bits: u8,
+    /// Stop bits
+    pub stop_bits: u8,
+    /// Parity: "none", "even", "odd"
+    pub parity: String,
+    /// Modbus unit ID
+    pub unit_id: u8,
+}
+
+impl RtuConnection {
+    /// Line settings the serial port cannot be opened with
+    pub fn settings_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.baud_rate == 0 {
+            errors.push("baud_rate must be greater than 0".to_string());
+        }
+        if !(5..=8).contains(&self.data_bits) {
+            errors.push(format!(
+                "data_bits {} must be between 5 and 8",
+                self.data_bits
+            ));
+        }
+        if !(1..=2).contains(&self.stop_bits) {
+            errors.push(format!("stop_bits {} must be 1 or 2", self.stop_bits));
+        }
+        if !matches!(self.parity.to_lowercase().as_str(), "none" | "even" | "odd") {
+            errors.push(format!(
+                "parity '{}' must be none, even or odd",
+                self.parity
+            ));
+        }
+        errors
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterConfig {
+    /// Register name
+    pub name: String,
+    /// Register address
+    pub address: u16,
+    /// Register type: "holding", "input", "coil", "discrete"
+    pub register_type: RegisterType,
+    /// Number of registers to read
+    pub count: u16,
+    /// Data type for interpretation
+    pub data_type: DataType,
+    /// Sign-extend the value from its lowest `signed_bits` bits, for
+    /// signed values narrower than the data type (integer types only,
+    /// e.g. 24 for a two's-complement value in the low 24 bits of a u32)
+    #[serde(default)]
+    pub signed_bits: Option<u8>,
+    /// Read only this bit (0-15) of the register's word, as 0 or 1, e.g.
+    /// one flag of a status word
+    #[serde(default)]
+    pub bit: Option<u8>,
+    /// Unit of measurement (optional)
+    pub unit: Option<String>,
+    /// Scaling factor (optional)
+    pub scale: Option<f64>,
+    /// Offset (optional)
+    pub offset: Option<f64>,
+    /// Round the final value to this many decimal places (full precision
+    /// when unset)
+    #[serde(default)]
+    pub decimals: Option<u8>,
+    /// Name of a register of the same device holding a power-of-ten
+    /// exponent (SunSpec scale factor) applied before scale and offset;
+    /// it must be listed before this register so it is read first
+    #[serde(default)]
+    pub scale_from_register: Option<String>,
+    /// Arithmetic over other registers of the device by name, e.g.
+    /// `phase_a + phase_b + phase_c` (`virtual` registers only)
+    #[serde(default)]
+    pub expression: Option<String>,
+    /// The value as a formula of the decoded number `x`, e.g.
+    /// `1 / (0.5 + 0.002 * x)`, in place of `scale` and `offset`
+    #[serde(default, alias = "expr")]
+    pub formula: Option<Formula>,
+    /// Also publish each underlying word of a multi-register value as a
+    /// read-only `<name>.word0`, `<name>.word1`, ... register
+    #[serde(default)]
+    pub expose_words: bool,
+    /// Character encoding of a `string` register
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    /// The low byte of each register comes first (`string` registers)
+    #[serde(default)]
+    pub swap_bytes: bool,
+    /// Order of the words of 32-bit values, and of the bytes within
+    /// words of numeric values
+    #[serde(default)]
+    pub word_order: WordOrder,
+    /// Read this register from another unit id than the device's
+    #[serde(default)]
+    pub unit_id: Option<u8>,
+    /// Allow writes through the API; registers are read-only unless set
+    #[serde(default)]
+    pub writable: bool,
+    /// How this register's value appears in MQTT payloads (`bool` data type
+    /// only; default: the global `bool_format`)
+    #[serde(default)]
+    pub bool_format: Option<BoolFormat>,
+    /// Publish to MQTT at most this often, sending the latest reading
+    /// (default: every reading)
+    #[serde(default)]
+    pub publish_interval_ms: Option<u64>,
+    /// Value stored and published with bad quality when a read fails
+    /// (`.nan` for NaN); the last good value is kept when unset
+    #[serde(default)]
+    pub fault_value: Option<f64>,
+    /// Count the input's 0 -> 1 transitions between reads and publish the
+    /// running total as a `<name>.edges` register (coils and discrete
+    /// inputs only)
+    #[serde(default)]
+    pub count_edges: bool,
+    /// Only report a change once the value moved at least this much from
+    /// the last reported value (default: any difference)
+    #[serde(default)]
+    pub deadband: Option<f64>,
+    /// Deadband as a percentage of the span `range_min`..`range_max`,
+    /// instead of an absolute `deadband`
+    #[serde(default)]
+    pub deadband_percent: Option<f64>,
+    /// Lowest value of the register's measuring range (`deadband_percent`)
+    #[serde(default)]
+    pub range_min: Option<f64>,
+    /// Highest value of the register's measuring range (`deadband_percent`)
+    #[serde(default)]
+    pub range_max: Option<f64>,
+    /// Limit the value to at least this (in the units of the `clamp` step)
+    #[serde(default)]
+    pub clamp_min: Option<f64>,
+    /// Limit the value to at most this (in the units of the `clamp` step)
+    #[serde(default)]
+    pub clamp_max: Option<f64>,
+    /// Order of the conversion steps after decoding (default: `scale`,
+    /// `clamp`, `round`)
+    #[serde(default)]
+    pub pipeline: Option<Vec<ConversionStep>>,
+}
+
+/// A step converting a register's decoded number into its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionStep {
+    /// Multiply by `scale` and add `offset`
+    Scale,
+    /// Limit to `clamp_min`..`clamp_max`
+    Clamp,
+    /// Round to `decimals` places
+    Round,
+}
+
+impl ConversionStep {
+    /// Steps in their default order
+    pub const DEFAULT_PIPELINE: [ConversionStep; 3] = [Self::Scale, Self::Clamp, Self::Round];
+}
+
+impl RegisterConfig {
+    /// Smallest change of the value that is reported, from either the
+    /// absolute or the percentage deadband
+    pub fn deadband_width(&self) -> Option<f64> {
+        match (self.deadband, self.deadband_percent) {
+            (Some(deadband), _) => Some(deadband),
+            (None, Some(percent)) => {
+                let span = self.range_max? - self.range_min?;
+                Some(span * percent / 100.0)
+            }
+            (None, None) => None,
+        }
+        .filter(|&width| width > 0.0)
+    }
+
+    /// Conversion steps in the order they apply
+    pub fn conversion_steps(&self) -> &[ConversionStep] {
+        self.pipeline
+            .as_deref()
+            .unwrap_or(&ConversionStep::DEFAULT_PIPELINE)
+    }
+
+    /// Problems with how the register's words are decoded and converted
+    /// into its value, independent of its device
+    pub fn conversion_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(bits) = self.signed_bits {
+            match self.data_type.integer_bits() {
+                Some(width) if (2..=width).contains(&bits) => {}
+                Some(width) => errors.push(format!(
+                    "register '{}' signed_bits must be between 2 and {}",
+                    self.name, width
+                )),
+                None => errors.push(format!(
+                    "register '{}' sets signed_bits but is not of an integer data type",
+                    self.name
+                )),
+            }
+        }
+        if let Some(bit) = self.bit {
+            if bit > 15 {
+                errors.push(format!(
+                    "register '{}' bit must be between 0 and 15",
+                    self.name
+                ));
+            }
+            let single_word = matches!(
+                self.register_type,
+                RegisterType::Holding | RegisterType::Input
+            ) && self.count == 1
+                && self.data_type.word_count() == 1;
+            if !single_word {
+                errors.push(format!(
+                    "register '{}' sets bit but is not a single holding or input register",
+                    self.name
+                ));
+            }
+            if self.signed_bits.is_some() {
+                errors.push(format!(
+                    "register '{}' sets both bit and signed_bits",
+                    self.name
+                ));
+            }
+        }
+        if self.formula.is_some() && matches!(self.data_type, DataType::String) {
+            errors.push(format!(
+                "register '{}' sets formula but is a string register",
+                self.name
+            ));
+        }
+        if let (Some(min), Some(max)) = (self.clamp_min, self.clamp_max) {
+            if min > max {
+                errors.push(format!(
+                    "register '{}' clamp_min must not be greater than clamp_max",
+                    self.name
+                ));
+            }
+        }
+        // Every step runs exactly once
+        if let Some(pipeline) = &self.pipeline {
+            for step in ConversionStep::DEFAULT_PIPELINE {
+                let times = pipeline.iter().filter(|&&s| s == step).count();
+                if times != 1 {
+                    errors.push(format!(
+                        "register '{}' pipeline must list {:?} once, not {} times",
+                        self.name, step, times
+                    ));
+                }
+            }
+        }
+        if self.decimals.is_some_and(|d| d > MAX_DECIMALS) {
+            errors.push(format!(
+                "register '{}' decimals must be at most {}",
+                self.name, MAX_DECIMALS
+            ));
+        }
+        errors
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterType {
+    Holding,
+    Input,
+    Coil,
+    Discrete,
+    /// Computed from other registers by `expression`, never read
+    Virtual,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    Bool,
+    /// Binary-coded decimal, four digits per register across `count` registers
+    Bcd,
+    /// Text, two bytes per register across `count` registers
+    String,
+}
+
+impl DataType {
+    /// Number of registers a value of this type takes; strings and BCD
+    /// have no natural width and default to one
+    pub fn word_count(&self) -> u16 {
+        match self {
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+            DataType::U16 | DataType::I16 | DataType::Bool | DataType::Bcd | DataType::String => 1,
+        }
+    }
+
+    /// Name of the data type as written in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataType::U16 => "u16",
+            DataType::I16 => "i16",
+            DataType::U32 => "u32",
+            DataType::I32 => "i32",
+            DataType::F32 => "f32",
+            DataType::Bool => "bool",
+            DataType::Bcd => "bcd",
+            DataType::String => "string",
+        }
+    }
+
+    /// The `count` a register of this type needs, if `count` is wrong for it
+    ///
+    /// 16-bit values and booleans take exactly one register; 32-bit values
+    /// need at least two and decode from the first two. Strings and BCD span
+    /// any number of registers.
+    pub fn expected_count(&self, count: u16) -> Option<&'static str> {
+        match self {
+            DataType::U32 | DataType::I32 | DataType::F32 if count < 2 => Some("2 or more"),
+            DataType::U16 | DataType::I16 | DataType::Bool if count != 1 => Some("1"),
+            DataType::Bcd | DataType::String if count == 0 => Some("1 or more"),
+            _ => None,
+        }
+    }
+
+    /// Width in bits of the integer types
+    pub fn integer_bits(&self) -> Option<u8> {
+        match self {
+            DataType::U16 | DataType::I16 => Some(16),
+            DataType::U32 | DataType::I32 => Some(32),
+            _ => None,
+        }
+    }
+}
+
+/// Order of the bytes of a value across its registers, named after the
+/// bytes `ABCD` of a 32-bit value from most to least significant
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordOrder {
+    /// High word first, the Modbus convention (`ABCD`)
+    #[default]
+    #[serde(alias = "abcd")]
+    Big,
+    /// Low word first (`CDAB`)
+    #[serde(alias = "cdab")]
+    Little,
+    /// High word first, low byte first within each word (`BADC`)
+    #[serde(alias = "badc")]
+    ByteSwap,
+    /// Low word first, low byte first within each word (`DCBA`)
+    #[serde(alias = "dcba")]
+    WordByteSwap,
+}
+
+impl WordOrder {
+    /// Whether the low word of a 32-bit value comes first
+    pub fn swaps_words(self) -> bool {
+        matches!(self, WordOrder::Little | WordOrder::WordByteSwap)
+    }
+
+    /// Whether the low byte of each word comes first
+    pub fn swaps_bytes(self) -> bool {
+        matches!(self, WordOrder::ByteSwap | WordOrder::WordByteSwap)
+    }
+}
+
+/// Character encoding of `string` registers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextEncoding {
+    /// 7-bit ASCII; other bytes are replaced
+    #[default]
+    Ascii,
+    /// ISO 8859-1, every byte is a character
+    Latin1,
+    /// UTF-8; invalid sequences are replaced
+    Utf8,
+}
+
+/// Placeholder substituted for secrets in redacted configs
+pub const REDACTED: &str = "<redacted>";
+
+impl Config {
+    fn current_version() -> u32 {
+        CONFIG_VERSION
+    }
+
+    /// Copy of the configuration with secrets replaced by a placeholder
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.mqtt.password.is_some() {
+            config.mqtt.password = Some(REDACTED.to_string());
+        }
+        if config.influx.token.is_some() {
+            config.influx.token = Some(REDACTED.to_string());
+        }
+        for key in &mut config.auth.api_keys {
+            *key = REDACTED.to_string();
+        }
+        if config.auth.jwt.secret.is_some() {
+            config.auth.jwt.secret = Some(REDACTED.to_string());
+        }
+        config
+    }
+
+    /// Check the configuration for values that deserialize but cannot work
+    ///
+    /// All problems are reported together rather than stopping at the first.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        let mut group_ids = HashSet::new();
+        for group in &self.rate_limit_groups {
+            if !group_ids.insert(group.id.as_str()) {
+                errors.push(format!("rate limit group '{}' is defined twice", group.id));
+            }
+            if !(group.max_reads_per_second.is_finite() && group.max_reads_per_second > 0.0) {
+                errors.push(format!(
+                    "rate limit group '{}': max_reads_per_second must be greater than 0",
+                    group.id
+                ));
+            }
+        }
+
+        // Values of devices and registers are keyed by id and name
+        let mut device_ids = HashSet::new();
+        for device in &self.devices {
+            if !device_ids.insert(device.id.as_str()) {
+                errors.push(format!("device id '{}' is used twice", device.id));
+            }
+        }
+
+        for device in &self.devices {
+            if device.id.trim().is_empty() {
+                errors.push(format!("device '{}' has an empty id", device.name));
+            }
+            let mut register_names = HashSet::new();
+            for register in &device.registers {
+                if !register_names.insert(register.name.as_str()) {
+                    errors.push(format!(
+                        "device '{}': register name '{}' is used twice",
+                        device.id, register.name
+                    ));
+                }
+                if register.register_type == RegisterType::Virtual {
+                    continue;
+                }
+                if register.count == 0 {
+                    errors.push(format!(
+                        "device '{}': register '{}' count must be at least 1",
+                        device.id, register.name
+                    ));
+                } else if let Some(expected) = register.data_type.expected_count(register.count) {
+                    errors.push(format!(
+                        "device '{}': register '{}' of data type {} needs count {}, got {}",
+                        device.id,
+                        register.name,
+                        register.data_type.name(),
+                        expected,
+                        register.count
+                    ));
+                }
+            }
+            if device.poll_interval_ms == 0 {
+                errors.push(format!(
+                    "device '{}': poll_interval_ms must be greater than 0",
+                    device.id
+                ));
+            }
+            if device.read_timeout_ms == 0 {
+                errors.push(format!(
+                    "device '{}': read_timeout_ms must be greater than 0",
+                    device.id
+                ));
+            }
+            if let Some(ratio) = device.offline_error_ratio {
+                if !(0.0..=1.0).contains(&ratio) {
+                    errors.push(format!(
+                        "device '{}': offline_error_ratio must be between 0.0 and 1.0, got {}",
+                        device.id, ratio
+                    ));
+                }
+            }
+
+            for dependency in &device.depends_on {
+                if dependency == &device.id {
+                    errors.push(format!("device '{}' depends on itself", device.id));
+                } else if !self.devices.iter().any(|d| &d.id == dependency) {
+                    errors.push(format!(
+                        "device '{}' depends on unknown device '{}'",
+                        device.id, dependency
+                    ));
+                }
+            }
+
+            if device.id == "gateway" && self.mqtt.diagnostics.enabled {
+                errors.push(
+                    "device id 'gateway' clashes with the MQTT diagnostics topic".to_string(),
+                );
+            }
+            if device
+                .max_reads_per_second
+                .is_some_and(|rate| !(rate.is_finite() && rate > 0.0))
+            {
+                errors.push(format!(
+                    "device '{}': max_reads_per_second must be greater than 0",
+                    device.id
+                ));
+            }
+            if let Some(group) = &device.rate_limit_group {
+                if !group_ids.contains(group.as_str()) {
+                    errors.push(format!(
+                        "device '{}': rate_limit_group '{}' is not defined in rate_limit_groups",
+                        device.id, group
+                    ));
+                }
+            }
+            if device
+                .heartbeat
+                .as_ref()
+                .is_some_and(|h| h.interval_ms == 0)
+            {
+                errors.push(format!(
+                    "device '{}': heartbeat.interval_ms must be greater than 0",
+                    device.id
+                ));
+            }
+            if let Some(breaker) = &device.circuit_breaker {
+                if breaker.failure_threshold == 0 {
+                    errors.push(format!(
+                        "device '{}': circuit_breaker.failure_threshold must be at least 1",
+                        device.id
+                    ));
+                }
+            }
+            for (name, retry) in [
+                ("connect_retry", &device.connect_retry),
+                ("reconnect", &device.reconnect.backoff),
+            ] {
+                if retry.delay_ms == 0 || retry.max_delay_ms < retry.delay_ms {
+                    errors.push(format!(
+                        "device '{}': {}.delay_ms must be at least 1 and not exceed max_delay_ms",
+                        device.id, name
+                    ));
+                }
+            }
+            if let Some(level) = &device.log_level {
+                if level
+                    .parse::<tracing::level_filters::LevelFilter>()
+                    .is_err()
+                {
+                    errors.push(format!(
+                        "device '{}': log_level '{}' must be trace, debug, info, warn, error or off",
+                        device.id, level
+                    ));
+                }
+            }
+            match device.max_cycle_ms {
+                Some(0) => errors.push(format!(
+                    "device '{}': max_cycle_ms must be greater than 0",
+                    device.id
+                )),
+                Some(_) if device.spread_reads => errors.push(format!(
+                    "device '{}': max_cycle_ms cannot be combined with spread_reads",
+                    device.id
+                )),
+                _ => {}
+            }
+            if let Some(blocks) = &device.block_reads {
+                if !(1..=crate::modbus::MAX_READ_COUNT).contains(&blocks.max_size) {
+                    errors.push(format!(
+                        "device '{}': block_reads.max_size must be between 1 and {}, got {}",
+                        device.id,
+                        crate::modbus::MAX_READ_COUNT,
+                        blocks.max_size
+                    ));
+                }
+                if device.spread_reads {
+                    errors.push(format!(
+                        "device '{}': block_reads cannot be combined with spread_reads",
+                        device.id
+                    ));
+                }
+            }
+            if device.reconnect_after_timeouts == Some(0) {
+                errors.push(format!(
+                    "device '{}': reconnect_after_timeouts must be at least 1",
+                    device.id
+                ));
+            }
+            if device.parallel_reads == 0 {
+                errors.push(format!(
+                    "device '{}': parallel_reads must be at least 1",
+                    device.id
+                ));
+            }
+
+            if let ConnectionConfig::Rtu(rtu) = &device.connection {
+                errors.extend(
+                    rtu.settings_errors()
+                        .into_iter()
+                        .map(|e| format!("device '{}': {}", device.id, e)),
+                );
+            }
+
+            // A serial slave answers only to 1-247; 0 is a broadcast
+            // without a reply
+            if device.uses_rtu_framing() {
+                for register in &device.registers {
+                    if let Some(unit_id @ (0 | 248..)) = register.unit_id {
+                        errors.push(format!(
+                            "device '{}': register '{}' has unit_id {}, RTU slaves must be 1-247",
+                            device.id, register.name, unit_id
+                        ));
+                    }
+                }
+            }
+
+            if let Err(virtual_errors) = device.virtual_registers() {
+                errors.extend(
+                    virtual_errors
+                        .into_iter()
+                        .map(|e| format!("device '{}': {}", device.id, e)),
+                );
+            }
+            for register in &device.registers {
+                let is_virtual = register.register_type == RegisterType::Virtual;
+                if register.expression.is_some() && !is_virtual {
+                    errors.push(format!(
+                        "device '{}': register '{}' sets expression but is not virtual",
+                        device.id, register.name
+                    ));
+                }
+                if is_virtual && register.writable {
+                    errors.push(format!(
+                        "device '{}': virtual register '{}' cannot be writable",
+                        device.id, register.name
+                    ));
+                }
+                errors.extend(
+                    register
+                        .conversion_errors()
+                        .into_iter()
+                        .map(|e| format!("device '{}': {}", device.id, e)),
+                );
+                if register.count_edges
+                    && !matches!(
+                        register.register_type,
+                        RegisterType::Coil | RegisterType::Discrete
+                    )
+                {
+                    errors.push(format!(
+                        "device '{}': register '{}' sets count_edges but is not a coil or discrete input",
+                        device.id, register.name
+                    ));
+                }
+                if register.count_edges && register.count > 1 {
+                    errors.push(format!(
+                        "device '{}': register '{}' with count_edges must have count 1",
+                        device.id, register.name
+                    ));
+                }
+                errors.extend(deadband_errors(&device.id, register));
+                if register.publish_interval_ms == Some(0) {
+                    errors.push(format!(
+                        "device '{}': register '{}' publish_interval_ms must be greater than 0",
+                        device.id, register.name
+                    ));
+                }
+                if let Some(source) = &register.scale_from_register {
+                    let position = device.registers.iter().position(|r| &r.name == source);
+                    let own = device
+                        .registers
+                        .iter()
+                        .position(|r| r.name == register.name);
+                    match position {
+                        None => errors.push(format!(
+                            "device '{}': register '{}' scale_from_register '{}' is not a register of this device",
+                            device.id, register.name, source
+                        )),
+                        Some(position) if position >= own.unwrap_or(0) => errors.push(format!(
+                            "device '{}': register '{}' scale_from_register '{}' must be listed before it",
+                            device.id, register.name, source
+                        )),
+                        Some(position)
+                            if matches!(device.registers[position].data_type, DataType::String) =>
+                        {
+                            errors.push(format!(
+                                "device '{}': register '{}' scale_from_register '{}' is a string register",
+                                device.id, register.name, source
+                            ))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                match &register.bool_format {
+                    Some(_) if !matches!(register.data_type, DataType::Bool) => {
+                        errors.push(format!(
+                        "device '{}': register '{}' sets bool_format but is not of data type bool",
+                        device.id, register.name
+                    ))
+                    }
+                    Some(BoolFormat::Custom { on, off }) if on == off => errors.push(format!(
+                        "device '{}': register '{}' bool_format on and off must differ",
+                        device.id, register.name
+                    )),
+                    _ => {}
+                }
+            }
+
+            for overlap in register_overlaps(device) {
+                match device.register_overlaps {
+                    OverlapPolicy::Allow => {}
+                    OverlapPolicy::Warn => tracing::warn!("{}", overlap),
+                    OverlapPolicy::Error => errors.push(overlap),
+                }
+            }
+        }
+
+        if let Some(cycle) = dependency_cycle(&self.devices) {
+            errors.push(format!("device dependency cycle: {}", cycle.join(" -> ")));
+        }
+        errors.extend(serial_bus_conflicts(&self.devices));
+        if let BoolFormat::Custom { on, off } = &self.bool_format {
+            if on == off {
+                errors.push("bool_format on and off must differ".to_string());
+            }
+        }
+        if let Some(template) = &self.register_name_template {
+            errors.extend(register_name_template_errors(template));
+        }
+        if !is_gateway_id(&self.gateway_id) {
+            errors.push(format!(
+                "gateway_id '{}' must be 1-64 letters, digits, '.', '_', ':' or '-'",
+                self.gateway_id
+            ));
+        }
+        if !is_metric_name(&self.metrics.prefix) {
+            errors.push(format!(
+                "metrics.prefix '{}' is not a valid Prometheus metric name (letters, digits and '_', not starting with a digit)",
+                self.metrics.prefix
+            ));
+        }
+        let server = &self.server;
+        if server.ws_idle_timeout_secs > 0
+            && server.ws_idle_timeout_secs <= server.ws_ping_interval_secs
+        {
+            errors.push(format!(
+                "server.ws_idle_timeout_secs ({}) must be longer than server.ws_ping_interval_secs ({}), or idle clients answering pings are disconnected",
+                server.ws_idle_timeout_secs, server.ws_ping_interval_secs
+            ));
+        }
+        if server.stream_max_lifetime_secs == Some(0) {
+            errors.push("server.stream_max_lifetime_secs must be greater than 0".to_string());
+        }
+        if let Some(limit) = &server.write_rate_limit {
+            if !(limit.requests_per_second.is_finite() && limit.requests_per_second > 0.0) {
+                errors.push(format!(
+                    "server.write_rate_limit.requests_per_second must be greater than 0, got {}",
+                    limit.requests_per_second
+                ));
+            }
+            if limit.burst == 0 {
+                errors.push("server.write_rate_limit.burst must be at least 1".to_string());
+            }
+        }
+        if !(1..=crate::api::MAX_WRITE_TIMEOUT_MS).contains(&server.write_timeout_ms) {
+            errors.push(format!(
+                "server.write_timeout_ms must be between 1 and {}, got {}",
+                crate::api::MAX_WRITE_TIMEOUT_MS,
+                server.write_timeout_ms
+            ));
+        }
+        if self.server.device_ttl_secs == Some(0) {
+            errors.push("server.device_ttl_secs must be greater than 0".to_string());
+        }
+        if self.server.write_queue_capacity == 0 {
+            errors.push("server.write_queue_capacity must be greater than 0".to_string());
+        }
+        if self.server.write_queue_policy == WriteQueuePolicy::Wait
+            && self.server.write_queue_wait_ms == 0
+        {
+            errors.push("server.write_queue_wait_ms must be greater than 0".to_string());
+        }
+        if self.startup.require_all_devices && self.startup.timeout_ms == 0 {
+            errors.push("startup.timeout_ms must be greater than 0".to_string());
+        }
+        if self.history.enabled && (self.history.depth == 0 || self.history.max_samples == 0) {
+            errors.push("history.depth and history.max_samples must be greater than 0".to_string());
+        }
+        if self.report_by_exception.enabled && self.report_by_exception.heartbeat_ms == 0 {
+            errors.push("report_by_exception.heartbeat_ms must be greater than 0".to_string());
+        }
+        if self.mqtt.qos > 2 {
+            errors.push(format!("mqtt.qos must be 0, 1 or 2, got {}", self.mqtt.qos));
+        }
+        if self.mqtt.diagnostics.enabled && self.mqtt.diagnostics.interval_ms == 0 {
+            errors.push("mqtt.diagnostics.interval_ms must be greater than 0".to_string());
+        }
+        let tls = &self.mqtt.tls;
+        if tls.client_cert.is_some() != tls.client_key.is_some() {
+            errors.push("mqtt.tls.client_cert and client_key must be set together".to_string());
+        }
+        if self.influx.enabled {
+            errors.extend(self.influx.errors());
+        }
+        if self.storage.enabled {
+            errors.extend(self.storage.errors());
+        }
+        if self.auth.enabled {
+            errors.extend(self.auth.errors());
+        }
+        errors.extend(self.cors.errors());
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("Invalid configuration:\n  - {}", errors.join("\n  - "))
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            server: ServerConfig {
+                host: "0.0.0.0".to_string(),
+                port: 3000,
+                metrics_enabled: true,
+                write_timeout_ms: ServerConfig::default_write_timeout_ms(),
+                success_ratio_window: ServerConfig::default_success_ratio_window(),
+                device_ttl_secs: None,
+                write_queue_capacity: ServerConfig::default_write_queue_capacity(),
+                write_queue_policy: WriteQueuePolicy::default(),
+                write_queue_wait_ms: ServerConfig::default_write_queue_wait_ms(),
+                snapshot_path: None,
+                ws_ping_interval_secs: ServerConfig::default_ws_ping_interval_secs(),
+                ws_idle_timeout_secs: ServerConfig::default_ws_idle_timeout_secs(),
+                stream_max_lifetime_secs: None,
+                write_rate_limit: None,
+                allow_other_units: false,
+            },
+            mqtt: MqttConfig {
+                enabled: false,
+                host: "localhost".to_string(),
+                port: 1883,
+                client_id: "rustbridge".to_string(),
+                topic_prefix: "rustbridge".to_string(),
+                qos: 1,
+                retain: false,
+                username: None,
+                password: None,
+                status_payload: StatusPayloadConfig::default(),
+                required: false,
+                diagnostics: DiagnosticsConfig::default(),
+                change_events: false,
+                republish_on_connect: false,
+                sequence_numbers: false,
+                status_topic: None,
+                tls: MqttTlsConfig::default(),
+            },
+            influx: InfluxConfig::default(),
+            auth: AuthConfig::default(),
+            cors: CorsConfig::default(),
+            raw_format: RawFormat::default(),
+            bool_format: BoolFormat::default(),
+            register_name_template: None,
+            report_by_exception: ReportByExceptionConfig::default(),
+            history: HistoryConfig::default(),
+            storage: StorageConfig::default(),
+            startup: StartupConfig::default(),
+            metrics: MetricsConfig::default(),
+            gateway_id: default_gateway_id(),
+            rate_limit_groups: vec![],
+            devices: vec![],
+        }
+    }
+}
+
+/// Describe every pair of same-type registers on a device whose address
+/// ranges overlap
+fn register_overlaps(device: &DeviceConfig) -> Vec<String> {
+    let range = |r: &RegisterConfig| {
+        let end = r.address as u32 + r.count.max(1) as u32 - 1;
+        (r.address as u32, end)
+    };
+    let describe = |r: &RegisterConfig| {
+        let (start, end) = range(r);
+        if start == end {
+            format!("'{}' ({})", r.name, start)
+        } else {
+            format!("'{}' ({}-{})", r.name, start, end)
+        }
+    };
+
+    let mut overlaps = Vec::new();
+    for (i, a) in device.registers.iter().enumerate() {
+        for b in &device.registers[i + 1..] {
+            // Other unit ids are separate address spaces
+            let unit_id = |r: &RegisterConfig| r.unit_id.unwrap_or(device.unit_id());
+            // Registers reading different bits of a word share it on purpose
+            let both_bits = a.bit.is_some() && b.bit.is_some();
+            if a.register_type != b.register_type
+                || a.register_type == RegisterType::Virtual
+                || unit_id(a) != unit_id(b)
+                || both_bits
+            {
+                continue;
+            }
+            let (a_start, a_end) = range(a);
+            let (b_start, b_end) = range(b);
+            if a_start <= b_end && b_start <= a_end {
+                overlaps.push(format!(
+                    "device '{}': {:?} registers {} and {} overlap",
+                    device.id,
+                    a.register_type,
+                    describe(a),
+                    describe(b)
+                ));
+            }
+        }
+    }
+    overlaps
+}
+
+/// Find a cycle in the devices' `depends_on`, e.g. `["a", "b", "a"]`
+///
+/// Self-dependencies and unknown devices are reported separately and
+/// ignored here.
+fn dependency_cycle(devices: &[DeviceConfig]) -> Option<Vec<String>> {
+    fn visit<'a>(
+        id: &'a str,
+        devices: &'a [DeviceConfig],
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|&p| p == id) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|p| p.to_string()).collect();
+            cycle.push(id.to_string());
+            return Some(cycle);
+        }
+        if !done.insert(id) {
+            return None;
+        }
+        let device = devices.iter().find(|d| d.id == id)?;
+        path.push(id);
+        for dependency in device.depends_on.iter().filter(|d| *d != id) {
+            if let Some(cycle) = visit(dependency, devices, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        None
+    }
+
+    let mut done = HashSet::new();
+    devices
+        .iter()
+        .find_map(|d| visit(&d.id, devices, &mut Vec::new(), &mut done))
+}
+
+/// Devices on one serial port share a bus, so they must agree on the line
+/// settings and each need a unit id of their own
+fn serial_bus_conflicts(devices: &[DeviceConfig]) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut first_on_port: HashMap<&str, (&str, &RtuConnection)> = HashMap::new();
+    let mut units: HashMap<(&str, u8), &str> = HashMap::new();
+    for device in devices {
+        let ConnectionConfig::Rtu(rtu) = &device.connection else {
+            continue;
+        };
+        let (first_id, first) = *first_on_port
+            .entry(rtu.port.as_str())
+            .or_insert((device.id.as_str(), rtu));
+        if (
+            rtu.baud_rate,
+            rtu.data_bits,
+            rtu.stop_bits,
+            rtu.parity.to_lowercase(),
+        ) != (
+            first.baud_rate,
+            first.data_bits,
+            first.stop_bits,
+            first.parity.to_lowercase(),
+        ) {
+            errors.push(format!(
+                "device '{}': serial settings differ from device '{}' on the same port {}",
+                device.id, first_id, rtu.port
+            ));
+        }
+        if let Some(other) = units.insert((rtu.port.as_str(), rtu.unit_id), device.id.as_str()) {
+            errors.push(format!(
+                "device '{}': unit_id {} is already used by device '{}' on port {}",
+                device.id, rtu.unit_id, other, rtu.port
+            ));
+        }
+    }
+    errors
+}
+
+/// A register has an absolute or a percentage deadband, not both, and a
+/// percentage needs the measuring range it applies to
+fn deadband_errors(device_id: &str, register: &RegisterConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    let name = &register.name;
+    if register.deadband.is_some() && register.deadband_percent.is_some() {
+        errors.push(format!(
+            "device '{}': register '{}' sets both deadband and deadband_percent",
+            device_id, name
+        ));
+    }
+    if register
+        .deadband
+        .is_some_and(|d| !(d >= 0.0 && d.is_finite()))
+    {
+        errors.push(format!(
+            "device '{}': register '{}' deadband must be a non-negative number",
+            device_id, name
+        ));
+    }
+    let Some(percent) = register.deadband_percent else {
+        return errors;
+    };
+    if !(0.0..=100.0).contains(&percent) {
+        errors.push(format!(
+            "device '{}': register '{}' deadband_percent must be between 0 and 100",
+            device_id, name
+        ));
+    }
+    match (register.range_min, register.range_max) {
+        (Some(min), Some(max)) if min < max && (max - min).is_finite() => {}
+        (Some(_), Some(_)) => errors.push(format!(
+            "device '{}': register '{}' range_min must be less than range_max",
+            device_id, name
+        )),
+        _ => errors.push(format!(
+            "device '{}': register '{}' with deadband_percent needs range_min and range_max",
+            device_id, name
+        )),
+    }
+    errors
+}
+
+/// A register name template must name the register and use only known
+/// placeholders
+fn register_name_template_errors(template: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    if !template.contains("{name}") {
+        errors.push(format!(
+            "register_name_template '{}' must contain {{name}}",
+            template
+        ));
+    }
+    for placeholder in template.split('{').skip(1) {
+        let placeholder = placeholder.split('}').next().unwrap_or_default();
+        if !crate::api::naming::PLACEHOLDERS.contains(&placeholder) {
+            errors.push(format!(
+                "register_name_template '{}' has unknown placeholder {{{}}} (use {})",
+                template,
+                placeholder,
+                crate::api::naming::PLACEHOLDERS
+                    .map(|p| format!("{{{}}}", p))
+                    .join(", ")
+            ));
+        }
+    }
+    errors
+}
+
+/// The machine's hostname, falling back to `rustbridge`
+fn default_gateway_id() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| is_gateway_id(name))
+        .unwrap_or_else(|| "rustbridge".to_string())
+}
+
+/// Whether a gateway id is safe as a Prometheus label value and inside
+/// MQTT payloads and logs
+fn is_gateway_id(id: &str) -> bool {
+    (1..=64).contains(&id.len())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | ':' | '-'))
+}
+
+/// Whether a string is a legal Prometheus metric name (colons are reserved
+/// for recording rules)
+fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Path of the configuration file, `RUSTBRIDGE_CONFIG` or `config.yaml`
+pub fn config_path() -> String {
+    std::env::var("RUSTBRIDGE_CONFIG").unwrap_or_else(|_| "config.yaml".to_string())
+}
+
+/// Load configuration from file or use defaults
+pub fn load_config() -> Result<Config> {
+    let config_path = config_path();
+
+    if Path::new(&config_path).exists() {
+        load_config_file(&config_path)
+    } else {
+        tracing::warn!("Config file not found, using defaults");
+        Ok(Config::default())
+    }
+}
+
+/// Load and validate the configuration file at `path`
+pub fn load_config_file(path: &str) -> Result<Config> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path))?;
+
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(&content).with_context(|| "Failed to parse config file")?;
+    let value = migrate_config(value)?;
+    let config: Config =
+        serde_yaml::from_value(value).with_context(|| "Failed to parse config file")?;
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// Upgrade a raw configuration document to the current schema version
+///
+/// Files without a `version` field predate versioning and are treated as
+/// version 0. Files newer than this build are rejected rather than having
+/// their unknown semantics silently ignored.
+pub fn migrate_config(mut value: serde_yaml::Value) -> Result<serde_yaml::Value> {
+    let version = match value.get("version") {
+        None => {
+            tracing::warn!(
+                "Config has no `version` field, assuming legacy version 0 (current: {})",
+                CONFIG_VERSION
+            );
+            0
+        }
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid config version: {:?}", v))?,
+    };
+
+    if version > CONFIG_VERSION {
+        anyhow::bail!(
+            "Config version {} is newer than the latest version supported by this build ({}); \
+             please upgrade RustBridge",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    // Apply migrations one version at a time
+    for from in version..CONFIG_VERSION {
+        match from {
+            // Version 1 introduced the `version` field itself; the schema
+            // is otherwise unchanged.
+            0 => {}
+            _ => unreachable!("no migration defined from config version {}", from),
+        }
+        tracing::info!("Migrated config from version {} to {}", from, from + 1);
+    }
+
+    if let Some(map) = value.as_mapping_mut() {
+        map.insert(
+            serde_yaml::Value::String("version".to_string()),
+            serde_yaml::Value::Number(CONFIG_VERSION.into()),
+        );
+    }
+
+    Ok(value)
+}
+
 /// Load configuration from a YAML string (used in tests)
 #[cfg(test)]
 pub fn load_config_from_str(yaml: &str) -> Result<Config> {
-    serde_yaml::from_str(yaml).with_context(|| "Failed to parse config")
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(yaml).with_context(|| "Failed to parse config")?;
+    serde_yaml::from_value(migrate_config(value)?).with_context(|| "Failed to parse config")
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 3000);
+        assert!(config.server.metrics_enabled);
+        assert_eq!(config.server.write_timeout_ms, 5000);
+        assert!(!config.mqtt.enabled); // MQTT disabled by default
+        assert_eq!(config.mqtt.host, "localhost");
+        assert_eq!(config.mqtt.port, 1883);
+        assert_eq!(config.mqtt.qos, 1);
+        assert!(!config.mqtt.retain);
+        assert!(config.devices.is_empty());
+    }
+
+    #[test]
+    fn test_parse_minimal_config() {
+        let yaml = r#"
+server:
+  host: "127.0.0.1"
+  port: 8080
+  metrics_enabled: false
+mqtt:
+  host: "mqtt.example.com"
+  port: 1883
+  client_id: "test-client"
+  topic_prefix: "test"
+  qos: 2
+devices: []
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 8080);
+        assert!(!config.server.metrics_enabled);
+        assert_eq!(config.mqtt.host, "mqtt.example.com");
+        assert_eq!(config.mqtt.qos, 2);
+    }
+
+    #[test]
+    fn test_parse_tcp_device() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: "localhost"
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+devices:
+  - id: "plc-001"
+    name: "Test PLC"
+    device_type: tcp
+    connection:
+      host: "192.168.1.100"
+      port: 502
+      unit_id: 1
+    poll_interval_ms: 1000
+    registers:
+      - name: "temperature"
+        address: 0
+        register_type: holding
+        count: 1
+        data_type: i16
+        unit: "°C"
+        scale: 0.1
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+
+        assert_eq!(config.devices.len(), 1);
+        let device = &config.devices[0];
+        assert_eq!(device.id, "plc-001");
+        assert_eq!(device.name, "Test PLC");
+        assert_eq!(device.poll_interval_ms, 1000);
+        assert!(!device.wire_log); // Wire log is off by default
+
+        match &device.connection {
+            ConnectionConfig::Tcp(tcp) => {
+                assert_eq!(tcp.host, "192.168.1.100");
+                assert_eq!(tcp.port, 502);
+                assert_eq!(tcp.unit_id, 1);
+                assert_eq!(tcp.slave_id, None);
+            }
+            _ => panic!("Expected TCP connection"),
+        }
+
+        assert_eq!(device.registers.len(), 1);
+        let reg = &device.registers[0];
+        assert_eq!(reg.name, "temperature");
+        assert_eq!(reg.address, 0);
+        assert_eq!(reg.scale, Some(0.1));
+        assert_eq!(reg.unit, Some("°C".to_string()));
+    }
+
+    #[test]
+    fn test_parse_tcp_slave_id() {
+        let yaml = r#"
+host: "192.168.1.50"
+port: 4001
+unit_id: 1
+slave_id: 17
+"#;
+        let tcp: TcpConnection = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(tcp.unit_id, 1);
+        assert_eq!(tcp.slave_id, Some(17));
+    }
+
+    #[test]
+    fn test_parse_rtu_device() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: ""
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+devices:
+  - id: "sensor-001"
+    name: "RTU Sensor"
+    device_type: rtu
+    connection:
+      port: "/dev/ttyUSB0"
+      baud_rate: 9600
+      data_bits: 8
+      stop_bits: 1
+      parity: "none"
+      unit_id: 1
+    poll_interval_ms: 500
+    registers:
+      - name: "humidity"
+        address: 100
+        register_type: input
+        count: 1
+        data_type: u16
+        unit: "%"
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+
+        assert_eq!(config.devices.len(), 1);
+        let device = &config.devices[0];
+
+        match &device.connection {
+            ConnectionConfig::Rtu(rtu) => {
+                assert_eq!(rtu.port, "/dev/ttyUSB0");
+                assert_eq!(rtu.baud_rate, 9600);
+                assert_eq!(rtu.data_bits, 8);
+                assert_eq!(rtu.parity, "none");
+            }
+            _ => panic!("Expected RTU connection"),
+        }
+    }
+
+    #[test]
+    fn test_all_register_types() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: ""
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+devices:
+  - id: "test"
+    name: "Test"
+    device_type: tcp
+    connection:
+      host: "localhost"
+      port: 502
+      unit_id: 1
+    poll_interval_ms: 1000
+    registers:
+      - name: "holding_reg"
+        address: 0
+        register_type: holding
+        count: 1
+        data_type: u16
+      - name: "input_reg"
+        address: 10
+        register_type: input
+        count: 1
+        data_type: i16
+      - name: "coil_reg"
+        address: 20
+        register_type: coil
+        count: 1
+        data_type: bool
+      - name: "discrete_reg"
+        address: 30
+        register_type: discrete
+        count: 1
+        data_type: bool
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+
+        let regs = &config.devices[0].registers;
+        assert_eq!(regs.len(), 4);
+
+        assert!(matches!(regs[0].register_type, RegisterType::Holding));
+        assert!(matches!(regs[1].register_type, RegisterType::Input));
+        assert!(matches!(regs[2].register_type, RegisterType::Coil));
+        assert!(matches!(regs[3].register_type, RegisterType::Discrete));
+    }
+
+    #[test]
+    fn test_all_data_types() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: ""
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+devices:
+  - id: "test"
+    name: "Test"
+    device_type: tcp
+    connection:
+      host: "localhost"
+      port: 502
+      unit_id: 1
+    poll_interval_ms: 1000
+    registers:
+      - name: "u16_val"
+        address: 0
+        register_type: holding
+        count: 1
+        data_type: u16
+      - name: "i16_val"
+        address: 1
+        register_type: holding
+        count: 1
+        data_type: i16
+      - name: "u32_val"
+        address: 2
+        register_type: holding
+        count: 2
+        data_type: u32
+      - name: "i32_val"
+        address: 4
+        register_type: holding
+        count: 2
+        data_type: i32
+      - name: "f32_val"
+        address: 6
+        register_type: holding
+        count: 2
+        data_type: f32
+      - name: "bool_val"
+        address: 8
+        register_type: holding
+        count: 1
+        data_type: bool
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+
+        let regs = &config.devices[0].registers;
+        assert_eq!(regs.len(), 6);
 
-    #[test]
-    fn test_default_config() {
-        let config = Config::default();
+        assert!(matches!(regs[0].data_type, DataType::U16));
+        assert!(matches!(regs[1].data_type, DataType::I16));
+        assert!(matches!(regs[2].data_type, DataType::U32));
+        assert!(matches!(regs[3].data_type, DataType::I32));
+        assert!(matches!(regs[4].data_type, DataType::F32));
+        assert!(matches!(regs[5].data_type, DataType::Bool));
+    }
 
-        assert_eq!(config.server.host, "0.0.0.0");
-        assert_eq!(config.server.port, 3000);
-        assert!(config.server.metrics_enabled);
-        assert!(!config.mqtt.enabled); // MQTT disabled by default
-        assert_eq!(config.mqtt.host, "localhost");
-        assert_eq!(config.mqtt.port, 1883);
-        assert_eq!(config.mqtt.qos, 1);
-        assert!(!config.mqtt.retain);
-        assert!(config.devices.is_empty());
+    #[test]
+    fn test_invalid_yaml() {
+        let yaml = "this is not valid yaml: [";
+        let result = load_config_from_str(yaml);
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_parse_minimal_config() {
+    fn test_mqtt_with_auth() {
         let yaml = r#"
 server:
-  host: "127.0.0.1"
-  port: 8080
-  metrics_enabled: false
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
 mqtt:
-  host: "mqtt.example.com"
-  port: 1883
-  client_id: "test-client"
-  topic_prefix: "test"
+  host: "mqtt.secure.com"
+  port: 8883
+  client_id: "secure-client"
+  topic_prefix: "secure"
   qos: 2
+  username: "admin"
+  password: "secret123"
 devices: []
 "#;
         let config = load_config_from_str(yaml).unwrap();
 
-        assert_eq!(config.server.host, "127.0.0.1");
-        assert_eq!(config.server.port, 8080);
-        assert!(!config.server.metrics_enabled);
-        assert_eq!(config.mqtt.host, "mqtt.example.com");
-        assert_eq!(config.mqtt.qos, 2);
+        assert_eq!(config.mqtt.username, Some("admin".to_string()));
+        assert_eq!(config.mqtt.password, Some("secret123".to_string()));
     }
 
+    const VERSIONED_BODY: &str = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: "localhost"
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+devices: []
+"#;
+
     #[test]
-    fn test_parse_tcp_device() {
+    fn test_unversioned_config_is_migrated() {
+        let config = load_config_from_str(VERSIONED_BODY).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn test_current_version_accepted() {
+        let yaml = format!("version: {}\n{}", CONFIG_VERSION, VERSIONED_BODY);
+        let config = load_config_from_str(&yaml).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let yaml = format!("version: {}\n{}", CONFIG_VERSION + 1, VERSIONED_BODY);
+        let err = load_config_from_str(&yaml).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+    }
+
+    #[test]
+    fn test_invalid_version_rejected() {
+        let yaml = format!("version: \"one\"\n{}", VERSIONED_BODY);
+        assert!(load_config_from_str(&yaml).is_err());
+    }
+
+    #[test]
+    fn test_redacted_config_hides_secrets() {
+        let mut config = Config::default();
+        config.mqtt.username = Some("admin".to_string());
+        config.mqtt.password = Some("secret123".to_string());
+        config.auth.api_keys = vec!["key-1".to_string(), "key-2".to_string()];
+        config.influx.token = Some("influx-token".to_string());
+        config.auth.jwt.secret = Some("jwt-secret".to_string());
+
+        let redacted = config.redacted();
+        assert_eq!(redacted.mqtt.username, Some("admin".to_string()));
+        assert_eq!(redacted.mqtt.password, Some(REDACTED.to_string()));
+        assert_eq!(redacted.influx.token, Some(REDACTED.to_string()));
+        assert_eq!(redacted.auth.api_keys, vec![REDACTED, REDACTED]);
+        assert_eq!(redacted.auth.jwt.secret, Some(REDACTED.to_string()));
+
+        // Absent secrets stay absent
+        assert_eq!(Config::default().redacted().mqtt.password, None);
+    }
+
+    fn overlapping_device(policy: OverlapPolicy) -> DeviceBuilder {
+        DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .register_overlaps(policy)
+            .register(RegisterBuilder::holding("energy", 40).data_type(DataType::U32))
+            .register(RegisterBuilder::holding("energy_low", 41))
+            // Same address in another register space is not an overlap
+            .register(RegisterBuilder::input("flow", 40).data_type(DataType::U32))
+            // Nor is the same address on another unit id
+            .register(RegisterBuilder::holding("bank2_energy", 40).unit_id(2))
+    }
+
+    #[test]
+    fn test_register_overlaps_reported() {
+        let device = overlapping_device(OverlapPolicy::Warn).build();
+        assert_eq!(
+            register_overlaps(&device),
+            vec!["device 'plc-001': Holding registers 'energy' (40-41) and 'energy_low' (41) overlap"]
+        );
+
+        // Warnings and intentional overlaps don't fail validation
+        for policy in [OverlapPolicy::Warn, OverlapPolicy::Allow] {
+            let config = ConfigBuilder::new()
+                .device(overlapping_device(policy))
+                .build();
+            assert!(config.is_ok());
+        }
+    }
+
+    #[test]
+    fn test_register_overlaps_rejected() {
+        let err = ConfigBuilder::new()
+            .device(overlapping_device(OverlapPolicy::Error))
+            .build()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("'energy' (40-41) and 'energy_low' (41)"));
+    }
+
+    #[test]
+    fn test_register_unit_id_for_rtu() {
         let yaml = r#"
 server:
   host: "0.0.0.0"
   port: 3000
   metrics_enabled: true
 mqtt:
-  host: "localhost"
+  host: ""
   port: 1883
   client_id: "rustbridge"
   topic_prefix: "rustbridge"
   qos: 1
 devices:
-  - id: "plc-001"
-    name: "Test PLC"
-    device_type: tcp
+  - id: "meter"
+    name: "Power Meter"
+    device_type: rtu
     connection:
-      host: "192.168.1.100"
-      port: 502
+      port: "/dev/ttyUSB0"
+      baud_rate: 9600
+      data_bits: 8
+      stop_bits: 1
+      parity: "none"
       unit_id: 1
     poll_interval_ms: 1000
     registers:
-      - name: "temperature"
+      - name: "voltage"
         address: 0
-        register_type: holding
+        register_type: input
         count: 1
-        data_type: i16
-        unit: "°C"
-        scale: 0.1
+        data_type: u16
+      - name: "bank2_voltage"
+        address: 0
+        register_type: input
+        count: 1
+        data_type: u16
+        unit_id: 2
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let device = &config.devices[0];
+        assert_eq!(device.unit_id(), 1);
+        assert_eq!(device.registers[0].unit_id, None);
+        assert_eq!(device.registers[1].unit_id, Some(2));
+        config.validate().unwrap();
+
+        // Broadcast and reserved addresses get no reply on a serial line
+        for unit_id in [0, 248] {
+            let err = ConfigBuilder::new()
+                .device(
+                    DeviceBuilder::rtu("meter", "/dev/ttyUSB0", 9600, 1)
+                        .register(RegisterBuilder::input("bank", 0).unit_id(unit_id)),
+                )
+                .build()
+                .unwrap_err();
+            assert!(err.to_string().contains("RTU slaves must be 1-247"));
+        }
+    }
+
+    #[test]
+    fn test_max_cycle_ms_validation() {
+        let device = || {
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("a", 0))
+                .register(RegisterBuilder::holding("b", 1))
+        };
+
+        assert!(ConfigBuilder::new()
+            .device(device().max_cycle_ms(200))
+            .build()
+            .is_ok());
+
+        let err = ConfigBuilder::new()
+            .device(device().max_cycle_ms(0))
+            .build()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("max_cycle_ms must be greater than 0"));
+
+        let err = ConfigBuilder::new()
+            .device(device().max_cycle_ms(200).spread_reads(true))
+            .build()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("cannot be combined with spread_reads"));
+    }
+
+    #[test]
+    fn test_metrics_prefix_validation() {
+        assert_eq!(Config::default().metrics.prefix, "rustbridge");
+
+        for prefix in ["plant_a", "_bridge2"] {
+            let mut config = Config::default();
+            config.metrics.prefix = prefix.to_string();
+            assert!(config.validate().is_ok(), "{} rejected", prefix);
+        }
+        for prefix in ["", "2plant", "plant-a", "plant a"] {
+            let mut config = Config::default();
+            config.metrics.prefix = prefix.to_string();
+            assert!(config.validate().is_err(), "{} accepted", prefix);
+        }
+    }
+
+    #[test]
+    fn test_mqtt_tls_validation() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: "broker.example.com"
+  port: 8883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+  tls:
+    enabled: true
+devices: []
 "#;
         let config = load_config_from_str(yaml).unwrap();
+        assert!(config.mqtt.tls.enabled);
+        assert!(config.mqtt.tls.ca_cert.is_none());
+
+        let mut config = Config::default();
+        config.mqtt.tls.client_cert = Some("client.crt".to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("set together"), "{}", error);
+
+        // Mutual TLS works with the system's root certificates too
+        config.mqtt.tls.client_key = Some("client.key".to_string());
+        assert!(config.validate().is_ok());
+
+        config.mqtt.tls.ca_cert = Some("ca.crt".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_storage_validation() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: "localhost"
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+storage:
+  enabled: true
+  path: "/var/lib/rustbridge/history.db"
+devices: []
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+        assert_eq!(config.storage.path, "/var/lib/rustbridge/history.db");
+        assert_eq!(config.storage.retention_hours, 168);
+        assert_eq!(config.storage.batch_size, 500);
+
+        let mut config = Config::default();
+        config.storage.enabled = true;
+        config.storage.path = " ".to_string();
+        config.storage.retention_hours = 0;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("storage.path must not be empty"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("storage.retention_hours must be greater than 0"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_influx_validation() {
+        let yaml = r#"
+server:
+  host: "0.0.0.0"
+  port: 3000
+  metrics_enabled: true
+mqtt:
+  host: "localhost"
+  port: 1883
+  client_id: "rustbridge"
+  topic_prefix: "rustbridge"
+  qos: 1
+influx:
+  enabled: true
+  org: "plant"
+  bucket: "modbus"
+devices: []
+"#;
+        let config = load_config_from_str(yaml).unwrap();
+        assert_eq!(config.influx.url, "http://localhost:8086");
+        assert_eq!(config.influx.measurement, "modbus");
+        assert_eq!(config.influx.batch_size, 1000);
+
+        // Disabled settings are not checked
+        assert!(Config::default().validate().is_ok());
+
+        let mut config = Config::default();
+        config.influx.enabled = true;
+        config.influx.url = "localhost:8086".to_string();
+        config.influx.bucket = Some("modbus".to_string());
+        config.influx.batch_size = 0;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("influx.url must start with http://"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("influx.bucket requires influx.org"),
+            "{}",
+            error
+        );
+        assert!(error.contains("must be greater than 0"), "{}", error);
+
+        config.influx.url = "https://influx.example.com".to_string();
+        config.influx.database = Some("modbus".to_string());
+        config.influx.batch_size = 100;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("mutually exclusive"), "{}", error);
+
+        config.influx.bucket = None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_depends_on_validation() {
+        let device = |id: &str| DeviceBuilder::tcp(id, "localhost", 502, 1);
+
+        let ordered = ConfigBuilder::new()
+            .device(device("gateway-1"))
+            .device(device("meter-1").depends_on("gateway-1"))
+            .device(
+                device("meter-2")
+                    .depends_on("gateway-1")
+                    .depends_on("meter-1"),
+            )
+            .build();
+        assert!(ordered.is_ok());
+
+        let error = ConfigBuilder::new()
+            .device(device("a").depends_on("c"))
+            .device(device("b").depends_on("a"))
+            .device(device("c").depends_on("b"))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("device dependency cycle: a -> c -> b -> a"),
+            "{}",
+            error
+        );
+
+        let error = ConfigBuilder::new()
+            .device(device("a").depends_on("a").depends_on("missing"))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("device 'a' depends on itself"));
+        assert!(error.contains("depends on unknown device 'missing'"));
+        assert!(!error.contains("cycle"));
+    }
+
+    #[test]
+    fn test_device_log_level_validation() {
+        let device = || DeviceBuilder::tcp("plc-001", "localhost", 502, 1);
+        assert!(ConfigBuilder::new()
+            .device(device().log_level("debug"))
+            .build()
+            .is_ok());
+        assert!(ConfigBuilder::new()
+            .device(device().log_level("verbose"))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_scale_from_register_validation() {
+        let build = |registers: Vec<RegisterBuilder>| {
+            let device = registers.into_iter().fold(
+                DeviceBuilder::tcp("inverter", "localhost", 502, 1),
+                |d, r| d.register(r),
+            );
+            ConfigBuilder::new().device(device).build()
+        };
+        let power = || RegisterBuilder::holding("power", 40084).scale_from_register("power_sf");
+        let power_sf = || RegisterBuilder::holding("power_sf", 40085).data_type(DataType::I16);
+
+        assert!(build(vec![power_sf(), power()]).is_ok());
+
+        let error = build(vec![power(), power_sf()]).unwrap_err().to_string();
+        assert!(error.contains("scale_from_register 'power_sf' must be listed before it"));
+
+        let error = build(vec![power()]).unwrap_err().to_string();
+        assert!(error.contains("'power_sf' is not a register of this device"));
+    }
+
+    #[test]
+    fn test_signed_bits_validation() {
+        let build = |register: RegisterBuilder| {
+            ConfigBuilder::new()
+                .device(DeviceBuilder::tcp("meter", "localhost", 502, 1).register(register))
+                .build()
+        };
+
+        assert!(build(RegisterBuilder::holding("power", 0).signed_bits(12)).is_ok());
+        assert!(build(
+            RegisterBuilder::holding("energy", 0)
+                .data_type(DataType::U32)
+                .signed_bits(24)
+        )
+        .is_ok());
+
+        let error = build(RegisterBuilder::holding("power", 0).signed_bits(24)).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("signed_bits must be between 2 and 16"));
+        let error = build(
+            RegisterBuilder::holding("power", 0)
+                .data_type(DataType::F32)
+                .signed_bits(24),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("not of an integer data type"));
+    }
+
+    #[test]
+    fn test_bit_validation() {
+        let build = |registers: Vec<RegisterBuilder>| {
+            let device = registers.into_iter().fold(
+                DeviceBuilder::tcp("pump", "localhost", 502, 1)
+                    .register_overlaps(OverlapPolicy::Error),
+                DeviceBuilder::register,
+            );
+            ConfigBuilder::new().device(device).build()
+        };
 
-        assert_eq!(config.devices.len(), 1);
-        let device = &config.devices[0];
-        assert_eq!(device.id, "plc-001");
-        assert_eq!(device.name, "Test PLC");
-        assert_eq!(device.poll_interval_ms, 1000);
+        // Several flags of the same status word do not overlap
+        assert!(build(vec![
+            RegisterBuilder::holding("running", 0).bit(0),
+            RegisterBuilder::holding("fault", 0).bit(1),
+            RegisterBuilder::input("remote", 10).bit(15),
+        ])
+        .is_ok());
 
-        match &device.connection {
-            ConnectionConfig::Tcp(tcp) => {
-                assert_eq!(tcp.host, "192.168.1.100");
-                assert_eq!(tcp.port, 502);
-                assert_eq!(tcp.unit_id, 1);
-            }
-            _ => panic!("Expected TCP connection"),
+        let error = build(vec![RegisterBuilder::holding("running", 0).bit(16)]).unwrap_err();
+        assert!(error.to_string().contains("bit must be between 0 and 15"));
+        for register in [
+            RegisterBuilder::holding("running", 0)
+                .data_type(DataType::U32)
+                .bit(0),
+            RegisterBuilder::holding("running", 0).count(2).bit(0),
+            RegisterBuilder::coil("running", 0).bit(0),
+        ] {
+            let error = build(vec![register]).unwrap_err();
+            assert!(error
+                .to_string()
+                .contains("not a single holding or input register"));
         }
-
-        assert_eq!(device.registers.len(), 1);
-        let reg = &device.registers[0];
-        assert_eq!(reg.name, "temperature");
-        assert_eq!(reg.address, 0);
-        assert_eq!(reg.scale, Some(0.1));
-        assert_eq!(reg.unit, Some("°C".to_string()));
+        let error = build(vec![RegisterBuilder::holding("running", 0)
+            .signed_bits(4)
+            .bit(0)])
+        .unwrap_err();
+        assert!(error.to_string().contains("sets both bit and signed_bits"));
     }
 
     #[test]
-    fn test_parse_rtu_device() {
-        let yaml = r#"
+    fn test_formula_config() {
+        let yaml = |formula: &str| {
+            format!(
+                r#"
 server:
   host: "0.0.0.0"
   port: 3000
   metrics_enabled: true
 mqtt:
-  host: ""
+  host: localhost
   port: 1883
-  client_id: "rustbridge"
-  topic_prefix: "rustbridge"
+  client_id: test
+  topic_prefix: test
   qos: 1
 devices:
-  - id: "sensor-001"
-    name: "RTU Sensor"
-    device_type: rtu
+  - id: plc-001
+    name: PLC
+    device_type: tcp
     connection:
-      port: "/dev/ttyUSB0"
-      baud_rate: 9600
-      data_bits: 8
-      stop_bits: 1
-      parity: "none"
+      host: localhost
+      port: 502
       unit_id: 1
-    poll_interval_ms: 500
+    poll_interval_ms: 1000
     registers:
-      - name: "humidity"
-        address: 100
+      - name: temperature
+        address: 0
         register_type: input
         count: 1
         data_type: u16
-        unit: "%"
-"#;
-        let config = load_config_from_str(yaml).unwrap();
+        formula: "{}"
+"#,
+                formula
+            )
+        };
 
-        assert_eq!(config.devices.len(), 1);
-        let device = &config.devices[0];
+        let config = load_config_from_str(&yaml("1 / (0.5 + 0.25 * x)")).unwrap();
+        let formula = config.devices[0].registers[0].formula.as_ref().unwrap();
+        assert_eq!(formula.eval(2.0), 1.0);
 
-        match &device.connection {
-            ConnectionConfig::Rtu(rtu) => {
-                assert_eq!(rtu.port, "/dev/ttyUSB0");
-                assert_eq!(rtu.baud_rate, 9600);
-                assert_eq!(rtu.data_bits, 8);
-                assert_eq!(rtu.parity, "none");
-            }
-            _ => panic!("Expected RTU connection"),
+        // `expr` is another name for it
+        let expr = yaml("x ^ 2").replace("formula:", "expr:");
+        let config = load_config_from_str(&expr).unwrap();
+        let formula = config.devices[0].registers[0].formula.as_ref().unwrap();
+        assert_eq!(formula.eval(3.0), 9.0);
+
+        for invalid in ["1 / (x", "x * gain"] {
+            let error = load_config_from_str(&yaml(invalid)).unwrap_err();
+            assert!(
+                format!("{:#}", error).contains(&format!("formula '{}' is invalid", invalid)),
+                "{:#}",
+                error
+            );
         }
+
+        let error = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1).register(
+                    RegisterBuilder::holding("site", 0)
+                        .data_type(DataType::String)
+                        .formula(Formula::parse("x").unwrap()),
+                ),
+            )
+            .build()
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("sets formula but is a string register"));
     }
 
     #[test]
-    fn test_all_register_types() {
+    fn test_jwt_auth_validation() {
+        let mut config = Config::default();
+        config.auth.enabled = true;
+        config.auth.mode = AuthMode::Jwt;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("auth.mode jwt needs auth.jwt.secret or auth.jwt.jwks_url"));
+
+        config.auth.jwt.secret = Some("shared-secret".to_string());
+        config.validate().unwrap();
+
+        config.auth.jwt.jwks_url = Some("https://idp.example.com/jwks.json".to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("mutually exclusive"));
+
+        config.auth.jwt.secret = None;
+        config.validate().unwrap();
+        config.auth.jwt.jwks_url = Some("idp.example.com/jwks.json".to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("auth.jwt.jwks_url must start with http:// or https://"));
+
+        // Unchecked while authentication is off
+        config.auth.enabled = false;
+        config.validate().unwrap();
+
+        let auth: AuthConfig = serde_yaml::from_str("mode: jwt\njwt:\n  secret: s").unwrap();
+        assert_eq!(auth.mode, AuthMode::Jwt);
+        assert_eq!(auth.jwt.leeway_secs, 60);
+        let auth: AuthConfig = serde_yaml::from_str("enabled: true").unwrap();
+        assert_eq!(auth.mode, AuthMode::ApiKey);
+    }
+
+    #[test]
+    fn test_cors_validation() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.cors.allowed_origins = vec![
+            "https://dashboard.example.com".to_string(),
+            "http://localhost:8080".to_string(),
+        ];
+        assert!(config.validate().is_ok());
+
+        config.cors.permissive = true;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("cors.permissive and cors.allowed_origins are mutually exclusive"));
+
+        config.cors.permissive = false;
+        for origin in [
+            "dashboard.example.com",
+            "https://example.com/",
+            "https://",
+            "*",
+        ] {
+            config.cors.allowed_origins = vec![origin.to_string()];
+            let error = config.validate().unwrap_err().to_string();
+            assert!(
+                error.contains(&format!("entry '{}' must be an origin", origin)),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_config() {
         let yaml = r#"
 server:
   host: "0.0.0.0"
   port: 3000
   metrics_enabled: true
 mqtt:
-  host: ""
+  host: localhost
   port: 1883
-  client_id: "rustbridge"
-  topic_prefix: "rustbridge"
+  client_id: test
+  topic_prefix: test
   qos: 1
 devices:
-  - id: "test"
-    name: "Test"
+  - id: plc-001
+    name: PLC
     device_type: tcp
     connection:
-      host: "localhost"
+      host: localhost
       port: 502
       unit_id: 1
     poll_interval_ms: 1000
-    registers:
-      - name: "holding_reg"
-        address: 0
-        register_type: holding
-        count: 1
-        data_type: u16
-      - name: "input_reg"
-        address: 10
-        register_type: input
-        count: 1
-        data_type: i16
-      - name: "coil_reg"
-        address: 20
-        register_type: coil
-        count: 1
-        data_type: bool
-      - name: "discrete_reg"
-        address: 30
-        register_type: discrete
-        count: 1
-        data_type: bool
+    heartbeat:
+      address: 100
+      mode: toggle
+    registers: []
 "#;
         let config = load_config_from_str(yaml).unwrap();
+        let heartbeat = config.devices[0].heartbeat.as_ref().unwrap();
+        assert_eq!(heartbeat.address, 100);
+        assert_eq!(heartbeat.interval_ms, 1000);
+        assert_eq!(heartbeat.mode, HeartbeatMode::Toggle);
 
-        let regs = &config.devices[0].registers;
-        assert_eq!(regs.len(), 4);
+        assert_eq!(HeartbeatMode::Toggle.next(0), 1);
+        assert_eq!(HeartbeatMode::Toggle.next(1), 0);
+        assert_eq!(HeartbeatMode::Increment.next(41), 42);
+        assert_eq!(HeartbeatMode::Increment.next(u16::MAX), 0);
 
-        assert!(matches!(regs[0].register_type, RegisterType::Holding));
-        assert!(matches!(regs[1].register_type, RegisterType::Input));
-        assert!(matches!(regs[2].register_type, RegisterType::Coil));
-        assert!(matches!(regs[3].register_type, RegisterType::Discrete));
+        let error = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1).heartbeat(
+                    100,
+                    0,
+                    HeartbeatMode::Increment,
+                ),
+            )
+            .build()
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("heartbeat.interval_ms must be greater than 0"));
     }
 
     #[test]
-    fn test_all_data_types() {
+    fn test_rate_limit_validation() {
+        let config = ConfigBuilder::new()
+            .rate_limit_group("rs485-a", 20.0)
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .max_reads_per_second(5.0)
+                    .rate_limit_group("rs485-a"),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(config.rate_limit_groups[0].max_reads_per_second, 20.0);
+        assert_eq!(config.devices[0].max_reads_per_second, Some(5.0));
+
+        let error = ConfigBuilder::new()
+            .rate_limit_group("rs485-a", 0.0)
+            .rate_limit_group("rs485-a", 20.0)
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .max_reads_per_second(-1.0)
+                    .rate_limit_group("rs485-b"),
+            )
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("rate limit group 'rs485-a' is defined twice"));
+        assert!(error
+            .contains("rate limit group 'rs485-a': max_reads_per_second must be greater than 0"));
+        assert!(error.contains("device 'plc-001': max_reads_per_second must be greater than 0"));
+        assert!(error.contains("rate_limit_group 'rs485-b' is not defined"));
+    }
+
+    #[test]
+    fn test_duplicate_and_range_validation() {
+        let valid = || {
+            ConfigBuilder::new()
+                .device(
+                    DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                        .register(RegisterBuilder::holding("temperature", 0))
+                        .register(RegisterBuilder::holding("pressure", 1)),
+                )
+                .device(
+                    DeviceBuilder::tcp("plc-002", "localhost", 502, 2)
+                        .register(RegisterBuilder::holding("temperature", 0)),
+                )
+        };
+        let config = valid().build().unwrap();
+
+        let error = valid()
+            .device(DeviceBuilder::tcp("plc-001", "localhost", 502, 3))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("device id 'plc-001' is used twice"),
+            "{}",
+            error
+        );
+
+        let mut duplicate = config.clone();
+        duplicate.devices[0]
+            .registers
+            .push(RegisterBuilder::input("temperature", 10).build());
+        let error = duplicate.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("device 'plc-001': register name 'temperature' is used twice"),
+            "{}",
+            error
+        );
+
+        let mut empty = config.clone();
+        empty.devices[1].registers[0].count = 0;
+        let error = empty.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("device 'plc-002': register 'temperature' count must be at least 1"),
+            "{}",
+            error
+        );
+
+        let mut qos = config.clone();
+        qos.mqtt.qos = 3;
+        let error = qos.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("mqtt.qos must be 0, 1 or 2, got 3"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_count_matches_data_type() {
+        let device = |register: RegisterBuilder| {
+            ConfigBuilder::new()
+                .device(DeviceBuilder::tcp("plc-001", "localhost", 502, 1).register(register))
+                .build()
+        };
+        // The builder's default counts fit every type
+        for data_type in [
+            DataType::U16,
+            DataType::F32,
+            DataType::Bool,
+            DataType::String,
+        ] {
+            device(RegisterBuilder::holding("value", 0).data_type(data_type)).unwrap();
+        }
+        device(
+            RegisterBuilder::holding("value", 0)
+                .data_type(DataType::F32)
+                .count(4),
+        )
+        .unwrap();
+        device(
+            RegisterBuilder::holding("serial", 0)
+                .data_type(DataType::String)
+                .count(8),
+        )
+        .unwrap();
+
+        let error = |register: RegisterBuilder| device(register).unwrap_err().to_string();
+        assert!(error(
+            RegisterBuilder::holding("power", 0)
+                .data_type(DataType::F32)
+                .count(1)
+        )
+        .contains(
+            "device 'plc-001': register 'power' of data type f32 needs count 2 or more, got 1"
+        ));
+        assert!(error(
+            RegisterBuilder::holding("energy", 0)
+                .data_type(DataType::U32)
+                .count(1)
+        )
+        .contains("of data type u32 needs count 2 or more"));
+        assert!(error(
+            RegisterBuilder::holding("speed", 0)
+                .data_type(DataType::I16)
+                .count(2)
+        )
+        .contains("register 'speed' of data type i16 needs count 1, got 2"));
+        assert!(error(RegisterBuilder::coil("pump", 0).count(3))
+            .contains("register 'pump' of data type bool needs count 1, got 3"));
+    }
+
+    #[test]
+    fn test_count_edges_validation() {
+        ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(RegisterBuilder::discrete("flow_pulse", 0).count_edges())
+                    .register(RegisterBuilder::computed(
+                        "litres",
+                        "flow_pulse.edges * 0.5",
+                    )),
+            )
+            .build()
+            .unwrap();
+
+        let error = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(RegisterBuilder::holding("level", 0).count_edges())
+                    .register(RegisterBuilder::coil("pulses", 0).count(2).count_edges())
+                    .register(RegisterBuilder::coil("valve", 5))
+                    .register(RegisterBuilder::computed("total", "valve.edges")),
+            )
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("register 'level' sets count_edges but is not a coil or discrete input")
+        );
+        assert!(error.contains("register 'pulses' with count_edges must have count 1"));
+        // Only registers counting edges have an edge count
+        assert!(error.contains("references unknown register 'valve.edges'"));
+    }
+
+    #[test]
+    fn test_register_name_template_validation() {
+        let mut config = Config {
+            register_name_template: Some("{device_type}.{device_id}.{name}".to_string()),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+
+        config.register_name_template = Some("{device}/{register}".to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("must contain {name}"));
+        assert!(error.contains("unknown placeholder {device}"));
+        assert!(error.contains("unknown placeholder {register}"));
+    }
+
+    #[test]
+    fn test_write_timeout_validation() {
+        let mut config = Config::default();
+        for write_timeout_ms in [0, crate::api::MAX_WRITE_TIMEOUT_MS + 1, u64::MAX] {
+            config.server.write_timeout_ms = write_timeout_ms;
+            let error = config.validate().unwrap_err().to_string();
+            assert!(error.contains("server.write_timeout_ms must be between 1 and 60000"));
+        }
+        config.server.write_timeout_ms = crate::api::MAX_WRITE_TIMEOUT_MS;
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_stream_timeout_validation() {
+        let mut config = Config::default();
+        config.validate().unwrap();
+
+        config.server.ws_ping_interval_secs = 90;
+        config.server.stream_max_lifetime_secs = Some(0);
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("server.ws_idle_timeout_secs (90) must be longer than server.ws_ping_interval_secs (90)"));
+        assert!(error.contains("server.stream_max_lifetime_secs must be greater than 0"));
+
+        // Without an idle timeout any ping interval will do
+        config.server.ws_idle_timeout_secs = 0;
+        config.server.stream_max_lifetime_secs = None;
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_write_rate_limit_validation() {
+        let mut config = Config::default();
+        config.server.write_rate_limit = Some(WriteRateLimitConfig {
+            requests_per_second: 0.5,
+            burst: 3,
+        });
+        config.validate().unwrap();
+
+        config.server.write_rate_limit = Some(WriteRateLimitConfig {
+            requests_per_second: 0.0,
+            burst: 0,
+        });
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("requests_per_second must be greater than 0, got 0"));
+        assert!(error.contains("burst must be at least 1"));
+    }
+
+    #[test]
+    fn test_deadband_validation() {
+        let config = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(RegisterBuilder::holding("level", 0).deadband(0.5))
+                    .register(RegisterBuilder::holding("flow", 1).deadband_percent(1.0, 0.0, 10.0))
+                    .register(
+                        RegisterBuilder::holding("pressure", 2).deadband_percent(1.0, 0.0, 10000.0),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let widths: Vec<_> = config.devices[0]
+            .registers
+            .iter()
+            .map(RegisterConfig::deadband_width)
+            .collect();
+        assert_eq!(widths, [Some(0.5), Some(0.1), Some(100.0)]);
+
+        let error = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(
+                        RegisterBuilder::holding("both", 0)
+                            .deadband(0.5)
+                            .deadband_percent(1.0, 0.0, 10.0),
+                    )
+                    .register(
+                        RegisterBuilder::holding("inverted", 1).deadband_percent(1.0, 10.0, 0.0),
+                    )
+                    .register(
+                        RegisterBuilder::holding("too_wide", 2).deadband_percent(150.0, 0.0, 10.0),
+                    )
+                    .register(RegisterBuilder::holding("negative", 3).deadband(-1.0)),
+            )
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("register 'both' sets both deadband and deadband_percent"));
+        assert!(error.contains("register 'inverted' range_min must be less than range_max"));
+        assert!(error.contains("register 'too_wide' deadband_percent must be between 0 and 100"));
+        assert!(error.contains("register 'negative' deadband must be a non-negative number"));
+
+        // A percentage without the range it applies to
+        let mut config = config;
+        config.devices[0].registers[1].range_max = None;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("register 'flow' with deadband_percent needs range_min and range_max")
+        );
+    }
+
+    #[test]
+    fn test_word_order_names() {
+        for (name, word_order) in [
+            ("big", WordOrder::Big),
+            ("cdab", WordOrder::Little),
+            ("byte_swap", WordOrder::ByteSwap),
+            ("badc", WordOrder::ByteSwap),
+            ("word_byte_swap", WordOrder::WordByteSwap),
+            ("dcba", WordOrder::WordByteSwap),
+        ] {
+            assert_eq!(serde_yaml::from_str::<WordOrder>(name).unwrap(), word_order);
+        }
+        assert!(serde_yaml::from_str::<WordOrder>("middle").is_err());
+    }
+
+    #[test]
+    fn test_pipeline_validation() {
+        let error = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                    .register(
+                        RegisterBuilder::holding("ok", 0)
+                            .clamp(0.0, 100.0)
+                            .pipeline([
+                                ConversionStep::Clamp,
+                                ConversionStep::Round,
+                                ConversionStep::Scale,
+                            ]),
+                    )
+                    .register(RegisterBuilder::holding("crossed", 1).clamp(10.0, 0.0))
+                    .register(RegisterBuilder::holding("twice", 2).pipeline([
+                        ConversionStep::Scale,
+                        ConversionStep::Scale,
+                        ConversionStep::Round,
+                    ])),
+            )
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(!error.contains("'ok'"));
+        assert!(error.contains("register 'crossed' clamp_min must not be greater than clamp_max"));
+        assert!(error.contains("register 'twice' pipeline must list Scale once, not 2 times"));
+        assert!(error.contains("register 'twice' pipeline must list Clamp once, not 0 times"));
+
+        let register: RegisterConfig = serde_yaml::from_str(
+            r#"
+name: level
+address: 0
+register_type: holding
+count: 1
+data_type: u16
+clamp_min: 0
+pipeline: [clamp, scale, round]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            register.conversion_steps(),
+            [
+                ConversionStep::Clamp,
+                ConversionStep::Scale,
+                ConversionStep::Round
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serial_bus_validation() {
+        ConfigBuilder::new()
+            .device(DeviceBuilder::rtu("meter-1", "/dev/ttyUSB0", 9600, 1))
+            .device(DeviceBuilder::rtu("meter-2", "/dev/ttyUSB0", 9600, 2))
+            .device(DeviceBuilder::rtu("meter-3", "/dev/ttyUSB1", 19200, 1))
+            .build()
+            .unwrap();
+
+        let error = ConfigBuilder::new()
+            .device(DeviceBuilder::rtu("meter-1", "/dev/ttyUSB0", 9600, 1))
+            .device(DeviceBuilder::rtu("meter-2", "/dev/ttyUSB0", 19200, 1))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(
+            "device 'meter-2': serial settings differ from device 'meter-1' on the same port /dev/ttyUSB0"
+        ));
+        assert!(error.contains(
+            "device 'meter-2': unit_id 1 is already used by device 'meter-1' on port /dev/ttyUSB0"
+        ));
+    }
+
+    #[test]
+    fn test_virtual_register_validation() {
+        let device = |registers: Vec<RegisterBuilder>| {
+            registers
+                .into_iter()
+                .fold(DeviceBuilder::tcp("meter", "localhost", 502, 1), |d, r| {
+                    d.register(r)
+                })
+        };
+        let phase = |name: &str, address| RegisterBuilder::holding(name, address);
+
+        // Virtual registers are ordered after the ones they reference
+        let ok = device(vec![
+            RegisterBuilder::computed("apparent", "total / 0.9"),
+            RegisterBuilder::computed("total", "phase_a + phase_b"),
+            phase("phase_a", 0),
+            phase("phase_b", 1),
+        ]);
+        let ordered = ok.clone().build().virtual_registers().unwrap();
+        let names: Vec<_> = ordered.iter().map(|(r, _)| r.name.as_str()).collect();
+        assert_eq!(names, ["total", "apparent"]);
+        assert!(ConfigBuilder::new().device(ok).build().is_ok());
+
+        let build = |registers| ConfigBuilder::new().device(device(registers)).build();
+        let error = build(vec![RegisterBuilder::computed(
+            "total",
+            "phase_a + phase_c",
+        )])
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("references unknown register 'phase_a'"));
+        assert!(error.contains("references unknown register 'phase_c'"));
+
+        let error = build(vec![
+            RegisterBuilder::computed("a", "b + 1"),
+            RegisterBuilder::computed("b", "a * 2"),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("form a cycle: a -> b -> a"));
+
+        let error = build(vec![RegisterBuilder::computed("total", "1 +")])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("expression '1 +' is invalid"));
+
+        let error = build(vec![
+            phase("phase_a", 0),
+            RegisterBuilder::computed("total", "phase_a").writable(true),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("virtual register 'total' cannot be writable"));
+    }
+
+    #[test]
+    fn test_bool_format_config() {
         let yaml = r#"
 server:
   host: "0.0.0.0"
   port: 3000
   metrics_enabled: true
 mqtt:
-  host: ""
+  host: localhost
   port: 1883
-  client_id: "rustbridge"
-  topic_prefix: "rustbridge"
+  client_id: test
+  topic_prefix: test
   qos: 1
+bool_format: on_off
 devices:
-  - id: "test"
-    name: "Test"
+  - id: plc-001
+    name: PLC
     device_type: tcp
     connection:
-      host: "localhost"
+      host: localhost
       port: 502
       unit_id: 1
     poll_interval_ms: 1000
     registers:
-      - name: "u16_val"
+      - name: valve
         address: 0
-        register_type: holding
-        count: 1
-        data_type: u16
-      - name: "i16_val"
-        address: 1
-        register_type: holding
-        count: 1
-        data_type: i16
-      - name: "u32_val"
-        address: 2
-        register_type: holding
-        count: 2
-        data_type: u32
-      - name: "i32_val"
-        address: 4
-        register_type: holding
-        count: 2
-        data_type: i32
-      - name: "f32_val"
-        address: 6
-        register_type: holding
-        count: 2
-        data_type: f32
-      - name: "bool_val"
-        address: 8
-        register_type: holding
+        register_type: coil
         count: 1
         data_type: bool
+        bool_format: { on: OPEN, off: CLOSED }
 "#;
         let config = load_config_from_str(yaml).unwrap();
+        assert_eq!(config.bool_format, BoolFormat::Preset(BoolPreset::OnOff));
+        assert_eq!(
+            config.devices[0].registers[0].bool_format,
+            Some(BoolFormat::Custom {
+                on: "OPEN".to_string(),
+                off: "CLOSED".to_string()
+            })
+        );
+        assert!(config.validate().is_ok());
 
-        let regs = &config.devices[0].registers;
-        assert_eq!(regs.len(), 6);
-
-        assert!(matches!(regs[0].data_type, DataType::U16));
-        assert!(matches!(regs[1].data_type, DataType::I16));
-        assert!(matches!(regs[2].data_type, DataType::U32));
-        assert!(matches!(regs[3].data_type, DataType::I32));
-        assert!(matches!(regs[4].data_type, DataType::F32));
-        assert!(matches!(regs[5].data_type, DataType::Bool));
-    }
-
-    #[test]
-    fn test_invalid_yaml() {
-        let yaml = "this is not valid yaml: [";
-        let result = load_config_from_str(yaml);
-        assert!(result.is_err());
+        // Only boolean registers have a boolean representation
+        let result = ConfigBuilder::new()
+            .device(
+                DeviceBuilder::tcp("plc-001", "localhost", 502, 1).register(
+                    RegisterBuilder::holding("speed", 0)
+                        .bool_format(BoolFormat::Preset(BoolPreset::Bool)),
+                ),
+            )
+            .build();
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("not of data type bool"));
     }
 
     #[test]
-    fn test_mqtt_with_auth() {
+    fn test_write_queue_config() {
         let yaml = r#"
 server:
   host: "0.0.0.0"
   port: 3000
   metrics_enabled: true
+  write_queue_capacity: 20
+  write_queue_policy: wait
+  write_queue_wait_ms: 250
 mqtt:
-  host: "mqtt.secure.com"
-  port: 8883
-  client_id: "secure-client"
-  topic_prefix: "secure"
-  qos: 2
-  username: "admin"
-  password: "secret123"
+  host: localhost
+  port: 1883
+  client_id: test
+  topic_prefix: test
+  qos: 1
 devices: []
 "#;
         let config = load_config_from_str(yaml).unwrap();
+        assert_eq!(config.server.write_queue_capacity, 20);
+        assert_eq!(config.server.write_queue_policy, WriteQueuePolicy::Wait);
+        assert!(config.validate().is_ok());
 
-        assert_eq!(config.mqtt.username, Some("admin".to_string()));
-        assert_eq!(config.mqtt.password, Some("secret123".to_string()));
+        let defaults = Config::default();
+        assert_eq!(defaults.server.write_queue_capacity, 100);
+        assert_eq!(defaults.server.write_queue_policy, WriteQueuePolicy::Reject);
+
+        let mut config = Config::default();
+        config.server.write_queue_capacity = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.server.write_queue_policy = WriteQueuePolicy::Wait;
+        config.server.write_queue_wait_ms = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_gateway_id_validation() {
+        // Defaults to the hostname, which is always a valid id
+        let config = Config::default();
+        assert!(is_gateway_id(&config.gateway_id));
+        assert!(config.validate().is_ok());
+
+        for id in ["plant-a.gw01", "gw_2", "edge:7"] {
+            assert!(is_gateway_id(id), "{} rejected", id);
+        }
+        for id in ["", "plant a", "gw/1", "gw\"1\""] {
+            let config = Config {
+                gateway_id: id.to_string(),
+                ..Config::default()
+            };
+            assert!(config.validate().is_err(), "{} accepted", id);
+        }
+    }
+
+    #[test]
+    fn test_connect_retry_backoff() {
+        let retry = ConnectRetryConfig {
+            max_attempts: 3,
+            delay_ms: 1000,
+            max_delay_ms: 5000,
+        };
+
+        assert_eq!(retry.delay(1), std::time::Duration::from_millis(1000));
+        assert_eq!(retry.delay(2), std::time::Duration::from_millis(2000));
+        assert_eq!(retry.delay(3), std::time::Duration::from_millis(4000));
+        assert_eq!(retry.delay(4), std::time::Duration::from_millis(5000));
+        assert_eq!(
+            retry.delay(u32::MAX),
+            std::time::Duration::from_millis(5000)
+        );
+        assert!(retry.should_retry(2));
+        assert!(!retry.should_retry(3));
+
+        // Unlimited by default
+        assert!(ConnectRetryConfig::default().should_retry(u32::MAX));
+    }
+
+    #[test]
+    fn test_reconnect_config() {
+        let reconnect = ReconnectConfig::default();
+        assert_eq!(reconnect.after_failures, 3);
+        assert_eq!(
+            reconnect.backoff.delay(1),
+            std::time::Duration::from_secs(1)
+        );
+        assert_eq!(
+            reconnect.backoff.delay(6),
+            std::time::Duration::from_secs(30)
+        );
+
+        let reconnect: ReconnectConfig =
+            serde_yaml::from_str("after_failures: 5\nmax_attempts: 10\nmax_delay_ms: 60000")
+                .unwrap();
+        assert_eq!(reconnect.after_failures, 5);
+        assert_eq!(reconnect.backoff.max_attempts, 10);
+        assert_eq!(reconnect.backoff.delay_ms, 1000);
+        assert_eq!(reconnect.backoff.max_delay_ms, 60000);
+    }
+
+    #[test]
+    fn test_block_reads_validation() {
+        let device = || {
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("a", 0))
+                .register(RegisterBuilder::holding("b", 1))
+        };
+
+        let config = ConfigBuilder::new()
+            .device(device().block_reads(2))
+            .build()
+            .unwrap();
+        let blocks = config.devices[0].block_reads.as_ref().unwrap();
+        assert_eq!((blocks.max_gap, blocks.max_size), (2, 125));
+
+        let mut config = config;
+        config.devices[0].block_reads.as_mut().unwrap().max_size = 126;
+        let err = config.validate().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("block_reads.max_size must be between 1 and 125, got 126"));
+
+        let err = ConfigBuilder::new()
+            .device(device().block_reads(0).spread_reads(true))
+            .build()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("block_reads cannot be combined with spread_reads"));
+    }
+
+    #[test]
+    fn test_raw_format_to_json() {
+        let raw = [250, 0xBEEF];
+        assert_eq!(
+            RawFormat::Decimal.to_json(&raw),
+            serde_json::json!([250, 48879])
+        );
+        assert_eq!(
+            RawFormat::Hex.to_json(&raw),
+            serde_json::json!(["0x00FA", "0xBEEF"])
+        );
     }
 
     #[test]
@@ -0,0 +1,704 @@
+//! Fluent builders for constructing a [`Config`] in code
+//!
+//! ```
+//! use rustbridge::config::{ConfigBuilder, DataType, DeviceBuilder, RegisterBuilder};
+//!
+//! let config = ConfigBuilder::new()
+//!     .device(
+//!         DeviceBuilder::tcp("plc-001", "192.168.1.100", 502, 1)
+//!             .register(RegisterBuilder::holding("temperature", 0).data_type(DataType::I16))
+//!             .register(RegisterBuilder::input("flow", 10).data_type(DataType::F32)),
+//!     )
+//!     .build()
+//!     .unwrap();
+//!
+//! assert_eq!(config.devices[0].registers[1].count, 2);
+//! ```
+
+use anyhow::Result;
+
+use crate::modbus::expression::Formula;
+
+use super::{
+    AddressFamily, AuthConfig, BlockReadConfig, BoolFormat, CircuitBreakerConfig, Config,
+    ConnectRetryConfig, ConnectionConfig, ConversionStep, DataType, DeviceConfig, DeviceType,
+    HeartbeatConfig, HeartbeatMode, OverlapPolicy, RateLimitGroupConfig, ReconnectConfig,
+    RegisterConfig, RegisterType, RtuConnection, SunSpecConfig, TcpConnection, TextEncoding,
+    WordOrder,
+};
+
+/// Builder for a complete [`Config`], starting from the defaults
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// HTTP API listen address
+    pub fn server(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.server.host = host.into();
+        self.config.server.port = port;
+        self
+    }
+
+    /// Enable or disable the Prometheus metrics endpoint
+    pub fn metrics_enabled(mut self, enabled: bool) -> Self {
+        self.config.server.metrics_enabled = enabled;
+        self
+    }
+
+    /// How long API writes wait for the device
+    pub fn write_timeout_ms(mut self, timeout: u64) -> Self {
+        self.config.server.write_timeout_ms = timeout;
+        self
+    }
+
+    /// Enable MQTT publishing to the given broker
+    pub fn mqtt(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.mqtt.enabled = true;
+        self.config.mqtt.host = host.into();
+        self.config.mqtt.port = port;
+        self
+    }
+
+    /// MQTT client ID
+    pub fn mqtt_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.config.mqtt.client_id = client_id.into();
+        self
+    }
+
+    /// Global MQTT topic prefix
+    pub fn mqtt_topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.mqtt.topic_prefix = prefix.into();
+        self
+    }
+
+    /// MQTT broker credentials
+    pub fn mqtt_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.config.mqtt.username = Some(username.into());
+        self.config.mqtt.password = Some(password.into());
+        self
+    }
+
+    /// Broadcast registers only on change, and at least every
+    /// `heartbeat_ms`
+    pub fn report_by_exception(mut self, heartbeat_ms: u64) -> Self {
+        self.config.report_by_exception.enabled = true;
+        self.config.report_by_exception.heartbeat_ms = heartbeat_ms;
+        self
+    }
+
+    /// Qualify register names in outputs, e.g.
+    /// `{device_type}.{device_id}.{name}`
+    pub fn register_name_template(mut self, template: impl Into<String>) -> Self {
+        self.config.register_name_template = Some(template.into());
+        self
+    }
+
+    /// Limit the devices of group `id` to `max_reads_per_second` together
+    pub fn rate_limit_group(mut self, id: &str, max_reads_per_second: f64) -> Self {
+        self.config.rate_limit_groups.push(RateLimitGroupConfig {
+            id: id.to_string(),
+            max_reads_per_second,
+        });
+        self
+    }
+
+    /// Fail startup unless every device connects within `timeout_ms`
+    pub fn require_all_devices(mut self, timeout_ms: u64) -> Self {
+        self.config.startup.require_all_devices = true;
+        self.config.startup.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Spread the poll cycles of devices with the same interval
+    pub fn stagger_polls(mut self) -> Self {
+        self.config.startup.stagger_polls = true;
+        self
+    }
+
+    /// API authentication settings
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.config.auth = auth;
+        self
+    }
+
+    /// Add a device
+    pub fn device(mut self, device: DeviceBuilder) -> Self {
+        self.config.devices.push(device.build());
+        self
+    }
+
+    /// Validate and return the configuration
+    pub fn build(self) -> Result<Config> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Builder for a single [`DeviceConfig`]
+#[derive(Debug, Clone)]
+pub struct DeviceBuilder {
+    device: DeviceConfig,
+}
+
+impl DeviceBuilder {
+    /// Modbus TCP device
+    pub fn tcp(id: impl Into<String>, host: impl Into<String>, port: u16, unit_id: u8) -> Self {
+        Self::new(
+            id.into(),
+            DeviceType::Tcp,
+            ConnectionConfig::Tcp(TcpConnection {
+                host: host.into(),
+                port,
+                unit_id,
+                slave_id: None,
+                address_family: AddressFamily::Any,
+            }),
+        )
+    }
+
+    /// Modbus RTU device on a serial port, using 8N1 framing
+    pub fn rtu(
+        id: impl Into<String>,
+        port: impl Into<String>,
+        baud_rate: u32,
+        unit_id: u8,
+    ) -> Self {
+        Self::new(
+            id.into(),
+            DeviceType::Rtu,
+            ConnectionConfig::Rtu(RtuConnection {
+                port: port.into(),
+                baud_rate,
+                data_bits: 8,
+                stop_bits: 1,
+                parity: "none".to_string(),
+                unit_id,
+            }),
+        )
+    }
+
+    fn new(id: String, device_type: DeviceType, connection: ConnectionConfig) -> Self {
+        Self {
+            device: DeviceConfig {
+                name: id.clone(),
+                id,
+                device_type,
+                connection,
+                poll_interval_ms: 1000,
+                read_timeout_ms: DeviceConfig::default_read_timeout_ms(),
+                registers: vec![],
+                mask_write: false,
+                mqtt_topic_prefix: None,
+                wire_log: false,
+                offline_error_ratio: None,
+                spread_reads: false,
+                register_overlaps: OverlapPolicy::default(),
+                parallel_reads: 1,
+                circuit_breaker: None,
+                connect_retry: ConnectRetryConfig::default(),
+                reconnect: ReconnectConfig::default(),
+                max_cycle_ms: None,
+                prioritize_writes: true,
+                depends_on: vec![],
+                log_level: None,
+                reconnect_after_timeouts: None,
+                reset_stats_on_reconnect: false,
+                sunspec: None,
+                heartbeat: None,
+                max_reads_per_second: None,
+                rate_limit_group: None,
+                block_reads: None,
+            },
+        }
+    }
+
+    /// Address family used when connecting to a TCP device by hostname
+    pub fn address_family(mut self, family: AddressFamily) -> Self {
+        if let ConnectionConfig::Tcp(tcp) = &mut self.device.connection {
+            tcp.address_family = family;
+        }
+        self
+    }
+
+    /// Human-readable name (defaults to the ID)
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.device.name = name.into();
+        self
+    }
+
+    /// Polling interval (defaults to 1000 ms)
+    pub fn poll_interval_ms(mut self, interval: u64) -> Self {
+        self.device.poll_interval_ms = interval;
+        self
+    }
+
+    /// Give up on reads the device has not answered after `timeout_ms`
+    pub fn read_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.device.read_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Add a register
+    pub fn register(mut self, register: RegisterBuilder) -> Self {
+        self.device.registers.push(register.build());
+        self
+    }
+
+    /// Device supports FC22 Mask Write Register
+    pub fn mask_write(mut self, enabled: bool) -> Self {
+        self.device.mask_write = enabled;
+        self
+    }
+
+    /// MQTT topic prefix for this device
+    pub fn mqtt_topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.device.mqtt_topic_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Log Modbus traffic for this device
+    pub fn wire_log(mut self, enabled: bool) -> Self {
+        self.device.wire_log = enabled;
+        self
+    }
+
+    /// Fraction of failed reads that marks the device offline
+    pub fn offline_error_ratio(mut self, ratio: f64) -> Self {
+        self.device.offline_error_ratio = Some(ratio);
+        self
+    }
+
+    /// Pace reads evenly across the poll interval
+    pub fn spread_reads(mut self, enabled: bool) -> Self {
+        self.device.spread_reads = enabled;
+        self
+    }
+
+    /// Maximum concurrent reads (Modbus TCP only)
+    pub fn parallel_reads(mut self, reads: usize) -> Self {
+        self.device.parallel_reads = reads;
+        self
+    }
+
+    /// Open a circuit breaker after `failure_threshold` failed cycles and
+    /// probe every `probe_interval_ms`
+    pub fn circuit_breaker(mut self, failure_threshold: u32, probe_interval_ms: u64) -> Self {
+        self.device.circuit_breaker = Some(CircuitBreakerConfig {
+            failure_threshold,
+            probe_interval_ms,
+        });
+        self
+    }
+
+    /// Cut poll cycles short after `max_cycle_ms`
+    pub fn max_cycle_ms(mut self, max_cycle_ms: u64) -> Self {
+        self.device.max_cycle_ms = Some(max_cycle_ms);
+        self
+    }
+
+    /// Run writes between the reads of a poll cycle (default) or only
+    /// between cycles
+    pub fn prioritize_writes(mut self, enabled: bool) -> Self {
+        self.device.prioritize_writes = enabled;
+        self
+    }
+
+    /// Wait for another device's first successful read before connecting
+    pub fn depends_on(mut self, device_id: impl Into<String>) -> Self {
+        self.device.depends_on.push(device_id.into());
+        self
+    }
+
+    /// Reopen the connection after `failures` reads in a row got no answer,
+    /// retrying with the given backoff while the device is unreachable
+    pub fn reconnect(mut self, failures: u32, backoff: ConnectRetryConfig) -> Self {
+        self.device.reconnect = ReconnectConfig {
+            after_failures: failures,
+            backoff,
+        };
+        self
+    }
+
+    /// Reconnect after `timeouts` reads in a row timed out
+    pub fn reconnect_after_timeouts(mut self, timeouts: u32) -> Self {
+        self.device.reconnect_after_timeouts = Some(timeouts);
+        self
+    }
+
+    /// Reset the rolling read statistics after every successful reconnect
+    pub fn reset_stats_on_reconnect(mut self) -> Self {
+        self.device.reset_stats_on_reconnect = true;
+        self
+    }
+
+    /// Discover SunSpec models from `base_address`, or the usual addresses
+    pub fn sunspec(mut self, base_address: Option<u16>) -> Self {
+        self.device.sunspec = Some(SunSpecConfig { base_address });
+        self
+    }
+
+    /// Send at most `rate` requests per second to the device
+    pub fn max_reads_per_second(mut self, rate: f64) -> Self {
+        self.device.max_reads_per_second = Some(rate);
+        self
+    }
+
+    /// Read adjacent registers with one request, allowing `max_gap` unused
+    /// addresses between them
+    pub fn block_reads(mut self, max_gap: u16) -> Self {
+        self.device.block_reads = Some(BlockReadConfig {
+            max_gap,
+            ..BlockReadConfig::default()
+        });
+        self
+    }
+
+    /// Count the device's requests towards a rate limit group
+    pub fn rate_limit_group(mut self, group: &str) -> Self {
+        self.device.rate_limit_group = Some(group.to_string());
+        self
+    }
+
+    /// Write the watchdog register at `address` every `interval_ms`
+    pub fn heartbeat(mut self, address: u16, interval_ms: u64, mode: HeartbeatMode) -> Self {
+        self.device.heartbeat = Some(HeartbeatConfig {
+            address,
+            interval_ms,
+            mode,
+        });
+        self
+    }
+
+    /// Log this device's messages at `level` instead of the global level
+    pub fn log_level(mut self, level: &str) -> Self {
+        self.device.log_level = Some(level.to_string());
+        self
+    }
+
+    /// Retry the initial connection up to `max_attempts` times (0 = keep
+    /// retrying), starting `delay_ms` apart
+    pub fn connect_retry(mut self, max_attempts: u32, delay_ms: u64) -> Self {
+        self.device.connect_retry.max_attempts = max_attempts;
+        self.device.connect_retry.delay_ms = delay_ms;
+        self.device.connect_retry.max_delay_ms =
+            self.device.connect_retry.max_delay_ms.max(delay_ms);
+        self
+    }
+
+    /// How overlapping register addresses are reported
+    pub fn register_overlaps(mut self, policy: OverlapPolicy) -> Self {
+        self.device.register_overlaps = policy;
+        self
+    }
+
+    /// Return the device configuration (validated as part of the [`Config`])
+    pub fn build(self) -> DeviceConfig {
+        self.device
+    }
+}
+
+/// Builder for a single [`RegisterConfig`]
+///
+/// Registers default to `u16`; the register count follows the data type
+/// unless set explicitly.
+#[derive(Debug, Clone)]
+pub struct RegisterBuilder {
+    register: RegisterConfig,
+    count: Option<u16>,
+}
+
+impl RegisterBuilder {
+    pub fn new(name: impl Into<String>, address: u16, register_type: RegisterType) -> Self {
+        let data_type = match register_type {
+            RegisterType::Coil | RegisterType::Discrete => DataType::Bool,
+            RegisterType::Holding | RegisterType::Input | RegisterType::Virtual => DataType::U16,
+        };
+        Self {
+            register: RegisterConfig {
+                name: name.into(),
+                address,
+                register_type,
+                count: 1,
+                data_type,
+                signed_bits: None,
+                bit: None,
+                unit: None,
+                scale: None,
+                offset: None,
+                decimals: None,
+                scale_from_register: None,
+                expression: None,
+                formula: None,
+                expose_words: false,
+                encoding: TextEncoding::default(),
+                swap_bytes: false,
+                word_order: WordOrder::default(),
+                unit_id: None,
+                writable: false,
+                bool_format: None,
+                publish_interval_ms: None,
+                fault_value: None,
+                count_edges: false,
+                deadband: None,
+                deadband_percent: None,
+                range_min: None,
+                range_max: None,
+                clamp_min: None,
+                clamp_max: None,
+                pipeline: None,
+            },
+            count: None,
+        }
+    }
+
+    /// Holding register (FC3)
+    pub fn holding(name: impl Into<String>, address: u16) -> Self {
+        Self::new(name, address, RegisterType::Holding)
+    }
+
+    /// Input register (FC4)
+    pub fn input(name: impl Into<String>, address: u16) -> Self {
+        Self::new(name, address, RegisterType::Input)
+    }
+
+    /// Coil (FC1)
+    pub fn coil(name: impl Into<String>, address: u16) -> Self {
+        Self::new(name, address, RegisterType::Coil)
+    }
+
+    /// Discrete input (FC2)
+    pub fn discrete(name: impl Into<String>, address: u16) -> Self {
+        Self::new(name, address, RegisterType::Discrete)
+    }
+
+    pub fn data_type(mut self, data_type: DataType) -> Self {
+        self.register.data_type = data_type;
+        self
+    }
+
+    /// Sign-extend the value from its lowest `bits` bits
+    pub fn signed_bits(mut self, bits: u8) -> Self {
+        self.register.signed_bits = Some(bits);
+        self
+    }
+
+    /// Compute the value from the decoded number `x` instead of scale and
+    /// offset
+    pub fn formula(mut self, formula: Formula) -> Self {
+        self.register.formula = Some(formula);
+        self
+    }
+
+    /// Read only this bit of the register's word
+    pub fn bit(mut self, bit: u8) -> Self {
+        self.register.bit = Some(bit);
+        self
+    }
+
+    /// Number of registers to read (overrides the data type's width)
+    pub fn count(mut self, count: u16) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn unit(mut self, unit: impl Into<String>) -> Self {
+        self.register.unit = Some(unit.into());
+        self
+    }
+
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.register.scale = Some(scale);
+        self
+    }
+
+    pub fn offset(mut self, offset: f64) -> Self {
+        self.register.offset = Some(offset);
+        self
+    }
+
+    /// Publish the underlying words as `<name>.wordN` diagnostics
+    pub fn expose_words(mut self, enabled: bool) -> Self {
+        self.register.expose_words = enabled;
+        self
+    }
+
+    /// Character encoding of a string register
+    pub fn encoding(mut self, encoding: TextEncoding) -> Self {
+        self.register.encoding = encoding;
+        self
+    }
+
+    /// Low byte first within each register
+    pub fn swap_bytes(mut self, enabled: bool) -> Self {
+        self.register.swap_bytes = enabled;
+        self
+    }
+
+    /// Order of the two words of a 32-bit value
+    pub fn word_order(mut self, word_order: WordOrder) -> Self {
+        self.register.word_order = word_order;
+        self
+    }
+
+    /// Round the value to `decimals` places
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.register.decimals = Some(decimals);
+        self
+    }
+
+    /// A virtual register computed from other registers of the device
+    pub fn computed(name: impl Into<String>, expression: impl Into<String>) -> Self {
+        let mut builder = Self::new(name, 0, RegisterType::Virtual);
+        builder.register.expression = Some(expression.into());
+        builder
+    }
+
+    /// Apply the exponent held by another register of the device
+    pub fn scale_from_register(mut self, register: impl Into<String>) -> Self {
+        self.register.scale_from_register = Some(register.into());
+        self
+    }
+
+    /// Allow writes to this register through the API
+    pub fn writable(mut self, enabled: bool) -> Self {
+        self.register.writable = enabled;
+        self
+    }
+
+    /// Representation of this boolean register in MQTT payloads
+    pub fn bool_format(mut self, format: BoolFormat) -> Self {
+        self.register.bool_format = Some(format);
+        self
+    }
+
+    /// Publish `value` with bad quality when a read fails
+    pub fn fault_value(mut self, value: f64) -> Self {
+        self.register.fault_value = Some(value);
+        self
+    }
+
+    /// Count rising edges of this coil or discrete input
+    pub fn count_edges(mut self) -> Self {
+        self.register.count_edges = true;
+        self
+    }
+
+    /// Report a change only once the value moved by `deadband`
+    pub fn deadband(mut self, deadband: f64) -> Self {
+        self.register.deadband = Some(deadband);
+        self
+    }
+
+    /// Report a change only once the value moved by `percent` of the
+    /// measuring range `min`..`max`
+    pub fn deadband_percent(mut self, percent: f64, min: f64, max: f64) -> Self {
+        self.register.deadband_percent = Some(percent);
+        self.register.range_min = Some(min);
+        self.register.range_max = Some(max);
+        self
+    }
+
+    /// Limit the value to `min`..`max`
+    pub fn clamp(mut self, min: f64, max: f64) -> Self {
+        self.register.clamp_min = Some(min);
+        self.register.clamp_max = Some(max);
+        self
+    }
+
+    /// Order of the conversion steps after decoding
+    pub fn pipeline(mut self, steps: impl Into<Vec<ConversionStep>>) -> Self {
+        self.register.pipeline = Some(steps.into());
+        self
+    }
+
+    /// Publish to MQTT at most every `interval_ms`
+    pub fn publish_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.register.publish_interval_ms = Some(interval_ms);
+        self
+    }
+
+    /// Read from another unit id than the device's
+    pub fn unit_id(mut self, unit_id: u8) -> Self {
+        self.register.unit_id = Some(unit_id);
+        self
+    }
+
+    /// Return the register configuration
+    pub fn build(self) -> RegisterConfig {
+        let mut register = self.register;
+        // Strings have no natural width; set `count` for the text length
+        register.count = self.count.unwrap_or(register.data_type.word_count());
+        register
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_tcp_device_with_two_registers() {
+        let config = ConfigBuilder::new()
+            .server("127.0.0.1", 8080)
+            .device(
+                DeviceBuilder::tcp("plc-001", "192.168.1.100", 502, 1)
+                    .name("Main PLC")
+                    .poll_interval_ms(500)
+                    .register(
+                        RegisterBuilder::holding("temperature", 0)
+                            .data_type(DataType::I16)
+                            .scale(0.1)
+                            .unit("°C"),
+                    )
+                    .register(RegisterBuilder::input("energy", 100).data_type(DataType::U32)),
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(config.server.port, 8080);
+        assert!(!config.mqtt.enabled);
+
+        let device = &config.devices[0];
+        assert_eq!(device.name, "Main PLC");
+        assert_eq!(device.poll_interval_ms, 500);
+        assert!(matches!(device.connection, ConnectionConfig::Tcp(ref tcp) if tcp.port == 502));
+        assert_eq!(device.registers.len(), 2);
+        assert_eq!(device.registers[0].count, 1);
+        assert_eq!(device.registers[0].scale, Some(0.1));
+        assert_eq!(device.registers[1].count, 2);
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let device = DeviceBuilder::rtu("sensor", "/dev/ttyUSB0", 9600, 3).build();
+        assert_eq!(device.name, "sensor");
+        assert!(
+            matches!(device.connection, ConnectionConfig::Rtu(ref rtu) if rtu.parity == "none")
+        );
+
+        let coil = RegisterBuilder::coil("pump", 5).build();
+        assert!(matches!(coil.data_type, DataType::Bool));
+        assert_eq!(coil.count, 1);
+
+        let wide = RegisterBuilder::holding("block", 0).count(8).build();
+        assert_eq!(wide.count, 8);
+    }
+
+    #[test]
+    fn test_builder_validates() {
+        let result = ConfigBuilder::new()
+            .device(DeviceBuilder::tcp("plc-001", "localhost", 502, 1).poll_interval_ms(0))
+            .build();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,40 @@
+//! HTTP client for outgoing requests, e.g. InfluxDB writes and JWKS fetches
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+
+/// HTTP client for the URL's scheme; `https://` verifies servers with the
+/// system's root certificates
+pub enum HttpClient {
+    Plain(Client<HttpConnector, Body>),
+    Tls(Client<hyper_rustls::HttpsConnector<HttpConnector>, Body>),
+}
+
+impl HttpClient {
+    pub fn new(url: &str) -> Result<Self> {
+        let builder = Client::builder(TokioExecutor::new());
+        if !url.starts_with("https://") {
+            return Ok(Self::Plain(builder.build_http()));
+        }
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .context("Failed to load the system's root certificates")?
+            .https_only()
+            .enable_http1()
+            .build();
+        Ok(Self::Tls(builder.build(connector)))
+    }
+
+    pub async fn request(
+        &self,
+        request: hyper::Request<Body>,
+    ) -> Result<hyper::Response<hyper::body::Incoming>> {
+        let response = match self {
+            Self::Plain(client) => client.request(request).await,
+            Self::Tls(client) => client.request(request).await,
+        };
+        Ok(response?)
+    }
+}
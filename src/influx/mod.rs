@@ -0,0 +1,487 @@
+//! InfluxDB writer
+//!
+//! Writes register updates to InfluxDB in line protocol, one point per
+//! update:
+//! `{measurement},device={device_id},register={name} value=23.5,quality="good" {timestamp}`
+//!
+//! Points are collected into batches, which are written when they are full
+//! (`batch_size`) or `flush_interval_ms` after their first point, whichever
+//! comes first. InfluxDB 2.x is written to `/api/v2/write` with `org` and
+//! `bucket`, 1.x to `/write` with `database`.
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+use crate::api::RegisterUpdate;
+use crate::config::InfluxConfig;
+use crate::http::HttpClient;
+use crate::metrics;
+
+/// Longest error message kept from a rejected write
+const MAX_ERROR_BODY: usize = 1024;
+
+/// How long writing one batch may take
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes register updates to InfluxDB
+pub struct InfluxPublisher {
+    client: HttpClient,
+    write_url: String,
+    token: Option<String>,
+    measurement: String,
+    flush_interval: Duration,
+    batch_size: usize,
+    /// Give up on a write after this long, so an unresponsive server does
+    /// not hold up the writer
+    timeout: Duration,
+}
+
+impl InfluxPublisher {
+    /// Create a writer for the configured server
+    pub fn new(config: &InfluxConfig) -> Result<Self> {
+        Ok(Self {
+            client: HttpClient::new(&config.url)?,
+            write_url: write_url(config)?,
+            token: config.token.clone(),
+            measurement: config.measurement.clone(),
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+            batch_size: config.batch_size,
+            timeout: WRITE_TIMEOUT,
+        })
+    }
+
+    /// Write updates from the broadcast channel until `stop` resolves, then
+    /// write the points still waiting
+    pub async fn start_publishing(
+        &self,
+        mut update_rx: broadcast::Receiver<RegisterUpdate>,
+        stop: impl Future<Output = ()>,
+    ) {
+        info!("InfluxDB writer started");
+        let mut batch = Batch::new(self.batch_size, self.flush_interval);
+        tokio::pin!(stop);
+
+        loop {
+            let received = tokio::select! {
+                received = update_rx.recv() => received,
+                _ = sleep_until(batch.deadline()) => {
+                    if let Some(lines) = batch.take() {
+                        self.write(lines).await;
+                    }
+                    continue;
+                }
+                _ = &mut stop => break,
+            };
+            match received {
+                Ok(update) => {
+                    let line = line(&self.measurement, &update);
+                    if let Some(lines) = batch.push(line, Instant::now()) {
+                        self.write(lines).await;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("InfluxDB writer lagged, missed {} updates", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        if let Some(lines) = batch.take() {
+            self.write(lines).await;
+        }
+        info!("InfluxDB writer stopped");
+    }
+
+    /// Write one batch; a batch the server does not accept is dropped
+    async fn write(&self, lines: Vec<String>) {
+        let points = lines.len();
+        match self.send(lines.join("\n")).await {
+            Ok(()) => {
+                debug!("Wrote {} points to InfluxDB", points);
+                metrics::record_influx_write(points, true);
+            }
+            Err(e) => {
+                error!("Failed to write {} points to InfluxDB: {:#}", points, e);
+                metrics::record_influx_write(points, false);
+            }
+        }
+    }
+
+    async fn send(&self, body: String) -> Result<()> {
+        tokio::time::timeout(self.timeout, self.request(body))
+            .await
+            .context("Timed out")?
+    }
+
+    async fn request(&self, body: String) -> Result<()> {
+        let mut request =
+            hyper::Request::post(&self.write_url).header(CONTENT_TYPE, "text/plain; charset=utf-8");
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Token {}", token));
+        }
+        let response = self.client.request(request.body(Body::from(body))?).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = axum::body::to_bytes(Body::new(response.into_body()), MAX_ERROR_BODY)
+            .await
+            .unwrap_or_default();
+        anyhow::bail!(
+            "InfluxDB answered {}: {}",
+            status,
+            String::from_utf8_lossy(&body).trim()
+        )
+    }
+}
+
+/// The write endpoint with the target database or bucket in its query
+fn write_url(config: &InfluxConfig) -> Result<String> {
+    let base = config.url.trim_end_matches('/');
+    // Timestamps are written in nanoseconds
+    let (path, query) = match (&config.org, &config.bucket, &config.database) {
+        (Some(org), Some(bucket), _) => (
+            "api/v2/write",
+            serde_urlencoded::to_string([
+                ("org", org.as_str()),
+                ("bucket", bucket),
+                ("precision", "ns"),
+            ])?,
+        ),
+        (_, _, Some(database)) => (
+            "write",
+            serde_urlencoded::to_string([("db", database.as_str()), ("precision", "ns")])?,
+        ),
+        _ => anyhow::bail!("influx needs a bucket (InfluxDB 2.x) or a database (1.x)"),
+    };
+    Ok(format!("{}/{}?{}", base, path, query))
+}
+
+/// Points waiting to be written
+#[derive(Debug)]
+struct Batch {
+    lines: Vec<String>,
+    size: usize,
+    flush_interval: Duration,
+    /// When the batch is written even if it is not full
+    deadline: Option<Instant>,
+}
+
+impl Batch {
+    fn new(size: usize, flush_interval: Duration) -> Self {
+        Self {
+            lines: Vec::new(),
+            size,
+            flush_interval,
+            deadline: None,
+        }
+    }
+
+    /// Add a point, returning the batch once it is full
+    fn push(&mut self, line: String, now: Instant) -> Option<Vec<String>> {
+        if self.lines.is_empty() {
+            self.deadline = Some(now + self.flush_interval);
+        }
+        self.lines.push(line);
+        if self.lines.len() >= self.size {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The points collected so far, if any
+    fn take(&mut self) -> Option<Vec<String>> {
+        self.deadline = None;
+        if self.lines.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.lines))
+    }
+}
+
+/// Sleep until `deadline`, forever if there is none
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Line protocol for one register update
+///
+/// Values that line protocol cannot represent (NaN, infinity) are left out;
+/// the quality is always written, so the point stays valid.
+pub fn line(measurement: &str, update: &RegisterUpdate) -> String {
+    let mut line = format!(
+        "{},device={},register={}",
+        escape(measurement, &[',', ' ']),
+        escape(&update.device_id, &[',', '=', ' ']),
+        escape(&update.register_name, &[',', '=', ' ']),
+    );
+    if let Some(unit) = update.unit.as_deref().filter(|unit| !unit.is_empty()) {
+        line.push_str(",unit=");
+        line.push_str(&escape(unit, &[',', '=', ' ']));
+    }
+
+    let mut fields = Vec::with_capacity(3);
+    if update.value.is_finite() {
+        fields.push(format!("value={}", update.value));
+    }
+    if let Some(text) = &update.text {
+        fields.push(format!("text={}", quote(text)));
+    }
+    let quality = serde_json::to_value(update.quality)
+        .ok()
+        .and_then(|quality| quality.as_str().map(str::to_string))
+        .unwrap_or_default();
+    fields.push(format!("quality={}", quote(&quality)));
+    line.push(' ');
+    line.push_str(&fields.join(","));
+
+    let timestamp = chrono::DateTime::parse_from_rfc3339(&update.timestamp)
+        .ok()
+        .and_then(|timestamp| timestamp.timestamp_nanos_opt());
+    // Without one the server's time is used
+    if let Some(timestamp) = timestamp {
+        line.push(' ');
+        line.push_str(&timestamp.to_string());
+    }
+    line
+}
+
+/// Backslash-escape the characters special in a measurement, tag key or
+/// tag value
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A string field value
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::reader::Quality;
+    use std::sync::{Arc, Mutex};
+
+    fn update(device_id: &str, register_name: &str, value: f64) -> RegisterUpdate {
+        RegisterUpdate {
+            device_id: device_id.to_string(),
+            register_name: register_name.to_string(),
+            value,
+            decoded: value,
+            raw: vec![],
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            changed: true,
+            old_value: None,
+            timestamp: "2024-01-15T10:30:00.5Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_line_protocol() {
+        let mut temperature = update("plc-001", "temperature", 23.5);
+        temperature.unit = Some("°C".to_string());
+        assert_eq!(
+            line("modbus", &temperature),
+            "modbus,device=plc-001,register=temperature,unit=°C value=23.5,quality=\"good\" 1705314600500000000"
+        );
+
+        // Special characters are escaped, unrepresentable values left out
+        let mut name = update("hall a", "serial,no=1", f64::NAN);
+        name.text = Some("SN \"42\"".to_string());
+        name.quality = Quality::Uncertain;
+        name.timestamp = "not a timestamp".to_string();
+        assert_eq!(
+            line("plant data", &name),
+            "plant\\ data,device=hall\\ a,register=serial\\,no\\=1 text=\"SN \\\"42\\\"\",quality=\"uncertain\""
+        );
+    }
+
+    #[test]
+    fn test_write_url() {
+        let mut config = InfluxConfig {
+            url: "http://influx.local:8086/".to_string(),
+            org: Some("my org".to_string()),
+            bucket: Some("modbus".to_string()),
+            ..InfluxConfig::default()
+        };
+        assert_eq!(
+            write_url(&config).unwrap(),
+            "http://influx.local:8086/api/v2/write?org=my+org&bucket=modbus&precision=ns"
+        );
+
+        config.org = None;
+        config.bucket = None;
+        config.database = Some("plant".to_string());
+        assert_eq!(
+            write_url(&config).unwrap(),
+            "http://influx.local:8086/write?db=plant&precision=ns"
+        );
+    }
+
+    #[test]
+    fn test_batch_flushes_when_full_or_due() {
+        let start = Instant::now();
+        let mut batch = Batch::new(3, Duration::from_secs(1));
+        assert_eq!(batch.deadline(), None);
+        assert_eq!(batch.take(), None);
+
+        // The first point sets the deadline, later ones don't move it
+        assert_eq!(batch.push("a".to_string(), start), None);
+        assert_eq!(batch.deadline(), Some(start + Duration::from_secs(1)));
+        assert_eq!(
+            batch.push("b".to_string(), start + Duration::from_millis(500)),
+            None
+        );
+        assert_eq!(batch.deadline(), Some(start + Duration::from_secs(1)));
+
+        // Full before the deadline
+        assert_eq!(
+            batch.push("c".to_string(), start + Duration::from_millis(600)),
+            Some(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        );
+        assert_eq!(batch.deadline(), None);
+
+        // Due before it is full
+        batch.push("d".to_string(), start + Duration::from_secs(2));
+        assert_eq!(batch.deadline(), Some(start + Duration::from_secs(3)));
+        assert_eq!(batch.take(), Some(vec!["d".to_string()]));
+        assert_eq!(batch.deadline(), None);
+    }
+
+    /// First line and whole body of each write request
+    type Writes = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// An InfluxDB stand-in recording the bodies of its write requests
+    async fn influx_server() -> (String, Writes) {
+        use axum::extract::{RawQuery, State};
+
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let app = axum::Router::new()
+            .route(
+                "/api/v2/write",
+                axum::routing::post(
+                    |State(writes): State<Writes>,
+                     headers: axum::http::HeaderMap,
+                     RawQuery(query): RawQuery,
+                     body: String| async move {
+                        assert_eq!(headers[AUTHORIZATION], "Token secret");
+                        assert!(query.unwrap().contains("bucket=modbus"));
+                        let first = body.lines().next().unwrap_or_default().to_string();
+                        writes.lock().unwrap().push((first, body));
+                        axum::http::StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state(writes.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, writes)
+    }
+
+    #[tokio::test]
+    async fn test_publisher_writes_batches() {
+        let (url, writes) = influx_server().await;
+        let publisher = InfluxPublisher::new(&InfluxConfig {
+            enabled: true,
+            url,
+            org: Some("plant".to_string()),
+            bucket: Some("modbus".to_string()),
+            token: Some("secret".to_string()),
+            flush_interval_ms: 200,
+            batch_size: 2,
+            ..InfluxConfig::default()
+        })
+        .unwrap();
+        let (update_tx, update_rx) = broadcast::channel(16);
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn(async move {
+            publisher
+                .start_publishing(update_rx, async {
+                    let _ = stop_rx.await;
+                })
+                .await
+        });
+        let written = |n: usize| {
+            let writes = writes.clone();
+            async move {
+                for _ in 0..100 {
+                    if writes.lock().unwrap().len() >= n {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                panic!("expected {} writes", n);
+            }
+        };
+
+        // A full batch is written right away
+        let started = Instant::now();
+        update_tx.send(update("plc-001", "a", 1.0)).unwrap();
+        update_tx.send(update("plc-001", "b", 2.0)).unwrap();
+        written(1).await;
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert_eq!(writes.lock().unwrap()[0].1.lines().count(), 2);
+
+        // A partial one once it is due
+        update_tx.send(update("plc-001", "c", 3.0)).unwrap();
+        written(2).await;
+        assert!(writes.lock().unwrap()[1].0.contains("register=c"));
+
+        // And what is left when stopping
+        update_tx.send(update("plc-001", "d", 4.0)).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stop_tx.send(()).unwrap();
+        running.await.unwrap();
+        let writes = writes.lock().unwrap();
+        assert_eq!(writes.len(), 3);
+        assert!(writes[2].0.contains("register=d"));
+    }
+
+    #[tokio::test]
+    async fn test_write_times_out() {
+        // A server that accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let silent = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await
+        });
+        let mut publisher = InfluxPublisher::new(&InfluxConfig {
+            enabled: true,
+            url,
+            database: Some("modbus".to_string()),
+            ..InfluxConfig::default()
+        })
+        .unwrap();
+        publisher.timeout = Duration::from_millis(50);
+
+        let error = publisher.send("m value=1".to_string()).await.unwrap_err();
+        assert!(error.to_string().contains("Timed out"));
+        silent.abort();
+    }
+}
@@ -6,6 +6,9 @@
 pub mod api;
 pub mod bridge;
 pub mod config;
+pub mod http;
+pub mod influx;
 pub mod metrics;
 pub mod modbus;
 pub mod mqtt;
+pub mod storage;
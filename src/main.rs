@@ -4,40 +4,57 @@
 //! Built with Rust for Industry 4.0 edge deployments
 
 use anyhow::Result;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::info;
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 mod api;
 mod bridge;
 mod config;
+mod http;
+mod influx;
 mod metrics;
 mod modbus;
 mod mqtt;
+mod storage;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
+    // Initialize logging (RUST_LOG overrides the default `info` level)
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(base_log_filter())
         .with_target(false)
         .with_thread_ids(true)
         .with_file(true)
         .with_line_number(true)
-        .init();
+        .with_filter_reloading();
+    let log_filter = subscriber.reload_handle();
+    subscriber.init();
 
     print_banner();
 
     info!("Starting RustBridge v{}", env!("CARGO_PKG_VERSION"));
 
     // Load configuration
+    let config_path = config::config_path();
     let config = config::load_config()?;
     info!(
         "Configuration loaded: {} devices configured",
         config.devices.len()
     );
 
+    // Devices with their own log level
+    let directives = bridge::device_log_directives(&config);
+    if !directives.is_empty() {
+        let filter = directives
+            .into_iter()
+            .fold(base_log_filter(), EnvFilter::add_directive);
+        log_filter.reload(filter)?;
+    }
+
     // Initialize bridge
-    let bridge = bridge::Bridge::new(config).await?;
+    let bridge = bridge::Bridge::new(config)
+        .await?
+        .with_config_path(config_path);
 
     // Start the bridge
     bridge.run().await?;
@@ -45,6 +62,10 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn base_log_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
 fn print_banner() {
     println!(
         r#"
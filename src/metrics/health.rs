@@ -0,0 +1,133 @@
+//! Sliding-window read success ratio per register
+//!
+//! Counters only grow, so an intermittent wiring fault from an hour ago
+//! looks the same as one happening now. The window keeps the last N read
+//! outcomes of each register and exposes their success ratio.
+
+use metrics::gauge;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use super::name;
+
+/// Outcomes of recent reads, oldest first, keyed by device and register
+type Outcomes = HashMap<(String, String), VecDeque<bool>>;
+
+/// Recent read outcomes of every register, shared between the pollers and
+/// the API
+#[derive(Debug, Clone)]
+pub struct ReadHealth {
+    window: usize,
+    registers: Arc<Mutex<Outcomes>>,
+}
+
+/// Snapshot of one register's window
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterHealth {
+    /// Window size (reads kept)
+    pub window: usize,
+    /// Reads currently in the window
+    pub reads: usize,
+    pub successes: usize,
+    /// `successes / reads`, or `None` before the first read
+    pub success_ratio: Option<f64>,
+}
+
+impl ReadHealth {
+    /// Keep the last `window` reads of each register (at least one)
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            registers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record a read outcome and update the success ratio gauge
+    pub fn record(&self, device_id: &str, register_name: &str, success: bool) {
+        let ratio = {
+            let mut registers = self.registers.lock().unwrap();
+            let outcomes = registers
+                .entry((device_id.to_string(), register_name.to_string()))
+                .or_default();
+            if outcomes.len() == self.window {
+                outcomes.pop_front();
+            }
+            outcomes.push_back(success);
+            success_ratio(outcomes)
+        };
+
+        gauge!(
+            name("register_success_ratio"),
+            "device" => device_id.to_string(),
+            "register" => register_name.to_string()
+        )
+        .set(ratio);
+    }
+
+    /// Current window of a register, if it has been read at least once
+    pub fn get(&self, device_id: &str, register_name: &str) -> Option<RegisterHealth> {
+        let registers = self.registers.lock().unwrap();
+        let outcomes = registers.get(&(device_id.to_string(), register_name.to_string()))?;
+        Some(RegisterHealth {
+            window: self.window,
+            reads: outcomes.len(),
+            successes: outcomes.iter().filter(|&&ok| ok).count(),
+            success_ratio: Some(success_ratio(outcomes)),
+        })
+    }
+
+    /// Forget the recent reads of all of a device's registers
+    pub fn reset_device(&self, device_id: &str) {
+        self.registers
+            .lock()
+            .unwrap()
+            .retain(|(device, _), _| device != device_id);
+    }
+
+    /// Window of a register that has not been read yet
+    pub fn empty(&self) -> RegisterHealth {
+        RegisterHealth {
+            window: self.window,
+            reads: 0,
+            successes: 0,
+            success_ratio: None,
+        }
+    }
+}
+
+fn success_ratio(outcomes: &VecDeque<bool>) -> f64 {
+    let successes = outcomes.iter().filter(|&&ok| ok).count();
+    successes as f64 / outcomes.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_drops_oldest_reads() {
+        let health = ReadHealth::new(4);
+        assert_eq!(health.get("plc", "temp"), None);
+
+        for ok in [false, false, true, true] {
+            health.record("plc", "temp", ok);
+        }
+        assert_eq!(health.get("plc", "temp").unwrap().success_ratio, Some(0.5));
+
+        // Two more successes push both failures out of the window
+        health.record("plc", "temp", true);
+        health.record("plc", "temp", true);
+        let window = health.get("plc", "temp").unwrap();
+        assert_eq!(window.reads, 4);
+        assert_eq!(window.successes, 4);
+        assert_eq!(window.success_ratio, Some(1.0));
+
+        // Registers are tracked independently
+        assert_eq!(health.get("plc", "pressure"), None);
+
+        health.record("other", "temp", false);
+        health.reset_device("plc");
+        assert_eq!(health.get("plc", "temp"), None);
+        assert!(health.get("other", "temp").is_some());
+    }
+}
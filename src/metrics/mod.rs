@@ -6,23 +6,65 @@
 //! - Poll latency histograms
 //! - Device connection status
 //! - MQTT publish counts
+//! - Per-register success ratio over recent reads
+
+mod health;
+mod stats;
+
+#[allow(unused_imports)]
+pub use health::{ReadHealth, RegisterHealth};
+pub use stats::{device_states, gateway_stats, mark_started, DeviceState, GatewayStats};
 
 use metrics::{counter, gauge, histogram};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
 use std::time::Instant;
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::config::MetricsConfig;
+use crate::modbus::breaker::BreakerState;
+
+/// Prefix of every metric name unless configured otherwise
+pub const DEFAULT_PREFIX: &str = "rustbridge";
+
+static PREFIX: OnceLock<String> = OnceLock::new();
 
 /// Initialize Prometheus metrics exporter
 /// Returns a handle to render metrics
-pub fn init_metrics() -> PrometheusHandle {
+///
+/// Every metric carries a `gateway_id` label.
+pub fn init_metrics(config: &MetricsConfig, gateway_id: &str) -> PrometheusHandle {
+    set_prefix(&config.prefix);
     let handle = PrometheusBuilder::new()
+        .add_global_label("gateway_id", gateway_id)
         .install_recorder()
         .expect("Failed to install Prometheus recorder");
 
-    info!("Prometheus metrics initialized");
+    info!("Prometheus metrics initialized (prefix: {})", prefix());
     handle
 }
 
+/// Set the metric name prefix; only the first call takes effect
+fn set_prefix(prefix: &str) {
+    if PREFIX.set(prefix.to_string()).is_err() && self::prefix() != prefix {
+        warn!(
+            "Metric prefix already set to '{}', ignoring '{}'",
+            self::prefix(),
+            prefix
+        );
+    }
+}
+
+fn prefix() -> &'static str {
+    PREFIX.get().map_or(DEFAULT_PREFIX, String::as_str)
+}
+
+/// Full name of a metric, e.g. `rustbridge_register_reads_total`
+pub(crate) fn name(metric: &str) -> String {
+    format!("{}_{}", prefix(), metric)
+}
+
 /// Metrics for register read operations
 pub struct ReadMetrics {
     start: Instant,
@@ -43,10 +85,11 @@ impl ReadMetrics {
     /// Record successful read
     pub fn success(self, value: f64) {
         let duration = self.start.elapsed().as_secs_f64();
+        stats::count_read(&self.device_id, true);
 
         // Increment read counter
         counter!(
-            "rustbridge_register_reads_total",
+            name("register_reads_total"),
             "device" => self.device_id.clone(),
             "register" => self.register_name.clone(),
             "status" => "success"
@@ -55,7 +98,7 @@ impl ReadMetrics {
 
         // Record latency histogram
         histogram!(
-            "rustbridge_read_duration_seconds",
+            name("read_duration_seconds"),
             "device" => self.device_id.clone(),
             "register" => self.register_name.clone()
         )
@@ -63,7 +106,7 @@ impl ReadMetrics {
 
         // Set current value gauge
         gauge!(
-            "rustbridge_register_value",
+            name("register_value"),
             "device" => self.device_id,
             "register" => self.register_name
         )
@@ -73,10 +116,11 @@ impl ReadMetrics {
     /// Record failed read
     pub fn failure(self, error_type: &str) {
         let duration = self.start.elapsed().as_secs_f64();
+        stats::count_read(&self.device_id, false);
 
         // Increment error counter
         counter!(
-            "rustbridge_register_reads_total",
+            name("register_reads_total"),
             "device" => self.device_id.clone(),
             "register" => self.register_name.clone(),
             "status" => "error"
@@ -85,7 +129,7 @@ impl ReadMetrics {
 
         // Increment specific error counter
         counter!(
-            "rustbridge_errors_total",
+            name("errors_total"),
             "device" => self.device_id.clone(),
             "type" => error_type.to_string()
         )
@@ -93,7 +137,7 @@ impl ReadMetrics {
 
         // Still record the latency
         histogram!(
-            "rustbridge_read_duration_seconds",
+            name("read_duration_seconds"),
             "device" => self.device_id,
             "register" => self.register_name
         )
@@ -103,18 +147,40 @@ impl ReadMetrics {
 
 /// Record device connection status
 pub fn record_device_status(device_id: &str, connected: bool) {
+    stats::set_device_online(device_id, connected);
     gauge!(
-        "rustbridge_device_connected",
+        name("device_connected"),
         "device" => device_id.to_string()
     )
     .set(if connected { 1.0 } else { 0.0 });
 }
 
+/// Record a device's circuit breaker state (0 = closed, 1 = open,
+/// 2 = half-open)
+pub fn record_breaker_state(device_id: &str, state: BreakerState) {
+    stats::set_breaker_state(device_id, state);
+    gauge!(
+        name("device_breaker_state"),
+        "device" => device_id.to_string()
+    )
+    .set(state.gauge_value());
+}
+
+/// Record whether a device's polling is paused
+pub fn record_device_paused(device_id: &str, paused: bool) {
+    stats::set_device_paused(device_id, paused);
+    gauge!(
+        name("device_paused"),
+        "device" => device_id.to_string()
+    )
+    .set(if paused { 1.0 } else { 0.0 });
+}
+
 /// Record MQTT publish event
 #[allow(dead_code)] // Available for MQTT integration
 pub fn record_mqtt_publish(device_id: &str, register_name: &str, success: bool) {
     counter!(
-        "rustbridge_mqtt_publishes_total",
+        name("mqtt_publishes_total"),
         "device" => device_id.to_string(),
         "register" => register_name.to_string(),
         "status" => if success { "success" } else { "error" }
@@ -122,31 +188,206 @@ pub fn record_mqtt_publish(device_id: &str, register_name: &str, success: bool)
     .increment(1);
 }
 
+/// Count register updates not published to MQTT, by reason: `lagged` when
+/// the publisher fell behind the update channel, `out_of_order` for an
+/// update older than the last one published on its topic
+pub fn record_mqtt_dropped(reason: &'static str, count: u64) {
+    counter!(name("mqtt_updates_dropped_total"), "reason" => reason).increment(count);
+}
+
+/// Count points written to InfluxDB, or dropped because the write failed
+pub fn record_influx_write(points: usize, success: bool) {
+    counter!(
+        name("influx_points_total"),
+        "status" => if success { "written" } else { "failed" }
+    )
+    .increment(points as u64);
+}
+
+/// Count register updates written to the history database by outcome
+pub fn record_storage_write(rows: usize, success: bool) {
+    counter!(
+        name("storage_rows_total"),
+        "status" => if success { "written" } else { "failed" }
+    )
+    .increment(rows as u64);
+}
+
+/// Count a configuration reload by outcome
+pub fn record_config_reload(success: bool) {
+    counter!(
+        name("config_reloads_total"),
+        "result" => if success { "success" } else { "failure" }
+    )
+    .increment(1);
+}
+
 /// Record MQTT connection status
-#[allow(dead_code)] // Available for MQTT integration
 pub fn record_mqtt_connection(connected: bool) {
-    gauge!("rustbridge_mqtt_connected").set(if connected { 1.0 } else { 0.0 });
+    gauge!(name("mqtt_connected")).set(if connected { 1.0 } else { 0.0 });
 }
 
 /// Record active polling devices count
 #[allow(dead_code)] // Available for bridge stats
 pub fn record_active_devices(count: usize) {
-    gauge!("rustbridge_active_devices").set(count as f64);
+    gauge!(name("active_devices")).set(count as f64);
 }
 
 /// Record poll cycle timing
 pub fn record_poll_cycle(device_id: &str, duration_ms: u64) {
     histogram!(
-        "rustbridge_poll_cycle_seconds",
+        name("poll_cycle_seconds"),
         "device" => device_id.to_string()
     )
     .record(duration_ms as f64 / 1000.0);
 }
 
+/// Count a connection dropped and reopened because reads kept timing out
+pub fn record_forced_reconnect(device_id: &str) {
+    counter!(
+        name("forced_reconnects_total"),
+        "device" => device_id.to_string()
+    )
+    .increment(1);
+}
+
+/// Record a successfully reopened connection of a device
+///
+/// The device's "since reconnect" read statistics start over; with
+/// `reset_rolling` its consecutive failures do too. Prometheus counters
+/// are not affected.
+pub fn record_reconnected(device_id: &str, reset_rolling: bool) {
+    stats::mark_reconnected(device_id, reset_rolling);
+}
+
+/// Window over which `modbus_requests_per_second` is measured
+const REQUEST_RATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Request count of each device in its current rate window
+static REQUEST_WINDOWS: Mutex<BTreeMap<String, (Instant, u64)>> = Mutex::new(BTreeMap::new());
+
+/// Count a Modbus request sent to a device, updating the device's request
+/// rate once a second
+pub fn record_modbus_request(device_id: &str) {
+    counter!(
+        name("modbus_requests_total"),
+        "device" => device_id.to_string()
+    )
+    .increment(1);
+
+    let rate = {
+        let mut windows = REQUEST_WINDOWS.lock().unwrap();
+        let now = Instant::now();
+        let (started, count) = windows.entry(device_id.to_string()).or_insert((now, 0));
+        *count += 1;
+        let elapsed = now.duration_since(*started);
+        if elapsed < REQUEST_RATE_WINDOW {
+            return;
+        }
+        let rate = *count as f64 / elapsed.as_secs_f64();
+        (*started, *count) = (now, 0);
+        rate
+    };
+    gauge!(
+        name("modbus_requests_per_second"),
+        "device" => device_id.to_string()
+    )
+    .set(rate);
+}
+
+/// Busy time of each serial bus in its current utilization window
+static BUS_WINDOWS: Mutex<BTreeMap<String, (Instant, std::time::Duration)>> =
+    Mutex::new(BTreeMap::new());
+
+/// Record a transaction on a shared serial bus: how long the device waited
+/// for the bus and how long it then held it
+///
+/// The bus utilization, the fraction of time the bus was held, is updated
+/// once a second.
+pub fn record_bus_transaction(bus: &str, waited: std::time::Duration, held: std::time::Duration) {
+    counter!(name("bus_transactions_total"), "bus" => bus.to_string()).increment(1);
+    histogram!(name("bus_wait_seconds"), "bus" => bus.to_string()).record(waited.as_secs_f64());
+
+    let utilization = {
+        let mut windows = BUS_WINDOWS.lock().unwrap();
+        let now = Instant::now();
+        let (started, busy) = windows.entry(bus.to_string()).or_insert((
+            now.checked_sub(held).unwrap_or(now),
+            std::time::Duration::ZERO,
+        ));
+        *busy += held;
+        let elapsed = now.duration_since(*started);
+        if elapsed < REQUEST_RATE_WINDOW {
+            return;
+        }
+        let utilization = (busy.as_secs_f64() / elapsed.as_secs_f64()).min(1.0);
+        (*started, *busy) = (now, std::time::Duration::ZERO);
+        utilization
+    };
+    gauge!(name("bus_utilization"), "bus" => bus.to_string()).set(utilization);
+}
+
+/// Count a rising edge of a `count_edges` register seen as soon as the poll
+/// rate allows, a sign that pulses are being missed
+pub fn record_edge_aliasing(device_id: &str, register_name: &str) {
+    counter!(
+        name("edge_counter_aliasing_total"),
+        "device" => device_id.to_string(),
+        "register" => register_name.to_string()
+    )
+    .increment(1);
+}
+
+/// Count a watchdog heartbeat write that failed
+pub fn record_heartbeat_failure(device_id: &str) {
+    counter!(
+        name("heartbeat_failures_total"),
+        "device" => device_id.to_string()
+    )
+    .increment(1);
+}
+
+/// Record the samples held by the register history and how many were just
+/// evicted to stay within `history.max_samples`
+pub fn record_history(samples: usize, evicted: u64) {
+    gauge!(name("history_samples")).set(samples as f64);
+    if evicted > 0 {
+        counter!(name("history_evictions_total")).increment(evicted);
+    }
+}
+
+/// Record how long a write waited in the queue before the device worker
+/// executed it
+pub fn record_write_wait(device_id: &str, wait: std::time::Duration) {
+    histogram!(
+        name("write_queue_wait_seconds"),
+        "device" => device_id.to_string()
+    )
+    .record(wait.as_secs_f64());
+}
+
+/// Count an API write rejected by `server.write_rate_limit`
+pub fn record_write_rate_limited() {
+    counter!(name("api_writes_rate_limited_total")).increment(1);
+}
+
+/// Record a poll cycle cut short by `max_cycle_ms`
+pub fn record_poll_truncated(device_id: &str, skipped: usize) {
+    counter!(
+        name("poll_cycles_truncated_total"),
+        "device" => device_id.to_string()
+    )
+    .increment(1);
+    counter!(
+        name("poll_registers_skipped_total"),
+        "device" => device_id.to_string()
+    )
+    .increment(skipped as u64);
+}
+
 /// Record WebSocket connections
-#[allow(dead_code)] // Available for WebSocket stats
 pub fn record_websocket_connections(count: usize) {
-    gauge!("rustbridge_websocket_connections").set(count as f64);
+    gauge!(name("websocket_connections")).set(count as f64);
 }
 
 #[cfg(test)]
@@ -178,7 +419,10 @@ mod tests {
 
         record_device_status("plc-001", true);
         record_device_status("plc-002", false);
-        // No panic = success
+
+        let stats = gateway_stats();
+        assert!(stats.devices_total >= 2);
+        assert!(stats.devices_online < stats.devices_total);
     }
 
     #[test]
@@ -191,6 +435,39 @@ mod tests {
         // No panic = success
     }
 
+    #[test]
+    fn test_metric_prefix_applied() {
+        // The only test that changes the prefix; the others don't check names
+        set_prefix("plant_a");
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            ReadMetrics::start("plc-001", "temperature").success(21.5);
+            ReadMetrics::start("plc-001", "pressure").failure("timeout");
+            record_device_status("plc-001", true);
+            record_breaker_state("plc-001", BreakerState::Closed);
+            record_mqtt_publish("plc-001", "temperature", true);
+            record_mqtt_connection(true);
+            record_active_devices(1);
+            record_poll_cycle("plc-001", 150);
+            record_poll_truncated("plc-001", 2);
+            record_websocket_connections(1);
+            ReadHealth::new(10).record("plc-001", "temperature", true);
+        });
+
+        let rendered = handle.render();
+        let names: Vec<&str> = rendered
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.is_empty())
+            .collect();
+        assert!(names.len() > 10);
+        for line in names {
+            assert!(line.starts_with("plant_a_"), "unprefixed metric: {}", line);
+        }
+        assert!(rendered.contains("plant_a_register_reads_total{"));
+    }
+
     #[test]
     fn test_poll_cycle_metrics() {
         let _ = PrometheusBuilder::new().install_recorder();
@@ -0,0 +1,188 @@
+//! Gateway-wide counters that can be read back in-process
+//!
+//! Prometheus counters are write-only from the application's side, so the
+//! totals needed for `/api/stats` and the MQTT diagnostics message are kept
+//! here as well.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::modbus::breaker::BreakerState;
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static READS: AtomicU64 = AtomicU64::new(0);
+static READ_ERRORS: AtomicU64 = AtomicU64::new(0);
+static DEVICES: Mutex<BTreeMap<String, DeviceState>> = Mutex::new(BTreeMap::new());
+
+/// Last reported state of a device
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DeviceState {
+    pub online: bool,
+    pub breaker: BreakerState,
+    /// Polling paused through the API
+    pub paused: bool,
+    pub reads: DeviceReads,
+}
+
+/// Register reads of a device and how many failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReadCounts {
+    pub reads: u64,
+    pub errors: u64,
+}
+
+/// Read statistics of a device, since the gateway started and since its
+/// last reconnect
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DeviceReads {
+    pub total: ReadCounts,
+    pub since_reconnect: ReadCounts,
+    /// Failed reads since the last successful one
+    pub consecutive_failures: u64,
+    /// When a connection of the device was last reopened, `null` if never
+    pub reconnected_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DeviceReads {
+    fn count(&mut self, success: bool) {
+        for counts in [&mut self.total, &mut self.since_reconnect] {
+            counts.reads += 1;
+            counts.errors += u64::from(!success);
+        }
+        self.consecutive_failures = if success {
+            0
+        } else {
+            self.consecutive_failures + 1
+        };
+    }
+}
+
+/// Snapshot of the gateway's counters
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayStats {
+    pub uptime_secs: u64,
+    /// Devices that have reported a status
+    pub devices_total: usize,
+    /// Devices currently online
+    pub devices_online: usize,
+    pub reads_total: u64,
+    pub read_errors_total: u64,
+}
+
+/// Start the uptime clock (later calls have no effect)
+pub fn mark_started() {
+    STARTED.get_or_init(Instant::now);
+}
+
+pub(crate) fn count_read(device_id: &str, success: bool) {
+    READS.fetch_add(1, Ordering::Relaxed);
+    if !success {
+        READ_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+    DEVICES
+        .lock()
+        .unwrap()
+        .entry(device_id.to_string())
+        .or_default()
+        .reads
+        .count(success);
+}
+
+/// Start the device's "since reconnect" statistics over, and with
+/// `reset_failures` its consecutive failures too
+pub(crate) fn mark_reconnected(device_id: &str, reset_failures: bool) {
+    let mut devices = DEVICES.lock().unwrap();
+    let reads = &mut devices.entry(device_id.to_string()).or_default().reads;
+    reads.since_reconnect = ReadCounts::default();
+    reads.reconnected_at = Some(chrono::Utc::now());
+    if reset_failures {
+        reads.consecutive_failures = 0;
+    }
+}
+
+pub(crate) fn set_device_online(device_id: &str, online: bool) {
+    DEVICES
+        .lock()
+        .unwrap()
+        .entry(device_id.to_string())
+        .or_default()
+        .online = online;
+}
+
+pub(crate) fn set_device_paused(device_id: &str, paused: bool) {
+    DEVICES
+        .lock()
+        .unwrap()
+        .entry(device_id.to_string())
+        .or_default()
+        .paused = paused;
+}
+
+pub(crate) fn set_breaker_state(device_id: &str, breaker: BreakerState) {
+    DEVICES
+        .lock()
+        .unwrap()
+        .entry(device_id.to_string())
+        .or_default()
+        .breaker = breaker;
+}
+
+/// Last reported state of every device
+pub fn device_states() -> BTreeMap<String, DeviceState> {
+    DEVICES.lock().unwrap().clone()
+}
+
+/// Current values of the gateway counters
+pub fn gateway_stats() -> GatewayStats {
+    let devices = DEVICES.lock().unwrap();
+    GatewayStats {
+        uptime_secs: STARTED.get_or_init(Instant::now).elapsed().as_secs(),
+        devices_total: devices.len(),
+        devices_online: devices.values().filter(|d| d.online).count(),
+        reads_total: READS.load(Ordering::Relaxed),
+        read_errors_total: READ_ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_since_reconnect() {
+        // Device state is global; the id keeps this test's device apart
+        let device = "stats-test-reconnect";
+        for success in [true, false, false] {
+            count_read(device, success);
+        }
+        mark_reconnected(device, false);
+        count_read(device, false);
+
+        let reads = device_states()[device].reads;
+        assert_eq!(
+            reads.total,
+            ReadCounts {
+                reads: 4,
+                errors: 3
+            }
+        );
+        assert_eq!(
+            reads.since_reconnect,
+            ReadCounts {
+                reads: 1,
+                errors: 1
+            }
+        );
+        assert_eq!(reads.consecutive_failures, 3);
+        assert!(reads.reconnected_at.is_some());
+
+        mark_reconnected(device, true);
+        let reads = device_states()[device].reads;
+        assert_eq!(reads.since_reconnect, ReadCounts::default());
+        assert_eq!(reads.consecutive_failures, 0);
+        assert_eq!(reads.total.reads, 4, "cumulative counts are kept");
+    }
+}
@@ -0,0 +1,147 @@
+//! Per-device circuit breaker
+//!
+//! After `failure_threshold` consecutive poll cycles in which no register
+//! could be read, the breaker opens and polling stops. Every
+//! `probe_interval_ms` a single register is read (half-open); the breaker
+//! closes again as soon as a probe succeeds.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::config::CircuitBreakerConfig;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Polling normally
+    #[default]
+    Closed,
+    /// Device considered down; only probes are sent
+    Open,
+    /// A probe is in progress
+    HalfOpen,
+}
+
+impl BreakerState {
+    /// Value of the `rustbridge_device_breaker_state` gauge
+    pub fn gauge_value(self) -> f64 {
+        match self {
+            BreakerState::Closed => 0.0,
+            BreakerState::Open => 1.0,
+            BreakerState::HalfOpen => 2.0,
+        }
+    }
+}
+
+/// What the next poll cycle should do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Read every register
+    Poll,
+    /// Read a single register to test the device
+    Probe,
+    /// Don't touch the device
+    Skip,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    probe_interval: Duration,
+    failures: u32,
+    state: BreakerState,
+    opened_at: Instant,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            probe_interval: Duration::from_millis(config.probe_interval_ms),
+            failures: 0,
+            state: BreakerState::Closed,
+            opened_at: Instant::now(),
+        }
+    }
+
+    /// Decide how to run the poll cycle starting at `now`
+    pub fn admit(&mut self, now: Instant) -> Admission {
+        match self.state {
+            BreakerState::Closed => Admission::Poll,
+            BreakerState::HalfOpen => Admission::Probe,
+            BreakerState::Open if now.duration_since(self.opened_at) >= self.probe_interval => {
+                self.state = BreakerState::HalfOpen;
+                Admission::Probe
+            }
+            BreakerState::Open => Admission::Skip,
+        }
+    }
+
+    /// Record the outcome of a poll cycle or probe
+    ///
+    /// Returns the new state if it changed.
+    pub fn record(&mut self, success: bool, now: Instant) -> Option<BreakerState> {
+        let previous = self.state;
+
+        if success {
+            self.failures = 0;
+            self.state = BreakerState::Closed;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            if self.state == BreakerState::HalfOpen || self.failures >= self.failure_threshold {
+                self.state = BreakerState::Open;
+                self.opened_at = now;
+            }
+        }
+
+        (self.state != previous).then_some(self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_probes_and_closes() {
+        let mut breaker = CircuitBreaker::new(&CircuitBreakerConfig {
+            failure_threshold: 3,
+            probe_interval_ms: 1000,
+        });
+        let start = Instant::now();
+
+        // Failures below the threshold keep polling
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.admit(start), Admission::Poll);
+
+        // A success resets the count
+        assert_eq!(breaker.record(true, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), Some(BreakerState::Open));
+
+        // Open: nothing is sent until the probe interval has passed
+        assert_eq!(
+            breaker.admit(start + Duration::from_millis(500)),
+            Admission::Skip
+        );
+        let probe_at = start + Duration::from_millis(1000);
+        assert_eq!(breaker.admit(probe_at), Admission::Probe);
+        // Still half-open until the probe's outcome is recorded
+        assert_eq!(breaker.admit(probe_at), Admission::Probe);
+
+        // A failed probe reopens immediately and waits another interval
+        assert_eq!(breaker.record(false, probe_at), Some(BreakerState::Open));
+        assert_eq!(
+            breaker.admit(probe_at + Duration::from_millis(999)),
+            Admission::Skip
+        );
+
+        // A successful probe closes the breaker
+        let probe_at = probe_at + Duration::from_millis(1000);
+        assert_eq!(breaker.admit(probe_at), Admission::Probe);
+        assert_eq!(breaker.record(true, probe_at), Some(BreakerState::Closed));
+        assert_eq!(breaker.admit(probe_at), Admission::Poll);
+    }
+}
@@ -0,0 +1,172 @@
+//! Serial buses shared by several devices
+//!
+//! RS-485 is multi-drop: several devices hang off one serial port, and only
+//! one transaction may be on the line at a time. Devices configured on the
+//! same RTU port share one [`SerialBus`], which owns the port. Each request
+//! locks the bus for its whole transaction; the lock is fair, so devices
+//! waiting for the bus take turns, one request each.
+//!
+//! A transaction that fails on the port itself, e.g. because the USB
+//! adapter was unplugged, closes the port; the next device to use the bus
+//! reopens it.
+
+use anyhow::Result;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio_modbus::prelude::Slave;
+
+use super::client;
+use crate::config::RtuConnection;
+use crate::metrics;
+use tracing::warn;
+
+/// A serial port shared by the devices configured on it
+#[derive(Clone)]
+pub struct SerialBus {
+    settings: RtuConnection,
+    /// The open port, `None` until the first device connects and after a
+    /// transport error closed it
+    port: Arc<Mutex<Option<client::Context>>>,
+}
+
+impl std::fmt::Debug for SerialBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialBus")
+            .field("port", &self.settings.port)
+            .finish()
+    }
+}
+
+impl SerialBus {
+    /// A bus on the port of `settings`, opened by the first [`open`](Self::open)
+    pub fn new(settings: &RtuConnection) -> Self {
+        Self {
+            settings: settings.clone(),
+            port: Arc::default(),
+        }
+    }
+
+    /// A bus whose port is already open, e.g. a stream standing in for it
+    #[cfg(test)]
+    pub(crate) fn attached(settings: &RtuConnection, ctx: client::Context) -> Self {
+        Self {
+            settings: settings.clone(),
+            port: Arc::new(Mutex::new(Some(ctx))),
+        }
+    }
+
+    /// Serial port path, which also identifies the bus
+    pub fn id(&self) -> &str {
+        &self.settings.port
+    }
+
+    /// Open the serial port unless another device already did
+    pub async fn open(&self) -> Result<()> {
+        let mut port = self.port.lock().await;
+        if port.is_none() {
+            *port = Some(super::open_rtu(&self.settings)?);
+        }
+        Ok(())
+    }
+
+    /// Wait for the bus and hold it for one transaction with `unit_id`
+    ///
+    /// Reopens the port if a transport error closed it.
+    pub async fn acquire(&self, unit_id: u8) -> Result<BusGuard> {
+        let waiting = Instant::now();
+        let mut port = self.port.clone().lock_owned().await;
+        let ctx = match port.as_mut() {
+            Some(ctx) => ctx,
+            None => port.insert(super::open_rtu(&self.settings)?),
+        };
+        ctx.set_slave(Slave(unit_id));
+        Ok(BusGuard {
+            port,
+            bus: self.id().to_string(),
+            waited: waiting.elapsed(),
+            acquired: Instant::now(),
+        })
+    }
+}
+
+/// Exclusive use of a serial bus, released when dropped
+pub struct BusGuard {
+    port: OwnedMutexGuard<Option<client::Context>>,
+    bus: String,
+    waited: Duration,
+    acquired: Instant,
+}
+
+impl BusGuard {
+    /// Close the port after the transaction failed on it, for every device
+    /// on the bus; the next transaction reopens it
+    pub fn close(mut self) {
+        if self.port.take().is_some() {
+            warn!("Serial port {} closed after a transport error", self.bus);
+        }
+    }
+}
+
+impl Deref for BusGuard {
+    type Target = client::Context;
+
+    fn deref(&self) -> &client::Context {
+        self.port.as_ref().expect("bus is only acquired while open")
+    }
+}
+
+impl DerefMut for BusGuard {
+    fn deref_mut(&mut self) -> &mut client::Context {
+        self.port.as_mut().expect("bus is only acquired while open")
+    }
+}
+
+impl Drop for BusGuard {
+    fn drop(&mut self) {
+        metrics::record_bus_transaction(&self.bus, self.waited, self.acquired.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(port: &str) -> RtuConnection {
+        RtuConnection {
+            port: port.to_string(),
+            baud_rate: 9600,
+            data_bits: 8,
+            stop_bits: 1,
+            parity: "none".to_string(),
+            unit_id: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_bus_rejects_requests() {
+        let bus = SerialBus::new(&settings("/dev/does-not-exist"));
+        assert_eq!(bus.id(), "/dev/does-not-exist");
+        assert!(bus.open().await.is_err());
+        let error = bus.acquire(1).await.err().unwrap();
+        assert!(error.to_string().contains("Failed to open serial port"));
+    }
+
+    #[tokio::test]
+    async fn test_closed_port_is_reopened_by_next_transaction() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let bus = SerialBus::attached(
+            &settings("/dev/does-not-exist"),
+            client::Context::Rtu(tokio_modbus::client::rtu::attach(stream)),
+        );
+
+        bus.acquire(1).await.unwrap().close();
+        // The next transaction, whichever device makes it, opens the port
+        let error = bus.acquire(2).await.err().unwrap();
+        assert!(error.to_string().contains("Failed to open serial port"));
+    }
+}
@@ -21,6 +21,25 @@ pub enum ModbusError {
     #[error("Serial port error: {0}")]
     #[allow(dead_code)] // Available for RTU error handling
     Serial(String),
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+    #[error("Response length mismatch: requested {expected} values, got {actual}")]
+    LengthMismatch { expected: u16, actual: usize },
+    #[error("No response within {}ms", .0.as_millis())]
+    Timeout(std::time::Duration),
+}
+
+/// Reject a response with more or fewer values than requested, e.g. a frame
+/// garbled on the line that still passed the CRC check
+fn check_len<T>(values: Vec<T>, cnt: u16) -> Result<Vec<T>, ModbusError> {
+    if values.len() == cnt as usize {
+        Ok(values)
+    } else {
+        Err(ModbusError::LengthMismatch {
+            expected: cnt,
+            actual: values.len(),
+        })
+    }
 }
 
 /// Unified context for TCP and RTU clients
@@ -30,6 +49,24 @@ pub enum Context {
 }
 
 impl Context {
+    /// Address subsequent requests to another slave / unit id
+    pub fn set_slave(&mut self, slave: Slave) {
+        match self {
+            Context::Tcp(ctx) => ctx.set_slave(slave),
+            Context::Rtu(ctx) => ctx.set_slave(slave),
+        }
+    }
+
+    /// Close the connection
+    pub async fn disconnect(&mut self) -> std::io::Result<()> {
+        let result = match self {
+            Context::Tcp(ctx) => ctx.disconnect().await,
+            Context::Rtu(ctx) => ctx.disconnect().await,
+        };
+        // Closing the transport never yields a Modbus exception
+        result.map(|_| ()).map_err(std::io::Error::other)
+    }
+
     pub async fn read_holding_registers(
         &mut self,
         addr: u16,
@@ -38,11 +75,15 @@ impl Context {
         match self {
             Context::Tcp(ctx) => {
                 let result = ctx.read_holding_registers(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
             Context::Rtu(ctx) => {
                 let result = ctx.read_holding_registers(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
         }
     }
@@ -55,11 +96,15 @@ impl Context {
         match self {
             Context::Tcp(ctx) => {
                 let result = ctx.read_input_registers(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
             Context::Rtu(ctx) => {
                 let result = ctx.read_input_registers(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
         }
     }
@@ -68,11 +113,15 @@ impl Context {
         match self {
             Context::Tcp(ctx) => {
                 let result = ctx.read_coils(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
             Context::Rtu(ctx) => {
                 let result = ctx.read_coils(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
         }
     }
@@ -85,11 +134,15 @@ impl Context {
         match self {
             Context::Tcp(ctx) => {
                 let result = ctx.read_discrete_inputs(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
             Context::Rtu(ctx) => {
                 let result = ctx.read_discrete_inputs(addr, cnt).await?;
-                result.map_err(ModbusError::Exception)
+                result
+                    .map_err(ModbusError::Exception)
+                    .and_then(|values| check_len(values, cnt))
             }
         }
     }
@@ -128,6 +181,45 @@ impl Context {
         }
     }
 
+    pub async fn masked_write_register(
+        &mut self,
+        addr: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> Result<(), ModbusError> {
+        match self {
+            Context::Tcp(ctx) => {
+                let result = ctx.masked_write_register(addr, and_mask, or_mask).await?;
+                result.map_err(ModbusError::Exception)
+            }
+            Context::Rtu(ctx) => {
+                let result = ctx.masked_write_register(addr, and_mask, or_mask).await?;
+                result.map_err(ModbusError::Exception)
+            }
+        }
+    }
+
+    /// Send a Read Device Identification request (FC43 / MEI type 14) and
+    /// return the response data following the function code
+    pub async fn read_device_identification(
+        &mut self,
+        object_id: u8,
+    ) -> Result<Vec<u8>, ModbusError> {
+        let data = super::identification::request(object_id);
+        let request = Request::Custom(
+            super::identification::FUNCTION_CODE,
+            std::borrow::Cow::Borrowed(&data[..]),
+        );
+        let result = match self {
+            Context::Tcp(ctx) => ctx.call(request).await?,
+            Context::Rtu(ctx) => ctx.call(request).await?,
+        };
+        match result.map_err(ModbusError::Exception)? {
+            Response::Custom(_, data) => Ok(data.to_vec()),
+            other => Err(ModbusError::UnexpectedResponse(format!("{:?}", other))),
+        }
+    }
+
     pub async fn write_single_coil(&mut self, addr: u16, value: bool) -> Result<(), ModbusError> {
         match self {
             Context::Tcp(ctx) => {
@@ -141,3 +233,21 @@ impl Context {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_len_rejects_wrong_word_count() {
+        assert_eq!(check_len(vec![1u16, 2], 2).unwrap(), vec![1, 2]);
+        assert!(matches!(
+            check_len(vec![1u16], 2),
+            Err(ModbusError::LengthMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(check_len(vec![true, false, true], 2).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Rising edge counters for pulse inputs
+//!
+//! A `count_edges` register is a coil or discrete input wired to a pulse
+//! source, e.g. a flow meter. Each read is compared with the previous one
+//! and every 0 -> 1 transition adds one to the register's counter. Only
+//! the levels seen at reads are known: a pulse that starts and ends between
+//! two reads is missed, so pulses must be longer than the poll interval and
+//! arrive at less than half the poll rate to all be counted.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::metrics;
+use crate::modbus::reader::{Quality, RegisterValue};
+
+/// Counter state of one register
+#[derive(Debug, Default)]
+struct EdgeCounter {
+    /// Level at the last read, `None` before the first
+    level: Option<bool>,
+    count: u64,
+    /// Reads since the last counted edge
+    reads_since_edge: Option<u64>,
+}
+
+/// Edge counters of every `count_edges` register, shared by a device's
+/// connections; counts start at 0 when the gateway starts
+#[derive(Debug, Clone, Default)]
+pub struct EdgeCounters {
+    counters: Arc<Mutex<HashMap<(String, String), EdgeCounter>>>,
+}
+
+impl EdgeCounters {
+    /// Count a read of the register and return its total as the
+    /// `<name>.edges` value
+    ///
+    /// The first read only establishes the level. An edge seen two reads
+    /// after the previous one (levels 1, 0, 1) means pulses come as fast as
+    /// the poll rate can resolve, and some are probably being missed; it is
+    /// counted in `edge_counter_aliasing_total`.
+    pub fn record(&self, device_id: &str, value: &RegisterValue) -> RegisterValue {
+        let level = value.raw.first().is_some_and(|&word| word != 0);
+        let count = {
+            let mut counters = self.counters.lock().unwrap();
+            let counter = counters
+                .entry((device_id.to_string(), value.name.clone()))
+                .or_default();
+            let rising = counter.level == Some(false) && level;
+            counter.level = Some(level);
+            counter.reads_since_edge = counter.reads_since_edge.map(|n| n + 1);
+            if rising {
+                if counter.reads_since_edge == Some(2) {
+                    metrics::record_edge_aliasing(device_id, &value.name);
+                }
+                counter.count += 1;
+                counter.reads_since_edge = Some(0);
+            }
+            counter.count
+        };
+
+        RegisterValue {
+            name: edge_register_name(&value.name),
+            raw: vec![],
+            decoded: count as f64,
+            value: count as f64,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: value.timestamp,
+        }
+    }
+}
+
+/// Name of the derived register holding a register's edge count
+fn edge_register_name(name: &str) -> String {
+    format!("{}.edges", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(level: u16) -> RegisterValue {
+        RegisterValue {
+            name: "flow_pulse".to_string(),
+            raw: vec![level],
+            decoded: level as f64,
+            value: level as f64,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_counts_rising_edges() {
+        let counters = EdgeCounters::default();
+        let counts: Vec<f64> = [1, 1, 0, 1, 1, 0, 0, 1]
+            .into_iter()
+            .map(|level| counters.record("meter", &reading(level)).value)
+            .collect();
+        // The first read sets the level without counting
+        assert_eq!(counts, [0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 2.0]);
+
+        let edges = counters.record("meter", &reading(0));
+        assert_eq!(edges.name, "flow_pulse.edges");
+        assert_eq!(edges.value, 2.0);
+        assert_eq!(counters.record("other", &reading(1)).value, 0.0);
+    }
+}
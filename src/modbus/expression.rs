@@ -0,0 +1,445 @@
+//! Arithmetic over register values, for virtual registers and formulas
+//!
+//! Supports numbers, register names (letters, digits, `_` and `.`),
+//! `+ - * / ^`, parentheses and the functions `abs`, `sqrt`, `ln`, `log10`,
+//! `exp`, `min` and `max`. An expression is parsed once and evaluated every
+//! poll cycle.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Register(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Log10,
+    Exp,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Function::Abs),
+            "sqrt" => Some(Function::Sqrt),
+            "ln" => Some(Function::Ln),
+            "log10" => Some(Function::Log10),
+            "exp" => Some(Function::Exp),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
+
+    /// Whether the function takes `n` arguments
+    fn accepts(self, n: usize) -> bool {
+        match self {
+            Function::Abs | Function::Sqrt | Function::Ln | Function::Log10 | Function::Exp => {
+                n == 1
+            }
+            Function::Min | Function::Max => n >= 1,
+        }
+    }
+}
+
+/// Why an expression could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(ParseError(format!("unexpected '{}'", token))),
+        }
+    }
+
+    /// Register names the expression reads, in order of appearance
+    pub fn references(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_references(&mut names);
+        names
+    }
+
+    fn collect_references<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Register(name) => names.push(name),
+            Expr::Neg(expr) => expr.collect_references(names),
+            Expr::Binary(_, a, b) => {
+                a.collect_references(names);
+                b.collect_references(names);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.collect_references(names)),
+        }
+    }
+
+    /// Evaluate with register values from `lookup`; `None` if a register
+    /// has no value
+    pub fn eval(&self, lookup: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Expr::Number(n) => *n,
+            Expr::Register(name) => lookup(name)?,
+            Expr::Neg(expr) => -expr.eval(lookup)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(lookup)?, b.eval(lookup)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(function, args) => {
+                let values = args
+                    .iter()
+                    .map(|a| a.eval(lookup))
+                    .collect::<Option<Vec<_>>>()?;
+                match function {
+                    Function::Abs => values[0].abs(),
+                    Function::Sqrt => values[0].sqrt(),
+                    Function::Ln => values[0].ln(),
+                    Function::Log10 => values[0].log10(),
+                    Function::Exp => values[0].exp(),
+                    Function::Min => values.into_iter().fold(f64::INFINITY, f64::min),
+                    Function::Max => values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                }
+            }
+        })
+    }
+}
+
+/// A register's value as an expression of its decoded number `x`, e.g. a
+/// sensor curve; written as its source in the configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    source: String,
+    expr: Expr,
+}
+
+impl Formula {
+    /// Name of the decoded number in a formula
+    pub const VARIABLE: &'static str = "x";
+
+    /// Parse a formula; `x` is the only name it may use
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let expr = Expr::parse(source)?;
+        if let Some(name) = expr.references().into_iter().find(|&n| n != Self::VARIABLE) {
+            return Err(ParseError(format!(
+                "unknown name '{}', only '{}' is defined",
+                name,
+                Self::VARIABLE
+            )));
+        }
+        Ok(Formula {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// The formula's value for the decoded number `x`
+    pub fn eval(&self, x: f64) -> f64 {
+        self.expr.eval(&|_| Some(x)).unwrap_or(f64::NAN)
+    }
+}
+
+impl Serialize for Formula {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Formula {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Formula::parse(&source).map_err(|e| {
+            serde::de::Error::custom(format!("formula '{}' is invalid: {}", source, e))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(name) => f.write_str(name),
+            Token::Symbol(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let text = &source[start..end];
+            let number = text
+                .parse()
+                .map_err(|_| ParseError(format!("invalid number '{}'", text)))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(source[start..end].to_string()));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(ParseError(format!("unexpected character '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, lowest precedence first
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => Err(ParseError(format!(
+                "expected '{}', found '{}'",
+                symbol, token
+            ))),
+            None => Err(ParseError(format!("expected '{}'", symbol))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.primary()?;
+        if self.eat('^') {
+            // Right associative, binds tighter than a leading minus
+            return Ok(Expr::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::Symbol('(')) => {
+                let function = Function::parse(&name)
+                    .ok_or_else(|| ParseError(format!("unknown function '{}'", name)))?;
+                self.pos += 1;
+                let mut args = vec![self.expr()?];
+                while self.eat(',') {
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if !function.accepts(args.len()) {
+                    return Err(ParseError(format!(
+                        "wrong number of arguments for '{}'",
+                        name
+                    )));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Register(name)),
+            Some(Token::Symbol('(')) => {
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(token) => Err(ParseError(format!("unexpected '{}'", token))),
+            None => Err(ParseError("unexpected end of expression".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Option<f64> {
+        let values = [("phase_a", 1.5), ("phase_b", 2.0), ("meter.W", -3.0)];
+        let lookup = |name: &str| values.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+        Expr::parse(source).unwrap().eval(&lookup)
+    }
+
+    #[test]
+    fn test_arithmetic_and_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Some(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Some(9.0));
+        assert_eq!(eval("10 - 4 - 3"), Some(3.0));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Some(512.0));
+        assert_eq!(eval("-2 ^ 2"), Some(-4.0));
+        assert_eq!(eval("max(1, 5, 3) - min(4, abs(-2))"), Some(3.0));
+        assert_eq!(eval("sqrt(16) / 0.5"), Some(8.0));
+        assert_eq!(eval("exp(ln(1)) * log10(100)"), Some(2.0));
+    }
+
+    #[test]
+    fn test_formula() {
+        // Reciprocal of a linear term
+        let formula = Formula::parse("1 / (0.5 + 0.25 * x)").unwrap();
+        assert_eq!(formula.eval(2.0), 1.0);
+        assert_eq!(formula.eval(6.0), 0.5);
+
+        // Polynomial
+        let formula = Formula::parse("2 * x ^ 2 - 3 * x + 1").unwrap();
+        assert_eq!(formula.eval(0.0), 1.0);
+        assert_eq!(formula.eval(3.0), 10.0);
+
+        // Steinhart-Hart thermistor curve, resistance in ohms to °C
+        let formula = Formula::parse(
+            "1 / (0.001129148 + 0.000234125 * ln(x) + 0.0000000876741 * ln(x) ^ 3) - 273.15",
+        )
+        .unwrap();
+        assert!((formula.eval(10_000.0) - 25.0).abs() < 0.01);
+
+        // Only x is defined, and the source round-trips through the config
+        assert!(Formula::parse("x * scale").is_err());
+        assert!(Formula::parse("2 *").is_err());
+        let formula: Formula = serde_json::from_str("\"x / 10\"").unwrap();
+        assert_eq!(formula.eval(250.0), 25.0);
+        assert_eq!(serde_json::to_string(&formula).unwrap(), "\"x / 10\"");
+        let error = serde_json::from_str::<Formula>("\"y / 10\"").unwrap_err();
+        assert!(error.to_string().contains("formula 'y / 10' is invalid"));
+    }
+
+    #[test]
+    fn test_register_references() {
+        assert_eq!(eval("phase_a + phase_b + meter.W"), Some(0.5));
+        assert_eq!(eval("phase_a + phase_c"), None);
+
+        let expr = Expr::parse("abs(phase_a) * phase_b - phase_a").unwrap();
+        assert_eq!(expr.references(), ["phase_a", "phase_b", "phase_a"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for source in [
+            "",
+            "1 +",
+            "(1 + 2",
+            "1 2",
+            "foo(1)",
+            "abs(1, 2)",
+            "1 $ 2",
+            "1..2",
+        ] {
+            assert!(
+                Expr::parse(source).is_err(),
+                "{:?} should not parse",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Recent values of every register
+//!
+//! Each register keeps its last `history.depth` readings. On top of that,
+//! `history.max_samples` caps the samples held across all registers: once
+//! it is reached, the oldest samples are evicted whichever register they
+//! belong to, so a large device list with a generous depth cannot exhaust
+//! memory.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::config::HistoryConfig;
+use crate::metrics;
+use crate::modbus::reader::{self, Quality, RegisterValue};
+
+/// One recorded reading
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    /// The text of a string register, else the scaled number
+    pub value: serde_json::Value,
+    pub quality: Quality,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Recording order across all registers
+    #[serde(skip)]
+    seq: u64,
+}
+
+impl Sample {
+    /// A reading kept outside the in-memory history, e.g. in the database
+    pub(crate) fn new(
+        value: serde_json::Value,
+        quality: Quality,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            value,
+            quality,
+            timestamp,
+            seq: 0,
+        }
+    }
+}
+
+/// Which of a register's samples to return
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct SampleRange {
+    /// Only samples taken at or after this time
+    #[serde(default)]
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only samples taken at or before this time
+    #[serde(default)]
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only the most recent samples within the range
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl SampleRange {
+    fn contains(&self, sample: &Sample) -> bool {
+        self.from.is_none_or(|from| sample.timestamp >= from)
+            && self.to.is_none_or(|to| sample.timestamp <= to)
+    }
+}
+
+/// Samples of every register, oldest first, keyed by device and register
+#[derive(Debug, Default)]
+struct Samples {
+    registers: HashMap<(String, String), VecDeque<Sample>>,
+    total: usize,
+    next_seq: u64,
+}
+
+impl Samples {
+    /// Drop the oldest sample of all registers
+    ///
+    /// Scans the oldest sample of each register, which is cheap next to
+    /// the number of samples kept.
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .registers
+            .iter()
+            .filter_map(|(key, samples)| Some((samples.front()?.seq, key)))
+            .min_by_key(|(seq, _)| *seq)
+            .map(|(_, key)| key.clone());
+        if let Some(key) = oldest {
+            let samples = self.registers.get_mut(&key).expect("key was just found");
+            samples.pop_front();
+            if samples.is_empty() {
+                self.registers.remove(&key);
+            }
+            self.total -= 1;
+        }
+    }
+}
+
+/// Register history shared between the pollers and the API; records
+/// nothing unless enabled
+#[derive(Debug, Clone)]
+pub struct History {
+    enabled: bool,
+    depth: usize,
+    max_samples: usize,
+    samples: Arc<Mutex<Samples>>,
+}
+
+impl History {
+    pub fn new(config: &HistoryConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            depth: config.depth.max(1),
+            max_samples: config.max_samples.max(1),
+            samples: Arc::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record a reading, evicting the register's oldest sample beyond
+    /// `depth` and the oldest samples overall beyond `max_samples`
+    pub fn record(&self, device_id: &str, value: &RegisterValue) {
+        if !self.enabled {
+            return;
+        }
+
+        let (total, evicted) = {
+            let mut samples = self.samples.lock().unwrap();
+            let seq = samples.next_seq;
+            samples.next_seq += 1;
+
+            let register = samples
+                .registers
+                .entry((device_id.to_string(), value.name.clone()))
+                .or_default();
+            let mut removed = 0;
+            if register.len() >= self.depth {
+                register.pop_front();
+                removed = 1;
+            }
+            register.push_back(Sample {
+                value: reader::output_value(value),
+                quality: value.quality,
+                timestamp: value.timestamp,
+                seq,
+            });
+            samples.total = samples.total + 1 - removed;
+
+            let mut evicted = 0;
+            while samples.total > self.max_samples {
+                samples.evict_oldest();
+                evicted += 1;
+            }
+            (samples.total, evicted)
+        };
+
+        metrics::record_history(total, evicted);
+    }
+
+    /// Forget the samples of one register of a device, or of all its
+    /// registers
+    pub fn remove(&self, device_id: &str, register_name: Option<&str>) {
+        let total = {
+            let mut samples = self.samples.lock().unwrap();
+            let mut removed = 0;
+            samples.registers.retain(|(device, register), kept| {
+                let matches =
+                    device == device_id && register_name.is_none_or(|name| name == register);
+                if matches {
+                    removed += kept.len();
+                }
+                !matches
+            });
+            samples.total -= removed;
+            samples.total
+        };
+        metrics::record_history(total, 0);
+    }
+
+    /// Recorded samples of a register, oldest first
+    pub fn get(&self, device_id: &str, register_name: &str) -> Vec<Sample> {
+        let samples = self.samples.lock().unwrap();
+        samples
+            .registers
+            .get(&(device_id.to_string(), register_name.to_string()))
+            .map(|samples| samples.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Recorded samples of a register within `range`, oldest first
+    pub fn query(&self, device_id: &str, register_name: &str, range: &SampleRange) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = self
+            .get(device_id, register_name)
+            .into_iter()
+            .filter(|sample| range.contains(sample))
+            .collect();
+        if let Some(limit) = range.limit {
+            samples.drain(..samples.len().saturating_sub(limit));
+        }
+        samples
+    }
+
+    /// Samples held across all registers
+    pub fn sample_count(&self) -> usize {
+        self.samples.lock().unwrap().total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(name: &str, value: f64) -> RegisterValue {
+        RegisterValue {
+            name: name.to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn values(history: &History, register: &str) -> Vec<serde_json::Value> {
+        history
+            .get("plc-001", register)
+            .into_iter()
+            .map(|s| s.value)
+            .collect()
+    }
+
+    #[test]
+    fn test_depth_per_register() {
+        let history = History::new(&HistoryConfig {
+            enabled: true,
+            depth: 2,
+            max_samples: 100,
+        });
+        for value in [1.0, 2.0, 3.0] {
+            history.record("plc-001", &reading("temperature", value));
+        }
+        assert_eq!(values(&history, "temperature"), [2.0, 3.0]);
+        assert_eq!(history.sample_count(), 2);
+    }
+
+    #[test]
+    fn test_global_budget_evicts_oldest_across_registers() {
+        let history = History::new(&HistoryConfig {
+            enabled: true,
+            depth: 10,
+            max_samples: 3,
+        });
+        history.record("plc-001", &reading("temperature", 1.0));
+        history.record("plc-001", &reading("pressure", 10.0));
+        history.record("plc-001", &reading("temperature", 2.0));
+        history.record("plc-001", &reading("pressure", 20.0));
+        history.record("plc-001", &reading("pressure", 30.0));
+
+        assert_eq!(history.sample_count(), 3);
+        assert_eq!(values(&history, "temperature"), [2.0]);
+        assert_eq!(values(&history, "pressure"), [20.0, 30.0]);
+    }
+
+    #[test]
+    fn test_query_range() {
+        let history = History::new(&HistoryConfig {
+            enabled: true,
+            depth: 10,
+            max_samples: 100,
+        });
+        let start = chrono::Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        for (secs, value) in [(0, 1.0), (10, 2.0), (20, 3.0), (30, 4.0)] {
+            history.record(
+                "plc-001",
+                &RegisterValue {
+                    timestamp: at(secs),
+                    ..reading("temperature", value)
+                },
+            );
+        }
+        let query = |range: SampleRange| -> Vec<serde_json::Value> {
+            history
+                .query("plc-001", "temperature", &range)
+                .into_iter()
+                .map(|s| s.value)
+                .collect()
+        };
+
+        assert_eq!(query(SampleRange::default()), [1.0, 2.0, 3.0, 4.0]);
+        // Both ends are included
+        let range = SampleRange {
+            from: Some(at(10)),
+            to: Some(at(20)),
+            limit: None,
+        };
+        assert_eq!(query(range), [2.0, 3.0]);
+        // The limit keeps the most recent samples
+        let range = SampleRange {
+            to: Some(at(25)),
+            limit: Some(2),
+            ..SampleRange::default()
+        };
+        assert_eq!(query(range), [2.0, 3.0]);
+        let range = SampleRange {
+            from: Some(at(31)),
+            ..SampleRange::default()
+        };
+        assert!(query(range).is_empty());
+    }
+
+    #[test]
+    fn test_remove_register_and_device() {
+        let history = History::new(&HistoryConfig {
+            enabled: true,
+            depth: 10,
+            max_samples: 100,
+        });
+        history.record("plc-001", &reading("temperature", 1.0));
+        history.record("plc-001", &reading("temperature", 2.0));
+        history.record("plc-001", &reading("pressure", 10.0));
+        history.record("plc-002", &reading("temperature", 5.0));
+
+        history.remove("plc-001", Some("temperature"));
+        assert_eq!(history.sample_count(), 2);
+        assert!(history.get("plc-001", "temperature").is_empty());
+        assert_eq!(values(&history, "pressure"), [10.0]);
+
+        history.remove("plc-001", None);
+        assert_eq!(history.sample_count(), 1);
+        assert_eq!(history.get("plc-002", "temperature").len(), 1);
+    }
+
+    #[test]
+    fn test_disabled_records_nothing() {
+        let history = History::new(&HistoryConfig::default());
+        history.record("plc-001", &reading("temperature", 1.0));
+        assert_eq!(history.sample_count(), 0);
+        assert!(history.get("plc-001", "temperature").is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Read Device Identification (FC43 / MEI type 14)
+//!
+//! Devices that implement it report their vendor, product code and firmware
+//! revision as the basic identification objects 0x00-0x02. The response may
+//! be split over several transactions ("more follows").
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Function code of Encapsulated Interface Transport
+pub const FUNCTION_CODE: u8 = 0x2B;
+
+/// MEI type of Read Device Identification
+const MEI_READ_DEVICE_ID: u8 = 0x0E;
+
+/// Read device id code for the basic objects (stream access)
+const BASIC_IDENTIFICATION: u8 = 0x01;
+
+/// Identification per device id, for devices that reported one
+pub type DeviceInfoStore = Arc<RwLock<HashMap<String, DeviceInfo>>>;
+
+/// Identification reported by a device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Store an identification object; objects other than the basic ones
+    /// are ignored
+    pub fn set_object(&mut self, id: u8, value: String) {
+        match id {
+            0x00 => self.vendor_name = Some(value),
+            0x01 => self.product_code = Some(value),
+            0x02 => self.revision = Some(value),
+            _ => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Request data (after the function code) asking for the basic objects
+/// starting at `object_id`
+pub fn request(object_id: u8) -> [u8; 3] {
+    [MEI_READ_DEVICE_ID, BASIC_IDENTIFICATION, object_id]
+}
+
+/// One parsed response: its objects and the object id to continue from if
+/// the device has more to send
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResponsePart {
+    pub objects: Vec<(u8, String)>,
+    pub next_object_id: Option<u8>,
+}
+
+/// Parse the data of a Read Device Identification response (after the
+/// function code)
+pub fn parse_response(data: &[u8]) -> Result<ResponsePart, String> {
+    let header = data
+        .get(..6)
+        .ok_or_else(|| format!("response too short ({} bytes)", data.len()))?;
+    if header[0] != MEI_READ_DEVICE_ID {
+        return Err(format!("unexpected MEI type {:#04x}", header[0]));
+    }
+    let more_follows = header[3] == 0xFF;
+    let next_object_id = header[4];
+    let count = header[5];
+
+    let mut objects = Vec::with_capacity(count as usize);
+    let mut rest = &data[6..];
+    for _ in 0..count {
+        let (&[id, len], tail) = rest
+            .split_first_chunk::<2>()
+            .ok_or_else(|| "truncated object header".to_string())?;
+        let value = tail
+            .get(..len as usize)
+            .ok_or_else(|| format!("truncated value of object {:#04x}", id))?;
+        objects.push((id, String::from_utf8_lossy(value).trim().to_string()));
+        rest = &tail[len as usize..];
+    }
+
+    Ok(ResponsePart {
+        objects,
+        next_object_id: more_follows.then_some(next_object_id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_basic_identification() {
+        let data = [
+            0x0E, 0x01, 0x01, 0x00, 0x00, 0x03, // header: 3 objects, complete
+            0x00, 0x07, b'A', b'c', b'm', b'e', b' ', b'A', b'G', // vendor
+            0x01, 0x04, b'P', b'M', b'-', b'3', // product code
+            0x02, 0x05, b'v', b'2', b'.', b'1', b' ', // revision, padded
+        ];
+
+        let part = parse_response(&data).unwrap();
+        assert_eq!(part.next_object_id, None);
+
+        let mut info = DeviceInfo::default();
+        for (id, value) in part.objects {
+            info.set_object(id, value);
+        }
+        assert_eq!(info.vendor_name.as_deref(), Some("Acme AG"));
+        assert_eq!(info.product_code.as_deref(), Some("PM-3"));
+        assert_eq!(info.revision.as_deref(), Some("v2.1"));
+    }
+
+    #[test]
+    fn test_parse_more_follows_and_truncation() {
+        // Only the vendor fits, the device continues at object 0x01
+        let data = [0x0E, 0x01, 0x01, 0xFF, 0x01, 0x01, 0x00, 0x02, b'A', b'B'];
+        assert_eq!(
+            parse_response(&data).unwrap(),
+            ResponsePart {
+                objects: vec![(0x00, "AB".to_string())],
+                next_object_id: Some(0x01),
+            }
+        );
+
+        assert!(parse_response(&[0x0E, 0x01]).is_err());
+        assert!(parse_response(&[0x0E, 0x01, 0x01, 0x00, 0x00, 0x01, 0x00, 0x05, b'A']).is_err());
+    }
+}
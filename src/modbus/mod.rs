@@ -4,20 +4,103 @@
 
 use anyhow::{Context as AnyhowContext, Result};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio_modbus::prelude::*;
 use tokio_serial::SerialPortBuilderExt;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, trace, warn};
 
-use crate::config::{ConnectionConfig, DeviceConfig, RegisterConfig, RegisterType};
+use crate::config::{
+    AddressFamily, ConnectionConfig, DeviceConfig, RegisterBuilder, RegisterConfig, RegisterType,
+    RtuConnection, TcpConnection,
+};
 
+pub mod breaker;
+pub mod bus;
 pub mod client;
+pub mod edges;
+pub mod expression;
+pub mod history;
+pub mod identification;
+pub mod pause;
 pub mod reader;
+pub mod removed;
+pub mod sunspec;
+pub mod throttle;
+
+/// Tracing target for the Modbus wire log
+pub const WIRE_LOG_TARGET: &str = "rustbridge::wire";
+
+/// Number of response words included in a wire log entry
+const WIRE_LOG_WORDS: usize = 4;
+
+/// Most holding registers read in one request
+pub const MAX_READ_COUNT: u16 = 125;
+
+/// Most Read Device Identification transactions followed for one device
+const MAX_IDENTIFICATION_PARTS: usize = 8;
+
+/// What a device's connections share with other connections
+#[derive(Debug, Clone, Default)]
+pub struct SharedAccess {
+    /// Rate limits every request waits for
+    pub throttle: throttle::Throttle,
+    /// Serial bus the device shares with other devices
+    pub bus: Option<bus::SerialBus>,
+}
+
+/// How a client reaches its device
+enum Link {
+    /// A connection of its own
+    Dedicated(client::Context),
+    /// A serial bus shared with other devices
+    Bus(bus::SerialBus),
+}
+
+/// A connection held for one request
+enum Connection<'a> {
+    Dedicated(&'a mut client::Context),
+    Bus(bus::BusGuard),
+}
+
+impl std::ops::Deref for Connection<'_> {
+    type Target = client::Context;
+
+    fn deref(&self) -> &client::Context {
+        match self {
+            Connection::Dedicated(ctx) => ctx,
+            Connection::Bus(guard) => guard,
+        }
+    }
+}
+
+impl std::ops::DerefMut for Connection<'_> {
+    fn deref_mut(&mut self) -> &mut client::Context {
+        match self {
+            Connection::Dedicated(ctx) => ctx,
+            Connection::Bus(guard) => guard,
+        }
+    }
+}
 
 /// Modbus client abstraction supporting TCP and RTU
 pub struct ModbusClient {
     device_id: String,
     device_type: String,
-    context: Option<client::Context>,
+    context: Option<Link>,
+    /// Unit id the next request is addressed to
+    unit_id: u8,
+    /// The device's configured unit id
+    default_unit_id: u8,
+    wire_log: bool,
+    request_seq: u64,
+    /// Reads in a row that timed out
+    consecutive_timeouts: u32,
+    /// Reads in a row that got no answer for any reason
+    consecutive_failures: u32,
+    /// Rate limits every request waits for
+    throttle: throttle::Throttle,
+    /// How long a read may wait for the device's answer
+    read_timeout: Duration,
 }
 
 impl ModbusClient {
@@ -27,80 +110,40 @@ impl ModbusClient {
 
         let (context, device_type) = match &config.connection {
             ConnectionConfig::Tcp(tcp) => {
-                let addr: SocketAddr = format!("{}:{}", tcp.host, tcp.port)
-                    .parse()
-                    .with_context(|| "Invalid TCP address")?;
-
-                info!("Connecting to Modbus TCP: {} (unit {})", addr, tcp.unit_id);
+                let (stream, addr) = connect_tcp(tcp).await?;
 
-                let ctx = tcp::connect_slave(addr, Slave(tcp.unit_id))
-                    .await
-                    .with_context(|| format!("Failed to connect to {}", addr))?;
+                match tcp.slave_id {
+                    None => {
+                        info!("Connected to Modbus TCP: {} (unit {})", addr, tcp.unit_id);
 
-                (Some(client::Context::Tcp(ctx)), "TCP".to_string())
-            }
-            ConnectionConfig::Rtu(rtu) => {
-                info!(
-                    "Connecting to Modbus RTU: {} @ {} baud (unit {})",
-                    rtu.port, rtu.baud_rate, rtu.unit_id
-                );
+                        let ctx = tcp::attach_slave(stream, Slave(tcp.unit_id));
 
-                // Parse parity
-                let parity = match rtu.parity.to_lowercase().as_str() {
-                    "none" => tokio_serial::Parity::None,
-                    "even" => tokio_serial::Parity::Even,
-                    "odd" => tokio_serial::Parity::Odd,
-                    _ => {
-                        warn!("Unknown parity '{}', using None", rtu.parity);
-                        tokio_serial::Parity::None
+                        (
+                            Some(Link::Dedicated(client::Context::Tcp(ctx))),
+                            "TCP".to_string(),
+                        )
                     }
-                };
-
-                // Parse stop bits
-                let stop_bits = match rtu.stop_bits {
-                    1 => tokio_serial::StopBits::One,
-                    2 => tokio_serial::StopBits::Two,
-                    _ => {
-                        warn!("Unknown stop bits {}, using 1", rtu.stop_bits);
-                        tokio_serial::StopBits::One
-                    }
-                };
-
-                // Parse data bits
-                let data_bits = match rtu.data_bits {
-                    5 => tokio_serial::DataBits::Five,
-                    6 => tokio_serial::DataBits::Six,
-                    7 => tokio_serial::DataBits::Seven,
-                    8 => tokio_serial::DataBits::Eight,
-                    _ => {
-                        warn!("Unknown data bits {}, using 8", rtu.data_bits);
-                        tokio_serial::DataBits::Eight
-                    }
-                };
-
-                // Create serial port builder
-                let builder = tokio_serial::new(&rtu.port, rtu.baud_rate)
-                    .parity(parity)
-                    .stop_bits(stop_bits)
-                    .data_bits(data_bits);
-
-                // Open serial port
-                let port = builder.open_native_async().with_context(|| {
-                    format!(
-                        "Failed to open serial port {} at {} baud",
-                        rtu.port, rtu.baud_rate
-                    )
-                })?;
+                    Some(slave_id) => {
+                        // MBAP frames carry no separate slave address, so a
+                        // transparent serial bridge is addressed with RTU
+                        // frames tunnelled over the TCP connection.
+                        info!(
+                            "Connected to Modbus RTU over TCP: {} (slave {})",
+                            addr, slave_id
+                        );
 
-                info!(
-                    "Serial port {} opened: {} baud, {} data bits, {:?} parity, {:?} stop bits",
-                    rtu.port, rtu.baud_rate, rtu.data_bits, parity, stop_bits
-                );
+                        let ctx = rtu::attach_slave(stream, Slave(slave_id));
 
-                // Create RTU context
-                let ctx = rtu::attach_slave(port, Slave(rtu.unit_id));
-
-                (Some(client::Context::Rtu(ctx)), "RTU".to_string())
+                        (
+                            Some(Link::Dedicated(client::Context::Rtu(ctx))),
+                            "RTU over TCP".to_string(),
+                        )
+                    }
+                }
+            }
+            ConnectionConfig::Rtu(rtu) => {
+                let ctx = open_rtu(rtu)?;
+                (Some(Link::Dedicated(ctx)), "RTU".to_string())
             }
         };
 
@@ -109,72 +152,479 @@ impl ModbusClient {
             device_type, config.id
         );
 
+        let unit_id = config.unit_id();
+
+        if config.wire_log {
+            warn!(
+                "Modbus wire log enabled for device {} (visible at trace level for target {})",
+                config.id, WIRE_LOG_TARGET
+            );
+        }
+
         Ok(Self {
             device_id: config.id.clone(),
             device_type,
             context,
+            unit_id,
+            default_unit_id: unit_id,
+            wire_log: config.wire_log,
+            request_seq: 0,
+            consecutive_timeouts: 0,
+            consecutive_failures: 0,
+            throttle: throttle::Throttle::default(),
+            read_timeout: Duration::from_millis(config.read_timeout_ms),
         })
     }
 
+    /// A client without a connection, whose requests all fail
+    #[cfg(test)]
+    pub(crate) fn disconnected(config: &DeviceConfig) -> Self {
+        Self {
+            device_id: config.id.clone(),
+            device_type: "TCP".to_string(),
+            context: None,
+            unit_id: config.unit_id(),
+            default_unit_id: config.unit_id(),
+            wire_log: false,
+            request_seq: 0,
+            consecutive_timeouts: 0,
+            consecutive_failures: 0,
+            throttle: throttle::Throttle::default(),
+            read_timeout: Duration::from_millis(config.read_timeout_ms),
+        }
+    }
+
+    /// Connect a client subject to the rate limits in `shared`
+    ///
+    /// A device on a shared serial bus uses the bus's port, opening it if
+    /// no other device has yet.
+    pub async fn connect(config: &DeviceConfig, shared: &SharedAccess) -> Result<Self> {
+        let mut client = match &shared.bus {
+            Some(bus) => {
+                bus.open().await?;
+                info!(
+                    "Modbus RTU client ready for device {} on shared bus {} (unit {})",
+                    config.id,
+                    bus.id(),
+                    config.unit_id()
+                );
+                Self {
+                    device_id: config.id.clone(),
+                    device_type: "RTU".to_string(),
+                    context: Some(Link::Bus(bus.clone())),
+                    unit_id: config.unit_id(),
+                    default_unit_id: config.unit_id(),
+                    wire_log: config.wire_log,
+                    request_seq: 0,
+                    consecutive_timeouts: 0,
+                    consecutive_failures: 0,
+                    throttle: throttle::Throttle::default(),
+                    read_timeout: Duration::from_millis(config.read_timeout_ms),
+                }
+            }
+            None => Self::new(config).await?,
+        };
+        client.throttle = shared.throttle.clone();
+        Ok(client)
+    }
+
+    /// The connection, once the rate limits allow the next request on it
+    ///
+    /// On a shared bus this waits for the bus too, and holds it until the
+    /// returned connection is dropped.
+    async fn connection(&mut self) -> Result<Connection<'_>> {
+        if self.context.is_none() {
+            anyhow::bail!("No connection available");
+        }
+        self.throttle.wait().await;
+        crate::metrics::record_modbus_request(&self.device_id);
+        match self.context.as_mut().expect("connection checked above") {
+            Link::Dedicated(ctx) => Ok(Connection::Dedicated(ctx)),
+            Link::Bus(bus) => Ok(Connection::Bus(bus.acquire(self.unit_id).await?)),
+        }
+    }
+
+    /// Record a request in the wire log, if enabled for this device
+    ///
+    /// `data` is the response words for reads and the written words for
+    /// writes. `seq` numbers requests on this client; tokio-modbus does not
+    /// expose the MBAP transaction identifier itself.
+    fn log_wire(
+        &mut self,
+        function_code: u8,
+        address: u16,
+        count: u16,
+        data: std::result::Result<&[u16], &anyhow::Error>,
+    ) {
+        if !self.wire_log {
+            return;
+        }
+        self.request_seq = self.request_seq.wrapping_add(1);
+
+        match data {
+            Ok(words) => trace!(
+                target: WIRE_LOG_TARGET,
+                device = %self.device_id,
+                seq = self.request_seq,
+                unit_id = self.unit_id,
+                function_code,
+                address,
+                count,
+                data = ?&words[..words.len().min(WIRE_LOG_WORDS)],
+                "Modbus request ok"
+            ),
+            Err(e) => trace!(
+                target: WIRE_LOG_TARGET,
+                device = %self.device_id,
+                seq = self.request_seq,
+                unit_id = self.unit_id,
+                function_code,
+                address,
+                count,
+                error = %e,
+                "Modbus request failed"
+            ),
+        }
+    }
+
     /// Read registers from the device
+    ///
+    /// A register with its own `unit_id` is read from that unit; the
+    /// device's unit id is restored afterwards.
     pub async fn read_registers(&mut self, register: &RegisterConfig) -> Result<Vec<u16>> {
-        let ctx = self
-            .context
-            .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("No connection available"))?;
+        self.read_registers_within(register, self.read_timeout)
+            .await
+    }
+
+    /// Read registers, giving up on the answer after `read_timeout`
+    ///
+    /// The timeout is applied to the request itself, so the unit id is
+    /// restored even when the device never answers.
+    async fn read_registers_within(
+        &mut self,
+        register: &RegisterConfig,
+        read_timeout: Duration,
+    ) -> Result<Vec<u16>> {
+        let unit_id = register.unit_id.unwrap_or(self.default_unit_id);
+        let result = if unit_id == self.unit_id {
+            self.read_from_current_unit(register, read_timeout).await
+        } else {
+            self.set_unit_id(unit_id);
+            let result = self.read_from_current_unit(register, read_timeout).await;
+            self.set_unit_id(self.default_unit_id);
+            result
+        };
+
+        match &result {
+            // Any answer, even an exception, shows the device is responsive
+            Ok(_) => {
+                self.consecutive_timeouts = 0;
+                self.consecutive_failures = 0;
+            }
+            Err(e) if is_exception(e) => {
+                self.consecutive_timeouts = 0;
+                self.consecutive_failures = 0;
+            }
+            Err(e) => {
+                if is_timeout(e) {
+                    self.consecutive_timeouts = self.consecutive_timeouts.saturating_add(1);
+                }
+                self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+            }
+        }
+        result
+    }
+
+    /// Reads in a row that got no answer on this connection
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Reads in a row that timed out on this connection
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.consecutive_timeouts
+    }
+
+    /// Close the connection; requests fail until the client is replaced
+    pub async fn disconnect(&mut self) {
+        // A shared bus stays open for the other devices on it; a transport
+        // error on it has closed the port already, and reconnecting reopens it
+        if let Some(Link::Dedicated(mut ctx)) = self.context.take() {
+            if let Err(e) = ctx.disconnect().await {
+                debug!(
+                    "Device {}: error while disconnecting: {}",
+                    self.device_id, e
+                );
+            }
+        }
+    }
+
+    async fn read_from_current_unit(
+        &mut self,
+        register: &RegisterConfig,
+        read_timeout: Duration,
+    ) -> Result<Vec<u16>> {
+        let device_type = self.device_type.clone();
+        let mut ctx = self.connection().await?;
 
-        let values = match register.register_type {
+        let (function_code, result) = match register.register_type {
             RegisterType::Holding => {
                 debug!(
                     "Reading {} holding registers from address {} ({})",
-                    register.count, register.address, self.device_type
+                    register.count, register.address, device_type
                 );
-                ctx.read_holding_registers(register.address, register.count)
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Modbus error: {}", e))?
+                let result = within(
+                    read_timeout,
+                    ctx.read_holding_registers(register.address, register.count),
+                )
+                .await
+                .map_err(anyhow::Error::from);
+                (3, result)
             }
             RegisterType::Input => {
                 debug!(
                     "Reading {} input registers from address {} ({})",
-                    register.count, register.address, self.device_type
+                    register.count, register.address, device_type
                 );
-                ctx.read_input_registers(register.address, register.count)
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Modbus error: {}", e))?
+                let result = within(
+                    read_timeout,
+                    ctx.read_input_registers(register.address, register.count),
+                )
+                .await
+                .map_err(anyhow::Error::from);
+                (4, result)
             }
             RegisterType::Coil => {
-                let coils = ctx
-                    .read_coils(register.address, register.count)
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Modbus error: {}", e))?;
-                coils.iter().map(|&b| if b { 1u16 } else { 0u16 }).collect()
+                let result = within(
+                    read_timeout,
+                    ctx.read_coils(register.address, register.count),
+                )
+                .await
+                .map(|coils| coils.iter().map(|&b| if b { 1u16 } else { 0u16 }).collect())
+                .map_err(anyhow::Error::from);
+                (1, result)
+            }
+            RegisterType::Virtual => {
+                anyhow::bail!(
+                    "Register {} is virtual and not read from the device",
+                    register.name
+                )
             }
             RegisterType::Discrete => {
-                let inputs = ctx
-                    .read_discrete_inputs(register.address, register.count)
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Modbus error: {}", e))?;
-                inputs
-                    .iter()
-                    .map(|&b| if b { 1u16 } else { 0u16 })
-                    .collect()
+                let result = within(
+                    read_timeout,
+                    ctx.read_discrete_inputs(register.address, register.count),
+                )
+                .await
+                .map(|inputs| {
+                    inputs
+                        .iter()
+                        .map(|&b| if b { 1u16 } else { 0u16 })
+                        .collect()
+                })
+                .map_err(anyhow::Error::from);
+                (2, result)
             }
         };
+        if let (Connection::Bus(guard), Err(e)) = (ctx, &result) {
+            if is_transport_failure(e) {
+                guard.close();
+            }
+        }
+
+        self.log_wire(
+            function_code,
+            register.address,
+            register.count,
+            result.as_ref().map(Vec::as_slice),
+        );
+
+        result
+    }
+
+    /// Read an arbitrary address range, optionally from another unit id
+    ///
+    /// The device's own unit id is restored afterwards, so polling is not
+    /// affected. `timeout` replaces the device's read timeout for this read.
+    pub async fn read_raw(
+        &mut self,
+        register_type: RegisterType,
+        address: u16,
+        count: u16,
+        unit_id: Option<u8>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u16>> {
+        let mut register = RegisterBuilder::new("raw", address, register_type)
+            .count(count)
+            .build();
+        register.unit_id = unit_id;
 
-        Ok(values)
+        self.read_registers_within(&register, timeout.unwrap_or(self.read_timeout))
+            .await
+    }
+
+    fn set_unit_id(&mut self, unit_id: u8) {
+        // A shared bus is addressed when acquired for a request
+        if let Some(Link::Dedicated(ctx)) = self.context.as_mut() {
+            ctx.set_slave(Slave(unit_id));
+        }
+        self.unit_id = unit_id;
+    }
+
+    /// Read the device's identification objects (FC43)
+    ///
+    /// Returns `None` if the device rejects the request, i.e. does not
+    /// implement it.
+    pub async fn read_device_info(&mut self) -> Result<Option<identification::DeviceInfo>> {
+        let mut info = identification::DeviceInfo::default();
+        let mut object_id = 0;
+
+        for _ in 0..MAX_IDENTIFICATION_PARTS {
+            let mut ctx = self.connection().await?;
+            let result = ctx.read_device_identification(object_id).await;
+            let data = match result {
+                Ok(data) => data,
+                Err(client::ModbusError::Exception(e)) => {
+                    self.log_wire(43, object_id as u16, 0, Err(&anyhow::anyhow!("{:?}", e)));
+                    debug!(
+                        "Device {} does not support device identification: {:?}",
+                        self.device_id, e
+                    );
+                    return Ok(None);
+                }
+                Err(e) => {
+                    let e = anyhow::Error::from(e);
+                    self.log_wire(43, object_id as u16, 0, Err(&e));
+                    return Err(e);
+                }
+            };
+            self.log_wire(43, object_id as u16, 0, Ok(&[]));
+
+            let part = identification::parse_response(&data)
+                .map_err(|e| anyhow::anyhow!("Invalid device identification response: {}", e))?;
+            for (id, value) in part.objects {
+                info.set_object(id, value);
+            }
+            match part.next_object_id {
+                Some(next) if next > object_id => object_id = next,
+                _ => break,
+            }
+        }
+
+        Ok((!info.is_empty()).then_some(info))
+    }
+
+    /// Find the device's SunSpec models and build registers for the
+    /// points of the supported ones
+    ///
+    /// `base_address` is where the "SunS" marker is expected; the usual
+    /// addresses are probed when it is not given.
+    pub async fn discover_sunspec(
+        &mut self,
+        base_address: Option<u16>,
+    ) -> Result<Vec<RegisterConfig>> {
+        let candidates = match base_address {
+            Some(address) => vec![address],
+            None => sunspec::BASE_ADDRESSES.to_vec(),
+        };
+        let mut base = None;
+        for address in &candidates {
+            match self
+                .read_raw(RegisterType::Holding, *address, 2, None, None)
+                .await
+            {
+                Ok(marker) if marker == sunspec::MARKER => {
+                    base = Some(*address);
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => debug!("No SunSpec marker at {}: {}", address, e),
+            }
+        }
+        let base =
+            base.ok_or_else(|| anyhow::anyhow!("No SunSpec marker found at {:?}", candidates))?;
+
+        let mut registers = Vec::new();
+        let mut instances: std::collections::HashMap<&str, usize> = Default::default();
+        let mut address = base + 2;
+        for _ in 0..sunspec::MAX_MODELS {
+            let header = self
+                .read_raw(RegisterType::Holding, address, 2, None, None)
+                .await?;
+            let (id, length) = (header[0], header[1]);
+            if id == sunspec::END_MODEL_ID {
+                info!(
+                    "Device {}: {} SunSpec registers discovered at base address {}",
+                    self.device_id,
+                    registers.len(),
+                    base
+                );
+                return Ok(registers);
+            }
+
+            let model = sunspec::ModelHeader {
+                id,
+                address: address + 2,
+                length,
+            };
+            match sunspec::model_prefix(id) {
+                Some(prefix) => {
+                    let mut block = Vec::with_capacity(length.into());
+                    while block.len() < usize::from(length) {
+                        let offset = block.len() as u16;
+                        let count = (length - offset).min(MAX_READ_COUNT);
+                        block.extend(
+                            self.read_raw(
+                                RegisterType::Holding,
+                                model.address + offset,
+                                count,
+                                None,
+                                None,
+                            )
+                            .await?,
+                        );
+                    }
+                    // Repeated models (e.g. two meters) get numbered names
+                    let instance = instances.entry(prefix).or_insert(0);
+                    *instance += 1;
+                    let name = match *instance {
+                        1 => prefix.to_string(),
+                        n => format!("{}_{}", prefix, n),
+                    };
+                    registers.extend(sunspec::model_registers(&model, &block, &name));
+                }
+                None => debug!(
+                    "Device {}: skipping unsupported SunSpec model {}",
+                    self.device_id, id
+                ),
+            }
+
+            address = address
+                .checked_add(2 + length)
+                .ok_or_else(|| anyhow::anyhow!("SunSpec model chain exceeds the address space"))?;
+        }
+
+        anyhow::bail!(
+            "SunSpec model chain has no end marker after {} models",
+            sunspec::MAX_MODELS
+        )
     }
 
     /// Write a single register
-    #[allow(dead_code)]
     pub async fn write_register(&mut self, address: u16, value: u16) -> Result<()> {
-        let ctx = self
-            .context
-            .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("No connection available"))?;
+        let mut ctx = self.connection().await?;
 
-        ctx.write_single_register(address, value)
+        let result = ctx
+            .write_single_register(address, value)
             .await
-            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e))?;
+            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e));
+        self.log_wire(
+            6,
+            address,
+            1,
+            result.as_ref().map(|_| std::slice::from_ref(&value)),
+        );
+        result?;
 
         info!(
             "Wrote value {} to register {} on device {} ({})",
@@ -184,17 +634,87 @@ impl ModbusClient {
         Ok(())
     }
 
+    /// Write only the bits selected by `mask` in a holding register
+    ///
+    /// With `use_mask_write` the device applies FC22 (Mask Write Register)
+    /// atomically, which is preferred whenever the device supports it.
+    /// Otherwise this falls back to a read-modify-write (FC3 + FC6), so the
+    /// caller must make sure no other request to the device interleaves.
+    pub async fn set_bits(
+        &mut self,
+        address: u16,
+        mask: u16,
+        value: u16,
+        use_mask_write: bool,
+    ) -> Result<()> {
+        let mut ctx = self.connection().await?;
+
+        if use_mask_write {
+            let masks = [!mask, value & mask];
+            let result = ctx
+                .masked_write_register(address, masks[0], masks[1])
+                .await
+                .map_err(|e| anyhow::anyhow!("Modbus mask write error: {}", e));
+            self.log_wire(22, address, 1, result.as_ref().map(|_| &masks[..]));
+            result?;
+        } else {
+            let result = ctx
+                .read_holding_registers(address, 1)
+                .await
+                .map_err(|e| anyhow::anyhow!("Modbus error: {}", e));
+            self.log_wire(3, address, 1, result.as_ref().map(Vec::as_slice));
+            let current = result?
+                .first()
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("Empty response reading register {}", address))?;
+
+            let updated = apply_bit_mask(current, mask, value);
+            let mut ctx = self.connection().await?;
+            let result = ctx
+                .write_single_register(address, updated)
+                .await
+                .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e));
+            self.log_wire(
+                6,
+                address,
+                1,
+                result.as_ref().map(|_| std::slice::from_ref(&updated)),
+            );
+            result?;
+        }
+
+        info!(
+            "Set bits {:#06x} = {:#06x} in register {} on device {} ({}, {})",
+            mask,
+            value & mask,
+            address,
+            self.device_id,
+            self.device_type,
+            if use_mask_write {
+                "FC22"
+            } else {
+                "read-modify-write"
+            }
+        );
+
+        Ok(())
+    }
+
     /// Write multiple registers
-    #[allow(dead_code)]
     pub async fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<()> {
-        let ctx = self
-            .context
-            .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("No connection available"))?;
+        let mut ctx = self.connection().await?;
 
-        ctx.write_multiple_registers(address, values)
+        let result = ctx
+            .write_multiple_registers(address, values)
             .await
-            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e))?;
+            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e));
+        self.log_wire(
+            16,
+            address,
+            values.len() as u16,
+            result.as_ref().map(|_| values),
+        );
+        result?;
 
         info!(
             "Wrote {} registers starting at {} on device {} ({})",
@@ -208,16 +728,16 @@ impl ModbusClient {
     }
 
     /// Write a single coil
-    #[allow(dead_code)]
     pub async fn write_coil(&mut self, address: u16, value: bool) -> Result<()> {
-        let ctx = self
-            .context
-            .as_mut()
-            .ok_or_else(|| anyhow::anyhow!("No connection available"))?;
+        let mut ctx = self.connection().await?;
 
-        ctx.write_single_coil(address, value)
+        let result = ctx
+            .write_single_coil(address, value)
             .await
-            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e))?;
+            .map_err(|e| anyhow::anyhow!("Modbus write error: {}", e));
+        let word = [value as u16];
+        self.log_wire(5, address, 1, result.as_ref().map(|_| &word[..]));
+        result?;
 
         info!(
             "Wrote coil {} = {} on device {} ({})",
@@ -227,8 +747,29 @@ impl ModbusClient {
         Ok(())
     }
 
+    /// Pulse a coil: write `value`, hold it for `duration`, then write the
+    /// opposite state
+    ///
+    /// Emulates a momentary pushbutton (start/reset). Succeeds only if both
+    /// writes succeed; the caller must keep other requests to the device from
+    /// interleaving while the pulse is held.
+    pub async fn pulse_coil(
+        &mut self,
+        address: u16,
+        value: bool,
+        duration: std::time::Duration,
+    ) -> Result<()> {
+        self.write_coil(address, value).await?;
+        tokio::time::sleep(duration).await;
+        self.write_coil(address, !value).await.with_context(|| {
+            format!(
+                "Coil {} was set to {} but could not be released",
+                address, value
+            )
+        })
+    }
+
     /// Check if connection is alive
-    #[allow(dead_code)]
     pub fn is_connected(&self) -> bool {
         self.context.is_some()
     }
@@ -240,10 +781,219 @@ impl ModbusClient {
     }
 }
 
+/// Whether an error is a Modbus exception response, i.e. the device
+/// answered but rejected the request
+pub fn is_exception(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<client::ModbusError>(),
+        Some(client::ModbusError::Exception(_))
+    )
+}
+
+/// Classify a failed read for the `type` label of the error metric
+pub fn error_type(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<client::ModbusError>() {
+        Some(client::ModbusError::LengthMismatch { .. }) => "length_mismatch",
+        _ if is_timeout(error) => "timeout",
+        _ => "modbus_error",
+    }
+}
+
+/// Give up on a request the device has not answered within `limit`
+async fn within<T>(
+    limit: Duration,
+    request: impl std::future::Future<Output = std::result::Result<T, client::ModbusError>>,
+) -> std::result::Result<T, client::ModbusError> {
+    tokio::time::timeout(limit, request)
+        .await
+        .unwrap_or(Err(client::ModbusError::Timeout(limit)))
+}
+
+/// Whether a request failed because the device did not answer in time
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    let timed_out = |e: &std::io::Error| e.kind() == std::io::ErrorKind::TimedOut;
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return timed_out(e);
+        }
+        match cause.downcast_ref::<client::ModbusError>() {
+            Some(client::ModbusError::Timeout(_)) => true,
+            Some(client::ModbusError::Io(e)) => timed_out(e),
+            Some(client::ModbusError::Transport(tokio_modbus::Error::Transport(e))) => timed_out(e),
+            _ => false,
+        }
+    })
+}
+
+/// Whether a request failed on the connection itself, e.g. an unplugged
+/// serial adapter, rather than for want of a valid answer
+fn is_transport_failure(error: &anyhow::Error) -> bool {
+    !is_timeout(error)
+        && error.chain().any(|cause| {
+            cause.is::<std::io::Error>()
+                || matches!(
+                    cause.downcast_ref::<client::ModbusError>(),
+                    Some(client::ModbusError::Io(_))
+                        | Some(client::ModbusError::Transport(
+                            tokio_modbus::Error::Transport(_)
+                        ))
+                )
+        })
+}
+
+/// Resolve a TCP device's host and connect to the first address that accepts
+async fn connect_tcp(tcp: &TcpConnection) -> Result<(tokio::net::TcpStream, SocketAddr)> {
+    let addrs = resolve_tcp_addrs(&tcp.host, tcp.port, tcp.address_family).await?;
+
+    let mut last_error = None;
+    for addr in addrs {
+        debug!("Connecting to {}", addr);
+        match tokio::net::TcpStream::connect(addr).await {
+            Ok(stream) => return Ok((stream, addr)),
+            Err(e) => {
+                debug!("Connection to {} failed: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Failed to connect to {}:{}: {}",
+        tcp.host,
+        tcp.port,
+        last_error.map_or_else(|| "no usable address".to_string(), |e| e.to_string())
+    ))
+}
+
+/// Resolve a host (IP literal, bracketed IPv6 literal or hostname) into
+/// the socket addresses to try, in order
+pub async fn resolve_tcp_addrs(
+    host: &str,
+    port: u16,
+    family: AddressFamily,
+) -> Result<Vec<SocketAddr>> {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .collect();
+
+    let addrs = order_addrs(addrs, family);
+    if addrs.is_empty() {
+        anyhow::bail!("{} has no {:?} address", host, family);
+    }
+    Ok(addrs)
+}
+
+/// Line settings of a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SerialSettings {
+    data_bits: tokio_serial::DataBits,
+    stop_bits: tokio_serial::StopBits,
+    parity: tokio_serial::Parity,
+}
+
+/// Map the configured line settings to the serial port's, rejecting values
+/// the port cannot be opened with
+fn serial_settings(rtu: &RtuConnection) -> Result<SerialSettings> {
+    let data_bits = match rtu.data_bits {
+        5 => tokio_serial::DataBits::Five,
+        6 => tokio_serial::DataBits::Six,
+        7 => tokio_serial::DataBits::Seven,
+        8 => tokio_serial::DataBits::Eight,
+        other => anyhow::bail!("Invalid data bits {} for {}, expected 5-8", other, rtu.port),
+    };
+    let stop_bits = match rtu.stop_bits {
+        1 => tokio_serial::StopBits::One,
+        2 => tokio_serial::StopBits::Two,
+        other => anyhow::bail!(
+            "Invalid stop bits {} for {}, expected 1 or 2",
+            other,
+            rtu.port
+        ),
+    };
+    let parity = match rtu.parity.to_lowercase().as_str() {
+        "none" => tokio_serial::Parity::None,
+        "even" => tokio_serial::Parity::Even,
+        "odd" => tokio_serial::Parity::Odd,
+        _ => anyhow::bail!(
+            "Invalid parity '{}' for {}, expected none, even or odd",
+            rtu.parity,
+            rtu.port
+        ),
+    };
+    Ok(SerialSettings {
+        data_bits,
+        stop_bits,
+        parity,
+    })
+}
+
+/// Open a serial port for Modbus RTU
+fn open_rtu(rtu: &RtuConnection) -> Result<client::Context> {
+    info!(
+        "Connecting to Modbus RTU: {} @ {} baud (unit {})",
+        rtu.port, rtu.baud_rate, rtu.unit_id
+    );
+
+    let SerialSettings {
+        data_bits,
+        stop_bits,
+        parity,
+    } = serial_settings(rtu)?;
+    let builder = tokio_serial::new(&rtu.port, rtu.baud_rate)
+        .parity(parity)
+        .stop_bits(stop_bits)
+        .data_bits(data_bits);
+
+    // Open serial port
+    let port = builder.open_native_async().with_context(|| {
+        format!(
+            "Failed to open serial port {} at {} baud",
+            rtu.port, rtu.baud_rate
+        )
+    })?;
+
+    info!(
+        "Serial port {} opened: {} baud, {} data bits, {:?} parity, {:?} stop bits",
+        rtu.port, rtu.baud_rate, rtu.data_bits, parity, stop_bits
+    );
+
+    // Create RTU context
+    let ctx = rtu::attach_slave(port, Slave(rtu.unit_id));
+    Ok(client::Context::Rtu(ctx))
+}
+
+/// Filter and order resolved addresses by address family preference
+fn order_addrs(mut addrs: Vec<SocketAddr>, family: AddressFamily) -> Vec<SocketAddr> {
+    match family {
+        AddressFamily::Any => {}
+        AddressFamily::PreferIpv4 => addrs.sort_by_key(|a| a.is_ipv6()),
+        AddressFamily::PreferIpv6 => addrs.sort_by_key(|a| a.is_ipv4()),
+        AddressFamily::Ipv4 => addrs.retain(|a| a.is_ipv4()),
+        AddressFamily::Ipv6 => addrs.retain(|a| a.is_ipv6()),
+    }
+    addrs
+}
+
+/// Replace the bits selected by `mask` in `current` with those from `value`
+///
+/// This is the result a device computes for FC22 with
+/// `and_mask = !mask` and `or_mask = value & mask`.
+pub fn apply_bit_mask(current: u16, mask: u16, value: u16) -> u16 {
+    (current & !mask) | (value & mask)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{DataType, RtuConnection, TcpConnection};
+    use crate::config::{
+        AddressFamily, DataType, RtuConnection, TcpConnection, TextEncoding, WordOrder,
+    };
 
     #[test]
     fn test_tcp_connection_config() {
@@ -251,6 +1001,8 @@ mod tests {
             host: "192.168.1.100".to_string(),
             port: 502,
             unit_id: 1,
+            slave_id: None,
+            address_family: AddressFamily::Any,
         };
 
         assert_eq!(tcp.host, "192.168.1.100");
@@ -278,25 +1030,83 @@ mod tests {
 
     #[test]
     fn test_parity_parsing() {
-        // Test parity string parsing logic
-        let test_cases = vec![
-            ("none", "None"),
-            ("even", "Even"),
-            ("odd", "Odd"),
-            ("NONE", "None"), // case insensitive
-            ("Even", "Even"),
-            ("invalid", "None"), // fallback
+        let mut rtu = RtuConnection {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 9600,
+            data_bits: 8,
+            stop_bits: 1,
+            parity: "none".to_string(),
+            unit_id: 1,
+        };
+        let test_cases = [
+            ("none", tokio_serial::Parity::None),
+            ("even", tokio_serial::Parity::Even),
+            ("odd", tokio_serial::Parity::Odd),
+            ("NONE", tokio_serial::Parity::None), // case insensitive
+            ("Even", tokio_serial::Parity::Even),
         ];
-
         for (input, expected) in test_cases {
-            let result = match input.to_lowercase().as_str() {
-                "none" => "None",
-                "even" => "Even",
-                "odd" => "Odd",
-                _ => "None",
-            };
-            assert_eq!(result, expected, "Failed for input: {}", input);
+            rtu.parity = input.to_string();
+            let settings = serial_settings(&rtu).unwrap();
+            assert_eq!(settings.parity, expected, "Failed for input: {}", input);
         }
+
+        // Unknown values are errors instead of silently falling back
+        rtu.parity = "invalid".to_string();
+        let error = serial_settings(&rtu).unwrap_err().to_string();
+        assert!(error.contains("Invalid parity 'invalid'"), "{}", error);
+    }
+
+    #[test]
+    fn test_serial_settings() {
+        let mut rtu = RtuConnection {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 19200,
+            data_bits: 7,
+            stop_bits: 2,
+            parity: "even".to_string(),
+            unit_id: 1,
+        };
+        assert_eq!(
+            serial_settings(&rtu).unwrap(),
+            SerialSettings {
+                data_bits: tokio_serial::DataBits::Seven,
+                stop_bits: tokio_serial::StopBits::Two,
+                parity: tokio_serial::Parity::Even,
+            }
+        );
+        assert!(rtu.settings_errors().is_empty());
+
+        rtu.data_bits = 9;
+        assert!(serial_settings(&rtu).is_err());
+        rtu.data_bits = 8;
+        rtu.stop_bits = 3;
+        assert!(serial_settings(&rtu).is_err());
+
+        rtu.baud_rate = 0;
+        rtu.parity = "mark".to_string();
+        assert_eq!(
+            rtu.settings_errors(),
+            [
+                "baud_rate must be greater than 0",
+                "stop_bits 3 must be 1 or 2",
+                "parity 'mark' must be none, even or odd",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_bit_mask() {
+        // Untouched bits are preserved
+        assert_eq!(
+            apply_bit_mask(0b1010_1010, 0b0000_1111, 0b0000_0101),
+            0b1010_0101
+        );
+        // Bits in value outside the mask are ignored
+        assert_eq!(apply_bit_mask(0x0000, 0x00F0, 0xFFFF), 0x00F0);
+        // Empty mask is a no-op, full mask replaces the word
+        assert_eq!(apply_bit_mask(0x1234, 0x0000, 0xFFFF), 0x1234);
+        assert_eq!(apply_bit_mask(0x1234, 0xFFFF, 0xABCD), 0xABCD);
     }
 
     #[test]
@@ -307,13 +1117,210 @@ mod tests {
             register_type: RegisterType::Holding,
             count: 1,
             data_type: DataType::I16,
+            signed_bits: None,
+            bit: None,
             unit: Some("°C".to_string()),
             scale: Some(0.1),
             offset: None,
+            decimals: None,
+            scale_from_register: None,
+            expression: None,
+            formula: None,
+            expose_words: false,
+            encoding: TextEncoding::default(),
+            swap_bytes: false,
+            word_order: WordOrder::default(),
+            unit_id: None,
+            writable: false,
+            bool_format: None,
+            publish_interval_ms: None,
+            fault_value: None,
+            count_edges: false,
+            deadband: None,
+            deadband_percent: None,
+            range_min: None,
+            range_max: None,
+            clamp_min: None,
+            clamp_max: None,
+            pipeline: None,
         };
 
         assert_eq!(reg.name, "temperature");
         assert_eq!(reg.address, 100);
         assert!(matches!(reg.register_type, RegisterType::Holding));
     }
+
+    #[tokio::test]
+    async fn test_resolve_ipv6_literal() {
+        let expected: SocketAddr = "[2001:db8::10]:502".parse().unwrap();
+
+        // Bracketed as in URLs, and bare as in most configs
+        let addrs = resolve_tcp_addrs("[2001:db8::10]", 502, AddressFamily::Any)
+            .await
+            .unwrap();
+        assert_eq!(addrs, vec![expected]);
+
+        let addrs = resolve_tcp_addrs("2001:db8::10", 502, AddressFamily::Any)
+            .await
+            .unwrap();
+        assert_eq!(addrs, vec![expected]);
+
+        // An IPv6-only device cannot be reached over IPv4
+        assert!(resolve_tcp_addrs("2001:db8::10", 502, AddressFamily::Ipv4)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_exception() {
+        let exception = anyhow::Error::from(client::ModbusError::Exception(
+            tokio_modbus::Exception::IllegalDataAddress,
+        ));
+        assert!(is_exception(&exception));
+
+        let io = anyhow::Error::from(client::ModbusError::Io(std::io::Error::from(
+            std::io::ErrorKind::TimedOut,
+        )));
+        assert!(!is_exception(&io));
+        assert!(!is_exception(&anyhow::anyhow!("No response")));
+    }
+
+    #[test]
+    fn test_error_type() {
+        let mismatch = anyhow::Error::from(client::ModbusError::LengthMismatch {
+            expected: 2,
+            actual: 3,
+        });
+        assert_eq!(error_type(&mismatch), "length_mismatch");
+        assert!(!is_exception(&mismatch));
+        assert_eq!(error_type(&anyhow::anyhow!("No response")), "modbus_error");
+    }
+
+    #[test]
+    fn test_timeouts_are_recognized() {
+        let timed_out = || std::io::Error::from(std::io::ErrorKind::TimedOut);
+        let transport = anyhow::Error::from(client::ModbusError::Transport(
+            tokio_modbus::Error::Transport(timed_out()),
+        ));
+        assert!(is_timeout(&transport));
+        assert_eq!(error_type(&transport), "timeout");
+        assert!(is_timeout(
+            &anyhow::Error::from(timed_out()).context("Reading register")
+        ));
+
+        let reset = anyhow::Error::from(client::ModbusError::Io(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset,
+        )));
+        assert!(!is_timeout(&reset));
+        assert!(!is_timeout(&anyhow::anyhow!("No connection available")));
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        // A device that accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let silent = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await
+        });
+
+        let config = crate::config::DeviceBuilder::tcp("timeout-test", "127.0.0.1", port, 1)
+            .read_timeout_ms(50)
+            .build();
+        let mut client = ModbusClient::new(&config).await.unwrap();
+        let register = RegisterBuilder::holding("temperature", 0).build();
+
+        let started = std::time::Instant::now();
+        for _ in 0..2 {
+            let error = client.read_registers(&register).await.unwrap_err();
+            assert!(error.to_string().contains("No response within 50ms"));
+            assert_eq!(error_type(&error), "timeout");
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(client.consecutive_timeouts(), 2);
+        silent.abort();
+    }
+
+    #[tokio::test]
+    async fn test_transport_error_closes_shared_bus() {
+        // A port that fails on the first request
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        drop(listener.accept().await.unwrap());
+        let config =
+            crate::config::DeviceBuilder::rtu("meter", "/dev/does-not-exist", 9600, 3).build();
+        let ConnectionConfig::Rtu(rtu) = &config.connection else {
+            unreachable!()
+        };
+        let bus = bus::SerialBus::attached(
+            rtu,
+            client::Context::Rtu(tokio_modbus::client::rtu::attach(stream)),
+        );
+        let shared = SharedAccess {
+            bus: Some(bus.clone()),
+            ..Default::default()
+        };
+        let mut client = ModbusClient::connect(&config, &shared).await.unwrap();
+
+        let register = RegisterBuilder::holding("energy", 0).build();
+        let error = client.read_registers(&register).await.unwrap_err();
+        assert!(is_transport_failure(&error), "{:#}", error);
+        // Closed for every device on the bus, and reopened on next use
+        let error = bus.acquire(4).await.err().unwrap();
+        assert!(error.to_string().contains("Failed to open serial port"));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_probe_restores_unit_id() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let silent = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await
+        });
+
+        // The probe gives up long before the device's own read timeout
+        let config = crate::config::DeviceBuilder::tcp("probe-test", "127.0.0.1", port, 1)
+            .read_timeout_ms(60_000)
+            .build();
+        let mut client = ModbusClient::new(&config).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let error = client
+            .read_raw(
+                RegisterType::Holding,
+                0,
+                1,
+                Some(7),
+                Some(Duration::from_millis(50)),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("No response within 50ms"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(client.unit_id, 1);
+        silent.abort();
+    }
+
+    #[test]
+    fn test_order_addrs_by_family() {
+        let v4: SocketAddr = "192.168.1.10:502".parse().unwrap();
+        let v6: SocketAddr = "[fd00::10]:502".parse().unwrap();
+        let addrs = vec![v4, v6];
+
+        assert_eq!(order_addrs(addrs.clone(), AddressFamily::Any), vec![v4, v6]);
+        assert_eq!(
+            order_addrs(addrs.clone(), AddressFamily::PreferIpv6),
+            vec![v6, v4]
+        );
+        assert_eq!(
+            order_addrs(vec![v6, v4], AddressFamily::PreferIpv4),
+            vec![v4, v6]
+        );
+        assert_eq!(order_addrs(addrs.clone(), AddressFamily::Ipv4), vec![v4]);
+        assert_eq!(order_addrs(addrs, AddressFamily::Ipv6), vec![v6]);
+    }
 }
@@ -0,0 +1,55 @@
+//! Polling paused for maintenance
+//!
+//! A paused device stays configured and connected, but its worker skips
+//! poll cycles until it is resumed. Its last values stay in the register
+//! store; writes, on-demand reads and heartbeats still go through, so a
+//! technician can work with the device over the API.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::metrics;
+
+/// Devices whose polling is paused, shared by the API and the workers
+#[derive(Debug, Clone, Default)]
+pub struct PausedDevices {
+    devices: Arc<Mutex<HashSet<String>>>,
+}
+
+impl PausedDevices {
+    /// Stop polling the device; returns whether it was polling before
+    pub fn pause(&self, device_id: &str) -> bool {
+        let paused = self.devices.lock().unwrap().insert(device_id.to_string());
+        metrics::record_device_paused(device_id, true);
+        paused
+    }
+
+    /// Poll the device again; returns whether it was paused before
+    pub fn resume(&self, device_id: &str) -> bool {
+        let resumed = self.devices.lock().unwrap().remove(device_id);
+        metrics::record_device_paused(device_id, false);
+        resumed
+    }
+
+    pub fn is_paused(&self, device_id: &str) -> bool {
+        self.devices.lock().unwrap().contains(device_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_resume() {
+        let paused = PausedDevices::default();
+        assert!(paused.pause("pause-test-plc"));
+        assert!(!paused.pause("pause-test-plc"), "already paused");
+        assert!(paused.is_paused("pause-test-plc"));
+        assert!(!paused.is_paused("pause-test-other"));
+
+        assert!(paused.resume("pause-test-plc"));
+        assert!(!paused.resume("pause-test-plc"), "already polling");
+        assert!(!paused.is_paused("pause-test-plc"));
+    }
+}
@@ -4,47 +4,78 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::config::{DataType, RegisterConfig};
+use crate::config::{
+    BlockReadConfig, ConversionStep, DataType, RegisterConfig, RegisterType, TextEncoding,
+    WordOrder,
+};
+use crate::modbus::pause::PausedDevices;
 
 /// Represents a register value with metadata
+///
+/// `raw` holds the words exactly as read from the device, `decoded` is the
+/// number those words represent for the configured data type (sign applied,
+/// no scaling) and `value` is `decoded * scale + offset`, after applying the
+/// exponent of a `scale_from_register` if configured. Consumers must use
+/// `value` as-is rather than re-applying scaling to `raw`. String registers
+/// carry their content in `text` and have no numeric value.
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct RegisterValue {
     pub name: String,
     pub raw: Vec<u16>,
+    pub decoded: f64,
     pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
     pub unit: Option<String>,
+    pub quality: Quality,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+/// How far a published value can be trusted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    #[default]
+    Good,
+    /// Read successfully but not decoded exactly, e.g. text with replaced
+    /// characters
+    Uncertain,
+    /// Not read from the device
+    Bad,
+}
+
 /// Shared state for register values
 pub type RegisterStore = Arc<RwLock<HashMap<String, HashMap<String, RegisterValue>>>>;
 
 /// Convert raw register values to typed value
+#[allow(dead_code)] // Library API; the poll loop keeps the decoded step separately
 pub fn convert_value(raw: &[u16], config: &RegisterConfig) -> f64 {
-    let raw_value: f64 = match config.data_type {
-        DataType::U16 => raw.first().copied().unwrap_or(0) as f64,
-        DataType::I16 => raw.first().copied().unwrap_or(0) as i16 as f64,
-        DataType::U32 => {
-            if raw.len() >= 2 {
-                ((raw[0] as u32) << 16 | raw[1] as u32) as f64
-            } else {
-                0.0
-            }
+    scale_value(decode_value(raw, config), config)
+}
+
+/// Decode raw register words into a number according to the data type,
+/// before scale and offset are applied
+pub fn decode_value(raw: &[u16], config: &RegisterConfig) -> f64 {
+    if let Some(bit) = config.bit {
+        return raw
+            .first()
+            .and_then(|word| word.checked_shr(bit.into()))
+            .map_or(0.0, |word| f64::from(word & 1));
+    }
+    if let Some(bits) = config.signed_bits {
+        if let Some(word) = integer_word(raw, config) {
+            return sign_extend(word, bits) as f64;
         }
+    }
+    match config.data_type {
+        DataType::U16 => single_word(raw, config.word_order).unwrap_or(0) as f64,
+        DataType::I16 => single_word(raw, config.word_order).unwrap_or(0) as i16 as f64,
+        DataType::U32 => combine_words(raw, config.word_order).map_or(0.0, |bits| bits as f64),
         DataType::I32 => {
-            if raw.len() >= 2 {
-                ((raw[0] as u32) << 16 | raw[1] as u32) as i32 as f64
-            } else {
-                0.0
-            }
+            combine_words(raw, config.word_order).map_or(0.0, |bits| bits as i32 as f64)
         }
         DataType::F32 => {
-            if raw.len() >= 2 {
-                let bits = (raw[0] as u32) << 16 | raw[1] as u32;
-                f32::from_bits(bits) as f64
-            } else {
-                0.0
-            }
+            combine_words(raw, config.word_order).map_or(0.0, |bits| f32::from_bits(bits) as f64)
         }
         DataType::Bool => {
             if raw.first().copied().unwrap_or(0) != 0 {
@@ -53,19 +84,482 @@ pub fn convert_value(raw: &[u16], config: &RegisterConfig) -> f64 {
                 0.0
             }
         }
+        DataType::Bcd => decode_bcd(raw).unwrap_or(f64::NAN),
+        DataType::String => f64::NAN,
+    }
+}
+
+/// A word with its bytes in `word_order`
+fn order_bytes(word: u16, word_order: WordOrder) -> u16 {
+    if word_order.swaps_bytes() {
+        word.swap_bytes()
+    } else {
+        word
+    }
+}
+
+/// The first word as the bits of a 16-bit value, in `word_order`
+fn single_word(raw: &[u16], word_order: WordOrder) -> Option<u16> {
+    raw.first().map(|&word| order_bytes(word, word_order))
+}
+
+/// The first two words as the bits of a 32-bit value, in `word_order`
+fn combine_words(raw: &[u16], word_order: WordOrder) -> Option<u32> {
+    let [first, second, ..] = *raw else {
+        return None;
+    };
+    let (high, low) = if word_order.swaps_words() {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    Some((order_bytes(high, word_order) as u32) << 16 | order_bytes(low, word_order) as u32)
+}
+
+/// The bits of a 32-bit value as two words in `word_order`, the inverse of
+/// [`combine_words`]
+fn split_words(bits: u32, word_order: WordOrder) -> Vec<u16> {
+    let high = order_bytes((bits >> 16) as u16, word_order);
+    let low = order_bytes(bits as u16, word_order);
+    if word_order.swaps_words() {
+        vec![low, high]
+    } else {
+        vec![high, low]
+    }
+}
+
+/// Encode a number into the words of the register's data type, the inverse
+/// of [`decode_value`]; scale and offset are not applied
+pub fn encode_value(value: f64, config: &RegisterConfig) -> Result<Vec<u16>, String> {
+    let whole = |min: f64, max: f64| {
+        if value.fract() == 0.0 && (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!(
+                "{} is not a whole number between {} and {}",
+                value, min, max
+            ))
+        }
+    };
+    if let Some(bit) = config.bit {
+        return Ok(vec![(whole(0.0, 1.0)? as u16) << bit]);
+    }
+    let word_order = config.word_order;
+    match config.data_type {
+        DataType::U16 => {
+            let word = whole(0.0, u16::MAX.into())? as u16;
+            Ok(vec![order_bytes(word, word_order)])
+        }
+        DataType::I16 => {
+            let word = whole(i16::MIN.into(), i16::MAX.into())? as i16 as u16;
+            Ok(vec![order_bytes(word, word_order)])
+        }
+        DataType::U32 => Ok(split_words(whole(0.0, u32::MAX.into())? as u32, word_order)),
+        DataType::I32 => Ok(split_words(
+            whole(i32::MIN.into(), i32::MAX.into())? as i32 as u32,
+            word_order,
+        )),
+        DataType::F32 => {
+            let float = value as f32;
+            if !float.is_finite() {
+                return Err(format!("{} is out of range for f32", value));
+            }
+            Ok(split_words(float.to_bits(), word_order))
+        }
+        DataType::Bool => Ok(vec![whole(0.0, 1.0)? as u16]),
+        DataType::Bcd | DataType::String => {
+            Err("values of this data type cannot be written".to_string())
+        }
+    }
+}
+
+/// The words of an integer type as one unsigned number
+fn integer_word(raw: &[u16], config: &RegisterConfig) -> Option<u64> {
+    match config.data_type {
+        DataType::U16 | DataType::I16 => single_word(raw, config.word_order).map(u64::from),
+        DataType::U32 | DataType::I32 => combine_words(raw, config.word_order).map(u64::from),
+        _ => None,
+    }
+}
+
+/// Interpret the lowest `bits` bits of `word` as a two's-complement number,
+/// ignoring the bits above them
+pub fn sign_extend(word: u64, bits: u8) -> i64 {
+    let shift = 64 - u32::from(bits.clamp(1, 64));
+    ((word << shift) as i64) >> shift
+}
+
+/// Whether a new reading differs from the previous one
+///
+/// Compares text for string registers and the scaled value otherwise; a
+/// value that stays NaN is not a change.
+pub fn value_changed(previous: &RegisterValue, current: &RegisterValue) -> bool {
+    if previous.text.is_some() || current.text.is_some() {
+        return previous.text != current.text;
+    }
+    let both_nan = previous.value.is_nan() && current.value.is_nan();
+    !both_nan && previous.value != current.value
+}
+
+/// Whether a new reading moved at least `width` away from `baseline`
+///
+/// Text changes and transitions to or from NaN always count. A difference
+/// a floating-point rounding error short of `width` counts as reaching it,
+/// so e.g. 5.0 -> 5.1 passes a 0.1 deadband.
+pub fn exceeds_deadband(baseline: &RegisterValue, current: &RegisterValue, width: f64) -> bool {
+    if baseline.text.is_some() || current.text.is_some() {
+        return baseline.text != current.text;
+    }
+    if baseline.value.is_nan() || current.value.is_nan() {
+        return baseline.value.is_nan() != current.value.is_nan();
+    }
+    (current.value - baseline.value).abs() >= width * (1.0 - DEADBAND_TOLERANCE)
+}
+
+/// Relative slack given to deadband comparisons for rounding errors
+const DEADBAND_TOLERANCE: f64 = 1e-9;
+
+/// The value consumers see: the text of a string register, else the
+/// scaled number
+pub fn output_value(value: &RegisterValue) -> serde_json::Value {
+    match &value.text {
+        Some(text) => text.clone().into(),
+        None => value.value.into(),
+    }
+}
+
+/// Decode the text of a `string` register
+///
+/// Trailing NUL and space padding is trimmed. Bytes that are not valid in
+/// the configured encoding are replaced with U+FFFD and the text is marked
+/// [`Quality::Uncertain`]. Returns `None` for other data types.
+pub fn decode_text(raw: &[u16], config: &RegisterConfig) -> Option<(String, Quality)> {
+    if !matches!(config.data_type, DataType::String) {
+        return None;
+    }
+
+    let mut bytes: Vec<u8> = raw
+        .iter()
+        .flat_map(|word| {
+            let [high, low] = word.to_be_bytes();
+            if config.swap_bytes {
+                [low, high]
+            } else {
+                [high, low]
+            }
+        })
+        .collect();
+    while matches!(bytes.last(), Some(0) | Some(b' ')) {
+        bytes.pop();
+    }
+
+    let (text, exact) = match config.encoding {
+        TextEncoding::Ascii => (
+            bytes
+                .iter()
+                .map(|&b| if b.is_ascii() { b as char } else { '\u{FFFD}' })
+                .collect(),
+            bytes.is_ascii(),
+        ),
+        TextEncoding::Latin1 => (bytes.iter().map(|&b| b as char).collect(), true),
+        TextEncoding::Utf8 => match String::from_utf8(bytes) {
+            Ok(text) => (text, true),
+            Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), false),
+        },
+    };
+
+    let quality = if exact {
+        Quality::Good
+    } else {
+        Quality::Uncertain
     };
+    Some((text, quality))
+}
+
+/// Split a register list into blocks that can each be read with one request
+///
+/// A register joins the block before it when it has the same type and unit
+/// id, does not start below the previous register, leaves at most
+/// `max_gap` unused addresses after the block and keeps the block within
+/// `max_size` words. Blocks are index ranges into `registers`; a register
+/// that fits nowhere forms a block of its own.
+pub fn read_blocks(
+    registers: &[RegisterConfig],
+    blocks: &BlockReadConfig,
+) -> Vec<std::ops::Range<usize>> {
+    let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+    // First and one past the last address of the current block
+    let mut span = (0u32, 0u32);
+    for (i, register) in registers.iter().enumerate() {
+        let start = u32::from(register.address);
+        let end = start + u32::from(register.count);
+        if let Some(block) = ranges.last_mut() {
+            let previous = &registers[block.end - 1];
+            let joins = register.register_type != RegisterType::Virtual
+                && register.register_type == previous.register_type
+                && register.unit_id == previous.unit_id
+                && register.address >= previous.address
+                && start <= span.1 + u32::from(blocks.max_gap)
+                && end.max(span.1) - span.0 <= u32::from(blocks.max_size);
+            if joins {
+                block.end = i + 1;
+                span.1 = span.1.max(end);
+                continue;
+            }
+        }
+        ranges.push(i..i + 1);
+        span = (start, end);
+    }
+    ranges
+}
+
+/// The single read covering all registers of a block
+pub fn block_request(block: &[RegisterConfig]) -> RegisterConfig {
+    let first = &block[0];
+    let end = block
+        .iter()
+        .map(|register| u32::from(register.address) + u32::from(register.count))
+        .max()
+        .unwrap_or_default();
+    RegisterConfig {
+        name: format!("{}..{}", first.name, block[block.len() - 1].name),
+        // Blocks are at most `max_size` long, well within u16
+        count: (end - u32::from(first.address)) as u16,
+        data_type: DataType::U16,
+        ..first.clone()
+    }
+}
+
+/// Cut the words of a block read back into the registers it covers
+///
+/// `words` were read from consecutive addresses starting at `start`. Some
+/// devices return fewer words than requested; a register that is not fully
+/// covered (or lies outside the block) gets no words and [`Quality::Bad`]
+/// instead of a partial or out-of-range slice, so the caller can count it
+/// as a failed read or retry it on its own.
+pub fn slice_block(
+    start: u16,
+    words: &[u16],
+    registers: &[RegisterConfig],
+) -> Vec<(Vec<u16>, Quality)> {
+    registers
+        .iter()
+        .map(|register| {
+            let offset = register.address.checked_sub(start).map(usize::from);
+            let range = offset.map(|offset| offset..offset + usize::from(register.count));
+            match range.and_then(|range| words.get(range)) {
+                Some(slice) => (slice.to_vec(), Quality::Good),
+                None => (Vec::new(), Quality::Bad),
+            }
+        })
+        .collect()
+}
+
+/// Check that raw register words are a valid encoding of the data type
+///
+/// Values that fail this check must not be published; the read is treated
+/// as failed instead.
+pub fn validate_raw(raw: &[u16], config: &RegisterConfig) -> Result<(), String> {
+    match config.data_type {
+        DataType::Bcd => match decode_bcd(raw) {
+            Some(_) => Ok(()),
+            None => Err(format!("invalid BCD digits in {:04X?}", raw)),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Decode binary-coded decimal words, most significant word first
+///
+/// Returns `None` if any nibble is not a decimal digit.
+pub fn decode_bcd(raw: &[u16]) -> Option<f64> {
+    let mut value = 0.0;
+    for word in raw {
+        for shift in [12, 8, 4, 0] {
+            let digit = (word >> shift) & 0xF;
+            if digit > 9 {
+                return None;
+            }
+            value = value * 10.0 + digit as f64;
+        }
+    }
+    Some(value)
+}
+
+/// Individual words of a multi-register value as `<name>.wordN` values
+///
+/// The words are reported unscaled and carry no unit. Single-register
+/// values have nothing to split and yield no words.
+pub fn word_values(value: &RegisterValue) -> Vec<RegisterValue> {
+    if value.raw.len() < 2 {
+        return vec![];
+    }
+
+    value
+        .raw
+        .iter()
+        .enumerate()
+        .map(|(i, &word)| RegisterValue {
+            name: word_register_name(&value.name, i),
+            raw: vec![word],
+            decoded: word as f64,
+            value: word as f64,
+            text: None,
+            unit: None,
+            quality: value.quality,
+            timestamp: value.timestamp,
+        })
+        .collect()
+}
+
+/// Name of the derived register for one word of a multi-register value
+fn word_register_name(name: &str, index: usize) -> String {
+    format!("{}.word{}", name, index)
+}
+
+/// Remove devices whose most recent successful read is older than `ttl`
+///
+/// Fault values published for failed reads do not count as reads. Returns
+/// the IDs of the evicted devices. A device that recovers is added back by
+/// its next successful read. Paused devices are not read, so they keep
+/// their last values however old.
+pub async fn evict_stale_devices(
+    store: &RegisterStore,
+    ttl: std::time::Duration,
+    paused: &PausedDevices,
+) -> Vec<String> {
+    let cutoff =
+        chrono::Utc::now() - chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+    let mut store = store.write().await;
+
+    let stale: Vec<String> = store
+        .iter()
+        .filter(|(id, registers)| {
+            !paused.is_paused(id)
+                && registers
+                    .values()
+                    .filter(|r| r.quality != Quality::Bad)
+                    .map(|r| r.timestamp)
+                    .max()
+                    .is_none_or(|last| last < cutoff)
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    for id in &stale {
+        store.remove(id);
+    }
+    stale
+}
 
-    // Apply scale and offset
-    let scale = config.scale.unwrap_or(1.0);
-    let offset = config.offset.unwrap_or(0.0);
+/// Largest power of ten a scale factor register may apply, either way
+pub const MAX_SCALE_EXPONENT: f64 = 10.0;
 
-    raw_value * scale + offset
+/// Multiply a decoded value by 10^`exponent` read from a scale factor
+/// register
+///
+/// Returns `None` for an exponent that is not a whole number within
+/// `MAX_SCALE_EXPONENT`, such as SunSpec's "not implemented" value -32768.
+pub fn apply_scale_factor(decoded: f64, exponent: f64) -> Option<f64> {
+    (exponent.fract() == 0.0 && exponent.abs() <= MAX_SCALE_EXPONENT)
+        .then(|| decoded * 10f64.powi(exponent as i32))
+}
+
+/// Apply scale and offset (or the formula), clamping and rounding to a
+/// decoded value, in the order of the register's pipeline
+pub fn scale_value(decoded: f64, config: &RegisterConfig) -> f64 {
+    config
+        .conversion_steps()
+        .iter()
+        .fold(decoded, |value, step| match step {
+            ConversionStep::Scale => match &config.formula {
+                Some(formula) => formula.eval(value),
+                None => value * config.scale.unwrap_or(1.0) + config.offset.unwrap_or(0.0),
+            },
+            ConversionStep::Clamp => {
+                let value = config.clamp_min.map_or(value, |min| value.max(min));
+                config.clamp_max.map_or(value, |max| value.min(max))
+            }
+            ConversionStep::Round => match config.decimals {
+                Some(decimals) => round_to(value, decimals),
+                None => value,
+            },
+        })
+}
+
+/// Round to `decimals` places, halves away from zero
+fn round_to(value: f64, decimals: u8) -> f64 {
+    let factor = 10f64.powi(decimals.into());
+    let rounded = (value * factor).round() / factor;
+    // Very large values have no fractional digits left to round
+    if rounded.is_finite() {
+        rounded
+    } else {
+        value
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::RegisterType;
+    use crate::modbus::expression::Formula;
+
+    #[tokio::test]
+    async fn test_evict_stale_devices() {
+        let value = |age_secs: i64| RegisterValue {
+            name: "temp".to_string(),
+            raw: vec![1],
+            decoded: 1.0,
+            value: 1.0,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now() - chrono::Duration::seconds(age_secs),
+        };
+        let store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+        {
+            let mut store = store.write().await;
+            store.insert(
+                "fresh".to_string(),
+                HashMap::from([("temp".to_string(), value(5))]),
+            );
+            store.insert(
+                "stale".to_string(),
+                HashMap::from([("temp".to_string(), value(600))]),
+            );
+            store.insert(
+                "paused".to_string(),
+                HashMap::from([("temp".to_string(), value(600))]),
+            );
+            // Failing for a while, with a fresh fault value for every read
+            let fault = RegisterValue {
+                name: "humidity".to_string(),
+                quality: Quality::Bad,
+                ..value(0)
+            };
+            store.insert(
+                "failing".to_string(),
+                HashMap::from([
+                    ("temp".to_string(), value(600)),
+                    ("humidity".to_string(), fault),
+                ]),
+            );
+        }
+        let paused = PausedDevices::default();
+        paused.pause("paused");
+
+        let mut evicted =
+            evict_stale_devices(&store, std::time::Duration::from_secs(300), &paused).await;
+        evicted.sort();
+
+        assert_eq!(evicted, vec!["failing".to_string(), "stale".to_string()]);
+        let store = store.read().await;
+        assert!(store.contains_key("fresh"));
+        assert!(!store.contains_key("stale"));
+        assert!(!store.contains_key("failing"));
+        assert!(store.contains_key("paused"));
+    }
 
     fn make_register_config(
         data_type: DataType,
@@ -78,9 +572,32 @@ mod tests {
             register_type: RegisterType::Holding,
             count: 1,
             data_type,
+            signed_bits: None,
+            bit: None,
             unit: None,
             scale,
             offset,
+            decimals: None,
+            scale_from_register: None,
+            expression: None,
+            formula: None,
+            expose_words: false,
+            encoding: TextEncoding::default(),
+            swap_bytes: false,
+            word_order: WordOrder::default(),
+            unit_id: None,
+            writable: false,
+            bool_format: None,
+            publish_interval_ms: None,
+            fault_value: None,
+            count_edges: false,
+            deadband: None,
+            deadband_percent: None,
+            range_min: None,
+            range_max: None,
+            clamp_min: None,
+            clamp_max: None,
+            pipeline: None,
         }
     }
 
@@ -105,6 +622,75 @@ mod tests {
         assert_eq!(convert_value(&[65436], &config), -100.0);
     }
 
+    #[test]
+    fn test_signed_bits_12() {
+        let config = RegisterConfig {
+            signed_bits: Some(12),
+            ..make_register_config(DataType::U16, None, None)
+        };
+
+        assert_eq!(decode_value(&[0x0000], &config), 0.0);
+        assert_eq!(decode_value(&[0x07FF], &config), 2047.0);
+        assert_eq!(decode_value(&[0x0800], &config), -2048.0);
+        assert_eq!(decode_value(&[0x0FFF], &config), -1.0);
+        assert_eq!(decode_value(&[0x0F9C], &config), -100.0);
+        // Bits above the width are ignored
+        assert_eq!(decode_value(&[0xF064], &config), 100.0);
+    }
+
+    #[test]
+    fn test_status_word_bits() {
+        // Running, no fault, remote mode, alarm in the top bit
+        let status = 0b1000_0000_0000_0101;
+        let bit = |bit| RegisterConfig {
+            bit: Some(bit),
+            ..make_register_config(DataType::U16, None, None)
+        };
+
+        assert_eq!(convert_value(&[status], &bit(0)), 1.0);
+        assert_eq!(convert_value(&[status], &bit(1)), 0.0);
+        assert_eq!(convert_value(&[status], &bit(2)), 1.0);
+        assert_eq!(convert_value(&[status], &bit(15)), 1.0);
+        assert_eq!(convert_value(&[], &bit(0)), 0.0);
+
+        // Writing sets the bit alone, for a masked write
+        assert_eq!(encode_value(1.0, &bit(2)).unwrap(), [0b100]);
+        assert_eq!(encode_value(0.0, &bit(2)).unwrap(), [0]);
+        assert!(encode_value(2.0, &bit(2)).is_err());
+    }
+
+    #[test]
+    fn test_formula_replaces_scale_and_offset() {
+        let config = RegisterConfig {
+            formula: Some(Formula::parse("x ^ 2 / 100").unwrap()),
+            decimals: Some(1),
+            ..make_register_config(DataType::I16, Some(10.0), Some(5.0))
+        };
+
+        // x is the decoded number; scale and offset are ignored
+        assert_eq!(convert_value(&[50], &config), 25.0);
+        assert_eq!(convert_value(&[0xFFF6], &config), 1.0);
+        assert_eq!(convert_value(&[123], &config), 151.3);
+    }
+
+    #[test]
+    fn test_signed_bits_24() {
+        let config = RegisterConfig {
+            signed_bits: Some(24),
+            scale: Some(0.1),
+            ..make_register_config(DataType::U32, None, None)
+        };
+
+        assert_eq!(decode_value(&[0x0000, 0x0001], &config), 1.0);
+        assert_eq!(decode_value(&[0x007F, 0xFFFF], &config), 8_388_607.0);
+        assert_eq!(decode_value(&[0x0080, 0x0000], &config), -8_388_608.0);
+        assert_eq!(decode_value(&[0x00FF, 0xFFFF], &config), -1.0);
+        // The unused top byte does not matter
+        assert_eq!(decode_value(&[0xAAFF, 0xFC18], &config), -1000.0);
+        // Sign extension happens before scaling
+        assert_eq!(convert_value(&[0x00FF, 0xFC18], &config), -100.0);
+    }
+
     #[test]
     fn test_convert_u32() {
         let config = make_register_config(DataType::U32, None, None);
@@ -171,6 +757,143 @@ mod tests {
         assert!((convert_value(&[high, low], &config) - (-42.5)).abs() < 0.0001);
     }
 
+    #[test]
+    fn test_word_order() {
+        // 0x12345678 = 305419896, sent high word first or low word first
+        let big = [0x1234, 0x5678];
+        let little = [0x5678, 0x1234];
+        let mut config = make_register_config(DataType::U32, None, None);
+        assert_eq!(convert_value(&big, &config), 305_419_896.0);
+        assert_eq!(convert_value(&little, &config), 1_450_709_556.0);
+        config.word_order = WordOrder::Little;
+        assert_eq!(convert_value(&little, &config), 305_419_896.0);
+
+        // -100 = 0xFFFFFF9C
+        config.data_type = DataType::I32;
+        assert_eq!(convert_value(&[0xFF9C, 0xFFFF], &config), -100.0);
+
+        // 1.0 = 0x3F800000
+        config.data_type = DataType::F32;
+        assert_eq!(convert_value(&[0x0000, 0x3F80], &config), 1.0);
+
+        // Sign extension applies to the combined value
+        config.data_type = DataType::U32;
+        config.signed_bits = Some(24);
+        assert_eq!(decode_value(&[0xFC18, 0x00FF], &config), -1000.0);
+
+        // 16-bit types have a single word to order
+        config.data_type = DataType::U16;
+        config.signed_bits = None;
+        assert_eq!(convert_value(&[0x1234, 0x5678], &config), 4660.0);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let orders = [
+            WordOrder::Big,
+            WordOrder::Little,
+            WordOrder::ByteSwap,
+            WordOrder::WordByteSwap,
+        ];
+        let cases = [
+            (DataType::F32, 21.75),
+            (DataType::F32, -1.0e-3_f32 as f64),
+            (DataType::U32, 305_419_896.0),
+            (DataType::I32, -100_000.0),
+            (DataType::U16, 65535.0),
+            (DataType::I16, -1000.0),
+        ];
+        for word_order in orders {
+            for (data_type, value) in cases.clone() {
+                let config = RegisterConfig {
+                    word_order,
+                    ..make_register_config(data_type.clone(), None, None)
+                };
+                let words = encode_value(value, &config).unwrap();
+                assert_eq!(words.len(), usize::from(data_type.word_count()));
+                assert_eq!(
+                    decode_value(&words, &config),
+                    value,
+                    "{:?} {:?}",
+                    data_type,
+                    word_order
+                );
+            }
+        }
+
+        // 123.456 = 0x42F6E979
+        let config = make_register_config(DataType::F32, None, None);
+        assert_eq!(encode_value(123.456, &config).unwrap(), [0x42F6, 0xE979]);
+    }
+
+    #[test]
+    fn test_encode_rejects_unrepresentable_values() {
+        let config = make_register_config(DataType::U16, None, None);
+        assert!(encode_value(65536.0, &config).is_err());
+        assert!(encode_value(-1.0, &config).is_err());
+        assert!(encode_value(1.5, &config).is_err());
+
+        let config = make_register_config(DataType::I32, None, None);
+        assert!(encode_value(2_147_483_648.0, &config).is_err());
+
+        let config = make_register_config(DataType::F32, None, None);
+        assert!(encode_value(1e39, &config).is_err());
+
+        let config = make_register_config(DataType::String, None, None);
+        assert!(encode_value(1.0, &config).is_err());
+    }
+
+    #[test]
+    fn test_byte_orders() {
+        // 123.456 = 0x42F6E979 with bytes ABCD = 42 F6 E9 79
+        let cases = [
+            (WordOrder::Big, [0x42F6, 0xE979]),
+            (WordOrder::Little, [0xE979, 0x42F6]),
+            (WordOrder::ByteSwap, [0xF642, 0x79E9]),
+            (WordOrder::WordByteSwap, [0x79E9, 0xF642]),
+        ];
+        for (word_order, raw) in cases {
+            let config = RegisterConfig {
+                word_order,
+                ..make_register_config(DataType::F32, None, None)
+            };
+            assert_eq!(
+                convert_value(&raw, &config),
+                123.456_f32 as f64,
+                "{:?}",
+                word_order
+            );
+
+            let config = RegisterConfig {
+                word_order,
+                ..make_register_config(DataType::U32, None, None)
+            };
+            assert_eq!(
+                convert_value(&raw, &config),
+                0x42F6E979 as f64,
+                "{:?}",
+                word_order
+            );
+        }
+
+        // A single word only has its bytes swapped
+        let mut config = make_register_config(DataType::I16, None, None);
+        for (word_order, expected) in [
+            (WordOrder::Big, 0x18FC_u16 as i16),
+            (WordOrder::Little, 0x18FC_u16 as i16),
+            (WordOrder::ByteSwap, -1000),
+            (WordOrder::WordByteSwap, -1000),
+        ] {
+            config.word_order = word_order;
+            assert_eq!(
+                convert_value(&[0x18FC], &config),
+                expected as f64,
+                "{:?}",
+                word_order
+            );
+        }
+    }
+
     #[test]
     fn test_convert_bool() {
         let config = make_register_config(DataType::Bool, None, None);
@@ -211,6 +934,66 @@ mod tests {
         assert_eq!(convert_value(&[650], &config), 25.0);
     }
 
+    #[test]
+    fn test_apply_scale_factor() {
+        assert_eq!(apply_scale_factor(12345.0, -2.0), Some(123.45));
+        assert_eq!(apply_scale_factor(42.0, 3.0), Some(42000.0));
+        assert_eq!(apply_scale_factor(42.0, 0.0), Some(42.0));
+
+        // Not implemented, fractional or non-finite exponents
+        assert_eq!(apply_scale_factor(42.0, -32768.0), None);
+        assert_eq!(apply_scale_factor(42.0, 1.5), None);
+        assert_eq!(apply_scale_factor(42.0, f64::NAN), None);
+    }
+
+    #[test]
+    fn test_decimals_rounding() {
+        let mut config = make_register_config(DataType::U16, Some(0.0001), None);
+
+        // Full precision when unset
+        assert_eq!(scale_value(252999.0, &config), 252999.0 * 0.0001);
+
+        config.decimals = Some(1);
+        assert_eq!(scale_value(252999.0, &config), 25.3);
+        assert_eq!(scale_value(-252999.0, &config), -25.3);
+        config.decimals = Some(0);
+        assert_eq!(scale_value(255000.0, &config), 26.0);
+
+        // The raw words are not affected
+        config.decimals = Some(1);
+        assert_eq!(decode_value(&[12345], &config), 12345.0);
+        assert_eq!(convert_value(&[12345], &config), 1.2);
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        // 0-10 V input scaled to 0-100 %, clamped to 0-100 %
+        let mut config = make_register_config(DataType::U16, Some(10.0), None);
+        config.clamp_min = Some(0.0);
+        config.clamp_max = Some(100.0);
+        assert_eq!(scale_value(12.0, &config), 100.0);
+
+        // Clamping the raw reading first leaves it unlimited after scaling
+        config.pipeline = Some(vec![
+            ConversionStep::Clamp,
+            ConversionStep::Scale,
+            ConversionStep::Round,
+        ]);
+        assert_eq!(scale_value(12.0, &config), 120.0);
+
+        // Rounding before clamping can land on the other side of a limit
+        let mut config = make_register_config(DataType::U16, Some(0.01), None);
+        config.clamp_min = Some(0.6);
+        config.decimals = Some(0);
+        assert_eq!(scale_value(49.0, &config), 1.0);
+        config.pipeline = Some(vec![
+            ConversionStep::Scale,
+            ConversionStep::Round,
+            ConversionStep::Clamp,
+        ]);
+        assert_eq!(scale_value(49.0, &config), 0.6);
+    }
+
     #[test]
     fn test_empty_raw_values() {
         let config = make_register_config(DataType::U16, None, None);
@@ -221,13 +1004,36 @@ mod tests {
         assert_eq!(convert_value(&[1], &config32), 0.0); // Not enough values
     }
 
+    #[test]
+    fn test_negative_scale_keeps_raw_and_decoded_consistent() {
+        // Inverted sensor: the value is the negated reading
+        let config = make_register_config(DataType::I16, Some(-0.1), None);
+
+        let raw = [(-250_i16) as u16];
+        let decoded = decode_value(&raw, &config);
+        let value = convert_value(&raw, &config);
+
+        // raw stays the unsigned wire word, decoded is the signed reading
+        assert_eq!(raw[0], 65286);
+        assert_eq!(decoded, -250.0);
+        assert!((value - 25.0).abs() < 0.0001);
+        assert_eq!(value, scale_value(decoded, &config));
+
+        let config = make_register_config(DataType::U16, Some(-1.0), Some(100.0));
+        assert_eq!(decode_value(&[40], &config), 40.0);
+        assert_eq!(convert_value(&[40], &config), 60.0);
+    }
+
     #[test]
     fn test_register_value_creation() {
         let reg_value = RegisterValue {
             name: "temperature".to_string(),
             raw: vec![250],
+            decoded: 250.0,
             value: 25.0,
             unit: Some("°C".to_string()),
+            text: None,
+            quality: Quality::Good,
             timestamp: chrono::Utc::now(),
         };
 
@@ -279,4 +1085,292 @@ mod tests {
         let low = value as u16;
         assert_eq!(convert_value(&[high, low], &config), 1_000_000.0);
     }
+
+    #[test]
+    fn test_word_values_split_multi_register_value() {
+        let value = RegisterValue {
+            name: "flow".to_string(),
+            raw: vec![0x4148, 0x0000],
+            decoded: 12.5,
+            value: 12.5,
+            unit: Some("m3/h".to_string()),
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        let words = word_values(&value);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].name, "flow.word0");
+        assert_eq!(words[0].value, 0x4148 as f64);
+        assert_eq!(words[1].name, "flow.word1");
+        assert_eq!(words[1].raw, vec![0]);
+        assert!(words[1].unit.is_none());
+
+        // Nothing to split for a single register
+        let single = RegisterValue {
+            raw: vec![7],
+            ..value
+        };
+        assert!(word_values(&single).is_empty());
+    }
+
+    /// Pack bytes two per register, first byte in the high byte
+    fn pack_bytes(bytes: &[u8]) -> Vec<u16> {
+        bytes
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_text_nameplate() {
+        let config = RegisterConfig {
+            count: 6,
+            ..make_register_config(DataType::String, None, None)
+        };
+
+        // Five registers of text and one of NUL padding
+        let raw = [0x5255, 0x5354, 0x4252, 0x4944, 0x4745, 0x0000];
+        assert_eq!(raw[..5], pack_bytes(b"RUSTBRIDGE")[..]);
+        let (text, quality) = decode_text(&raw, &config).unwrap();
+        assert_eq!(text, "RUSTBRIDGE");
+        assert_eq!(quality, Quality::Good);
+
+        // Consumers get the text instead of a number
+        assert!(convert_value(&raw, &config).is_nan());
+        let value = RegisterValue {
+            name: "model".to_string(),
+            raw: raw.to_vec(),
+            decoded: f64::NAN,
+            value: f64::NAN,
+            text: Some(text),
+            unit: None,
+            quality,
+            timestamp: chrono::Utc::now(),
+        };
+        assert_eq!(output_value(&value), "RUSTBRIDGE");
+    }
+
+    #[test]
+    fn test_decode_text_word_swapped_ascii() {
+        let mut config = make_register_config(DataType::String, None, None);
+        config.swap_bytes = true;
+
+        // "SN12345" sent low byte first: 'N','S' / '2','1' / '4','3' / NUL,'5'
+        let raw = pack_bytes(b"NS2143\x005");
+        assert_eq!(
+            decode_text(&raw, &config),
+            Some(("SN12345".to_string(), Quality::Good))
+        );
+
+        // Read without the swap the same words are garbled, not lossy
+        config.swap_bytes = false;
+        assert_eq!(decode_text(&raw, &config).unwrap().0, "NS2143\u{0}5");
+    }
+
+    #[test]
+    fn test_decode_text_latin1_nameplate() {
+        let mut config = make_register_config(DataType::String, None, None);
+        config.encoding = TextEncoding::Latin1;
+
+        // "Zürich Général" in ISO 8859-1, padded with spaces
+        let mut bytes = b"Z\xFCrich G\xE9n\xE9ral".to_vec();
+        bytes.extend_from_slice(b"    ");
+        let raw = pack_bytes(&bytes);
+        assert_eq!(
+            decode_text(&raw, &config),
+            Some(("Zürich Général".to_string(), Quality::Good))
+        );
+
+        // The same bytes are not ASCII: replaced and flagged
+        config.encoding = TextEncoding::Ascii;
+        let (text, quality) = decode_text(&raw, &config).unwrap();
+        assert_eq!(text, "Z\u{FFFD}rich G\u{FFFD}n\u{FFFD}ral");
+        assert_eq!(quality, Quality::Uncertain);
+
+        // ...and not valid UTF-8 either
+        config.encoding = TextEncoding::Utf8;
+        assert_eq!(decode_text(&raw, &config).unwrap().1, Quality::Uncertain);
+    }
+
+    #[test]
+    fn test_read_blocks() {
+        let register = |address: u16, count: u16, register_type: RegisterType| RegisterConfig {
+            name: format!("r{}", address),
+            address,
+            count,
+            register_type,
+            ..make_register_config(DataType::U16, None, None)
+        };
+        let registers = [
+            register(0, 2, RegisterType::Holding),
+            register(2, 1, RegisterType::Holding),
+            // Two unused addresses in between
+            register(5, 2, RegisterType::Holding),
+            // Another type starts a new block
+            register(7, 1, RegisterType::Input),
+            register(8, 1, RegisterType::Input),
+            // Going backwards starts a new block
+            register(3, 1, RegisterType::Input),
+            RegisterConfig {
+                unit_id: Some(2),
+                ..register(4, 1, RegisterType::Input)
+            },
+        ];
+
+        let contiguous = BlockReadConfig::default();
+        assert_eq!(
+            read_blocks(&registers, &contiguous),
+            vec![0..2, 2..3, 3..5, 5..6, 6..7]
+        );
+
+        let gaps = BlockReadConfig {
+            max_gap: 2,
+            ..BlockReadConfig::default()
+        };
+        assert_eq!(read_blocks(&registers, &gaps), vec![0..3, 3..5, 5..6, 6..7]);
+        let request = block_request(&registers[0..3]);
+        assert_eq!((request.address, request.count), (0, 7));
+        assert_eq!(request.register_type, RegisterType::Holding);
+
+        // Blocks stay within the maximum size
+        let small = BlockReadConfig {
+            max_gap: 2,
+            max_size: 3,
+        };
+        assert_eq!(
+            read_blocks(&registers, &small),
+            vec![0..2, 2..3, 3..5, 5..6, 6..7]
+        );
+        assert!(read_blocks(&[], &contiguous).is_empty());
+    }
+
+    #[test]
+    fn test_slice_block_short_read() {
+        let register = |name: &str, address: u16, count: u16| RegisterConfig {
+            name: name.to_string(),
+            address,
+            count,
+            ..make_register_config(DataType::U16, None, None)
+        };
+        let registers = [
+            register("a", 100, 1),
+            register("b", 101, 2),
+            register("c", 103, 2),
+            register("d", 105, 1),
+            // Below the start of the block
+            register("e", 99, 1),
+        ];
+
+        // Requested 6 words, the device truncated the response to 4
+        let sliced = slice_block(100, &[1, 2, 3, 4], &registers);
+
+        assert_eq!(
+            sliced,
+            vec![
+                (vec![1], Quality::Good),
+                (vec![2, 3], Quality::Good),
+                // Half covered is not covered
+                (vec![], Quality::Bad),
+                (vec![], Quality::Bad),
+                (vec![], Quality::Bad),
+            ]
+        );
+        assert!(slice_block(100, &[], &registers)
+            .iter()
+            .all(|(raw, quality)| raw.is_empty() && *quality == Quality::Bad));
+    }
+
+    #[test]
+    fn test_value_changed() {
+        let reading = |value: f64, text: Option<&str>| RegisterValue {
+            name: "test".to_string(),
+            raw: vec![0],
+            decoded: value,
+            value,
+            unit: None,
+            text: text.map(str::to_string),
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        assert!(!value_changed(&reading(1.5, None), &reading(1.5, None)));
+        assert!(value_changed(&reading(1.5, None), &reading(2.0, None)));
+        assert!(!value_changed(
+            &reading(f64::NAN, None),
+            &reading(f64::NAN, None)
+        ));
+        assert!(value_changed(
+            &reading(f64::NAN, Some("A")),
+            &reading(f64::NAN, Some("B"))
+        ));
+        assert_eq!(output_value(&reading(f64::NAN, Some("A"))), "A");
+    }
+
+    #[test]
+    fn test_exceeds_deadband_at_boundary() {
+        let reading = |value: f64| RegisterValue {
+            name: "test".to_string(),
+            raw: vec![0],
+            decoded: value,
+            value,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+
+        // 1% of a 0-10 span and of a 0-10000 span
+        assert!(exceeds_deadband(&reading(5.0), &reading(5.1), 0.1));
+        assert!(exceeds_deadband(&reading(5.1), &reading(5.0), 0.1));
+        assert!(!exceeds_deadband(&reading(5.0), &reading(5.099), 0.1));
+        assert!(exceeds_deadband(&reading(5000.0), &reading(5100.0), 100.0));
+        assert!(!exceeds_deadband(&reading(5000.0), &reading(5099.9), 100.0));
+
+        assert!(exceeds_deadband(&reading(5.0), &reading(f64::NAN), 0.1));
+        assert!(!exceeds_deadband(
+            &reading(f64::NAN),
+            &reading(f64::NAN),
+            0.1
+        ));
+    }
+
+    #[test]
+    fn test_decode_text_only_for_strings() {
+        let config = make_register_config(DataType::U16, None, None);
+        assert_eq!(decode_text(&[0x4142], &config), None);
+    }
+
+    #[test]
+    fn test_convert_bcd() {
+        let config = make_register_config(DataType::Bcd, None, None);
+
+        // 0x1234 is 1234 in BCD, not 4660
+        assert_eq!(convert_value(&[0x1234], &config), 1234.0);
+        assert_eq!(convert_value(&[0x0000], &config), 0.0);
+        assert_eq!(convert_value(&[0x9999], &config), 9999.0);
+
+        // Multi-word values continue with the next four digits
+        assert_eq!(convert_value(&[0x0012, 0x3456], &config), 123456.0);
+        assert_eq!(convert_value(&[0x1234, 0x5678], &config), 12345678.0);
+
+        // Scaling applies to the decimal value
+        let scaled = make_register_config(DataType::Bcd, Some(0.01), None);
+        assert_eq!(convert_value(&[0x1234], &scaled), 12.34);
+    }
+
+    #[test]
+    fn test_invalid_bcd_is_rejected() {
+        let config = make_register_config(DataType::Bcd, None, None);
+
+        assert!(validate_raw(&[0x1234, 0x5678], &config).is_ok());
+        assert!(validate_raw(&[0x12A4], &config).is_err());
+        assert!(validate_raw(&[0x1234, 0xF000], &config).is_err());
+        assert!(decode_value(&[0x00FF], &config).is_nan());
+
+        // Other types accept any bit pattern
+        let u16_config = make_register_config(DataType::U16, None, None);
+        assert!(validate_raw(&[0xFFFF], &u16_config).is_ok());
+    }
 }
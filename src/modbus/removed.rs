@@ -0,0 +1,198 @@
+//! Devices and registers removed by a configuration reload
+//!
+//! Device workers keep polling the configuration they were started with
+//! until the gateway restarts. When a reload drops a device or register,
+//! its values are evicted from the register store and the history, and the
+//! values its worker still reads are discarded instead of stored, so the
+//! API only shows what is configured. A later reload that adds it back lets
+//! its values through again.
+//!
+//! A reload that adds or changes devices or registers is rejected: the API
+//! would address registers the workers do not poll the same way.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::config::{Config, DeviceConfig};
+
+/// Polled devices and registers missing from the running configuration
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Removal {
+    pub devices: Vec<String>,
+    /// Removed registers of devices that are still configured, as
+    /// (device, register)
+    pub registers: Vec<(String, String)>,
+}
+
+/// What the workers poll and which of it was removed, shared by the API
+/// and the workers
+#[derive(Debug, Clone, Default)]
+pub struct RemovedRegisters {
+    /// Devices as their workers poll them
+    polled: Arc<Vec<DeviceConfig>>,
+    removed: Arc<Mutex<Removed>>,
+}
+
+#[derive(Debug, Default)]
+struct Removed {
+    devices: HashSet<String>,
+    registers: HashMap<String, HashSet<String>>,
+}
+
+impl RemovedRegisters {
+    /// Track the devices and registers polled under `config`
+    pub fn new(config: &Config) -> Self {
+        Self {
+            polled: Arc::new(config.devices.clone()),
+            removed: Arc::default(),
+        }
+    }
+
+    /// Compare a newly loaded configuration to the polled one, returning
+    /// everything polled that it no longer has
+    ///
+    /// Fails, without changing what is discarded, if the configuration adds
+    /// a device or register or changes one that is polled.
+    pub fn update(&self, running: &Config) -> Result<Removal> {
+        for device in &running.devices {
+            let Some(polled) = self.polled.iter().find(|d| d.id == device.id) else {
+                bail!(
+                    "Device {} is not polled; adding devices needs a restart",
+                    device.id
+                );
+            };
+            // Dropping registers is the only change the workers can follow
+            let mut kept = polled.clone();
+            kept.registers
+                .retain(|r| device.registers.iter().any(|new| new.name == r.name));
+            if serde_json::to_value(&kept)? != serde_json::to_value(device)? {
+                bail!(
+                    "Device {} differs from the polled one; adding or changing devices and registers needs a restart",
+                    device.id
+                );
+            }
+        }
+
+        let mut removed = Removed::default();
+        for polled in self.polled.iter() {
+            let Some(device) = running.devices.iter().find(|d| d.id == polled.id) else {
+                removed.devices.insert(polled.id.clone());
+                continue;
+            };
+            let missing: HashSet<String> = polled
+                .registers
+                .iter()
+                .filter(|r| !device.registers.iter().any(|new| new.name == r.name))
+                .map(|r| r.name.clone())
+                .collect();
+            if !missing.is_empty() {
+                removed.registers.insert(polled.id.clone(), missing);
+            }
+        }
+
+        let mut removal = Removal {
+            devices: removed.devices.iter().cloned().collect(),
+            registers: removed
+                .registers
+                .iter()
+                .flat_map(|(device_id, names)| {
+                    names.iter().map(|name| (device_id.clone(), name.clone()))
+                })
+                .collect(),
+        };
+        removal.devices.sort();
+        removal.registers.sort();
+        *self.removed.lock().unwrap() = removed;
+        Ok(removal)
+    }
+
+    /// Whether values of the register are discarded
+    pub fn contains(&self, device_id: &str, register_name: &str) -> bool {
+        let removed = self.removed.lock().unwrap();
+        removed.devices.contains(device_id)
+            || removed
+                .registers
+                .get(device_id)
+                .is_some_and(|names| names.contains(register_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    fn config(devices: &[(&str, &[&str])]) -> Config {
+        devices
+            .iter()
+            .enumerate()
+            .fold(ConfigBuilder::new(), |builder, (i, (id, registers))| {
+                let device = registers.iter().enumerate().fold(
+                    DeviceBuilder::tcp(*id, "localhost", 502 + i as u16, 1),
+                    |device, (address, name)| {
+                        device.register(RegisterBuilder::holding(*name, address as u16))
+                    },
+                );
+                builder.device(device)
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reload_removes_and_restores_registers() {
+        let removed = RemovedRegisters::new(&config(&[
+            ("plc-001", &["temperature", "humidity"]),
+            ("plc-002", &["pressure"]),
+        ]));
+        assert!(!removed.contains("plc-001", "humidity"));
+
+        let removal = removed
+            .update(&config(&[("plc-001", &["temperature"])]))
+            .unwrap();
+        assert_eq!(
+            removal,
+            Removal {
+                devices: vec!["plc-002".to_string()],
+                registers: vec![("plc-001".to_string(), "humidity".to_string())],
+            }
+        );
+        assert!(removed.contains("plc-001", "humidity"));
+        assert!(removed.contains("plc-002", "pressure"));
+        assert!(!removed.contains("plc-001", "temperature"));
+        // Registers the workers do not poll are never discarded
+        assert!(!removed.contains("plc-001", "temperature_word0"));
+
+        // Adding them back lets their values through
+        let removal = removed
+            .update(&config(&[
+                ("plc-001", &["temperature", "humidity"]),
+                ("plc-002", &["pressure"]),
+            ]))
+            .unwrap();
+        assert_eq!(removal, Removal::default());
+        assert!(!removed.contains("plc-001", "humidity"));
+        assert!(!removed.contains("plc-002", "pressure"));
+    }
+
+    #[test]
+    fn test_reload_rejects_changes_the_workers_cannot_follow() {
+        let removed = RemovedRegisters::new(&config(&[("plc-001", &["temperature", "humidity"])]));
+        removed
+            .update(&config(&[("plc-001", &["temperature"])]))
+            .unwrap();
+
+        // New register, moved register, new device
+        for changed in [
+            config(&[("plc-001", &["temperature", "setpoint"])]),
+            config(&[("plc-001", &["humidity", "temperature"])]),
+            config(&[("plc-001", &["temperature"]), ("plc-002", &["pressure"])]),
+        ] {
+            assert!(removed.update(&changed).is_err());
+        }
+        // A rejected reload keeps discarding what the last one removed
+        assert!(removed.contains("plc-001", "humidity"));
+    }
+}
@@ -0,0 +1,368 @@
+//! SunSpec model discovery
+//!
+//! SunSpec devices start their register map with the marker "SunS" at a
+//! well-known base address, followed by a chain of models. Each model is a
+//! header (model id, length in registers) and a fixed layout of points; the
+//! chain ends with model id 0xFFFF. Points of the supported models become
+//! registers named `<model>.<point>`, with their scale factors applied
+//! through `scale_from_register`.
+
+use crate::config::{DataType, RegisterBuilder, RegisterConfig};
+
+use PointType::*;
+
+/// "SunS", the first two registers at the base address
+pub const MARKER: [u16; 2] = [0x5375, 0x6E53];
+
+/// Base addresses probed when none is configured, in order
+pub const BASE_ADDRESSES: [u16; 3] = [40000, 50000, 0];
+
+/// Model id that ends the chain
+pub const END_MODEL_ID: u16 = 0xFFFF;
+
+/// Most models followed before the chain is considered broken
+pub const MAX_MODELS: usize = 64;
+
+/// Encoding of a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PointType {
+    Uint16,
+    Int16,
+    /// Power-of-ten exponent of other points
+    Sunssf,
+    Enum16,
+    /// Counter that only increases, 0 when not implemented
+    Acc32,
+    Bitfield32,
+    /// Text over the given number of registers
+    Text(u16),
+}
+
+impl PointType {
+    fn size(self) -> u16 {
+        match self {
+            PointType::Acc32 | PointType::Bitfield32 => 2,
+            PointType::Text(count) => count,
+            _ => 1,
+        }
+    }
+
+    fn data_type(self) -> DataType {
+        match self {
+            PointType::Uint16 | PointType::Enum16 => DataType::U16,
+            PointType::Int16 | PointType::Sunssf => DataType::I16,
+            PointType::Acc32 | PointType::Bitfield32 => DataType::U32,
+            PointType::Text(_) => DataType::String,
+        }
+    }
+
+    /// Whether `words` hold the value SunSpec uses for "not implemented"
+    fn is_implemented(self, words: &[u16]) -> bool {
+        match self {
+            PointType::Uint16 | PointType::Enum16 => words != [0xFFFF],
+            PointType::Int16 | PointType::Sunssf => words != [0x8000],
+            PointType::Acc32 => words != [0, 0],
+            PointType::Bitfield32 => words != [0xFFFF, 0xFFFF],
+            PointType::Text(_) => words.iter().any(|&w| w != 0),
+        }
+    }
+}
+
+/// A point of a model, at `offset` registers after the model header
+struct Point {
+    name: &'static str,
+    offset: u16,
+    kind: PointType,
+    scale_factor: Option<&'static str>,
+    unit: Option<&'static str>,
+}
+
+const fn point(
+    name: &'static str,
+    offset: u16,
+    kind: PointType,
+    scale_factor: Option<&'static str>,
+    unit: Option<&'static str>,
+) -> Point {
+    Point {
+        name,
+        offset,
+        kind,
+        scale_factor,
+        unit,
+    }
+}
+
+const fn sf(name: &'static str, offset: u16) -> Point {
+    point(name, offset, PointType::Sunssf, None, None)
+}
+
+/// Model 1: Common
+const COMMON: &[Point] = &[
+    point("Mn", 0, Text(16), None, None),
+    point("Md", 16, Text(16), None, None),
+    point("Opt", 32, Text(8), None, None),
+    point("Vr", 40, Text(8), None, None),
+    point("SN", 48, Text(16), None, None),
+    point("DA", 64, Uint16, None, None),
+];
+
+/// Models 101-103: Inverter (single phase, split phase, three phase)
+const INVERTER: &[Point] = &[
+    point("A", 0, Uint16, Some("A_SF"), Some("A")),
+    point("AphA", 1, Uint16, Some("A_SF"), Some("A")),
+    point("AphB", 2, Uint16, Some("A_SF"), Some("A")),
+    point("AphC", 3, Uint16, Some("A_SF"), Some("A")),
+    sf("A_SF", 4),
+    point("PPVphAB", 5, Uint16, Some("V_SF"), Some("V")),
+    point("PPVphBC", 6, Uint16, Some("V_SF"), Some("V")),
+    point("PPVphCA", 7, Uint16, Some("V_SF"), Some("V")),
+    point("PhVphA", 8, Uint16, Some("V_SF"), Some("V")),
+    point("PhVphB", 9, Uint16, Some("V_SF"), Some("V")),
+    point("PhVphC", 10, Uint16, Some("V_SF"), Some("V")),
+    sf("V_SF", 11),
+    point("W", 12, Int16, Some("W_SF"), Some("W")),
+    sf("W_SF", 13),
+    point("Hz", 14, Uint16, Some("Hz_SF"), Some("Hz")),
+    sf("Hz_SF", 15),
+    point("VA", 16, Int16, Some("VA_SF"), Some("VA")),
+    sf("VA_SF", 17),
+    point("VAr", 18, Int16, Some("VAr_SF"), Some("var")),
+    sf("VAr_SF", 19),
+    point("PF", 20, Int16, Some("PF_SF"), Some("Pct")),
+    sf("PF_SF", 21),
+    point("WH", 22, Acc32, Some("WH_SF"), Some("Wh")),
+    sf("WH_SF", 24),
+    point("DCA", 25, Uint16, Some("DCA_SF"), Some("A")),
+    sf("DCA_SF", 26),
+    point("DCV", 27, Uint16, Some("DCV_SF"), Some("V")),
+    sf("DCV_SF", 28),
+    point("DCW", 29, Int16, Some("DCW_SF"), Some("W")),
+    sf("DCW_SF", 30),
+    point("TmpCab", 31, Int16, Some("Tmp_SF"), Some("C")),
+    point("TmpSnk", 32, Int16, Some("Tmp_SF"), Some("C")),
+    point("TmpTrns", 33, Int16, Some("Tmp_SF"), Some("C")),
+    point("TmpOt", 34, Int16, Some("Tmp_SF"), Some("C")),
+    sf("Tmp_SF", 35),
+    point("St", 36, Enum16, None, None),
+    point("StVnd", 37, Enum16, None, None),
+    point("Evt1", 38, Bitfield32, None, None),
+    point("Evt2", 40, Bitfield32, None, None),
+];
+
+/// Models 201-204: Meter (single phase, split phase, wye, delta)
+const METER: &[Point] = &[
+    point("A", 0, Int16, Some("A_SF"), Some("A")),
+    point("AphA", 1, Int16, Some("A_SF"), Some("A")),
+    point("AphB", 2, Int16, Some("A_SF"), Some("A")),
+    point("AphC", 3, Int16, Some("A_SF"), Some("A")),
+    sf("A_SF", 4),
+    point("PhV", 5, Int16, Some("V_SF"), Some("V")),
+    point("PhVphA", 6, Int16, Some("V_SF"), Some("V")),
+    point("PhVphB", 7, Int16, Some("V_SF"), Some("V")),
+    point("PhVphC", 8, Int16, Some("V_SF"), Some("V")),
+    point("PPV", 9, Int16, Some("V_SF"), Some("V")),
+    point("PPVphAB", 10, Int16, Some("V_SF"), Some("V")),
+    point("PPVphBC", 11, Int16, Some("V_SF"), Some("V")),
+    point("PPVphCA", 12, Int16, Some("V_SF"), Some("V")),
+    sf("V_SF", 13),
+    point("Hz", 14, Int16, Some("Hz_SF"), Some("Hz")),
+    sf("Hz_SF", 15),
+    point("W", 16, Int16, Some("W_SF"), Some("W")),
+    point("WphA", 17, Int16, Some("W_SF"), Some("W")),
+    point("WphB", 18, Int16, Some("W_SF"), Some("W")),
+    point("WphC", 19, Int16, Some("W_SF"), Some("W")),
+    sf("W_SF", 20),
+    point("VA", 21, Int16, Some("VA_SF"), Some("VA")),
+    point("VAphA", 22, Int16, Some("VA_SF"), Some("VA")),
+    point("VAphB", 23, Int16, Some("VA_SF"), Some("VA")),
+    point("VAphC", 24, Int16, Some("VA_SF"), Some("VA")),
+    sf("VA_SF", 25),
+    point("VAR", 26, Int16, Some("VAR_SF"), Some("var")),
+    point("VARphA", 27, Int16, Some("VAR_SF"), Some("var")),
+    point("VARphB", 28, Int16, Some("VAR_SF"), Some("var")),
+    point("VARphC", 29, Int16, Some("VAR_SF"), Some("var")),
+    sf("VAR_SF", 30),
+    point("PF", 31, Int16, Some("PF_SF"), Some("Pct")),
+    point("PFphA", 32, Int16, Some("PF_SF"), Some("Pct")),
+    point("PFphB", 33, Int16, Some("PF_SF"), Some("Pct")),
+    point("PFphC", 34, Int16, Some("PF_SF"), Some("Pct")),
+    sf("PF_SF", 35),
+    point("TotWhExp", 36, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhExpPhA", 38, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhExpPhB", 40, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhExpPhC", 42, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhImp", 44, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhImpPhA", 46, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhImpPhB", 48, Acc32, Some("TotWh_SF"), Some("Wh")),
+    point("TotWhImpPhC", 50, Acc32, Some("TotWh_SF"), Some("Wh")),
+    sf("TotWh_SF", 52),
+];
+
+/// Register name prefix and point layout of a supported model
+fn model(id: u16) -> Option<(&'static str, &'static [Point])> {
+    match id {
+        1 => Some(("common", COMMON)),
+        101..=103 => Some(("inverter", INVERTER)),
+        201..=204 => Some(("meter", METER)),
+        _ => None,
+    }
+}
+
+/// Register name prefix of a supported model, `None` for other models
+pub fn model_prefix(id: u16) -> Option<&'static str> {
+    model(id).map(|(prefix, _)| prefix)
+}
+
+/// A model found in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelHeader {
+    pub id: u16,
+    /// Address of the first register after the header
+    pub address: u16,
+    /// Length in registers, excluding the header
+    pub length: u16,
+}
+
+/// Registers for the points of a model that the device implements
+///
+/// `block` holds the model's registers as read once during discovery.
+/// Points reading as "not implemented" are left out, as are points whose
+/// scale factor is not implemented. Scale factors come first, so they are
+/// read before the points that use them.
+pub fn model_registers(header: &ModelHeader, block: &[u16], prefix: &str) -> Vec<RegisterConfig> {
+    let Some((_, points)) = model(header.id) else {
+        return vec![];
+    };
+    let available = block.len().min(header.length.into());
+    let implemented: Vec<&Point> = points
+        .iter()
+        .filter(|p| {
+            let range = usize::from(p.offset)..usize::from(p.offset + p.kind.size());
+            range.end <= available && p.kind.is_implemented(&block[range])
+        })
+        .collect();
+    let has_scale_factor = |name: &str| {
+        implemented
+            .iter()
+            .any(|p| p.kind == PointType::Sunssf && p.name == name)
+    };
+
+    let (scale_factors, values): (Vec<&Point>, Vec<&Point>) = implemented
+        .iter()
+        .partition(|p| p.kind == PointType::Sunssf);
+    scale_factors
+        .into_iter()
+        .chain(
+            values
+                .into_iter()
+                .filter(|p| p.scale_factor.is_none_or(has_scale_factor)),
+        )
+        .map(|p| {
+            let mut register = RegisterBuilder::holding(
+                format!("{}.{}", prefix, p.name),
+                header.address + p.offset,
+            )
+            .data_type(p.kind.data_type())
+            .count(p.kind.size());
+            if let Some(unit) = p.unit {
+                register = register.unit(unit);
+            }
+            if let Some(scale_factor) = p.scale_factor {
+                register = register.scale_from_register(format!("{}.{}", prefix, scale_factor));
+            }
+            register.build()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(registers: &[RegisterConfig]) -> Vec<&str> {
+        registers.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_inverter_registers() {
+        // Single phase inverter: phases B and C and the line-to-line
+        // voltages are not implemented, nor is the DC side
+        let mut block = vec![0u16; 50];
+        block[0] = 52; // A
+        block[1] = 52; // AphA
+        block[2] = 0xFFFF;
+        block[3] = 0xFFFF;
+        block[4] = (-1i16) as u16; // A_SF
+        block[5..8].fill(0xFFFF);
+        block[8] = 2301; // PhVphA
+        block[9] = 0xFFFF;
+        block[10] = 0xFFFF;
+        block[11] = (-1i16) as u16; // V_SF
+        block[12] = 1200; // W
+        block[13] = 0; // W_SF
+        block[25] = 0xFFFF; // DCA
+        block[26] = 0x8000; // DCA_SF
+        let header = ModelHeader {
+            id: 101,
+            address: 40071,
+            length: 50,
+        };
+
+        let registers = model_registers(&header, &block, "inverter");
+        let names = names(&registers);
+        assert_eq!(names[..2], ["inverter.A_SF", "inverter.V_SF"]);
+        assert!(names.contains(&"inverter.PhVphA"));
+        assert!(!names.contains(&"inverter.AphB"));
+        assert!(!names.contains(&"inverter.PPVphAB"));
+        // Scale factor not implemented
+        assert!(!names.contains(&"inverter.DCA_SF"));
+        assert!(!names.contains(&"inverter.DCA"));
+
+        let watts = registers.iter().find(|r| r.name == "inverter.W").unwrap();
+        assert_eq!(watts.address, 40071 + 12);
+        assert!(matches!(watts.data_type, DataType::I16));
+        assert_eq!(watts.unit.as_deref(), Some("W"));
+        assert_eq!(watts.scale_from_register.as_deref(), Some("inverter.W_SF"));
+    }
+
+    #[test]
+    fn test_common_registers() {
+        let mut block = vec![0u16; 66];
+        block[0] = u16::from_be_bytes(*b"Ac");
+        block[1] = u16::from_be_bytes(*b"me");
+        block[48] = u16::from_be_bytes(*b"12");
+        block[64] = 1;
+        let header = ModelHeader {
+            id: 1,
+            address: 40004,
+            length: 66,
+        };
+
+        let registers = model_registers(&header, &block, "common");
+        assert_eq!(names(&registers), ["common.Mn", "common.SN", "common.DA"]);
+        assert!(matches!(registers[0].data_type, DataType::String));
+        assert_eq!(registers[0].count, 16);
+        assert_eq!(registers[1].address, 40004 + 48);
+    }
+
+    #[test]
+    fn test_short_and_unsupported_models() {
+        let header = ModelHeader {
+            id: 201,
+            address: 0,
+            length: 105,
+        };
+        // Points beyond the block are left out
+        let registers = model_registers(&header, &[10, 0x8000, 0x8000, 0x8000, 0], "meter");
+        assert_eq!(names(&registers), ["meter.A_SF", "meter.A"]);
+
+        let header = ModelHeader {
+            id: 64110,
+            ..header
+        };
+        assert!(model_registers(&header, &[0; 10], "vendor").is_empty());
+        assert_eq!(model_prefix(64110), None);
+        assert_eq!(model_prefix(103), Some("inverter"));
+    }
+}
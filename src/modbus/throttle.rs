@@ -0,0 +1,101 @@
+//! Request rate limits
+//!
+//! A [`RateLimiter`] spaces requests evenly at `max_reads_per_second`,
+//! queuing callers in the order they ask. It is shared by every connection
+//! it applies to: all connections of a device, or all devices of a rate
+//! limit group, e.g. the devices on one serial segment.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Paces requests to at most a fixed number per second
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// Earliest time the next request may go out
+    next_slot: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / max_per_second),
+            next_slot: Arc::default(),
+        }
+    }
+
+    /// Wait for this caller's request slot
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |next| next.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// The rate limits a device's requests are subject to
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    limiters: Vec<RateLimiter>,
+}
+
+impl Throttle {
+    pub fn new(limiters: Vec<RateLimiter>) -> Self {
+        Self { limiters }
+    }
+
+    /// Wait until a request is allowed by every limit
+    pub async fn wait(&self) {
+        for limiter in &self.limiters {
+            limiter.acquire().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(50.0);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        // The first request goes out at once, the others 20ms apart
+        assert!(start.elapsed() >= Duration::from_millis(80));
+
+        // An idle limiter does not build up a burst
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let start = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_shared_limiter_paces_all_users() {
+        let group = RateLimiter::new(50.0);
+        let a = Throttle::new(vec![RateLimiter::new(1000.0), group.clone()]);
+        let b = Throttle::new(vec![group]);
+        let start = Instant::now();
+        tokio::join!(
+            async {
+                a.wait().await;
+                a.wait().await;
+            },
+            async {
+                b.wait().await;
+                b.wait().await;
+            }
+        );
+        // Four requests through the 50/s group limit
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+}
@@ -2,49 +2,376 @@
 //!
 //! Publishes register updates to MQTT broker with topics like:
 //! `{prefix}/{device_id}/{register_name}`
+//!
+//! The prefix is `mqtt.topic_prefix` unless the device sets its own
+//! `mqtt_topic_prefix`.
 
 use anyhow::{Context, Result};
-use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use rumqttc::{
+    AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration,
+    Transport,
+};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::broadcast;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
 use tracing::{debug, error, info, warn};
 
-use crate::api::RegisterUpdate;
-use crate::config::MqttConfig;
+use crate::api::naming::RegisterNames;
+use crate::api::{RegisterUpdate, ValueChange};
+use crate::config::{
+    BoolFormat, DataType, DeviceConfig, MqttConfig, MqttTlsConfig, RawFormat, StatusPayloadConfig,
+    StatusPayloadFormat,
+};
+use crate::metrics;
+
+/// Topic prefixes with per-device overrides
+#[derive(Debug, Clone)]
+pub struct TopicPrefixes {
+    default: String,
+    per_device: HashMap<String, String>,
+}
+
+impl TopicPrefixes {
+    /// Build from the global prefix and each device's optional override
+    pub fn new(default: &str, devices: &[DeviceConfig]) -> Self {
+        let per_device = devices
+            .iter()
+            .filter_map(|d| {
+                d.mqtt_topic_prefix
+                    .as_ref()
+                    .map(|prefix| (d.id.clone(), prefix.clone()))
+            })
+            .collect();
+
+        Self {
+            default: default.to_string(),
+            per_device,
+        }
+    }
+
+    /// Get the topic prefix for a device
+    pub fn for_device(&self, device_id: &str) -> &str {
+        self.per_device
+            .get(device_id)
+            .map(String::as_str)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Representation of each device's boolean registers
+#[derive(Debug, Clone, Default)]
+pub struct BoolFormats {
+    default: BoolFormat,
+    /// Boolean registers per device, with their own format if they set one
+    per_device: HashMap<String, BTreeMap<String, Option<BoolFormat>>>,
+}
+
+impl BoolFormats {
+    /// Collect the boolean registers of every device
+    pub fn new(default: &BoolFormat, devices: &[DeviceConfig]) -> Self {
+        let per_device = devices
+            .iter()
+            .map(|d| {
+                let registers = d
+                    .registers
+                    .iter()
+                    .filter(|r| matches!(r.data_type, DataType::Bool))
+                    .map(|r| (r.name.clone(), r.bool_format.clone()))
+                    .collect();
+                (d.id.clone(), registers)
+            })
+            .collect();
+
+        Self {
+            default: default.clone(),
+            per_device,
+        }
+    }
+
+    /// Format of a register, `None` if it isn't boolean
+    pub fn for_register(&self, device_id: &str, register: &str) -> Option<&BoolFormat> {
+        let format = self.per_device.get(device_id)?.get(register)?;
+        Some(format.as_ref().unwrap_or(&self.default))
+    }
+
+    /// Formats of a device's boolean registers, for its status message
+    fn describe(&self, device_id: &str) -> serde_json::Map<String, serde_json::Value> {
+        self.per_device
+            .get(device_id)
+            .into_iter()
+            .flatten()
+            .map(|(name, format)| {
+                let format = format.as_ref().unwrap_or(&self.default);
+                (
+                    name.clone(),
+                    serde_json::to_value(format).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// Represent a numeric value of a register in its boolean format, if it
+    /// has one
+    fn value(
+        &self,
+        device_id: &str,
+        register: &str,
+        value: serde_json::Value,
+    ) -> serde_json::Value {
+        match (self.for_register(device_id, register), value.as_f64()) {
+            (Some(format), Some(number)) => format.to_json(number != 0.0),
+            _ => value,
+        }
+    }
+}
+
+/// Decimation of registers with a `publish_interval_ms`: at most one
+/// publish per interval, carrying the latest reading
+#[derive(Debug, Default)]
+pub struct Decimator {
+    intervals: HashMap<(String, String), Duration>,
+    last_sent: HashMap<(String, String), Instant>,
+    /// Latest reading of each register not published yet
+    held: HashMap<(String, String), RegisterUpdate>,
+}
+
+impl Decimator {
+    /// Collect the publish intervals of every device's registers
+    pub fn new(devices: &[DeviceConfig]) -> Self {
+        let intervals = devices
+            .iter()
+            .flat_map(|d| {
+                d.registers.iter().filter_map(|r| {
+                    let interval = Duration::from_millis(r.publish_interval_ms?);
+                    Some(((d.id.clone(), r.name.clone()), interval))
+                })
+            })
+            .collect();
+        Self {
+            intervals,
+            ..Default::default()
+        }
+    }
+
+    /// Pass on an update that is due at `now`, or hold it until its
+    /// register's interval has elapsed, replacing any older held reading
+    pub fn offer(&mut self, update: RegisterUpdate, now: Instant) -> Option<RegisterUpdate> {
+        let key = (update.device_id.clone(), update.register_name.clone());
+        let Some(interval) = self.intervals.get(&key) else {
+            return Some(update);
+        };
+        let due = self
+            .last_sent
+            .get(&key)
+            .is_none_or(|sent| now.duration_since(*sent) >= *interval);
+        if due {
+            self.held.remove(&key);
+            self.last_sent.insert(key, now);
+            Some(update)
+        } else {
+            self.held.insert(key, update);
+            None
+        }
+    }
+
+    /// When the next held reading is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.held
+            .keys()
+            .filter_map(|key| Some(*self.last_sent.get(key)? + *self.intervals.get(key)?))
+            .min()
+    }
+
+    /// Take the held readings that are due at `now`
+    pub fn take_due(&mut self, now: Instant) -> Vec<RegisterUpdate> {
+        let due: Vec<_> = self
+            .held
+            .keys()
+            .filter(|key| {
+                self.last_sent
+                    .get(*key)
+                    .zip(self.intervals.get(*key))
+                    .is_none_or(|(sent, interval)| now.duration_since(*sent) >= *interval)
+            })
+            .cloned()
+            .collect();
+        due.into_iter()
+            .filter_map(|key| {
+                let update = self.held.remove(&key)?;
+                self.last_sent.insert(key, now);
+                Some(update)
+            })
+            .collect()
+    }
+}
+
+/// Order of the messages on each register topic
+///
+/// Updates are queued to the broker connection in the order they are
+/// published, and the connection delivers them in that order. Updates can
+/// still be handed to the publisher out of order, e.g. a republish after
+/// reconnecting racing a fresh reading; those are dropped so every topic's
+/// timestamps only move forward. Each message of a topic gets the next
+/// sequence number, starting at 1 when the gateway starts.
+#[derive(Debug, Default)]
+pub struct TopicSequencer {
+    topics: HashMap<(String, String), TopicState>,
+}
+
+/// Last message published on a topic
+#[derive(Debug, Default)]
+struct TopicState {
+    seq: u64,
+    timestamp: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl TopicSequencer {
+    /// Sequence number of the update's message, or `None` if the update is
+    /// older than the last one published on its topic
+    pub fn next(&mut self, update: &RegisterUpdate) -> Option<u64> {
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&update.timestamp).ok();
+        let topic = self
+            .topics
+            .entry((update.device_id.clone(), update.register_name.clone()))
+            .or_default();
+        if let (Some(timestamp), Some(last)) = (timestamp, topic.timestamp) {
+            if timestamp < last {
+                return None;
+            }
+        }
+        topic.seq += 1;
+        if timestamp.is_some() {
+            topic.timestamp = timestamp;
+        }
+        Some(topic.seq)
+    }
+}
 
 /// MQTT Publisher for sending register values
 pub struct MqttPublisher {
     client: AsyncClient,
-    topic_prefix: String,
+    topic_prefixes: TopicPrefixes,
     qos: QoS,
     retain: bool,
-    #[allow(dead_code)] // Used for connection status checks
+    status_payload: StatusPayloadConfig,
+    gateway_id: String,
+    raw_format: RawFormat,
+    bool_formats: BoolFormats,
+    /// Qualified register names for payloads, if configured
+    register_names: RegisterNames,
+    /// Publish intervals of decimated registers
+    decimation: std::sync::Mutex<Decimator>,
+    /// Held while an update is queued, so sequence numbers follow the
+    /// queue order
+    sequencer: tokio::sync::Mutex<TopicSequencer>,
+    sequence_numbers: bool,
     connected: Arc<AtomicBool>,
+    /// Number of successful broker connections so far
+    connects: watch::Receiver<u64>,
+    bridge_status: BridgeStatus,
 }
 
-impl MqttPublisher {
-    /// Create a new MQTT publisher
-    pub async fn new(config: &MqttConfig) -> Result<Self> {
-        let mut mqttoptions = MqttOptions::new(&config.client_id, &config.host, config.port);
+/// The bridge's own status topic: `online` while connected, `offline` as
+/// the last will the broker publishes when the connection drops
+#[derive(Clone)]
+struct BridgeStatus {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+}
 
-        mqttoptions.set_keep_alive(Duration::from_secs(30));
-        mqttoptions.set_clean_session(true);
+impl BridgeStatus {
+    fn payload(online: bool) -> &'static str {
+        if online {
+            "online"
+        } else {
+            "offline"
+        }
+    }
+}
+
+/// Connection options for the broker, with the bridge's `offline` status as
+/// last will
+fn mqtt_options(config: &MqttConfig, qos: QoS) -> Result<MqttOptions> {
+    let mut mqttoptions = MqttOptions::new(&config.client_id, &config.host, config.port);
+
+    mqttoptions.set_keep_alive(Duration::from_secs(30));
+    mqttoptions.set_clean_session(true);
+    mqttoptions.set_last_will(LastWill::new(
+        config.bridge_status_topic(),
+        BridgeStatus::payload(false),
+        qos,
+        true,
+    ));
+
+    if let (Some(user), Some(pass)) = (&config.username, &config.password) {
+        mqttoptions.set_credentials(user, pass);
+    }
+    if config.tls.enabled {
+        mqttoptions.set_transport(Transport::tls_with_config(tls_configuration(&config.tls)?));
+    }
+    Ok(mqttoptions)
+}
 
-        if let (Some(user), Some(pass)) = (&config.username, &config.password) {
-            mqttoptions.set_credentials(user, pass);
+/// TLS settings from the configured certificate files, verifying the broker
+/// with the system's root certificates when no CA certificate is given
+fn tls_configuration(tls: &MqttTlsConfig) -> Result<TlsConfiguration> {
+    let read = |what: &str, path: &str| {
+        std::fs::read(path).with_context(|| format!("Failed to read MQTT {} {}", what, path))
+    };
+    let client_auth = match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            Some((read("client certificate", cert)?, read("client key", key)?))
         }
+        (None, None) => None,
+        _ => anyhow::bail!("mqtt.tls.client_cert and client_key must be set together"),
+    };
+    match &tls.ca_cert {
+        Some(ca) => Ok(TlsConfiguration::Simple {
+            ca: read("CA certificate", ca)?,
+            alpn: None,
+            client_auth,
+        }),
+        None => native_roots_configuration(client_auth),
+    }
+}
 
-        let (client, eventloop) = AsyncClient::new(mqttoptions, 100);
-        let connected = Arc::new(AtomicBool::new(false));
+/// TLS settings verifying the broker with the system's root certificates,
+/// with an optional PEM client certificate and key
+fn native_roots_configuration(client_auth: Option<(Vec<u8>, Vec<u8>)>) -> Result<TlsConfiguration> {
+    use rumqttc::tokio_rustls::rustls::{ClientConfig, RootCertStore};
 
-        // Spawn event loop handler
-        let connected_clone = connected.clone();
-        let host = config.host.clone();
-        let port = config.port;
-        Self::spawn_event_loop(eventloop, connected_clone, host, port);
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs()
+        .context("Failed to load the system's root certificates")?;
+    roots.add_parsable_certificates(native);
+    if roots.is_empty() {
+        anyhow::bail!("No system root certificates found; set mqtt.tls.ca_cert");
+    }
 
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let config = match client_auth {
+        Some((cert, key)) => {
+            let certs = rustls_pemfile::certs(&mut cert.as_slice())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .context("Invalid MQTT client certificate")?;
+            let key = rustls_pemfile::private_key(&mut key.as_slice())
+                .context("Invalid MQTT client key")?
+                .context("No private key in the MQTT client key file")?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("Invalid MQTT client certificate or key")?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(TlsConfiguration::Rustls(Arc::new(config)))
+}
+
+impl MqttPublisher {
+    /// Create a new MQTT publisher
+    pub async fn new(config: &MqttConfig, devices: &[DeviceConfig]) -> Result<Self> {
         let qos = match config.qos {
             0 => QoS::AtMostOnce,
             1 => QoS::AtLeastOnce,
@@ -55,6 +382,28 @@ impl MqttPublisher {
             }
         };
 
+        let (client, eventloop) = AsyncClient::new(mqtt_options(config, qos)?, 100);
+        let connected = Arc::new(AtomicBool::new(false));
+        let (connects_tx, connects) = watch::channel(0);
+        let bridge_status = BridgeStatus {
+            client: client.clone(),
+            topic: config.bridge_status_topic(),
+            qos,
+        };
+
+        // Spawn event loop handler
+        let connected_clone = connected.clone();
+        let host = config.host.clone();
+        let port = config.port;
+        Self::spawn_event_loop(
+            eventloop,
+            bridge_status.clone(),
+            connected_clone,
+            connects_tx,
+            host,
+            port,
+        );
+
         info!(
             "MQTT publisher initialized: {}:{} (prefix: {}, qos: {})",
             config.host, config.port, config.topic_prefix, config.qos
@@ -62,17 +411,54 @@ impl MqttPublisher {
 
         Ok(Self {
             client,
-            topic_prefix: config.topic_prefix.clone(),
+            topic_prefixes: TopicPrefixes::new(&config.topic_prefix, devices),
             qos,
             retain: config.retain,
+            status_payload: config.status_payload.clone(),
+            gateway_id: config.client_id.clone(),
+            raw_format: RawFormat::default(),
+            bool_formats: BoolFormats::new(&BoolFormat::default(), devices),
+            register_names: RegisterNames::default(),
+            decimation: std::sync::Mutex::new(Decimator::new(devices)),
+            sequencer: tokio::sync::Mutex::default(),
+            sequence_numbers: config.sequence_numbers,
             connected,
+            connects,
+            bridge_status,
         })
     }
 
+    /// Identify this gateway in payloads (defaults to the MQTT client id)
+    pub fn with_gateway_id(mut self, gateway_id: impl Into<String>) -> Self {
+        self.gateway_id = gateway_id.into();
+        self
+    }
+
+    /// Serialize raw register words in the given format
+    pub fn with_raw_format(mut self, raw_format: RawFormat) -> Self {
+        self.raw_format = raw_format;
+        self
+    }
+
+    /// Represent boolean values in the given format, unless a register
+    /// sets its own
+    pub fn with_bool_format(mut self, bool_format: &BoolFormat) -> Self {
+        self.bool_formats.default = bool_format.clone();
+        self
+    }
+
+    /// Add the qualified register name to update and change payloads
+    pub fn with_register_names(mut self, register_names: RegisterNames) -> Self {
+        self.register_names = register_names;
+        self
+    }
+
     /// Spawn the MQTT event loop handler
     fn spawn_event_loop(
         mut eventloop: EventLoop,
+        bridge_status: BridgeStatus,
         connected: Arc<AtomicBool>,
+        connects: watch::Sender<u64>,
         host: String,
         port: u16,
     ) {
@@ -82,7 +468,19 @@ impl MqttPublisher {
                     Ok(Event::Incoming(Packet::ConnAck(ack))) => {
                         if ack.code == rumqttc::ConnectReturnCode::Success {
                             connected.store(true, Ordering::SeqCst);
+                            metrics::record_mqtt_connection(true);
                             info!("Connected to MQTT broker at {}:{}", host, port);
+                            // Replaces the last will a previous connection left;
+                            // queued without waiting, as this task sends the queue
+                            if let Err(e) = bridge_status.client.try_publish(
+                                &bridge_status.topic,
+                                bridge_status.qos,
+                                true,
+                                BridgeStatus::payload(true),
+                            ) {
+                                warn!("Failed to publish bridge status: {}", e);
+                            }
+                            connects.send_modify(|n| *n += 1);
                         } else {
                             error!("MQTT connection rejected: {:?}", ack.code);
                         }
@@ -92,14 +490,23 @@ impl MqttPublisher {
                     }
                     Ok(Event::Incoming(Packet::Disconnect)) => {
                         connected.store(false, Ordering::SeqCst);
+                        metrics::record_mqtt_connection(false);
                         warn!("Disconnected from MQTT broker");
                     }
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                        // Everything queued before the disconnect was sent
+                        connected.store(false, Ordering::SeqCst);
+                        metrics::record_mqtt_connection(false);
+                        info!("Disconnected from MQTT broker at {}:{}", host, port);
+                        break;
+                    }
                     Ok(Event::Outgoing(_)) => {
                         // Outgoing events are normal
                     }
                     Ok(_) => {}
                     Err(e) => {
                         connected.store(false, Ordering::SeqCst);
+                        metrics::record_mqtt_connection(false);
                         error!("MQTT error: {:?}", e);
                         tokio::time::sleep(Duration::from_secs(5)).await;
                     }
@@ -108,25 +515,91 @@ impl MqttPublisher {
         });
     }
 
+    /// Publish the bridge's `offline` status and disconnect from the broker
+    /// once the messages queued so far are sent
+    ///
+    /// The publisher cannot be used afterwards.
+    pub async fn disconnect(&self) -> Result<()> {
+        // A clean disconnect doesn't trigger the last will
+        let status = &self.bridge_status;
+        status
+            .client
+            .publish(
+                &status.topic,
+                status.qos,
+                true,
+                BridgeStatus::payload(false),
+            )
+            .await
+            .with_context(|| format!("Failed to publish status to {}", status.topic))?;
+        self.client
+            .disconnect()
+            .await
+            .context("Failed to disconnect from MQTT broker")
+    }
+
     /// Check if connected to broker
     #[allow(dead_code)] // Available for future health checks
     pub fn is_connected(&self) -> bool {
         self.connected.load(Ordering::SeqCst)
     }
 
+    /// Shared broker connection flag, kept up to date by the event loop
+    pub fn connection_state(&self) -> Arc<AtomicBool> {
+        self.connected.clone()
+    }
+
+    /// Notified each time the broker connection is established
+    pub fn subscribe_connects(&self) -> watch::Receiver<u64> {
+        self.connects.clone()
+    }
+
     /// Publish a register update from the broadcast channel
     pub async fn publish_update(&self, update: &RegisterUpdate) -> Result<()> {
         let topic = format!(
             "{}/{}/{}",
-            self.topic_prefix, update.device_id, update.register_name
+            self.topic_prefixes.for_device(&update.device_id),
+            update.device_id,
+            update.register_name
         );
 
-        let payload = serde_json::json!({
-            "value": update.value,
-            "raw": update.raw,
+        let value = self.bool_formats.value(
+            &update.device_id,
+            &update.register_name,
+            update.value.into(),
+        );
+        let mut payload = serde_json::json!({
+            "value": value,
+            "decoded": update.decoded,
+            "raw": self.raw_format.to_json(&update.raw),
             "unit": update.unit,
+            "quality": update.quality,
+            "changed": update.changed,
+            "gateway_id": self.gateway_id,
             "timestamp": update.timestamp,
         });
+        if let Some(text) = &update.text {
+            payload["text"] = text.clone().into();
+        }
+        if self.register_names.is_qualified() {
+            payload["register"] = self
+                .register_names
+                .qualify(&update.device_id, &update.register_name)
+                .into();
+        }
+
+        let mut sequencer = self.sequencer.lock().await;
+        let Some(seq) = sequencer.next(update) else {
+            debug!(
+                "MQTT dropped update for {} older than the last one published",
+                topic
+            );
+            metrics::record_mqtt_dropped("out_of_order", 1);
+            return Ok(());
+        };
+        if self.sequence_numbers {
+            payload["seq"] = seq.into();
+        }
 
         let payload_str =
             serde_json::to_string(&payload).with_context(|| "Failed to serialize payload")?;
@@ -135,17 +608,67 @@ impl MqttPublisher {
             .publish(&topic, self.qos, self.retain, payload_str.as_bytes())
             .await
             .with_context(|| format!("Failed to publish to {}", topic))?;
+        drop(sequencer);
 
         debug!("MQTT published to {}: {}", topic, payload_str);
 
         Ok(())
     }
 
+    /// Publish a value transition to `{prefix}/{device_id}/{register}/change`
+    pub async fn publish_change(&self, change: &ValueChange) -> Result<()> {
+        let topic = format!(
+            "{}/{}/{}/change",
+            self.topic_prefixes.for_device(&change.device_id),
+            change.device_id,
+            change.register
+        );
+
+        let format = |value: &serde_json::Value| {
+            self.bool_formats
+                .value(&change.device_id, &change.register, value.clone())
+        };
+        let mut payload = serde_json::json!({
+            "old_value": format(&change.old_value),
+            "new_value": format(&change.new_value),
+            "gateway_id": self.gateway_id,
+            "timestamp": change.timestamp,
+        });
+        if self.register_names.is_qualified() {
+            payload["register"] = self
+                .register_names
+                .qualify(&change.device_id, &change.register)
+                .into();
+        }
+        let payload_str =
+            serde_json::to_string(&payload).with_context(|| "Failed to serialize change")?;
+
+        // Change events describe a transition, retaining one would be misleading
+        self.client
+            .publish(&topic, self.qos, false, payload_str.as_bytes())
+            .await
+            .with_context(|| format!("Failed to publish to {}", topic))?;
+
+        debug!("MQTT change published to {}: {}", topic, payload_str);
+
+        Ok(())
+    }
+
     /// Publish device status (online/offline)
-    #[allow(dead_code)] // Available for device lifecycle events
     pub async fn publish_status(&self, device_id: &str, online: bool) -> Result<()> {
-        let topic = format!("{}/{}/status", self.topic_prefix, device_id);
-        let payload = if online { "online" } else { "offline" };
+        let topic = format!(
+            "{}/{}/status",
+            self.topic_prefixes.for_device(device_id),
+            device_id
+        );
+        let payload = status_payload(
+            &self.status_payload,
+            &self.gateway_id,
+            device_id,
+            online,
+            &chrono::Utc::now().to_rfc3339(),
+            &self.bool_formats.describe(device_id),
+        );
 
         self.client
             .publish(&topic, self.qos, true, payload.as_bytes()) // Always retain status
@@ -157,6 +680,43 @@ impl MqttPublisher {
         Ok(())
     }
 
+    /// Publish gateway statistics to `{prefix}/gateway/diagnostics`
+    pub async fn publish_diagnostics(&self, stats: &metrics::GatewayStats) -> Result<()> {
+        let topic = format!("{}/gateway/diagnostics", self.topic_prefixes.default);
+
+        let mut payload =
+            serde_json::to_value(stats).with_context(|| "Failed to serialize diagnostics")?;
+        payload["gateway_id"] = self.gateway_id.clone().into();
+        payload["timestamp"] = chrono::Utc::now().to_rfc3339().into();
+        let payload_str = payload.to_string();
+
+        self.client
+            .publish(&topic, self.qos, false, payload_str.as_bytes())
+            .await
+            .with_context(|| format!("Failed to publish diagnostics to {}", topic))?;
+
+        debug!("MQTT diagnostics: {} = {}", topic, payload_str);
+
+        Ok(())
+    }
+
+    /// Publish gateway diagnostics every `interval`
+    pub async fn start_diagnostics(self: Arc<Self>, interval: Duration) {
+        info!(
+            "MQTT diagnostics every {}ms on {}/gateway/diagnostics",
+            interval.as_millis(),
+            self.topic_prefixes.default
+        );
+
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.publish_diagnostics(&metrics::gateway_stats()).await {
+                error!("MQTT diagnostics error: {}", e);
+            }
+        }
+    }
+
     /// Start the MQTT publishing loop that listens to broadcast channel
     pub async fn start_publishing(
         self: Arc<Self>,
@@ -165,14 +725,35 @@ impl MqttPublisher {
         info!("MQTT publishing loop started");
 
         loop {
-            match update_rx.recv().await {
+            let next_due = self.decimation.lock().unwrap().next_due();
+            let received = tokio::select! {
+                received = update_rx.recv() => received,
+                _ = sleep_until(next_due) => {
+                    let due = self.decimation.lock().unwrap().take_due(Instant::now());
+                    for update in due {
+                        if let Err(e) = self.publish_update(&update).await {
+                            error!("MQTT publish error: {}", e);
+                        }
+                    }
+                    continue;
+                }
+            };
+            match received {
                 Ok(update) => {
-                    if let Err(e) = self.publish_update(&update).await {
-                        error!("MQTT publish error: {}", e);
+                    let update = self
+                        .decimation
+                        .lock()
+                        .unwrap()
+                        .offer(update, Instant::now());
+                    if let Some(update) = update {
+                        if let Err(e) = self.publish_update(&update).await {
+                            error!("MQTT publish error: {}", e);
+                        }
                     }
                 }
                 Err(broadcast::error::RecvError::Lagged(n)) => {
                     warn!("MQTT publisher lagged, missed {} updates", n);
+                    metrics::record_mqtt_dropped("lagged", n);
                 }
                 Err(broadcast::error::RecvError::Closed) => {
                     info!("MQTT broadcast channel closed, stopping publisher");
@@ -181,6 +762,73 @@ impl MqttPublisher {
             }
         }
     }
+
+    /// Publish value change events from the change channel
+    pub async fn start_publishing_changes(
+        self: Arc<Self>,
+        mut change_rx: broadcast::Receiver<ValueChange>,
+    ) {
+        info!("MQTT change publishing loop started");
+
+        loop {
+            match change_rx.recv().await {
+                Ok(change) => {
+                    if let Err(e) = self.publish_change(&change).await {
+                        error!("MQTT change publish error: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("MQTT change publisher lagged, missed {} changes", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    info!("Change channel closed, stopping change publisher");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Sleep until `deadline`, forever if there is none
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Render a device status message according to the configured format
+fn status_payload(
+    config: &StatusPayloadConfig,
+    gateway_id: &str,
+    device_id: &str,
+    online: bool,
+    timestamp: &str,
+    bool_formats: &serde_json::Map<String, serde_json::Value>,
+) -> String {
+    let status = if online {
+        &config.online
+    } else {
+        &config.offline
+    };
+
+    match config.format {
+        StatusPayloadFormat::Plain => status.clone(),
+        StatusPayloadFormat::Json => {
+            let mut payload = serde_json::json!({
+                "status": status,
+                "online": online,
+                "device_id": device_id,
+                "gateway_id": gateway_id,
+                "timestamp": timestamp,
+            });
+            // The birth message tells consumers how boolean values are encoded
+            if online && !bool_formats.is_empty() {
+                payload["bool_format"] = bool_formats.clone().into();
+            }
+            payload.to_string()
+        }
+    }
 }
 
 /// Statistics for MQTT publishing
@@ -242,6 +890,96 @@ mod tests {
         assert_eq!(topic, "rustbridge/plc-001/temperature");
     }
 
+    #[test]
+    fn test_device_topic_prefix_override() {
+        let yaml = r#"
+- id: "tenant-a-meter"
+  name: "Tenant A"
+  device_type: tcp
+  connection: { host: "10.0.0.1", port: 502, unit_id: 1 }
+  poll_interval_ms: 1000
+  registers: []
+  mqtt_topic_prefix: "customers/a"
+- id: "shared-meter"
+  name: "Shared"
+  device_type: tcp
+  connection: { host: "10.0.0.2", port: 502, unit_id: 1 }
+  poll_interval_ms: 1000
+  registers: []
+"#;
+        let devices: Vec<DeviceConfig> = serde_yaml::from_str(yaml).unwrap();
+        let prefixes = TopicPrefixes::new("rustbridge", &devices);
+
+        assert_eq!(prefixes.for_device("tenant-a-meter"), "customers/a");
+        assert_eq!(prefixes.for_device("shared-meter"), "rustbridge");
+        assert_eq!(prefixes.for_device("unknown"), "rustbridge");
+    }
+
+    #[test]
+    fn test_last_will() {
+        let mut config = crate::config::Config::default().mqtt;
+        let will = mqtt_options(&config, QoS::AtLeastOnce)
+            .unwrap()
+            .last_will()
+            .unwrap();
+        assert_eq!(will.topic, "rustbridge/bridge/status");
+        assert_eq!(&will.message[..], b"offline");
+        assert_eq!(will.qos, QoS::AtLeastOnce);
+        assert!(will.retain);
+
+        config.status_topic = Some("site-1/gateways/rustbridge/state".to_string());
+        let will = mqtt_options(&config, QoS::ExactlyOnce)
+            .unwrap()
+            .last_will()
+            .unwrap();
+        assert_eq!(will.topic, "site-1/gateways/rustbridge/state");
+        assert_eq!(will.qos, QoS::ExactlyOnce);
+    }
+
+    #[test]
+    fn test_tls_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("ca.crt"), "CA").unwrap();
+        std::fs::write(path("client.crt"), "CERT").unwrap();
+        std::fs::write(path("client.key"), "KEY").unwrap();
+
+        let mut config = crate::config::Config::default().mqtt;
+        config.tls = MqttTlsConfig {
+            enabled: true,
+            ca_cert: Some(path("ca.crt")),
+            client_cert: Some(path("client.crt")),
+            client_key: Some(path("client.key")),
+        };
+        let options = mqtt_options(&config, QoS::AtLeastOnce).unwrap();
+        match options.transport() {
+            Transport::Tls(TlsConfiguration::Simple {
+                ca, client_auth, ..
+            }) => {
+                assert_eq!(ca, b"CA");
+                assert_eq!(client_auth, Some((b"CERT".to_vec(), b"KEY".to_vec())));
+            }
+            _ => panic!("expected TLS with the configured certificates"),
+        }
+
+        // Without a CA certificate the system's roots verify the broker;
+        // errors, such as a key that is not PEM, fail instead of panicking
+        config.tls.ca_cert = None;
+        assert!(mqtt_options(&config, QoS::AtLeastOnce).is_err());
+        config.tls.ca_cert = Some(path("ca.crt"));
+
+        // The failing file is named
+        config.tls.client_key = Some(path("missing.key"));
+        let error = format!("{:#}", mqtt_options(&config, QoS::AtLeastOnce).unwrap_err());
+        assert!(error.contains("client key"), "{}", error);
+        assert!(error.contains("missing.key"), "{}", error);
+
+        // Plaintext unless enabled
+        config.tls.enabled = false;
+        let options = mqtt_options(&config, QoS::AtLeastOnce).unwrap();
+        assert!(matches!(options.transport(), Transport::Tcp));
+    }
+
     #[test]
     fn test_status_topic_format() {
         let prefix = "rustbridge";
@@ -250,4 +988,184 @@ mod tests {
         let topic = format!("{}/{}/status", prefix, device_id);
         assert_eq!(topic, "rustbridge/plc-001/status");
     }
+
+    #[test]
+    fn test_status_payload_formats() {
+        let ts = "2025-12-27T10:30:00+00:00";
+        let none = serde_json::Map::new();
+
+        // Default stays the bare strings
+        let plain = StatusPayloadConfig::default();
+        assert_eq!(
+            status_payload(&plain, "gw", "plc-001", true, ts, &none),
+            "online"
+        );
+        assert_eq!(
+            status_payload(&plain, "gw", "plc-001", false, ts, &none),
+            "offline"
+        );
+
+        let json = StatusPayloadConfig {
+            format: StatusPayloadFormat::Json,
+            online: "UP".to_string(),
+            ..StatusPayloadConfig::default()
+        };
+        let payload: serde_json::Value =
+            serde_json::from_str(&status_payload(&json, "gw-1", "plc-001", true, ts, &none))
+                .unwrap();
+        assert_eq!(payload["status"], "UP");
+        assert_eq!(payload["online"], true);
+        assert_eq!(payload["device_id"], "plc-001");
+        assert_eq!(payload["gateway_id"], "gw-1");
+        assert_eq!(payload["timestamp"], ts);
+        assert!(payload.get("bool_format").is_none());
+    }
+
+    #[test]
+    fn test_decimation() {
+        use crate::config::{DeviceBuilder, RegisterBuilder};
+
+        let device = DeviceBuilder::tcp("sensor", "localhost", 502, 1)
+            .register(RegisterBuilder::input("vibration", 0).publish_interval_ms(1000))
+            .register(RegisterBuilder::input("temperature", 1))
+            .build();
+        let mut decimator = Decimator::new(&[device]);
+        let update = |register: &str, value: f64| RegisterUpdate {
+            device_id: "sensor".to_string(),
+            register_name: register.to_string(),
+            value,
+            decoded: value,
+            raw: vec![],
+            text: None,
+            unit: None,
+            quality: Default::default(),
+            changed: true,
+            old_value: None,
+            timestamp: String::new(),
+        };
+
+        // Polled every 50ms for 2s, published at most once a second
+        let start = Instant::now();
+        let mut vibration = Vec::new();
+        let mut temperature = 0;
+        for tick in 0..40u64 {
+            let now = start + Duration::from_millis(tick * 50);
+            vibration.extend(decimator.take_due(now).into_iter().map(|u| u.value));
+            vibration.extend(
+                decimator
+                    .offer(update("vibration", tick as f64), now)
+                    .map(|u| u.value),
+            );
+            temperature += decimator
+                .offer(update("temperature", 0.0), now)
+                .iter()
+                .count();
+        }
+        // At 1s the reading held since 950ms is the latest one
+        assert_eq!(vibration, [0.0, 19.0]);
+        assert_eq!(
+            temperature, 40,
+            "registers without an interval are not decimated"
+        );
+
+        // The latest reading is held until the next interval
+        let due = start + Duration::from_millis(2000);
+        assert_eq!(decimator.next_due(), Some(due));
+        assert!(decimator
+            .take_due(due - Duration::from_millis(1))
+            .is_empty());
+        let held: Vec<_> = decimator
+            .take_due(due)
+            .into_iter()
+            .map(|u| u.value)
+            .collect();
+        assert_eq!(held, [39.0]);
+        assert_eq!(decimator.next_due(), None);
+    }
+
+    #[test]
+    fn test_topic_sequencer() {
+        let update = |register: &str, timestamp: &str| RegisterUpdate {
+            device_id: "sensor".to_string(),
+            register_name: register.to_string(),
+            value: 0.0,
+            decoded: 0.0,
+            raw: vec![],
+            text: None,
+            unit: None,
+            quality: Default::default(),
+            changed: true,
+            old_value: None,
+            timestamp: timestamp.to_string(),
+        };
+        let mut sequencer = TopicSequencer::default();
+
+        assert_eq!(
+            sequencer.next(&update("a", "2024-01-01T00:00:01Z")),
+            Some(1)
+        );
+        assert_eq!(
+            sequencer.next(&update("a", "2024-01-01T00:00:02Z")),
+            Some(2)
+        );
+        // Each topic has its own sequence
+        assert_eq!(
+            sequencer.next(&update("b", "2024-01-01T00:00:00Z")),
+            Some(1)
+        );
+        // An older reading arriving late is dropped without using a number
+        assert_eq!(sequencer.next(&update("a", "2024-01-01T00:00:01Z")), None);
+        // Republishing the latest reading keeps its timestamp
+        assert_eq!(
+            sequencer.next(&update("a", "2024-01-01T00:00:02Z")),
+            Some(3)
+        );
+        assert_eq!(
+            sequencer.next(&update("a", "2024-01-01T02:00:01+02:00")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bool_formats() {
+        use crate::config::{BoolPreset, DeviceBuilder, RegisterBuilder};
+
+        let devices = vec![DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+            .register(RegisterBuilder::coil("pump", 0))
+            .register(
+                RegisterBuilder::coil("valve", 1).bool_format(BoolFormat::Custom {
+                    on: "OPEN".to_string(),
+                    off: "CLOSED".to_string(),
+                }),
+            )
+            .register(RegisterBuilder::holding("speed", 0))
+            .build()];
+        let formats = BoolFormats::new(&BoolFormat::Preset(BoolPreset::OnOff), &devices);
+
+        assert_eq!(formats.value("plc-001", "pump", 1.0.into()), "ON");
+        assert_eq!(formats.value("plc-001", "valve", 0.0.into()), "CLOSED");
+        assert_eq!(formats.value("plc-001", "speed", 1.0.into()), 1.0);
+        assert_eq!(formats.value("other", "pump", 1.0.into()), 1.0);
+        assert_eq!(BoolFormat::default().to_json(true), 1);
+        assert_eq!(BoolFormat::Preset(BoolPreset::Bool).to_json(false), false);
+
+        // The birth message announces the encoding of each boolean register
+        let json = StatusPayloadConfig {
+            format: StatusPayloadFormat::Json,
+            ..StatusPayloadConfig::default()
+        };
+        let ts = "2025-12-27T10:30:00+00:00";
+        let describe = formats.describe("plc-001");
+        let online: serde_json::Value =
+            serde_json::from_str(&status_payload(&json, "gw", "plc-001", true, ts, &describe))
+                .unwrap();
+        assert_eq!(online["bool_format"]["pump"], "on_off");
+        assert_eq!(online["bool_format"]["valve"]["on"], "OPEN");
+        assert!(online["bool_format"].get("speed").is_none());
+        let offline: serde_json::Value = serde_json::from_str(&status_payload(
+            &json, "gw", "plc-001", false, ts, &describe,
+        ))
+        .unwrap();
+        assert!(offline.get("bool_format").is_none());
+    }
 }
@@ -0,0 +1,470 @@
+//! Register history in a local SQLite database
+//!
+//! Every register update is recorded as a row of the `samples` table, so
+//! the history survives restarts and outlasts what the in-memory history
+//! can hold. Updates are collected into batches, each written with one
+//! transaction when it is full (`batch_size`) or `flush_interval_ms` after
+//! its first update, whichever comes first. Rows older than
+//! `retention_hours` are deleted every [`PRUNE_INTERVAL`].
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+use crate::api::RegisterUpdate;
+use crate::config::StorageConfig;
+use crate::metrics;
+use crate::modbus::history::{Sample, SampleRange};
+use crate::modbus::reader::Quality;
+
+/// How often rows beyond the retention period are deleted
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timestamps are stored as milliseconds since the Unix epoch
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS samples (
+    device_id TEXT NOT NULL,
+    register TEXT NOT NULL,
+    value,
+    raw TEXT NOT NULL,
+    unit TEXT,
+    quality TEXT NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_by_register ON samples (device_id, register, timestamp);
+CREATE INDEX IF NOT EXISTS samples_by_time ON samples (timestamp);
+";
+
+/// The history database, shared by the recorder and the API
+#[derive(Clone)]
+pub struct Storage {
+    connection: Arc<Mutex<Connection>>,
+    /// Rows in the table, counted on open and kept up to date by inserts
+    /// and pruning, so reporting it does not scan the table
+    rows: Arc<AtomicUsize>,
+    retention: chrono::Duration,
+    flush_interval: Duration,
+    batch_size: usize,
+}
+
+impl Storage {
+    /// Open the configured database, creating it and its table if missing
+    pub fn open(config: &StorageConfig) -> Result<Self> {
+        let connection = Connection::open(&config.path)
+            .with_context(|| format!("Failed to open history database {}", config.path))?;
+        // Readers are not blocked by the recorder's transactions
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        connection.execute_batch(SCHEMA)?;
+        let rows: i64 =
+            connection.query_row("SELECT COUNT(*) FROM samples", [], |row| row.get(0))?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            rows: Arc::new(AtomicUsize::new(rows as usize)),
+            retention: chrono::Duration::hours(config.retention_hours as i64),
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+            batch_size: config.batch_size,
+        })
+    }
+
+    /// Insert updates with one transaction, returning the rows written
+    ///
+    /// Updates without a valid timestamp are left out.
+    pub fn insert(&self, updates: &[RegisterUpdate]) -> Result<usize> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut rows = 0;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO samples (device_id, register, value, raw, unit, quality, timestamp)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for update in updates {
+                let Ok(timestamp) = DateTime::parse_from_rfc3339(&update.timestamp) else {
+                    warn!(
+                        "Not storing {}/{}: invalid timestamp {}",
+                        update.device_id, update.register_name, update.timestamp
+                    );
+                    continue;
+                };
+                let value = match &update.text {
+                    Some(text) => Value::Text(text.clone()),
+                    None if update.value.is_finite() => Value::Real(update.value),
+                    None => Value::Null,
+                };
+                rows += insert.execute(params![
+                    update.device_id,
+                    update.register_name,
+                    value,
+                    serde_json::to_string(&update.raw)?,
+                    update.unit,
+                    quality_name(update.quality),
+                    timestamp.timestamp_millis(),
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+        Ok(rows)
+    }
+
+    /// Delete rows older than the retention period, returning how many
+    pub fn prune(&self, now: DateTime<Utc>) -> Result<usize> {
+        let cutoff = (now - self.retention).timestamp_millis();
+        let connection = self.connection.lock().unwrap();
+        let deleted = connection.execute("DELETE FROM samples WHERE timestamp < ?1", [cutoff])?;
+        self.rows.fetch_sub(deleted, Ordering::Relaxed);
+        Ok(deleted)
+    }
+
+    /// A register's samples within `range`, oldest first
+    pub fn query(
+        &self,
+        device_id: &str,
+        register_name: &str,
+        range: &SampleRange,
+    ) -> Result<Vec<Sample>> {
+        let from = range.from.map_or(i64::MIN, |from| from.timestamp_millis());
+        let to = range.to.map_or(i64::MAX, |to| to.timestamp_millis());
+        // A negative limit means none
+        let limit = range.limit.map_or(-1, |limit| limit as i64);
+
+        let connection = self.connection.lock().unwrap();
+        // The most recent samples are selected, then put back in order
+        let mut select = connection.prepare_cached(
+            "SELECT value, quality, timestamp FROM samples
+             WHERE device_id = ?1 AND register = ?2 AND timestamp >= ?3 AND timestamp <= ?4
+             ORDER BY timestamp DESC, rowid DESC LIMIT ?5",
+        )?;
+        let mut samples = select
+            .query_map(params![device_id, register_name, from, to, limit], |row| {
+                let value = match row.get::<_, Value>(0)? {
+                    Value::Text(text) => serde_json::Value::String(text),
+                    Value::Real(value) => serde_json::json!(value),
+                    Value::Integer(value) => serde_json::json!(value),
+                    _ => serde_json::Value::Null,
+                };
+                let quality: String = row.get(1)?;
+                let timestamp = DateTime::from_timestamp_millis(row.get(2)?).unwrap_or_default();
+                Ok(Sample::new(value, parse_quality(&quality), timestamp))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        samples.reverse();
+        Ok(samples)
+    }
+
+    /// Rows held across all registers
+    pub fn sample_count(&self) -> usize {
+        self.rows.load(Ordering::Relaxed)
+    }
+
+    /// Run a database call without blocking the async runtime
+    pub async fn blocking<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Storage) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let storage = self.clone();
+        tokio::task::spawn_blocking(move || call(&storage)).await?
+    }
+
+    /// Record updates from the broadcast channel until `stop` resolves,
+    /// then write the updates still waiting
+    pub async fn start_recording(
+        &self,
+        mut update_rx: broadcast::Receiver<RegisterUpdate>,
+        stop: impl Future<Output = ()>,
+    ) {
+        info!("History recorder started");
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let mut deadline: Option<Instant> = None;
+        let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+        prune.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        tokio::pin!(stop);
+
+        loop {
+            let received = tokio::select! {
+                received = update_rx.recv() => received,
+                _ = sleep_until(deadline) => {
+                    deadline = None;
+                    self.write(std::mem::take(&mut batch)).await;
+                    continue;
+                }
+                _ = prune.tick() => {
+                    self.prune_expired().await;
+                    continue;
+                }
+                _ = &mut stop => break,
+            };
+            match received {
+                Ok(update) => {
+                    if batch.is_empty() {
+                        deadline = Some(Instant::now() + self.flush_interval);
+                    }
+                    batch.push(update);
+                    if batch.len() >= self.batch_size {
+                        deadline = None;
+                        self.write(std::mem::take(&mut batch)).await;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("History recorder lagged, missed {} updates", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write(batch).await;
+        }
+        info!("History recorder stopped");
+    }
+
+    /// Write one batch; a batch the database does not accept is dropped
+    async fn write(&self, batch: Vec<RegisterUpdate>) {
+        let updates = batch.len();
+        match self.blocking(move |storage| storage.insert(&batch)).await {
+            Ok(rows) => {
+                debug!("Stored {} register updates", rows);
+                metrics::record_storage_write(rows, true);
+            }
+            Err(e) => {
+                error!("Failed to store {} register updates: {:#}", updates, e);
+                metrics::record_storage_write(updates, false);
+            }
+        }
+    }
+
+    async fn prune_expired(&self) {
+        match self.blocking(|storage| storage.prune(Utc::now())).await {
+            Ok(0) => {}
+            Ok(rows) => debug!("Deleted {} stored samples past retention", rows),
+            Err(e) => error!("Failed to delete expired samples: {:#}", e),
+        }
+    }
+}
+
+/// Sleep until `deadline`, forever if there is none
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn quality_name(quality: Quality) -> String {
+    serde_json::to_value(quality)
+        .ok()
+        .and_then(|quality| quality.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn parse_quality(name: &str) -> Quality {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).unwrap_or(Quality::Bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(retention_hours: u64) -> Storage {
+        Storage::open(&StorageConfig {
+            enabled: true,
+            path: ":memory:".to_string(),
+            retention_hours,
+            ..StorageConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn update(register_name: &str, value: f64, timestamp: &str) -> RegisterUpdate {
+        RegisterUpdate {
+            device_id: "plc-001".to_string(),
+            register_name: register_name.to_string(),
+            value,
+            decoded: value,
+            raw: vec![value as u16],
+            text: None,
+            unit: Some("°C".to_string()),
+            quality: Quality::Good,
+            changed: true,
+            old_value: None,
+            timestamp: timestamp.to_string(),
+        }
+    }
+
+    fn values(samples: &[Sample]) -> Vec<serde_json::Value> {
+        samples.iter().map(|sample| sample.value.clone()).collect()
+    }
+
+    #[test]
+    fn test_insert_and_query_range() {
+        let storage = storage(24);
+        let mut serial = update("serial", 0.0, "2024-01-15T10:00:02Z");
+        serial.text = Some("SN-42".to_string());
+        serial.quality = Quality::Uncertain;
+        let rows = storage
+            .insert(&[
+                update("temperature", 20.0, "2024-01-15T10:00:00Z"),
+                update("temperature", 21.0, "2024-01-15T10:00:01Z"),
+                update("temperature", 22.0, "2024-01-15T10:00:02Z"),
+                update("temperature", 23.0, "not a timestamp"),
+                update("pressure", 1.5, "2024-01-15T10:00:01Z"),
+                serial,
+            ])
+            .unwrap();
+        assert_eq!(rows, 5);
+        assert_eq!(storage.sample_count(), 5);
+
+        let all = storage
+            .query("plc-001", "temperature", &SampleRange::default())
+            .unwrap();
+        assert_eq!(values(&all), vec![20.0, 21.0, 22.0]);
+        assert_eq!(
+            all[0].timestamp,
+            "2024-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+
+        let at = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        let range = SampleRange {
+            from: at("2024-01-15T10:00:01Z"),
+            to: at("2024-01-15T10:00:02Z"),
+            limit: None,
+        };
+        assert_eq!(
+            values(&storage.query("plc-001", "temperature", &range).unwrap()),
+            vec![21.0, 22.0]
+        );
+
+        // The limit keeps the most recent samples
+        let range = SampleRange {
+            limit: Some(2),
+            ..SampleRange::default()
+        };
+        assert_eq!(
+            values(&storage.query("plc-001", "temperature", &range).unwrap()),
+            vec![21.0, 22.0]
+        );
+
+        let serial = storage
+            .query("plc-001", "serial", &SampleRange::default())
+            .unwrap();
+        assert_eq!(values(&serial), vec!["SN-42"]);
+        assert_eq!(serial[0].quality, Quality::Uncertain);
+        assert!(storage
+            .query("plc-002", "temperature", &SampleRange::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_prune_deletes_rows_past_retention() {
+        let storage = storage(1);
+        let now = Utc::now();
+        let old = (now - chrono::Duration::minutes(90)).to_rfc3339();
+        let recent = (now - chrono::Duration::minutes(30)).to_rfc3339();
+        storage
+            .insert(&[
+                update("temperature", 20.0, &old),
+                update("temperature", 21.0, &recent),
+                update("pressure", 1.0, &old),
+            ])
+            .unwrap();
+
+        assert_eq!(storage.prune(now).unwrap(), 2);
+        assert_eq!(storage.sample_count(), 1);
+        assert_eq!(
+            values(
+                &storage
+                    .query("plc-001", "temperature", &SampleRange::default())
+                    .unwrap()
+            ),
+            vec![21.0]
+        );
+        assert_eq!(storage.prune(now).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sample_count_includes_rows_of_earlier_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            enabled: true,
+            path: dir.path().join("history.db").display().to_string(),
+            ..StorageConfig::default()
+        };
+        let now = Utc::now().to_rfc3339();
+        Storage::open(&config)
+            .unwrap()
+            .insert(&[
+                update("temperature", 20.0, &now),
+                update("pressure", 1.0, &now),
+            ])
+            .unwrap();
+
+        let storage = Storage::open(&config).unwrap();
+        assert_eq!(storage.sample_count(), 2);
+        storage
+            .insert(&[update("temperature", 21.0, &now)])
+            .unwrap();
+        assert_eq!(storage.sample_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_recorder_writes_batches() {
+        let storage = Storage::open(&StorageConfig {
+            enabled: true,
+            path: ":memory:".to_string(),
+            flush_interval_ms: 200,
+            batch_size: 2,
+            ..StorageConfig::default()
+        })
+        .unwrap();
+        let (update_tx, update_rx) = broadcast::channel(16);
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let recorder = storage.clone();
+        let running = tokio::spawn(async move {
+            recorder
+                .start_recording(update_rx, async {
+                    let _ = stop_rx.await;
+                })
+                .await
+        });
+        let stored = |n: usize| {
+            let storage = storage.clone();
+            async move {
+                for _ in 0..100 {
+                    if storage.sample_count() >= n {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                panic!("expected {} stored samples", n);
+            }
+        };
+        let now = || Utc::now().to_rfc3339();
+
+        // A full batch is written right away
+        let started = Instant::now();
+        update_tx.send(update("a", 1.0, &now())).unwrap();
+        update_tx.send(update("b", 2.0, &now())).unwrap();
+        stored(2).await;
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        // A partial one once it is due
+        update_tx.send(update("c", 3.0, &now())).unwrap();
+        stored(3).await;
+
+        // And what is left when stopping
+        update_tx.send(update("d", 4.0, &now())).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(storage.sample_count(), 3);
+        stop_tx.send(()).unwrap();
+        running.await.unwrap();
+        assert_eq!(storage.sample_count(), 4);
+    }
+}
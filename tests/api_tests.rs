@@ -12,9 +12,11 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower::ServiceExt;
 
-use rustbridge::api::{create_router, ApiState};
-use rustbridge::config::AuthConfig;
-use rustbridge::modbus::reader::{RegisterStore, RegisterValue};
+use rustbridge::api::{create_router, ApiState, RegisterUpdate, WriteRequest};
+use rustbridge::config::{AuthConfig, AuthMode, Config, RawFormat, RegisterType, StorageConfig};
+use rustbridge::modbus::identification::DeviceInfo;
+use rustbridge::modbus::reader::{Quality, RegisterStore, RegisterValue};
+use rustbridge::storage::Storage;
 
 /// Helper to create a disabled auth config for tests
 fn disabled_auth() -> AuthConfig {
@@ -22,6 +24,7 @@ fn disabled_auth() -> AuthConfig {
         enabled: false,
         api_keys: vec![],
         exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
+        ..AuthConfig::default()
     }
 }
 
@@ -43,8 +46,11 @@ async fn populate_test_data(state: &ApiState) {
         RegisterValue {
             name: "temperature".to_string(),
             raw: vec![250],
+            decoded: 250.0,
             value: 25.0,
             unit: Some("°C".to_string()),
+            text: None,
+            quality: Quality::Good,
             timestamp: chrono::Utc::now(),
         },
     );
@@ -53,8 +59,11 @@ async fn populate_test_data(state: &ApiState) {
         RegisterValue {
             name: "humidity".to_string(),
             raw: vec![650],
+            decoded: 650.0,
             value: 65.0,
             unit: Some("%".to_string()),
+            text: None,
+            quality: Quality::Good,
             timestamp: chrono::Utc::now(),
         },
     );
@@ -67,14 +76,56 @@ async fn populate_test_data(state: &ApiState) {
         RegisterValue {
             name: "pressure".to_string(),
             raw: vec![1000],
+            decoded: 1000.0,
             value: 10.0,
             unit: Some("bar".to_string()),
+            text: None,
+            quality: Quality::Good,
             timestamp: chrono::Utc::now(),
         },
     );
     store.insert("sensor-001".to_string(), device2_registers);
 }
 
+/// Helper to configure the test data's registers: writable holding
+/// registers and a coil on `plc-001` and a read-only input register on
+/// `sensor-001`
+fn writable_test_config() -> Config {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("temperature", 100).writable(true))
+                .register(RegisterBuilder::holding("humidity", 101).writable(true))
+                .register(RegisterBuilder::coil("pump", 5).writable(true)),
+        )
+        .device(
+            DeviceBuilder::tcp("sensor-001", "localhost", 503, 1)
+                .register(RegisterBuilder::input("pressure", 200).writable(true)),
+        )
+        .build()
+        .unwrap()
+}
+
+/// Helper to add the `pump` coil of `writable_test_config` to the store
+async fn populate_coil(state: &ApiState) {
+    let mut store = state.register_store.write().await;
+    store.entry("plc-001".to_string()).or_default().insert(
+        "pump".to_string(),
+        RegisterValue {
+            name: "pump".to_string(),
+            raw: vec![0],
+            decoded: 0.0,
+            value: 0.0,
+            unit: None,
+            text: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        },
+    );
+}
+
 /// Helper to make a GET request and get response body as JSON
 async fn get_json(app: axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
     let response = app
@@ -130,6 +181,80 @@ async fn test_health_endpoint() {
     assert!(json["version"].is_string());
 }
 
+#[tokio::test]
+async fn test_health_detailed_reports_mqtt() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // MQTT disabled: nothing to report
+    let app = create_router(create_test_state(), disabled_auth());
+    let (status, json) = get_json(app, "/api/health/detailed").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(json["mqtt_connected"].is_null());
+
+    // Broker down but not required: still healthy
+    let connected = Arc::new(AtomicBool::new(false));
+    let state = create_test_state().with_mqtt_status(connected.clone());
+    let app = create_router(state, disabled_auth());
+    let (status, json) = get_json(app, "/api/health/detailed").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["mqtt_connected"], false);
+
+    // Broker required: unhealthy until it connects
+    let mut config = Config::default();
+    config.mqtt.enabled = true;
+    config.mqtt.required = true;
+    let state = create_test_state()
+        .with_config(config)
+        .with_mqtt_status(connected.clone());
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = get_json(app.clone(), "/health").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["status"], "unhealthy");
+
+    connected.store(true, Ordering::SeqCst);
+    let (status, json) = get_json(app, "/api/health/detailed").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["mqtt_connected"], true);
+    assert_eq!(json["mqtt_required"], true);
+}
+
+#[tokio::test]
+async fn test_ready_after_first_poll_cycles() {
+    use rustbridge::api::Readiness;
+    use std::time::Duration;
+
+    let (first_cycle_tx, first_cycle_rx) = tokio::sync::watch::channel(false);
+    let state = create_test_state().with_readiness(Readiness::new(
+        vec![first_cycle_rx.clone()],
+        Duration::from_secs(60),
+    ));
+    let app = create_router(state, disabled_auth());
+
+    // Alive, but not ready for traffic yet
+    let (status, json) = get_json(app.clone(), "/health").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["status"], "starting");
+    let (status, json) = get_json(app.clone(), "/ready").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["status"], "starting");
+
+    first_cycle_tx.send_replace(true);
+    let (status, json) = get_json(app.clone(), "/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["status"], "ready");
+    let (_, json) = get_json(app, "/health").await;
+    assert_eq!(json["status"], "ok");
+
+    // Once the grace period is over, devices not read yet don't hold it up
+    let (_tx, never_read) = tokio::sync::watch::channel(false);
+    let state =
+        create_test_state().with_readiness(Readiness::new(vec![never_read], Duration::ZERO));
+    let app = create_router(state, disabled_auth());
+    let (status, _) = get_json(app, "/ready").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_health_version_format() {
     let state = create_test_state();
@@ -174,16 +299,371 @@ async fn test_api_info_endpoint() {
     assert!(json["version"].is_string());
     assert!(json["description"].is_string());
     assert!(json["endpoints"].is_array());
+    assert!(!json["gateway_id"].as_str().unwrap().is_empty());
 
     // Verify endpoints list contains expected entries
     let endpoints = json["endpoints"].as_array().unwrap();
     assert!(endpoints.len() >= 8); // At least 8 endpoints defined
 }
 
+#[tokio::test]
+async fn test_api_info_reports_gateway_id() {
+    let config = Config {
+        gateway_id: "plant-a.gw01".to_string(),
+        ..Config::default()
+    };
+    let state = create_test_state().with_config(config);
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = get_json(app, "/api/info").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["gateway_id"], "plant-a.gw01");
+}
+
+#[tokio::test]
+async fn test_snapshot_writes_register_store() {
+    // Disabled unless a path is configured
+    let (status, json) = post_json(
+        create_router(create_test_state(), disabled_auth()),
+        "/api/snapshot",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "Snapshots not enabled");
+
+    let path = std::env::temp_dir().join(format!(
+        "rustbridge-api-snapshot-{}.json",
+        std::process::id()
+    ));
+    let mut config = Config::default();
+    config.server.snapshot_path = Some(path.display().to_string());
+    let state = create_test_state().with_config(config);
+    populate_test_data(&state).await;
+
+    let (status, json) = post_json(
+        create_router(state, disabled_auth()),
+        "/api/snapshot",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["devices"], 2);
+    assert_eq!(json["registers"], 3);
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(json["bytes"], content.len() as u64);
+    let snapshot: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(
+        snapshot["devices"]["plc-001"]["temperature"]["quality"],
+        "good"
+    );
+}
+
+#[tokio::test]
+async fn test_register_history_endpoint() {
+    let (status, json) = get_json(
+        create_router(create_test_state(), disabled_auth()),
+        "/api/devices/plc-001/registers/temperature/history",
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "History not enabled");
+
+    let mut config = Config::default();
+    config.history.enabled = true;
+    config.history.max_samples = 2;
+    let state = create_test_state().with_config(config);
+    for value in [20.0, 21.0, 22.0] {
+        let reading = RegisterValue {
+            name: "temperature".to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: chrono::Utc::now(),
+        };
+        state.history.record("plc-001", &reading);
+    }
+
+    let (status, json) = get_json(
+        create_router(state, disabled_auth()),
+        "/api/devices/plc-001/registers/temperature/history",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["samples"][0]["value"], 21.0);
+    assert_eq!(json["samples"][1]["value"], 22.0);
+    assert_eq!(json["total_samples"], 2);
+}
+
+#[tokio::test]
+async fn test_register_history_range() {
+    let mut config = Config::default();
+    config.history.enabled = true;
+    let state = create_test_state().with_config(config);
+    let start = chrono::DateTime::parse_from_rfc3339("2025-12-27T10:00:00Z")
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+    for (secs, value) in [(0, 20.0), (60, 21.0), (120, 22.0), (180, 23.0)] {
+        let reading = RegisterValue {
+            name: "temperature".to_string(),
+            raw: vec![value as u16],
+            decoded: value,
+            value,
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            timestamp: start + chrono::Duration::seconds(secs),
+        };
+        state.history.record("plc-001", &reading);
+    }
+
+    let (status, json) = get_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/devices/plc-001/registers/temperature/history\
+         ?from=2025-12-27T10:01:00Z&to=2025-12-27T10:03:00Z&limit=2",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let samples = json["samples"].as_array().unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0]["value"], 22.0);
+    assert_eq!(samples[1]["value"], 23.0);
+
+    let (status, _) = get_json(
+        create_router(state, disabled_auth()),
+        "/api/devices/plc-001/registers/temperature/history\
+         ?from=2025-12-27T10:03:00Z&to=2025-12-27T10:01:00Z",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_register_history_from_storage() {
+    let storage = Storage::open(&StorageConfig {
+        enabled: true,
+        path: ":memory:".to_string(),
+        ..StorageConfig::default()
+    })
+    .unwrap();
+    let now = chrono::Utc::now();
+    let updates: Vec<RegisterUpdate> = [(120, 20.0), (60, 21.0), (0, 22.0)]
+        .into_iter()
+        .map(|(age, value)| RegisterUpdate {
+            device_id: "plc-001".to_string(),
+            register_name: "temperature".to_string(),
+            value,
+            decoded: value,
+            raw: vec![value as u16],
+            text: None,
+            unit: None,
+            quality: Quality::Good,
+            changed: true,
+            old_value: None,
+            timestamp: (now - chrono::Duration::seconds(age)).to_rfc3339(),
+        })
+        .collect();
+    storage.insert(&updates).unwrap();
+
+    // Served without the in-memory history
+    let state = create_test_state().with_storage(storage);
+    let (status, json) = get_json(
+        create_router(state, disabled_auth()),
+        "/api/devices/plc-001/registers/temperature/history?limit=2",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let samples = json["samples"].as_array().unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0]["value"], 21.0);
+    assert_eq!(samples[1]["value"], 22.0);
+    assert_eq!(json["total_samples"], 3);
+}
+
+#[tokio::test]
+async fn test_reload_status_reports_history() {
+    let state = create_test_state();
+    let (status, json) = get_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/reload/status",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["reloads"], 0);
+    assert!(json["last_reload"].is_null());
+
+    let config = Config {
+        gateway_id: "gw-reloaded".to_string(),
+        ..Default::default()
+    };
+    state.apply_reload(Ok(config)).await;
+    state
+        .apply_reload(Err(anyhow::anyhow!("port must be > 0")))
+        .await;
+    assert_eq!(state.config.read().await.gateway_id, "gw-reloaded");
+
+    let (_, json) = get_json(create_router(state, disabled_auth()), "/api/reload/status").await;
+    assert_eq!(json["reloads"], 2);
+    assert_eq!(json["failures"], 1);
+    assert_eq!(json["last_success"], false);
+    assert_eq!(json["last_error"], "port must be > 0");
+    assert!(json["last_reload"].is_string());
+}
+
+#[tokio::test]
+async fn test_reload_endpoint_reads_config_file() {
+    // Not available without a configuration file
+    let (status, json) = post_json(
+        create_router(create_test_state(), disabled_auth()),
+        "/api/reload",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "Reload not available");
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let config = Config {
+        gateway_id: "gw-reloaded".to_string(),
+        ..writable_test_config()
+    };
+    std::fs::write(file.path(), serde_yaml::to_string(&config).unwrap()).unwrap();
+    let state = create_test_state()
+        .with_config(writable_test_config())
+        .with_config_path(file.path().display().to_string());
+
+    let (status, json) = post_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/reload",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["reloads"], 1);
+    assert_eq!(json["last_success"], true);
+    assert_eq!(state.config.read().await.gateway_id, "gw-reloaded");
+
+    // A file that does not load keeps the running configuration
+    std::fs::write(file.path(), "devices: [").unwrap();
+    let (status, json) = post_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/reload",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json["error"], "Configuration reload failed");
+    assert_eq!(state.config.read().await.gateway_id, "gw-reloaded");
+
+    let (_, json) = get_json(create_router(state, disabled_auth()), "/api/reload/status").await;
+    assert_eq!(json["reloads"], 2);
+    assert_eq!(json["failures"], 1);
+    assert_eq!(json["last_success"], false);
+    assert!(json["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("Failed to parse config file"));
+}
+
+#[tokio::test]
+async fn test_reload_evicts_removed_devices_and_registers() {
+    let mut config = Config::default();
+    config.history.enabled = true;
+    config.devices = writable_test_config().devices;
+    let mut reloaded = config.clone();
+    reloaded.devices.retain(|d| d.id == "plc-001");
+    reloaded.devices[0]
+        .registers
+        .retain(|r| r.name != "humidity");
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), serde_yaml::to_string(&reloaded).unwrap()).unwrap();
+
+    let state = create_test_state()
+        .with_config(config)
+        .with_config_path(file.path().display().to_string());
+    populate_test_data(&state).await;
+    for value in state.register_store.read().await["plc-001"].values() {
+        state.history.record("plc-001", value);
+    }
+    let app = create_router(state.clone(), disabled_auth());
+
+    let (status, _) = post_json(app.clone(), "/api/reload", serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, json) = get_json(app.clone(), "/api/devices/plc-001/registers").await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["temperature"]);
+    assert!(state.history.get("plc-001", "humidity").is_empty());
+    assert_eq!(state.history.get("plc-001", "temperature").len(), 1);
+
+    let (status, _) = get_json(app, "/api/devices/sensor-001").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_reload_rejects_device_changes() {
+    let config = writable_test_config();
+    let mut reloaded = config.clone();
+    reloaded.devices[0].registers[0].address = 110;
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), serde_yaml::to_string(&reloaded).unwrap()).unwrap();
+
+    let state = create_test_state()
+        .with_config(config)
+        .with_config_path(file.path().display().to_string());
+    let (status, json) = post_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/reload",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["details"]
+        .as_str()
+        .unwrap()
+        .contains("Device plc-001 differs from the polled one"));
+    // Writes keep addressing the register where the worker polls it
+    assert_eq!(
+        state.config.read().await.devices[0].registers[0].address,
+        100
+    );
+}
+
 // ============================================================================
 // Device Endpoint Tests
 // ============================================================================
 
+#[tokio::test]
+async fn test_gateway_stats_endpoint() {
+    let state = create_test_state();
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = get_json(app, "/api/stats").await;
+
+    assert_eq!(status, StatusCode::OK);
+    for field in [
+        "uptime_secs",
+        "devices_total",
+        "devices_online",
+        "reads_total",
+        "read_errors_total",
+    ] {
+        assert!(json[field].is_u64(), "missing {}", field);
+    }
+}
+
 #[tokio::test]
 async fn test_list_devices_empty() {
     let state = create_test_state();
@@ -233,6 +713,38 @@ async fn test_get_device_found() {
     assert_eq!(registers.len(), 2);
 }
 
+#[tokio::test]
+async fn test_get_device_reports_device_info() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    state.device_info.write().await.insert(
+        "plc-001".to_string(),
+        DeviceInfo {
+            vendor_name: Some("Acme AG".to_string()),
+            product_code: Some("PM-3".to_string()),
+            revision: None,
+        },
+    );
+
+    let (status, json) = get_json(
+        create_router(state.clone(), disabled_auth()),
+        "/api/devices/plc-001",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["device_info"]["vendor_name"], "Acme AG");
+    assert_eq!(json["device_info"]["product_code"], "PM-3");
+    assert!(json["device_info"].get("revision").is_none());
+
+    // Devices without identification omit the field
+    let (_, json) = get_json(
+        create_router(state, disabled_auth()),
+        "/api/devices/sensor-001",
+    )
+    .await;
+    assert!(json.get("device_info").is_none());
+}
+
 #[tokio::test]
 async fn test_get_device_not_found() {
     let state = create_test_state();
@@ -301,6 +813,59 @@ async fn test_device_has_last_update() {
     }
 }
 
+#[tokio::test]
+async fn test_pause_and_resume_device() {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    let config = ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("pause-plc", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("temperature", 0)),
+        )
+        .build()
+        .unwrap();
+    let state = create_test_state().with_config(config);
+    let paused = state.paused.clone();
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/pause-plc/pause",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["paused"], true);
+    assert_eq!(json["changed"], true);
+    assert!(paused.is_paused("pause-plc"));
+
+    let (_, json) = get_json(app.clone(), "/api/health/detailed").await;
+    assert_eq!(json["device_health"]["pause-plc"]["paused"], true);
+
+    // Pausing again is harmless
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/pause-plc/pause",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["changed"], false);
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/pause-plc/resume",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["paused"], false);
+    assert!(!paused.is_paused("pause-plc"));
+
+    let (status, _) = post_json(app, "/api/devices/unknown/pause", serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
 // ============================================================================
 // Register Endpoint Tests
 // ============================================================================
@@ -327,6 +892,56 @@ async fn test_get_registers() {
     }
 }
 
+#[tokio::test]
+async fn test_batch_read_registers() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+    let uri = "/api/devices/plc-001/registers";
+
+    // All found, in the requested order
+    let (status, json) = post_json(
+        app.clone(),
+        uri,
+        serde_json::json!({"names": ["humidity", "temperature"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let registers = json.as_array().unwrap();
+    assert_eq!(registers.len(), 2);
+    assert_eq!(registers[0]["name"], "humidity");
+    assert_eq!(registers[0]["found"], true);
+    assert_eq!(registers[0]["register"]["value"], 65.0);
+    assert_eq!(registers[1]["name"], "temperature");
+    assert_eq!(registers[1]["register"]["unit"], "°C");
+
+    // Missing names are reported, not an error
+    let (status, json) = post_json(
+        app.clone(),
+        uri,
+        serde_json::json!({"names": ["temperature", "nonexistent"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json[0]["found"], true);
+    assert_eq!(json[1]["name"], "nonexistent");
+    assert_eq!(json[1]["found"], false);
+    assert!(json[1].get("register").is_none());
+
+    // Nothing asked, nothing returned
+    let (status, json) = post_json(app.clone(), uri, serde_json::json!({"names": []})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json, serde_json::json!([]));
+
+    let (status, _) = post_json(
+        app,
+        "/api/devices/unknown/registers",
+        serde_json::json!({"names": ["temperature"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn test_get_single_register() {
     let state = create_test_state();
@@ -341,6 +956,35 @@ async fn test_get_single_register() {
     assert_eq!(json["unit"], "°C");
 }
 
+#[tokio::test]
+async fn test_register_name_template() {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder};
+
+    let config = ConfigBuilder::new()
+        .device(DeviceBuilder::tcp("plc-001", "localhost", 502, 1))
+        .register_name_template("{device_type}.{device_id}.{name}")
+        .build()
+        .unwrap();
+    let state = create_test_state().with_config(config);
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // Paths keep the bare name, responses carry the qualified one
+    let (status, json) = get_json(app.clone(), "/api/devices/plc-001/registers/temperature").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["name"], "tcp.plc-001.temperature");
+
+    let (_, json) = get_json(app, "/api/devices/plc-001").await;
+    let mut names: Vec<_> = json["registers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["name"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["tcp.plc-001.humidity", "tcp.plc-001.temperature"]);
+}
+
 #[tokio::test]
 async fn test_get_register_not_found() {
     let state = create_test_state();
@@ -366,6 +1010,20 @@ async fn test_get_register_not_found() {
     assert_eq!(json["code"], 404);
 }
 
+#[tokio::test]
+async fn test_register_decoded_value_separate_from_raw() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = get_json(app, "/api/devices/plc-001/registers/temperature").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["raw"][0], 250);
+    assert_eq!(json["decoded"], 250.0);
+    assert_eq!(json["value"], 25.0);
+}
+
 #[tokio::test]
 async fn test_register_raw_values() {
     let state = create_test_state();
@@ -381,238 +1039,1750 @@ async fn test_register_raw_values() {
     assert_eq!(raw[0], 250);
 }
 
-// ============================================================================
-// Write Register Tests
-// ============================================================================
-
 #[tokio::test]
-async fn test_write_register_device_not_found() {
+async fn test_register_raw_format_hex_per_request() {
     let state = create_test_state();
+    populate_test_data(&state).await;
     let app = create_router(state, disabled_auth());
 
-    let (status, json) = post_json(
-        app,
-        "/api/devices/nonexistent/registers/temperature",
-        serde_json::json!({"value": 100}),
+    let (status, json) = get_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature?raw_format=hex",
     )
     .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["raw"], serde_json::json!(["0x00FA"]));
 
-    assert_eq!(status, StatusCode::NOT_FOUND);
-    assert_eq!(json["error"], "Device not found");
+    let (_, json) = get_json(app, "/api/devices/sensor-001/registers?raw_format=hex").await;
+    assert_eq!(json[0]["raw"], serde_json::json!(["0x03E8"]));
 }
 
 #[tokio::test]
-async fn test_write_register_not_found() {
-    let state = create_test_state();
+async fn test_register_raw_format_from_config() {
+    let config = Config {
+        raw_format: RawFormat::Hex,
+        ..Config::default()
+    };
+    let state = create_test_state().with_config(config);
     populate_test_data(&state).await;
     let app = create_router(state, disabled_auth());
 
-    let (status, json) = post_json(
+    let (_, json) = get_json(app.clone(), "/api/devices/sensor-001").await;
+    assert_eq!(json["registers"][0]["raw"], serde_json::json!(["0x03E8"]));
+
+    // A request can still ask for decimal words
+    let (_, json) = get_json(
         app,
-        "/api/devices/plc-001/registers/nonexistent",
-        serde_json::json!({"value": 100}),
+        "/api/devices/sensor-001/registers/pressure?raw_format=decimal",
+    )
+    .await;
+    assert_eq!(json["raw"], serde_json::json!([1000]));
+}
+
+#[tokio::test]
+async fn test_register_health_reports_success_ratio() {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    let config = ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("temperature", 0))
+                .register(RegisterBuilder::holding("humidity", 1)),
+        )
+        .build()
+        .unwrap();
+    let state = create_test_state().with_config(config);
+    for ok in [true, false, true, true] {
+        state.read_health.record("plc-001", "temperature", ok);
+    }
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = get_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature/health",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["window"], 100);
+    assert_eq!(json["reads"], 4);
+    assert_eq!(json["failures"], 1);
+    assert_eq!(json["success_ratio"], 0.75);
+
+    // Configured but not read yet
+    let (status, json) = get_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/humidity/health",
     )
     .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["reads"], 0);
+    assert!(json["success_ratio"].is_null());
 
+    let (status, _) = get_json(app.clone(), "/api/devices/plc-001/registers/nope/health").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get_json(app, "/api/devices/nope/registers/temperature/health").await;
     assert_eq!(status, StatusCode::NOT_FOUND);
-    assert_eq!(json["error"], "Register not found");
 }
 
 // ============================================================================
-// WebSocket Tests (Basic)
+// Configuration Tests
 // ============================================================================
 
+/// Helper to GET a plain-text body
+async fn get_text(app: axum::Router, uri: &str) -> (StatusCode, String) {
+    let response = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn config_with_secrets() -> Config {
+    let mut config = Config::default();
+    config.mqtt.password = Some("mqtt-secret".to_string());
+    config.auth.api_keys = vec!["api-secret".to_string()];
+    config
+}
+
+#[tokio::test]
+async fn test_config_yaml_round_trips() {
+    let state = create_test_state().with_config(config_with_secrets());
+    let app = create_router(state, disabled_auth());
+
+    let (status, body) = get_text(app, "/api/config.yaml").await;
+
+    assert_eq!(status, StatusCode::OK);
+    let parsed: Config = serde_yaml::from_str(&body).unwrap();
+    assert_eq!(parsed.server.port, 3000);
+    assert_eq!(parsed.mqtt.password, Some("mqtt-secret".to_string()));
+}
+
+#[tokio::test]
+async fn test_config_yaml_redacted() {
+    let state = create_test_state().with_config(config_with_secrets());
+    let app = create_router(state, disabled_auth());
+
+    let (status, body) = get_text(app, "/api/config.yaml?redact=true").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains("mqtt-secret"));
+    assert!(!body.contains("api-secret"));
+    assert!(body.contains("<redacted>"));
+}
+
+#[tokio::test]
+async fn test_config_yaml_requires_auth() {
+    let state = create_test_state().with_config(config_with_secrets());
+    let app = create_router(state, enabled_auth_with_keys(vec!["api-secret"]));
+
+    let (status, _) = get_text(app, "/api/config.yaml").await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ============================================================================
+// Write Register Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_write_register_device_not_found() {
+    let state = create_test_state();
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/nonexistent/registers/temperature",
+        serde_json::json!({"value": 100}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "Device not found");
+}
+
+#[tokio::test]
+async fn test_write_register_not_found() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/nonexistent",
+        serde_json::json!({"value": 100}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "Register not found");
+}
+
+#[tokio::test]
+async fn test_write_register_malformed_body_returns_api_error() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // Missing `value`
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Invalid request body");
+    assert_eq!(json["code"], 400);
+    assert!(json["details"].as_str().unwrap().contains("value"));
+
+    // Not JSON at all
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/devices/plc-001/raw")
+                .header("Content-Type", "application/json")
+                .body(Body::from("{not json"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Invalid request body");
+    assert_eq!(json["code"], 400);
+}
+
+#[tokio::test]
+async fn test_write_register_uses_configured_address() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    for (name, address) in [
+        ("temperature", 100),
+        ("humidity", 101),
+        ("temperature", 100),
+    ] {
+        let worker = tokio::spawn(async move {
+            let request = write_rx.recv().await.unwrap();
+            let address = request.address;
+            let _ = request.response_tx.send(Ok(()));
+            (write_rx, address)
+        });
+        let (status, _) = post_json(
+            app.clone(),
+            &format!("/api/devices/plc-001/registers/{}", name),
+            serde_json::json!({"value": 7}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (rx, forwarded) = worker.await.unwrap();
+        assert_eq!(forwarded, address, "{}", name);
+        write_rx = rx;
+    }
+
+    // Input registers are read-only in Modbus, even if marked writable
+    let (status, json) = post_json(
+        app,
+        "/api/devices/sensor-001/registers/pressure",
+        serde_json::json!({"value": 7}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Register is read-only");
+    assert!(write_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_write_register_encodes_data_type() {
+    use rustbridge::config::{ConfigBuilder, DataType, DeviceBuilder, RegisterBuilder, WordOrder};
+
+    let config = ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(
+                    RegisterBuilder::holding("temperature", 10)
+                        .data_type(DataType::F32)
+                        .word_order(WordOrder::Little)
+                        .writable(true),
+                )
+                .register(
+                    RegisterBuilder::holding("humidity", 12)
+                        .data_type(DataType::I16)
+                        .writable(true),
+                ),
+        )
+        .build()
+        .unwrap();
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(config);
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // 21.75 = 0x41AE0000, low word first
+    for (name, value, words) in [
+        (
+            "temperature",
+            serde_json::json!(21.75),
+            vec![0x0000, 0x41AE],
+        ),
+        ("humidity", serde_json::json!(-5), vec![0xFFFB]),
+    ] {
+        let worker = tokio::spawn(async move {
+            let request = write_rx.recv().await.unwrap();
+            let values = request.values.clone();
+            let _ = request.response_tx.send(Ok(()));
+            (write_rx, values)
+        });
+        let (status, json) = post_json(
+            app.clone(),
+            &format!("/api/devices/plc-001/registers/{}", name),
+            serde_json::json!({ "value": value }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", json);
+        assert_eq!(json["value_written"], value);
+        assert_eq!(json["raw_written"], serde_json::json!(words));
+        let (rx, forwarded) = worker.await.unwrap();
+        assert_eq!(forwarded, words, "{}", name);
+        write_rx = rx;
+    }
+
+    // Values the data type cannot hold, and masks across two registers
+    for (name, body) in [
+        ("humidity", serde_json::json!({"value": 40000})),
+        ("humidity", serde_json::json!({"value": 1.5})),
+        ("temperature", serde_json::json!({"value": 1, "mask": 1})),
+    ] {
+        let (status, json) = post_json(
+            app.clone(),
+            &format!("/api/devices/plc-001/registers/{}", name),
+            body,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["error"], "Invalid write request");
+    }
+    assert!(write_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_write_register_with_mask_forwards_mask() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // Acknowledge the write like a device worker would, capturing the
+    // address and mask
+    let worker = tokio::spawn(async move {
+        let request = write_rx.recv().await.unwrap();
+        let written = (request.register_type, request.address, request.mask);
+        let _ = request.response_tx.send(Ok(()));
+        written
+    });
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 4, "mask": 6}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["success"], true);
+    assert_eq!(json["mask"], 6);
+    assert_eq!(worker.await.unwrap(), (RegisterType::Holding, 100, Some(6)));
+}
+
+#[tokio::test]
+async fn test_write_coil_sent_as_coil_write() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    populate_coil(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let worker = tokio::spawn(async move {
+        let request = write_rx.recv().await.unwrap();
+        let written = (request.register_type, request.address, request.values);
+        let _ = request.response_tx.send(Ok(()));
+        written
+    });
+
+    let (status, _) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/pump",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(worker.await.unwrap(), (RegisterType::Coil, 5, vec![1]));
+
+    // A coil has no bits to mask
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/pump",
+        serde_json::json!({"value": 1, "mask": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Invalid write request");
+}
+
+#[tokio::test]
+async fn test_write_register_of_unconfigured_device_not_found() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let mut config = writable_test_config();
+    config.devices.retain(|d| d.id != "sensor-001");
+    let state = ApiState::new(register_store, write_tx).with_config(config);
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // The value is known but the configuration has no address to write to
+    let (status, json) = post_json(
+        app,
+        "/api/devices/sensor-001/registers/pressure",
+        serde_json::json!({"value": 7}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "Device not found");
+    assert!(write_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_write_rejected_with_retry_after_when_queue_full() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, _write_rx) = tokio::sync::mpsc::channel(1);
+    let state = ApiState::new(register_store, write_tx.clone()).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // Occupy the only slot; nothing drains the queue
+    let (response_tx, _response_rx) = tokio::sync::oneshot::channel();
+    write_tx
+        .try_send(WriteRequest {
+            device_id: "plc-001".to_string(),
+            register_type: RegisterType::Holding,
+            address: 100,
+            values: vec![1],
+            mask: None,
+            pulse_ms: None,
+            queued_at: std::time::Instant::now(),
+            response_tx,
+        })
+        .unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/devices/plc-001/registers/temperature")
+                .header("Content-Type", "application/json")
+                .body(Body::from(r#"{"value": 2}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Write queue full");
+}
+
+/// Writable test state with `server.write_rate_limit` set, whose writes
+/// all succeed
+fn rate_limited_state(requests_per_second: f64, burst: u32) -> ApiState {
+    use rustbridge::config::WriteRateLimitConfig;
+
+    let mut config = writable_test_config();
+    config.server.write_rate_limit = Some(WriteRateLimitConfig {
+        requests_per_second,
+        burst,
+    });
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel::<WriteRequest>(100);
+    tokio::spawn(async move {
+        while let Some(request) = write_rx.recv().await {
+            let _ = request.response_tx.send(Ok(()));
+        }
+    });
+    ApiState::new(register_store, write_tx).with_config(config)
+}
+
+/// Write to plc-001's temperature with an optional API key
+async fn write_with_key(app: &axum::Router, api_key: Option<&str>) -> axum::response::Response {
+    let mut builder = Request::builder()
+        .method(Method::POST)
+        .uri("/api/devices/plc-001/registers/temperature")
+        .header("Content-Type", "application/json");
+    if let Some(key) = api_key {
+        builder = builder.header("X-API-Key", key);
+    }
+    app.clone()
+        .oneshot(builder.body(Body::from(r#"{"value": 7}"#)).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_write_rate_limit() {
+    let state = rate_limited_state(10.0, 2);
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // The burst goes through, then writes are rejected
+    for _ in 0..2 {
+        assert_eq!(write_with_key(&app, None).await.status(), StatusCode::OK);
+    }
+    let response = write_with_key(&app, None).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Too many writes");
+
+    // Without authentication every client shares the bucket
+    let status = write_with_key(&app, Some("other")).await.status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    // Reads are not limited
+    let (status, _) = get_json(app.clone(), "/api/devices/plc-001/registers/temperature").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A token is back after 100ms
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    assert_eq!(write_with_key(&app, None).await.status(), StatusCode::OK);
+    let status = write_with_key(&app, None).await.status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_write_rate_limit_per_api_key() {
+    let state = rate_limited_state(0.1, 1);
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["key-a", "key-b"]));
+
+    assert_eq!(
+        write_with_key(&app, Some("key-a")).await.status(),
+        StatusCode::OK
+    );
+    let response = write_with_key(&app, Some("key-a")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "10");
+
+    // Each key has its own bucket
+    assert_eq!(
+        write_with_key(&app, Some("key-b")).await.status(),
+        StatusCode::OK
+    );
+    let status = write_with_key(&app, Some("key-b")).await.status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_write_coil_pulse_forwards_duration() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    populate_coil(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let worker = tokio::spawn(async move {
+        let request = write_rx.recv().await.unwrap();
+        let pulse = (request.register_type, request.pulse_ms);
+        let _ = request.response_tx.send(Ok(()));
+        pulse
+    });
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/pump",
+        serde_json::json!({"value": 1, "pulse_ms": 500}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["pulse_ms"], 500);
+    assert_eq!(worker.await.unwrap(), (RegisterType::Coil, Some(500)));
+}
+
+#[tokio::test]
+async fn test_write_coil_pulse_rejects_invalid_duration() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 1, "pulse_ms": 0}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Invalid write request");
+
+    let (status, _) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 1, "pulse_ms": 100, "mask": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_write_pulse_on_holding_register_rejected() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 1, "pulse_ms": 500}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Invalid write request");
+    assert!(write_rx.try_recv().is_err());
+}
+
+// ============================================================================
+// WebSocket Tests (Basic)
+// ============================================================================
+
+#[tokio::test]
+async fn test_websocket_endpoint_exists() {
+    let state = create_test_state();
+    let app = create_router(state, disabled_auth());
+
+    // Test that /ws endpoint exists and responds
+    // Note: Full WebSocket upgrade requires a real WebSocket client
+    // With oneshot(), we just verify the endpoint is routed
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/ws")
+                .header("Upgrade", "websocket")
+                .header("Connection", "upgrade")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .header("Sec-WebSocket-Version", "13")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // With oneshot + hyper, upgrade may return 426 (Upgrade Required)
+    // This confirms the endpoint exists and is trying to upgrade
+    // A real WebSocket client test would get 101 Switching Protocols
+    assert!(
+        response.status() == StatusCode::SWITCHING_PROTOCOLS
+            || response.status() == StatusCode::UPGRADE_REQUIRED,
+        "Expected 101 or 426, got {}",
+        response.status()
+    );
+}
+
+// ============================================================================
+// HTTP Streaming Tests
+// ============================================================================
+
+fn stream_update(device_id: &str, register_name: &str, value: f64) -> RegisterUpdate {
+    RegisterUpdate {
+        device_id: device_id.to_string(),
+        register_name: register_name.to_string(),
+        value,
+        decoded: value,
+        raw: vec![value as u16],
+        text: None,
+        unit: None,
+        quality: Quality::Good,
+        changed: true,
+        old_value: None,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    }
+}
+
+#[tokio::test]
+async fn test_stream_ndjson_filters_updates() {
+    let state = create_test_state();
+    let update_tx = state.update_tx.clone();
+    let app = create_router(state, disabled_auth());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/stream?devices=plc-001&registers=temperature")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    // Filtered out by device, then by register
+    update_tx
+        .send(stream_update("sensor-001", "temperature", 1.0))
+        .unwrap();
+    update_tx
+        .send(stream_update("plc-001", "humidity", 2.0))
+        .unwrap();
+    update_tx
+        .send(stream_update("plc-001", "temperature", 3.0))
+        .unwrap();
+
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap();
+    let line = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+    assert!(line.ends_with('\n'));
+    let json: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+    assert_eq!(json["device_id"], "plc-001");
+    assert_eq!(json["register_name"], "temperature");
+    assert_eq!(json["value"], 3.0);
+
+    // The subscription ends with the response body
+    drop(body);
+    assert_eq!(update_tx.receiver_count(), 0);
+}
+
+#[tokio::test]
+async fn test_stream_ndjson_several_devices() {
+    let state = create_test_state();
+    let update_tx = state.update_tx.clone();
+    let app = create_router(state, disabled_auth());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/stream?devices=plc-001,%20sensor-002")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for (device_id, value) in [
+        ("plc-001", 1.0),
+        ("other-003", 2.0),
+        ("sensor-002", 3.0),
+        ("plc-001", 4.0),
+    ] {
+        update_tx
+            .send(stream_update(device_id, "temperature", value))
+            .unwrap();
+    }
+
+    // Lines may arrive in one frame or several
+    let mut body = response.into_body();
+    let mut text = String::new();
+    while text.matches('\n').count() < 3 {
+        let frame = body.frame().await.unwrap().unwrap();
+        text.push_str(std::str::from_utf8(&frame.into_data().unwrap()).unwrap());
+    }
+    let lines: Vec<(String, f64)> = text
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                json["device_id"].as_str().unwrap().to_string(),
+                json["value"].as_f64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ("plc-001".to_string(), 1.0),
+            ("sensor-002".to_string(), 3.0),
+            ("plc-001".to_string(), 4.0),
+        ]
+    );
+}
+
 #[tokio::test]
-async fn test_websocket_endpoint_exists() {
+async fn test_stream_ends_at_max_lifetime() {
+    let mut config = Config::default();
+    config.server.stream_max_lifetime_secs = Some(1);
+    let state = create_test_state().with_config(config);
+    let update_tx = state.update_tx.clone();
+    let app = create_router(state, disabled_auth());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/stream?format=ndjson")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        response.into_body().collect(),
+    )
+    .await
+    .expect("stream should end after its lifetime")
+    .unwrap();
+    assert!(body.to_bytes().is_empty());
+    assert_eq!(update_tx.receiver_count(), 0);
+}
+
+#[tokio::test]
+async fn test_stream_sse_by_accept_header() {
     let state = create_test_state();
+    let update_tx = state.update_tx.clone();
     let app = create_router(state, disabled_auth());
 
-    // Test that /ws endpoint exists and responds
-    // Note: Full WebSocket upgrade requires a real WebSocket client
-    // With oneshot(), we just verify the endpoint is routed
     let response = app
         .oneshot(
             Request::builder()
-                .uri("/ws")
-                .header("Upgrade", "websocket")
-                .header("Connection", "upgrade")
-                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
-                .header("Sec-WebSocket-Version", "13")
+                .uri("/api/stream")
+                .header("Accept", "text/event-stream")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    update_tx
+        .send(stream_update("plc-001", "temperature", 3.0))
+        .unwrap();
+
+    let mut body = response.into_body();
+    let frame = body.frame().await.unwrap().unwrap();
+    let event = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+    assert!(event.starts_with("event: update\ndata: {"));
+    assert!(event.contains("\"register_name\":\"temperature\""));
+}
+
+// ============================================================================
+// Error Response Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_error_response_structure() {
+    let state = create_test_state();
+    let app = create_router(state, disabled_auth());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/devices/nonexistent")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    // All error responses should have these fields
+    assert!(json["error"].is_string());
+    assert!(json["code"].is_number());
+}
+
+#[tokio::test]
+async fn test_pretty_query_indents_json_responses() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let body = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            String::from_utf8(body.to_vec()).unwrap()
+        }
+    };
+
+    let compact = body("/api/devices/plc-001").await;
+    let pretty = body("/api/devices/plc-001?pretty=true").await;
+    assert!(!compact.contains('\n'));
+    assert!(pretty.starts_with("{\n  \""));
+    // Same content, same key order
+    assert_eq!(
+        pretty.split_whitespace().collect::<String>(),
+        compact.replace(' ', "")
+    );
+
+    // Errors are indented too
+    let error = body("/api/devices/nonexistent?pretty").await;
+    assert!(error.contains("\n  \"error\": "));
+}
+
+// ============================================================================
+// API Key Authentication Tests
+// ============================================================================
+
+/// Helper to create an enabled auth config for tests
+fn enabled_auth_with_keys(keys: Vec<&str>) -> AuthConfig {
+    AuthConfig {
+        enabled: true,
+        api_keys: keys.iter().map(|s| s.to_string()).collect(),
+        exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
+        ..AuthConfig::default()
+    }
+}
+
+/// Helper to make a GET request with API key header
+async fn get_json_with_key(
+    app: axum::Router,
+    uri: &str,
+    api_key: Option<&str>,
+) -> (StatusCode, serde_json::Value) {
+    let mut builder = Request::builder().uri(uri);
+
+    if let Some(key) = api_key {
+        builder = builder.header("X-API-Key", key);
+    }
+
+    let response = app
+        .oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap_or(serde_json::json!({}));
+
+    (status, json)
+}
+
+#[tokio::test]
+async fn test_auth_disabled_allows_all_requests() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    // Should succeed without API key when auth is disabled
+    let (status, _) = get_json_with_key(app, "/api/devices", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_auth_enabled_rejects_missing_key() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+
+    // Should fail without API key
+    let (status, json) = get_json_with_key(app, "/api/devices", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["error"], "unauthorized");
+    assert_eq!(json["message"], "Missing X-API-Key header");
+}
+
+#[tokio::test]
+async fn test_auth_enabled_rejects_invalid_key() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+
+    // Should fail with wrong API key
+    let (status, json) = get_json_with_key(app, "/api/devices", Some("wrong-key")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["error"], "unauthorized");
+    assert_eq!(json["message"], "Invalid API key");
+}
+
+#[tokio::test]
+async fn test_auth_enabled_accepts_valid_key() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+
+    // Should succeed with valid API key
+    let (status, json) = get_json_with_key(app, "/api/devices", Some("secret-key")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["count"], 2);
+}
+
+#[tokio::test]
+async fn test_auth_multiple_keys() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["key1", "key2", "key3"]));
+
+    // All keys should work
+    let (status, _) = get_json_with_key(app.clone(), "/api/devices", Some("key1")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = get_json_with_key(app.clone(), "/api/devices", Some("key2")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = get_json_with_key(app, "/api/devices", Some("key3")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_auth_excluded_paths_no_key_required() {
+    let state = create_test_state();
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+
+    // /health should work without key (excluded path)
+    let (status, _) = get_json_with_key(app.clone(), "/health", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // /metrics should work without key (excluded path)
+    let (status, _) = get_json_with_key(app, "/metrics", None).await;
+    // Metrics returns 503 if no handle, but not 401
+    assert_ne!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_auth_protected_endpoint_requires_key() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+
+    // /api/info should require key (not in excluded paths)
+    let (status, _) = get_json_with_key(app.clone(), "/api/info", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // But works with valid key
+    let (status, _) = get_json_with_key(app, "/api/info", Some("secret-key")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ============================================================================
+// Bearer Token (JWT) Authentication Tests
+// ============================================================================
+
+const JWT_SECRET: &str = "jwt-test-secret";
+
+/// Helper to create a JWT auth config for tests, accepting tokens for the
+/// `rustbridge` audience signed with `JWT_SECRET`
+fn jwt_auth() -> AuthConfig {
+    let mut auth = enabled_auth_with_keys(vec![]);
+    auth.mode = AuthMode::Jwt;
+    auth.jwt.secret = Some(JWT_SECRET.to_string());
+    auth.jwt.audiences = vec!["rustbridge".to_string()];
+    auth
+}
+
+/// Helper to sign a token for `audience` expiring `expires_in` seconds from
+/// now
+fn jwt_token(secret: &str, audience: &str, expires_in: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let claims = serde_json::json!({
+        "sub": "dashboard",
+        "aud": audience,
+        "exp": now + expires_in,
+    });
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// Helper to make a GET request with a bearer token
+async fn get_json_with_token(
+    app: axum::Router,
+    uri: &str,
+    token: Option<&str>,
+) -> (StatusCode, serde_json::Value) {
+    let mut builder = Request::builder().uri(uri);
+    if let Some(token) = token {
+        builder = builder.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app
+        .oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap_or(serde_json::json!({}));
+    (status, json)
+}
+
+#[tokio::test]
+async fn test_jwt_auth_accepts_valid_token() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, jwt_auth());
+
+    let token = jwt_token(JWT_SECRET, "rustbridge", 300);
+    let (status, json) = get_json_with_token(app, "/api/devices", Some(&token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["count"], 2);
+}
+
+#[tokio::test]
+async fn test_jwt_auth_rejects_invalid_tokens() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, jwt_auth());
+
+    // Expired beyond the default leeway of 60 seconds
+    let expired = jwt_token(JWT_SECRET, "rustbridge", -300);
+    let (status, json) = get_json_with_token(app.clone(), "/api/devices", Some(&expired)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["error"], "unauthorized");
+    assert_eq!(json["message"], "Token expired");
+
+    let forged = jwt_token("another-secret", "rustbridge", 300);
+    let (status, json) = get_json_with_token(app.clone(), "/api/devices", Some(&forged)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["message"], "Invalid token signature");
+
+    let other_audience = jwt_token(JWT_SECRET, "grafana", 300);
+    let (status, json) =
+        get_json_with_token(app.clone(), "/api/devices", Some(&other_audience)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["message"], "Token audience is not accepted");
+
+    let (status, json) = get_json_with_token(app.clone(), "/api/devices", Some("garbage")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(json["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid token"));
+}
+
+#[tokio::test]
+async fn test_jwt_auth_requires_bearer_header() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, jwt_auth());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/devices")
                 .body(Body::empty())
                 .unwrap(),
         )
         .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
 
-    // With oneshot + hyper, upgrade may return 426 (Upgrade Required)
-    // This confirms the endpoint exists and is trying to upgrade
-    // A real WebSocket client test would get 101 Switching Protocols
-    assert!(
-        response.status() == StatusCode::SWITCHING_PROTOCOLS
-            || response.status() == StatusCode::UPGRADE_REQUIRED,
-        "Expected 101 or 426, got {}",
-        response.status()
-    );
+    // API keys are not accepted in JWT mode
+    let (status, json) = get_json_with_key(app.clone(), "/api/devices", Some("secret-key")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["message"], "Missing Authorization: Bearer header");
+
+    // Excluded paths stay open
+    let (status, _) = get_json_with_token(app, "/health", None).await;
+    assert_eq!(status, StatusCode::OK);
 }
 
-// ============================================================================
-// Error Response Tests
-// ============================================================================
+/// Send a CORS preflight for a `POST` from `origin`
+async fn preflight(app: axum::Router, uri: &str, origin: &str) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri(uri)
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type,x-api-key")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
 
 #[tokio::test]
-async fn test_error_response_structure() {
-    let state = create_test_state();
-    let app = create_router(state, disabled_auth());
-
+async fn test_cors_allowed_origins() {
+    const DASHBOARD: &str = "https://dashboard.example.com";
+    let mut config = Config::default();
+    config.cors.allowed_origins = vec![DASHBOARD.to_string()];
+    let state = create_test_state().with_config(config);
+    populate_test_data(&state).await;
+    // Preflight requests carry no API key
+    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    let uri = "/api/devices/plc-001/registers/temperature";
+
+    let response = preflight(app.clone(), uri, DASHBOARD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], DASHBOARD);
+    let methods = headers["access-control-allow-methods"].to_str().unwrap();
+    assert!(methods.contains("GET") && methods.contains("POST"));
+    let allowed = headers["access-control-allow-headers"].to_str().unwrap();
+    assert!(allowed.contains("content-type") && allowed.contains("x-api-key"));
+
+    // The actual request carries the origin as well
     let response = app
+        .clone()
         .oneshot(
             Request::builder()
-                .uri("/api/devices/nonexistent")
+                .uri(uri)
+                .header("Origin", DASHBOARD)
+                .header("X-API-Key", "secret-key")
                 .body(Body::empty())
                 .unwrap(),
         )
         .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["access-control-allow-origin"], DASHBOARD);
+
+    // Other origins get no permission
+    let response = preflight(app, uri, "https://evil.example.com").await;
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+}
 
-    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+#[tokio::test]
+async fn test_cors_disabled_by_default() {
+    let app = create_router(create_test_state(), disabled_auth());
+    let response = preflight(app.clone(), "/api/info", "https://dashboard.example.com").await;
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    let mut config = Config::default();
+    config.cors.permissive = true;
+    let app = create_router(create_test_state().with_config(config), disabled_auth());
+    let response = preflight(app, "/api/info", "http://localhost:8080").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+}
 
-    let body = response.into_body().collect().await.unwrap().to_bytes();
-    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+#[tokio::test]
+async fn test_bridge_handle_start_and_stop() {
+    use rustbridge::bridge::Bridge;
 
-    // All error responses should have these fields
-    assert!(json["error"].is_string());
-    assert!(json["code"].is_number());
+    let mut config = Config::default();
+    config.server.host = "127.0.0.1".to_string();
+    config.server.port = 0;
+    config.server.metrics_enabled = false;
+
+    let handle = Bridge::new(config).await.unwrap().start().await.unwrap();
+    assert!(handle.register_store().read().await.is_empty());
+
+    // The API server is reachable while the bridge runs
+    let addr = handle.local_addr();
+    assert!(tokio::net::TcpStream::connect(addr).await.is_ok());
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), handle.stop())
+        .await
+        .expect("bridge did not stop in time")
+        .unwrap();
+
+    // The listener is closed once stopped
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
 }
 
-// ============================================================================
-// API Key Authentication Tests
-// ============================================================================
+/// Status code of a `GET` to a running bridge, over a real connection
+async fn http_status(addr: std::net::SocketAddr, path: &str, api_key: Option<&str>) -> u16 {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-/// Helper to create an enabled auth config for tests
-fn enabled_auth_with_keys(keys: Vec<&str>) -> AuthConfig {
-    AuthConfig {
-        enabled: true,
-        api_keys: keys.iter().map(|s| s.to_string()).collect(),
-        exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
-    }
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let key = api_key.map_or(String::new(), |key| format!("X-API-Key: {}\r\n", key));
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+        path, key
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    // "HTTP/1.1 200 OK"
+    response[9..12].parse().unwrap()
 }
 
-/// Helper to make a GET request with API key header
-async fn get_json_with_key(
-    app: axum::Router,
-    uri: &str,
-    api_key: Option<&str>,
-) -> (StatusCode, serde_json::Value) {
-    let mut builder = Request::builder().uri(uri);
+#[tokio::test]
+async fn test_bridge_applies_configured_auth() {
+    use rustbridge::bridge::Bridge;
 
-    if let Some(key) = api_key {
-        builder = builder.header("X-API-Key", key);
-    }
+    let mut config = Config::default();
+    config.server.host = "127.0.0.1".to_string();
+    config.server.port = 0;
+    config.server.metrics_enabled = false;
+    config.auth.enabled = true;
+    config.auth.api_keys = vec!["secret-key".to_string()];
 
-    let response = app
-        .oneshot(builder.body(Body::empty()).unwrap())
+    let handle = Bridge::new(config).await.unwrap().start().await.unwrap();
+    let addr = handle.local_addr();
+
+    assert_eq!(http_status(addr, "/api/info", None).await, 401);
+    assert_eq!(http_status(addr, "/api/info", Some("wrong-key")).await, 401);
+    assert_eq!(
+        http_status(addr, "/api/info", Some("secret-key")).await,
+        200
+    );
+    // Excluded by default
+    assert_eq!(http_status(addr, "/health", None).await, 200);
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_bridge_stop_reports_devices_offline() {
+    use rustbridge::bridge::Bridge;
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder};
+    use rustbridge::metrics::device_states;
+
+    // A device that accepts the connection but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    let mut config = ConfigBuilder::new()
+        .device(DeviceBuilder::tcp("shutdown-plc", "127.0.0.1", port, 1))
+        .build()
+        .unwrap();
+    config.server.host = "127.0.0.1".to_string();
+    config.server.port = 0;
+    config.server.metrics_enabled = false;
+
+    let handle = Bridge::new(config).await.unwrap().start().await.unwrap();
+    let online = || device_states().get("shutdown-plc").map(|d| d.online);
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while online() != Some(true) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("device did not come online");
+
+    // Stopping waits for the polling task, which reports the device offline
+    tokio::time::timeout(std::time::Duration::from_secs(5), handle.stop())
         .await
+        .expect("bridge did not stop in time")
         .unwrap();
+    assert_eq!(online(), Some(false));
+}
 
-    let status = response.status();
-    let body = response.into_body().collect().await.unwrap().to_bytes();
-    let json: serde_json::Value = serde_json::from_slice(&body).unwrap_or(serde_json::json!({}));
+#[tokio::test]
+async fn test_bridge_start_fails_when_a_required_device_is_unreachable() {
+    use rustbridge::bridge::Bridge;
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder};
+
+    // A port nothing listens on
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let mut config = ConfigBuilder::new()
+        .device(DeviceBuilder::tcp("plc-001", "127.0.0.1", port, 1).connect_retry(1, 10))
+        .require_all_devices(5_000)
+        .build()
+        .unwrap();
+    config.server.host = "127.0.0.1".to_string();
+    config.server.port = 0;
+    config.server.metrics_enabled = false;
 
-    (status, json)
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        Bridge::new(config).await.unwrap().start(),
+    )
+    .await
+    .expect("startup did not fail in time");
+    let error = result.err().expect("startup should fail").to_string();
+    assert!(error.contains("plc-001"), "{}", error);
 }
 
 #[tokio::test]
-async fn test_auth_disabled_allows_all_requests() {
-    let state = create_test_state();
+async fn test_write_timeout_override_reports_device_timeout() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
     populate_test_data(&state).await;
     let app = create_router(state, disabled_auth());
 
-    // Should succeed without API key when auth is disabled
-    let (status, _) = get_json_with_key(app, "/api/devices", None).await;
-    assert_eq!(status, StatusCode::OK);
+    // A device that accepts the request but never answers
+    let worker = tokio::spawn(async move {
+        let request = write_rx.recv().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        drop(request);
+    });
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature?timeout_ms=50",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(json["error"], "Write timeout");
+    worker.abort();
 }
 
 #[tokio::test]
-async fn test_auth_enabled_rejects_missing_key() {
-    let state = create_test_state();
+async fn test_write_handler_unavailable_distinct_from_timeout() {
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(writable_test_config());
     populate_test_data(&state).await;
-    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    let app = create_router(state, disabled_auth());
 
-    // Should fail without API key
-    let (status, json) = get_json_with_key(app, "/api/devices", None).await;
-    assert_eq!(status, StatusCode::UNAUTHORIZED);
-    assert_eq!(json["error"], "unauthorized");
-    assert_eq!(json["message"], "Missing X-API-Key header");
+    // The handler drops the request without answering
+    tokio::spawn(async move {
+        drop(write_rx.recv().await);
+    });
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "Write service unavailable");
+
+    let (status, _) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature?timeout_ms=0",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
-async fn test_auth_enabled_rejects_invalid_key() {
-    let state = create_test_state();
+async fn test_write_to_exposed_word_is_rejected() {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    let config = ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("temperature", 0))
+                .register(RegisterBuilder::holding("humidity", 1)),
+        )
+        .build()
+        .unwrap();
+    let state = create_test_state().with_config(config);
     populate_test_data(&state).await;
-    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    {
+        let mut store = state.register_store.write().await;
+        let registers = store.get_mut("plc-001").unwrap();
+        let word = RegisterValue {
+            name: "temperature.word0".to_string(),
+            ..registers["temperature"].clone()
+        };
+        registers.insert(word.name.clone(), word);
+    }
+    let app = create_router(state, disabled_auth());
 
-    // Should fail with wrong API key
-    let (status, json) = get_json_with_key(app, "/api/devices", Some("wrong-key")).await;
-    assert_eq!(status, StatusCode::UNAUTHORIZED);
-    assert_eq!(json["error"], "unauthorized");
-    assert_eq!(json["message"], "Invalid API key");
+    // Derived words are readable like any other register...
+    let (status, _) = get_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature.word0",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // ...but cannot be written
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/registers/temperature.word0",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "Register is read-only");
 }
 
 #[tokio::test]
-async fn test_auth_enabled_accepts_valid_key() {
-    let state = create_test_state();
+async fn test_write_requires_writable_register() {
+    use rustbridge::config::{ConfigBuilder, DeviceBuilder, RegisterBuilder};
+
+    let config = ConfigBuilder::new()
+        .device(
+            DeviceBuilder::tcp("plc-001", "localhost", 502, 1)
+                .register(RegisterBuilder::holding("temperature", 0))
+                .register(RegisterBuilder::holding("humidity", 1).writable(true)),
+        )
+        .build()
+        .unwrap();
+    let register_store: RegisterStore = Arc::new(RwLock::new(HashMap::new()));
+    let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(100);
+    let state = ApiState::new(register_store, write_tx).with_config(config);
     populate_test_data(&state).await;
-    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    let app = create_router(state, disabled_auth());
 
-    // Should succeed with valid API key
-    let (status, json) = get_json_with_key(app, "/api/devices", Some("secret-key")).await;
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/registers/temperature",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["error"], "Register is not writable");
+    assert!(write_rx.try_recv().is_err());
+
+    // Writable registers reach the device worker
+    let worker = tokio::spawn(async move {
+        let request = write_rx.recv().await.unwrap();
+        let _ = request.response_tx.send(Ok(()));
+    });
+    let (status, _) = post_json(
+        app,
+        "/api/devices/plc-001/registers/humidity",
+        serde_json::json!({"value": 1}),
+    )
+    .await;
     assert_eq!(status, StatusCode::OK);
-    assert_eq!(json["count"], 2);
+    worker.await.unwrap();
 }
 
 #[tokio::test]
-async fn test_auth_multiple_keys() {
-    let state = create_test_state();
+async fn test_raw_read_forwards_unit_id_override() {
+    let (raw_tx, mut raw_rx) = tokio::sync::mpsc::channel(10);
+    let state = create_test_state()
+        .with_config(other_units_config())
+        .with_raw_reads(raw_tx);
     populate_test_data(&state).await;
-    let app = create_router(state, enabled_auth_with_keys(vec!["key1", "key2", "key3"]));
+    let app = create_router(state, disabled_auth());
 
-    // All keys should work
-    let (status, _) = get_json_with_key(app.clone(), "/api/devices", Some("key1")).await;
-    assert_eq!(status, StatusCode::OK);
+    let worker = tokio::spawn(async move {
+        let request = raw_rx.recv().await.unwrap();
+        let seen = (request.address, request.count, request.unit_id);
+        let _ = request.response_tx.send(Ok(vec![0x00FA, 7]));
+        seen
+    });
 
-    let (status, _) = get_json_with_key(app.clone(), "/api/devices", Some("key2")).await;
-    assert_eq!(status, StatusCode::OK);
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/raw",
+        serde_json::json!({"register_type": "holding", "address": 40, "count": 2, "unit_id": 17}),
+    )
+    .await;
 
-    let (status, _) = get_json_with_key(app, "/api/devices", Some("key3")).await;
     assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["values"], serde_json::json!([250, 7]));
+    assert_eq!(json["unit_id"], 17);
+    assert_eq!(worker.await.unwrap(), (40, 2, Some(17)));
+}
+
+/// Configuration allowing raw reads from other unit ids and bus scans
+fn other_units_config() -> Config {
+    let mut config = Config::default();
+    config.server.allow_other_units = true;
+    config
 }
 
 #[tokio::test]
-async fn test_auth_excluded_paths_no_key_required() {
+async fn test_other_unit_reads_need_opt_in() {
+    let (raw_tx, mut raw_rx) = tokio::sync::mpsc::channel(10);
+    let state = create_test_state().with_raw_reads(raw_tx);
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/raw",
+        serde_json::json!({"register_type": "holding", "address": 40, "unit_id": 17}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["error"], "Reads from other unit ids not enabled");
+
+    let (status, _) = post_json(
+        app,
+        "/api/devices/plc-001/scan",
+        serde_json::json!({"first_unit_id": 1, "last_unit_id": 4}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(raw_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_raw_read_validation() {
     let state = create_test_state();
-    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
 
-    // /health should work without key (excluded path)
-    let (status, _) = get_json_with_key(app.clone(), "/health", None).await;
+    let (status, _) = post_json(
+        app.clone(),
+        "/api/devices/unknown/raw",
+        serde_json::json!({"register_type": "holding", "address": 0}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = post_json(
+        app.clone(),
+        "/api/devices/plc-001/raw",
+        serde_json::json!({"register_type": "holding", "address": 0, "unit_id": 0}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // No device workers behind this state
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/raw",
+        serde_json::json!({"register_type": "input", "address": 0}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "Read service unavailable");
+}
+
+#[tokio::test]
+async fn test_convert_without_device() {
+    let app = create_router(create_test_state(), disabled_auth());
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/convert",
+        serde_json::json!({"raw": [1, 0], "data_type": "u32", "scale": 0.001, "decimals": 1}),
+    )
+    .await;
     assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["decoded"], 65536.0);
+    assert_eq!(json["value"], 65.5);
+    assert_eq!(json["quality"], "good");
 
-    // /metrics should work without key (excluded path)
-    let (status, _) = get_json_with_key(app, "/metrics", None).await;
-    // Metrics returns 503 if no handle, but not 401
-    assert_ne!(status, StatusCode::UNAUTHORIZED);
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/convert",
+        serde_json::json!({"raw": [0x4142, 0x4300], "data_type": "string"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["value"], "ABC");
+
+    // Scale factor exponent, then scale and clamp in engineering units
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/convert",
+        serde_json::json!({
+            "raw": [12345], "data_type": "i16", "scale_factor": -2, "clamp_max": 100
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["value"], 100.0);
+
+    let (status, json) = post_json(
+        app.clone(),
+        "/api/convert",
+        serde_json::json!({"raw": [250], "data_type": "u16", "formula": "x / 10 - 5"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["value"], 20.0);
+
+    for body in [
+        serde_json::json!({"raw": [1], "data_type": "f32"}),
+        serde_json::json!({"raw": [1], "data_type": "u16", "pipeline": ["scale", "scale"]}),
+        serde_json::json!({"raw": [1], "data_type": "u16", "signed_bits": 20}),
+        serde_json::json!({"raw": [0x12AB], "data_type": "bcd"}),
+        serde_json::json!({"raw": [1], "data_type": "u16", "scale_factor": -32768}),
+        serde_json::json!({"raw": [1], "data_type": "u16", "scael": 2}),
+        serde_json::json!({"raw": [1], "data_type": "u16", "formula": "x * gain"}),
+    ] {
+        let (status, json) = post_json(app.clone(), "/api/convert", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert!(json["details"].is_string(), "{}", json);
+    }
 }
 
 #[tokio::test]
-async fn test_auth_protected_endpoint_requires_key() {
-    let state = create_test_state();
+async fn test_bus_scan_reports_responding_unit_ids() {
+    use rustbridge::api::RawReadError;
+
+    let (raw_tx, mut raw_rx) = tokio::sync::mpsc::channel(10);
+    let state = create_test_state()
+        .with_config(other_units_config())
+        .with_raw_reads(raw_tx);
     populate_test_data(&state).await;
-    let app = create_router(state, enabled_auth_with_keys(vec!["secret-key"]));
+    let app = create_router(state, disabled_auth());
 
-    // /api/info should require key (not in excluded paths)
-    let (status, _) = get_json_with_key(app.clone(), "/api/info", None).await;
-    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // Unit 2 answers, unit 3 rejects the register, the rest stay silent
+    let worker = tokio::spawn(async move {
+        let mut probes = Vec::new();
+        while let Some(request) = raw_rx.recv().await {
+            probes.push((request.unit_id, request.timeout));
+            let reply = match request.unit_id {
+                Some(2) => Ok(vec![42]),
+                Some(3) => Err(RawReadError::Exception("IllegalDataAddress".to_string())),
+                _ => Err(RawReadError::Failed("No response within 50ms".to_string())),
+            };
+            let _ = request.response_tx.send(reply);
+        }
+        probes
+    });
+
+    let (status, json) = post_json(
+        app,
+        "/api/devices/plc-001/scan",
+        serde_json::json!({"first_unit_id": 1, "last_unit_id": 4, "probe_timeout_ms": 50}),
+    )
+    .await;
 
-    // But works with valid key
-    let (status, _) = get_json_with_key(app, "/api/info", Some("secret-key")).await;
     assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["scanned"], 4);
+    let responding = json["responding"].as_array().unwrap();
+    assert_eq!(responding.len(), 2);
+    assert_eq!(responding[0]["unit_id"], 2);
+    assert_eq!(responding[0]["values"], serde_json::json!([42]));
+    assert_eq!(responding[1]["unit_id"], 3);
+    assert_eq!(responding[1]["status"], "exception");
+
+    let probes = worker.await.unwrap();
+    assert_eq!(probes.len(), 4);
+    assert!(probes
+        .iter()
+        .all(|(_, timeout)| *timeout == Some(std::time::Duration::from_millis(50))));
+}
+
+#[tokio::test]
+async fn test_bus_scan_rejects_invalid_range() {
+    let state = create_test_state();
+    populate_test_data(&state).await;
+    let app = create_router(state, disabled_auth());
+
+    let (status, _) = post_json(
+        app,
+        "/api/devices/plc-001/scan",
+        serde_json::json!({"first_unit_id": 10, "last_unit_id": 5}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
 }